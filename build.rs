@@ -1,6 +1,10 @@
+#[cfg(feature = "c_frames")]
 extern crate cc;
 
 fn main() {
+    // Les parseurs C ne sont plus nécessaires côté Rust (voir `transmission::servo::binary`), on
+    // ne les compile que pour les projets qui en dépendent encore.
+    #[cfg(feature = "c_frames")]
     cc::Build::new()
         .file("c_src/SharedWithRust.c")
         .warnings(false)
@@ -66,6 +66,13 @@ SharedServos2019 servo_read_frame(const uint8_t* message, buffer_size_t size) {
 		s.servos[index].blocked = (char)((0b00010000 & infos) >> 4);
 		s.servos[index].blocking_mode = (uint8_t)((0b00001000 & infos) >> 3);
 		s.servos[index].color = (uint8_t)(0b00000111 & infos);
+
+		uint16_t torque_limit = message[count++];
+		torque_limit <<= 8;
+		torque_limit |= message[count++];
+		s.servos[index].torque_limit = torque_limit;
+
+		s.servos[index].compliance_margin = message[count++];
 	}
 
 	// Tout s'est bien passé
@@ -113,6 +120,11 @@ buffer_size_t servo_write_frame(uint8_t* buf, buffer_size_t buf_size, const Shar
 			infos <<= 3;
 			infos |= obj->servos[index].color;
 			buf[size++] = infos;
+
+			buf[size++] = (uint8_t)((UINT8_MAX - ((0xff00 & obj->servos[index].torque_limit) >> 8)) ^ UINT8_MAX);
+			buf[size++] = (uint8_t)((UINT8_MAX - obj->servos[index].torque_limit) ^ UINT8_MAX);
+
+			buf[size++] = obj->servos[index].compliance_margin;
 		}
 	}
 
@@ -120,7 +132,7 @@ buffer_size_t servo_write_frame(uint8_t* buf, buffer_size_t buf_size, const Shar
 }
 
 uint8_t get_size_servo_frame(uint8_t nb_servos) {
-	return (uint8_t)(1 + nb_servos * 6);
+	return (uint8_t)(1 + nb_servos * 9);
 }
 
 ////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
@@ -36,6 +36,8 @@ typedef struct SharedServos2019 {
 		char blocked;
 		uint8_t blocking_mode;
 		uint8_t color;
+		uint16_t torque_limit; // en pour mille du couple nominal, 0 pour le couple par défaut
+		uint8_t compliance_margin; // 0 pour un asservissement rigide
 	} servos[MAX_SERVOS];
 
 	uint8_t nb_servos;
@@ -118,7 +120,8 @@ typedef uint8_t buffer_size_t;
 
 // Format d'une trame :
 // <nb_servo: u8>
-// <[<id: u8> <position: u16> <command: u16> <command_type, blocking data, color: u8>] ...>
+// <[<id: u8> <position: u16> <command: u16> <command_type, blocking data, color: u8>
+//   <torque_limit: u16> <compliance_margin: u8>] ...>
 extern SharedServos2019 servo_read_frame(const uint8_t* message, buffer_size_t size);
 extern buffer_size_t servo_write_frame(uint8_t* buf, buffer_size_t buf_size, const SharedServos2019* obj);
 extern uint8_t get_size_servo_frame(uint8_t nb_servos);
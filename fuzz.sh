@@ -16,3 +16,7 @@
 # Please make sure that you have installed cargo fuzz before
 # ---------------
  cargo fuzz run -j 8 fuzz_target_1 tests/fuzzing_data -- -max_len=256
+# Les autres cibles : toutes les trames JSON, la trame binaire des moteurs et la navigation
+# cargo fuzz run -j 8 json_frames -- -max_len=4096
+# cargo fuzz run -j 8 motor_binary -- -max_len=256
+# cargo fuzz run -j 8 navigation_frames -- -max_len=1024
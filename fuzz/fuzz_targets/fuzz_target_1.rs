@@ -10,5 +10,9 @@ fuzz_target!(|data: &[u8]| {
     for b in &data[0..min(data.len(), msg.capacity()) as usize] {
         msg.push(*b);
     }
-    let _ = ServoGroup::new(msg);
+    if let Ok(group) = ServoGroup::new(msg) {
+        if let Ok(bytes) = group.clone().into_bytes() {
+            assert_eq!(ServoGroup::new(bytes).ok(), Some(group));
+        }
+    }
 });
@@ -0,0 +1,30 @@
+#![no_main]
+#[macro_use] extern crate libfuzzer_sys;
+extern crate heapless;
+extern crate librobot;
+
+use heapless::consts::U4096;
+use heapless::String;
+use librobot::transmission::selftest::{visit_samples, SampleVisitor};
+use librobot::transmission::{Jsonizable, MessageKind};
+
+/// Lit les octets reçus comme chaque type de trame, et vérifie que les trames acceptées se
+/// relisent à l'identique après avoir été réécrites
+struct Parser<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> SampleVisitor for Parser<'a> {
+    fn visit<T: Jsonizable + PartialEq>(&mut self, kind: MessageKind, _sample: &T) {
+        if let Ok(frame) = T::from_json_slice(self.data) {
+            if let Ok(json) = frame.to_string::<U4096>() {
+                let again = T::from_json_slice(json.as_bytes());
+                assert!(again.ok() == Some(frame), "{:?} does not round trip", kind);
+            }
+        }
+    }
+}
+
+fuzz_target!(|data: &[u8]| {
+    visit_samples(&mut Parser { data });
+});
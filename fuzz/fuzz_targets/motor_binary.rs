@@ -0,0 +1,19 @@
+#![no_main]
+#[macro_use] extern crate libfuzzer_sys;
+extern crate librobot;
+
+use std::cmp::min;
+use librobot::transmission::motor::MotorsGroup;
+use librobot::transmission::*;
+
+fuzz_target!(|data: &[u8]| {
+    let mut msg = Message::new();
+    for b in &data[0..min(data.len(), msg.capacity()) as usize] {
+        msg.push(*b);
+    }
+    if let Ok(group) = MotorsGroup::new(msg) {
+        if let Ok(bytes) = group.clone().into_bytes() {
+            assert_eq!(MotorsGroup::new(bytes).ok(), Some(group));
+        }
+    }
+});
@@ -0,0 +1,21 @@
+#![no_main]
+#[macro_use] extern crate libfuzzer_sys;
+extern crate librobot;
+
+use librobot::navigation::PIDParameters;
+use librobot::transmission::navigation::{NavigationFrame, NavigationParametersFrame};
+use librobot::transmission::Jsonizable;
+use librobot::units::MilliMeter;
+
+fuzz_target!(|data: &[u8]| {
+    let mut params = PIDParameters::default();
+    if let Ok(frame) = NavigationParametersFrame::from_json_slice(data) {
+        params = PIDParameters::from_frame(&params, &frame);
+    }
+    if let Ok(frame) = NavigationFrame::from_json_slice(data) {
+        let left = MilliMeter(i64::from(frame.args_cmd1));
+        let right = MilliMeter(-i64::from(frame.args_cmd2));
+        let _ = params.distance_to_ticks(left, right);
+        let _ = params.ticks_to_distance(i64::from(frame.left_dist), i64::from(frame.right_dist));
+    }
+});
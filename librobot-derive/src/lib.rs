@@ -0,0 +1,56 @@
+#![warn(missing_docs)]
+
+//! Macros procédurales de la librairie du club.
+//!
+//! Ce crate est ré-exporté par `librobot`, il ne faut pas l'utiliser directement.
+
+extern crate proc_macro;
+
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{parse_macro_input, DeriveInput};
+
+/// Implémente `librobot::transmission::Jsonizable` pour une structure qui implémente déjà
+/// `Serialize` et `Deserialize`.
+///
+/// ```ignore
+/// #[derive(Debug, Serialize, Deserialize, Jsonizable)]
+/// pub struct MyFrame {
+///     pub value: u16,
+/// }
+/// ```
+#[proc_macro_derive(Jsonizable)]
+pub fn derive_jsonizable(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    let expanded = quote! {
+        impl #impl_generics ::librobot::transmission::Jsonizable for #name #ty_generics
+        #where_clause
+        {
+            /// Construit une trame a partir d'un flux de donnees json.
+            fn from_json_slice(
+                slice: &[u8],
+            ) -> ::core::result::Result<Self, ::librobot::transmission::Error> {
+                ::librobot::export::from_slice(slice)
+                    .map_err(::librobot::transmission::Error::Deserialization)
+            }
+
+            /// Construit une chaine de caractère en json à partir de cette trame
+            fn to_string<B>(
+                &self,
+            ) -> ::core::result::Result<
+                ::librobot::export::String<B>,
+                ::librobot::transmission::Error,
+            >
+            where
+                B: ::librobot::export::ArrayLength<u8>,
+            {
+                ::librobot::export::to_string(self)
+                    .map_err(::librobot::transmission::Error::Serialization)
+            }
+        }
+    };
+    expanded.into()
+}
@@ -7,20 +7,31 @@
 
 //! La librairie du club pour les µ-controlleurs arm.
 
-#[cfg(test)]
+#[cfg(any(test, feature = "std"))]
 #[macro_use]
 extern crate std;
 
 #[macro_use]
 extern crate serde_derive;
 
+// Permet au code généré par `#[derive(Jsonizable)]` d'utiliser le chemin `::librobot` à
+// l'intérieur de la librairie aussi.
+extern crate self as librobot;
+
 pub use crate::transmission::*;
 
 pub mod navigation;
+pub mod persistence;
+pub mod profile;
+pub mod status;
+pub mod time;
 pub mod transmission;
 pub mod units;
 
-#[cfg(not(feature = "robot_selected"))]
-fn error_message() {
-    compile_error!("You need to specify the robot using --features = \"primary\" or \"secondary\"")
+/// Les éléments utilisés par le code généré par `#[derive(Jsonizable)]`.
+#[doc(hidden)]
+pub mod export {
+    pub use heapless::{ArrayLength, String};
+    pub use serde_json_core::de::{from_slice, Error as DError};
+    pub use serde_json_core::ser::{to_string, Error as SError};
 }
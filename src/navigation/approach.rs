@@ -0,0 +1,140 @@
+//! Choix de la manœuvre pour rejoindre un point : en marche avant, ou en marche arrière quand la
+//! cible est derrière le robot.
+//!
+//! Le [`Controller`](super::Controller) ne sait faire que des rotations et des lignes droites.
+//! Pour rejoindre un point, le robot tourne vers lui puis avance ; si la cible est derrière lui
+//! et que l'arrière est autorisé, reculer vers elle évite un demi-tour :
+//!
+//! ```ignore
+//! let approach = pid.plan_goto(target, Reverse::Allowed);
+//! Controller::rotate_async(&pid, approach.rotation.as_milliradians() as f32).await?;
+//! Controller::forward_async(&pid, approach.distance.as_millimeters() as f32).await?;
+//! ```
+
+use super::{Coord, Pose};
+use crate::units::{Angle, MilliMeter};
+
+#[allow(unused_imports)]
+use libm::F32Ext;
+
+/// Autorise ou non le robot à rejoindre sa cible en marche arrière
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Reverse {
+    /// Toujours rejoindre la cible en marche avant
+    Forbidden,
+    /// Reculer vers la cible si la rotation est plus courte qu'en marche avant
+    Allowed,
+}
+
+/// La manœuvre pour rejoindre une cible : une rotation sur place, une ligne droite, puis une
+/// rotation finale pour prendre l'orientation demandée
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Approach {
+    /// La rotation à effectuer avant la ligne droite, dans `[-π, π[`
+    pub rotation: Angle,
+    /// La distance à parcourir, négative en marche arrière
+    pub distance: MilliMeter,
+    /// La rotation à effectuer à l'arrivée, nulle pour [`Approach::to_point`]
+    pub final_rotation: Angle,
+}
+
+impl Approach {
+    /// Calcule la manœuvre la plus courte pour aller de `from` jusqu'au point `target`
+    pub fn to_point(from: Pose, target: Coord, reverse: Reverse) -> Self {
+        let dx = (target.x - from.position.x).as_millimeters() as f32;
+        let dy = (target.y - from.position.y).as_millimeters() as f32;
+        let length = (dx * dx + dy * dy).sqrt();
+        if length < 1.0 {
+            return Approach {
+                rotation: Angle(0),
+                distance: MilliMeter(0),
+                final_rotation: Angle(0),
+            };
+        }
+        let forward = (Angle::from_radians(dy.atan2(dx)) - from.heading).normalize();
+        // Le cap de l'arrière du robot quand il recule droit vers la cible
+        let backward = (Angle::from_radians((-dy).atan2(-dx)) - from.heading).normalize();
+        let distance = MilliMeter(length.round() as i64);
+        if reverse == Reverse::Allowed && backward.0.abs() < forward.0.abs() {
+            Approach {
+                rotation: backward,
+                distance: -distance,
+                final_rotation: Angle(0),
+            }
+        } else {
+            Approach {
+                rotation: forward,
+                distance,
+                final_rotation: Angle(0),
+            }
+        }
+    }
+
+    /// Calcule la manœuvre la plus courte pour aller de `from` jusqu'à la position de `target`,
+    /// puis prendre son orientation
+    pub fn to_pose(from: Pose, target: Pose, reverse: Reverse) -> Self {
+        let mut approach = Approach::to_point(from, target.position, reverse);
+        let heading = from.heading + approach.rotation;
+        approach.final_rotation = (target.heading - heading).normalize();
+        approach
+    }
+
+    /// Renvoie vrai si la cible est rejointe en marche arrière
+    pub fn is_reverse(&self) -> bool {
+        self.distance.0 < 0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Approach, Reverse};
+    use crate::navigation::{Coord, Pose};
+    use crate::units::{Angle, MilliMeter};
+
+    fn pose(x: i64, y: i64, heading: Angle) -> Pose {
+        Pose {
+            position: Coord {
+                x: MilliMeter(x),
+                y: MilliMeter(y),
+            },
+            heading,
+        }
+    }
+
+    #[test]
+    fn target_behind() {
+        let from = pose(1000, 1000, Angle(0));
+        let behind = pose(500, 1000, Angle(0)).position;
+
+        let approach = Approach::to_point(from, behind, Reverse::Forbidden);
+        assert!(approach.rotation.0.abs() > 3100);
+        assert_eq!(approach.distance, MilliMeter(500));
+
+        let approach = Approach::to_point(from, behind, Reverse::Allowed);
+        assert_eq!(approach.rotation, Angle(0));
+        assert_eq!(approach.distance, MilliMeter(-500));
+        assert!(approach.is_reverse());
+
+        // Devant le robot, la marche avant reste la plus courte
+        let ahead = pose(1300, 1400, Angle(0)).position;
+        let approach = Approach::to_point(from, ahead, Reverse::Allowed);
+        assert_eq!(approach.rotation, Angle(927));
+        assert_eq!(approach.distance, MilliMeter(500));
+    }
+
+    #[test]
+    fn target_pose() {
+        let from = pose(0, 0, Angle::from_degrees(90.0));
+        let target = pose(0, -200, Angle(0));
+        let approach = Approach::to_pose(from, target, Reverse::Allowed);
+        assert_eq!(approach.rotation, Angle(0));
+        assert_eq!(approach.distance, MilliMeter(-200));
+        assert_eq!(approach.final_rotation, Angle(-1571));
+
+        let approach = Approach::to_pose(from, from, Reverse::Allowed);
+        assert_eq!(approach.distance, MilliMeter(0));
+        assert_eq!(approach.final_rotation, Angle(0));
+    }
+}
@@ -0,0 +1,341 @@
+//! Asservissement en position des axes auxiliaires (ascenseur, treuil...).
+//!
+//! Un [`ControlledAxis`] réutilise le PID de la navigation, un [`QeiManager`] et un
+//! [`Motor`] pour suivre les consignes d'un [`ControlledMotor`] de la trame [`MotorsGroup`] :
+//! un nombre de tours et un angle en 256èmes de tour. Le drapeau `finished` est levé quand l'axe
+//! atteint sa consigne, puis l'axe continue de la maintenir :
+//!
+//! ```ignore
+//! winch.command(&group);
+//! winch.update()?;
+//! status.controlled[0] = winch.status();
+//! ```
+
+use embedded_hal::digital::OutputPin;
+use embedded_hal::{PwmPin, Qei};
+use qei::QeiManager;
+
+use super::motor::{Command, Motor};
+use super::pid::PID;
+use super::SamplingError;
+use crate::transmission::motor::{ControlledMotor, MotorsGroup};
+
+/// Nombre de pas d'angle par tour dans un [`ControlledMotor`]
+pub const ANGLE_STEPS: u32 = 256;
+
+/// Les paramètres de l'asservissement d'un axe
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct AxisParameters {
+    /// Nombre de ticks de la roue codeuse par tour de l'axe
+    pub ticks_per_turn: u32,
+    /// Coefficient proportionnel
+    pub kp: f32,
+    /// Coefficient dérivé
+    pub kd: f32,
+    /// Coefficient intégral
+    pub ki: f32,
+    /// Écart maximal en ticks pour considérer la consigne atteinte
+    pub accuracy: u32,
+    /// Commande maximale appliquée au moteur, bornée par [`Motor::max_output`]
+    pub max_output: u16,
+}
+
+impl Default for AxisParameters {
+    fn default() -> Self {
+        AxisParameters {
+            ticks_per_turn: 1024,
+            kp: 1.0,
+            kd: 0.0,
+            ki: 0.0,
+            accuracy: 8,
+            max_output: u16::max_value(),
+        }
+    }
+}
+
+/// Un moteur asservi en position avec sa roue codeuse
+pub struct ControlledAxis<Q, MOT, DIR>
+where
+    Q: Qei<Count = u16>,
+    MOT: PwmPin<Duty = u16>,
+    DIR: OutputPin,
+{
+    id: u8,
+    qei: QeiManager<Q>,
+    motor: Motor<MOT, DIR>,
+    pid: PID,
+    params: AxisParameters,
+    target: (u8, u8),
+    finished: bool,
+    enabled: bool,
+    command: Command,
+}
+
+impl<Q, MOT, DIR> core::fmt::Debug for ControlledAxis<Q, MOT, DIR>
+where
+    Q: Qei<Count = u16>,
+    MOT: PwmPin<Duty = u16>,
+    DIR: OutputPin,
+{
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        write!(
+            f,
+            "ControlledAxis {{ id: {}, ticks: {}, goal: {}, finished: {} }}",
+            self.id,
+            self.qei.count(),
+            self.pid.get_goal(),
+            self.finished
+        )
+    }
+}
+
+impl<Q, MOT, DIR> ControlledAxis<Q, MOT, DIR>
+where
+    Q: Qei<Count = u16>,
+    MOT: PwmPin<Duty = u16>,
+    DIR: OutputPin,
+{
+    /// Crée l'axe `id` de la trame [`MotorsGroup`]. La position de départ correspond à 0 tour
+    /// et à un angle nul.
+    pub fn new(id: u8, qei: QeiManager<Q>, motor: Motor<MOT, DIR>, params: AxisParameters) -> Self {
+        ControlledAxis {
+            id,
+            qei,
+            motor,
+            pid: PID::new(params.kp, params.kd, params.ki),
+            params,
+            target: (0, 0),
+            finished: true,
+            enabled: true,
+            command: Command::Front(0),
+        }
+    }
+
+    /// Prend en compte la consigne de l'axe dans `group` si elle est nouvelle
+    pub fn command(&mut self, group: &MotorsGroup) {
+        let motor = group
+            .controlled
+            .iter()
+            .find(|motor| self.id != 0 && motor.id == self.id && motor.new_command);
+        if let Some(motor) = motor {
+            self.set_target(motor.wanted_angle_position, motor.wanted_nb_turns);
+        }
+    }
+
+    /// Demande à l'axe d'aller à `nb_turns` tours plus `angle` 256èmes de tour
+    pub fn set_target(&mut self, angle: u8, nb_turns: u8) {
+        self.target = (angle, nb_turns);
+        let steps = u32::from(nb_turns) * ANGLE_STEPS + u32::from(angle);
+        let ticks = steps as f32 * self.params.ticks_per_turn as f32 / ANGLE_STEPS as f32;
+        self.pid.set_goal(ticks);
+        self.finished = false;
+    }
+
+    /// Renvoie les paramètres de l'asservissement
+    pub fn get_params(&self) -> AxisParameters {
+        self.params
+    }
+
+    /// Change les paramètres de l'asservissement sans perdre la consigne
+    pub fn set_params(&mut self, params: AxisParameters) {
+        self.pid.set_gains(params.kp, params.kd, params.ki);
+        self.params = params;
+        let (angle, nb_turns) = self.target;
+        let finished = self.finished;
+        self.set_target(angle, nb_turns);
+        self.finished = finished;
+    }
+
+    /// Active ou coupe l'asservissement. Coupé, l'axe laisse son moteur à l'arrêt mais continue
+    /// de suivre sa position.
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    /// Renvoie vrai si l'asservissement est actif
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Échantillonne la roue codeuse, met à jour le PID et applique la commande au moteur. Si la
+    /// roue codeuse n'a pas pu être échantillonnée, le moteur est arrêté.
+    pub fn update(&mut self) -> Result<(), SamplingError> {
+        if self.qei.sample().is_err() {
+            self.apply(Command::Front(0));
+            return Err(SamplingError);
+        }
+        if !self.enabled {
+            self.apply(Command::Front(0));
+            return Ok(());
+        }
+        let position = self.qei.count() as f32;
+        self.pid.update(position);
+        let error = (position - self.pid.get_goal()).abs();
+        if error <= self.params.accuracy as f32 {
+            self.finished = true;
+        }
+        let max_output = self.motor.max_output().min(self.params.max_output);
+        self.apply(Command::truncate(-self.pid.get_command(), max_output));
+        Ok(())
+    }
+
+    fn apply(&mut self, command: Command) {
+        self.command = command;
+        self.motor.apply_command(command);
+    }
+
+    /// Renvoie la dernière commande appliquée au moteur
+    pub fn get_command(&self) -> Command {
+        self.command
+    }
+
+    /// Renvoie la position de l'axe en ticks
+    pub fn get_ticks(&self) -> i64 {
+        self.qei.count()
+    }
+
+    /// Renvoie vrai si l'axe a atteint sa dernière consigne
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Renvoie l'état de l'axe à envoyer à l'informatique dans la trame [`MotorsGroup`]
+    pub fn status(&self) -> ControlledMotor {
+        ControlledMotor {
+            id: self.id,
+            wanted_angle_position: self.target.0,
+            wanted_nb_turns: self.target.1,
+            finished: self.finished,
+            new_command: false,
+        }
+    }
+
+    /// Libère la roue codeuse et le moteur
+    pub fn release(self) -> (QeiManager<Q>, Motor<MOT, DIR>) {
+        (self.qei, self.motor)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{AxisParameters, ControlledAxis};
+    use crate::navigation::Motor;
+    use crate::transmission::motor::{ControlledMotor, MotorsGroup};
+    use core::cell::Cell;
+    use embedded_hal::digital::OutputPin;
+    use embedded_hal::{PwmPin, Qei};
+    use qei::QeiManager;
+    use std::rc::Rc;
+
+    /// Un axe simulé dont la vitesse est proportionnelle à la PWM
+    #[derive(Debug, Clone, Default)]
+    struct Winch {
+        duty: Rc<Cell<u16>>,
+        forward: Rc<Cell<bool>>,
+        position: Rc<Cell<i64>>,
+    }
+
+    impl Winch {
+        fn step(&self) {
+            let speed = i64::from(self.duty.get() / 10);
+            let speed = if self.forward.get() { speed } else { -speed };
+            self.position.set(self.position.get() + speed);
+        }
+    }
+
+    impl PwmPin for Winch {
+        type Channel = ();
+        type Time = ();
+        type Duty = u16;
+
+        fn disable(&mut self) {}
+
+        fn enable(&mut self) {}
+
+        fn get_period(&self) {}
+
+        fn get_duty(&self) -> u16 {
+            self.duty.get()
+        }
+
+        fn get_max_duty(&self) -> u16 {
+            1000
+        }
+
+        fn set_duty(&mut self, duty: u16) {
+            self.duty.set(duty);
+        }
+    }
+
+    impl OutputPin for Winch {
+        fn set_low(&mut self) {
+            self.forward.set(false);
+        }
+
+        fn set_high(&mut self) {
+            self.forward.set(true);
+        }
+    }
+
+    impl Qei for Winch {
+        type Count = u16;
+
+        fn count(&self) -> u16 {
+            self.position.get() as u16
+        }
+
+        fn direction(&self) -> embedded_hal::Direction {
+            embedded_hal::Direction::Upcounting
+        }
+    }
+
+    #[test]
+    fn winch_reaches_target() {
+        let winch = Winch::default();
+        let motor = Motor::new(winch.clone(), winch.clone());
+        let params = AxisParameters {
+            ticks_per_turn: 1024,
+            kp: 5.0,
+            accuracy: 4,
+            ..Default::default()
+        };
+        let mut axis = ControlledAxis::new(7, QeiManager::new(winch.clone()), motor, params);
+
+        let mut group = MotorsGroup::default();
+        group.controlled[0] = ControlledMotor {
+            id: 7,
+            wanted_angle_position: 64,
+            wanted_nb_turns: 2,
+            finished: false,
+            new_command: true,
+        };
+        axis.command(&group);
+        assert!(!axis.is_finished());
+        for _ in 0..100 {
+            axis.update().unwrap();
+            winch.step();
+        }
+        assert!(axis.is_finished());
+        assert!((axis.get_ticks() - 2304).abs() <= 4);
+        assert_eq!(
+            axis.status(),
+            ControlledMotor {
+                finished: true,
+                new_command: false,
+                ..group.controlled[0]
+            }
+        );
+
+        // Une trame sans nouvelle consigne ne change pas la consigne en cours
+        axis.set_target(0, 0);
+        group.controlled[0].new_command = false;
+        axis.command(&group);
+        for _ in 0..100 {
+            axis.update().unwrap();
+            winch.step();
+        }
+        assert!(axis.is_finished());
+        assert!(axis.get_ticks().abs() <= 4);
+    }
+}
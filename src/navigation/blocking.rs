@@ -3,6 +3,7 @@ use core::f32;
 use libm::F32Ext;
 
 use crate::navigation::motor::Command;
+use crate::time::{Clock, Instant};
 
 /// Module permettant de detecter si le robot est bloqué. Le robot est
 /// considéré bloqué s'il reçoit une commande non nulle mais ne bouge pas.
@@ -10,11 +11,15 @@ use crate::navigation::motor::Command;
 /// Plus précisément, si au moins une roue devrait avancer mais n'avance
 /// pas, le robot est considéré bloqué, sauf si l'autre roue est en train
 /// d'avancer.
+#[derive(Debug)]
 pub struct Blocking {
     command_threshold: u16,
     distance_threshold: f32,
+    /// Durée de la fenêtre d'observation de `update_with`, en ms
+    window: u32,
 
     last_dist: (f32, f32),
+    window_start: Option<Instant>,
     blocked: bool,
 }
 
@@ -28,17 +33,53 @@ impl Blocking {
         Blocking {
             command_threshold,
             distance_threshold,
+            window: 100,
             last_dist: (0.0, 0.0),
+            window_start: None,
             blocked: false,
         }
     }
 
+    /// `window`: La durée en ms pendant laquelle le robot doit avoir parcouru au moins
+    /// `distance_threshold` pour ne pas être considéré bloqué par `update_with`.
+    pub fn with_window(mut self, window: u32) -> Self {
+        self.window = window;
+        self
+    }
+
     /// Reset internal tracking data
     pub fn reset(&mut self) {
         self.last_dist = (0.0, 0.0);
+        self.window_start = None;
         self.blocked = false;
     }
 
+    /// Met à jour l'état de bloquage du robot à partir de l'heure donnée par `clock`. Cette
+    /// fonction peut être appelée à n'importe quelle fréquence : l'état n'est réévalué qu'une
+    /// fois la fenêtre d'observation écoulée.
+    ///
+    /// `command` La commande actuellement envoyée au moteur
+    /// `dist` La distance totale parcourue par les codeurs, en mm
+    pub fn update_with<C: Clock>(
+        &mut self,
+        command: (Command, Command),
+        dist: (f32, f32),
+        clock: &C,
+    ) {
+        let now = clock.now();
+        match self.window_start {
+            Some(start) if now.duration_since(start) < self.window => {}
+            Some(_) => {
+                self.update(command, dist);
+                self.window_start = Some(now);
+            }
+            None => {
+                self.last_dist = dist;
+                self.window_start = Some(now);
+            }
+        }
+    }
+
     /// Met à jour l'état de bloquage du robot. Cette fonction doit être appelée
     /// periodiquement avec une période suffisamment longue (par exemple 100ms).
     ///
@@ -74,6 +115,8 @@ impl Blocking {
 mod test {
     use crate::navigation::blocking::Blocking;
     use crate::navigation::Command;
+    use crate::time::Instant;
+    use core::cell::Cell;
 
     #[test]
     fn test_blocking() {
@@ -114,4 +157,28 @@ mod test {
         assert!(blocking.blocked());
         blocking.reset();
     }
+
+    #[test]
+    fn test_blocking_window() {
+        let now = Cell::new(Instant::from_millis(0));
+        let clock = || now.get();
+        let mut blocking = Blocking::new(100, 1.0).with_window(50);
+        let command = (Command::Front(120), Command::Front(120));
+
+        blocking.update_with(command, (0.0, 0.0), &clock);
+        assert!(!blocking.blocked());
+        // Le robot avance lentement : bloqué uniquement si la fenêtre est trop courte
+        for t in 1..50 {
+            now.set(Instant::from_millis(t));
+            blocking.update_with(command, (0.1 * t as f32, 0.1 * t as f32), &clock);
+            assert!(!blocking.blocked());
+        }
+        now.set(Instant::from_millis(50));
+        blocking.update_with(command, (5.0, 5.0), &clock);
+        assert!(!blocking.blocked());
+
+        now.set(Instant::from_millis(100));
+        blocking.update_with(command, (5.5, 5.5), &clock);
+        assert!(blocking.blocked());
+    }
 }
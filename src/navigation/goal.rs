@@ -0,0 +1,307 @@
+//! Futures attendant la fin d'un déplacement, pour les firmwares asynchrones.
+//!
+//! Le [`Controller`] est partagé dans une `RefCell` entre la tâche qui le met à jour
+//! périodiquement et les tâches qui donnent des ordres de déplacement :
+//!
+//! ```ignore
+//! let pid = RefCell::new(pid);
+//! // Dans la tâche de déplacement
+//! RealWorldPid::forward_async(&pid, 100.0).with_timeout(3000).await?;
+//! // Dans la tâche d'asservissement, appelée toutes les ms
+//! RealWorldPid::update_async(&pid);
+//! ```
+//!
+//! Chaque [`Controller::update_async`] met à jour le contrôleur puis réveille le futur en
+//! attente, qui vérifie alors si la consigne est atteinte, si le robot est bloqué ou si le délai
+//! est dépassé. Le réveil a lieu une fois la `RefCell` relâchée : un exécuteur qui interroge le
+//! futur directement depuis le réveil peut emprunter le contrôleur. Avec une autre mise à jour,
+//! comme [`Controller::update_with`], il faut appeler [`Controller::wake_goal`] ensuite.
+
+use core::cell::RefCell;
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll, Waker};
+
+use super::{Approach, Controller, Coord, Pose, Reverse, TickSource};
+use crate::units::Angle;
+
+/// La précision par défaut sur la position, en millimètres
+pub const DEFAULT_LINEAR_ACCURACY: f32 = 2.0;
+/// La précision par défaut sur l'angle, en milliradians
+pub const DEFAULT_ANGULAR_ACCURACY: f32 = 20.0;
+/// Le délai par défaut, en nombre de mises à jour du [`Controller`]
+pub const DEFAULT_TIMEOUT: u32 = 5000;
+
+/// Les raisons pour lesquelles un déplacement n'a pas atteint sa consigne
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum GoalError {
+    /// Le robot est bloqué (voir [`Controller::is_robot_blocked`])
+    Blocked,
+    /// La consigne n'a pas été atteinte dans le délai imparti
+    Timeout,
+}
+
+/// Le réveil des futurs en attente de la consigne, conservé dans le [`Controller`]
+#[derive(Debug, Default)]
+pub(crate) struct GoalWaker {
+    waker: Option<Waker>,
+    updates: u32,
+}
+
+impl GoalWaker {
+    pub(crate) fn new() -> Self {
+        GoalWaker {
+            waker: None,
+            updates: 0,
+        }
+    }
+
+    /// Compte une mise à jour. Le futur en attente n'est pas réveillé ici, le contrôleur étant
+    /// encore emprunté (voir [`Controller::wake_goal`]).
+    pub(crate) fn count_update(&mut self) {
+        self.updates = self.updates.wrapping_add(1);
+    }
+}
+
+/// Un futur terminé quand le robot a atteint sa consigne, est bloqué ou a dépassé le délai
+#[derive(Debug)]
+pub struct GoalFuture<'a, T: TickSource> {
+    controller: &'a RefCell<Controller<T>>,
+    started_at: u32,
+    timeout: u32,
+    lin_accuracy: f32,
+    ang_accuracy: f32,
+}
+
+impl<'a, T: TickSource> GoalFuture<'a, T> {
+    fn new(controller: &'a RefCell<Controller<T>>) -> Self {
+        GoalFuture {
+            started_at: controller.borrow().goal.updates,
+            controller,
+            timeout: DEFAULT_TIMEOUT,
+            lin_accuracy: DEFAULT_LINEAR_ACCURACY,
+            ang_accuracy: DEFAULT_ANGULAR_ACCURACY,
+        }
+    }
+
+    /// Change le délai, en nombre de mises à jour du [`Controller`]
+    pub fn with_timeout(mut self, updates: u32) -> Self {
+        self.timeout = updates;
+        self
+    }
+
+    /// Change la précision demandée, en millimètres et en milliradians
+    /// (voir [`Controller::is_goal_reached`])
+    pub fn with_accuracy(mut self, lin_accuracy: f32, ang_accuracy: f32) -> Self {
+        self.lin_accuracy = lin_accuracy;
+        self.ang_accuracy = ang_accuracy;
+        self
+    }
+}
+
+impl<'a, T: TickSource> Future for GoalFuture<'a, T> {
+    type Output = Result<(), GoalError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let mut controller = self.controller.borrow_mut();
+        if controller.is_goal_reached(self.lin_accuracy, self.ang_accuracy) {
+            Poll::Ready(Ok(()))
+        } else if controller.is_robot_blocked() {
+            Poll::Ready(Err(GoalError::Blocked))
+        } else if controller.goal.updates.wrapping_sub(self.started_at) >= self.timeout {
+            Poll::Ready(Err(GoalError::Timeout))
+        } else {
+            controller.goal.waker = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+impl<T: TickSource> Controller<T> {
+    /// Met à jour le contrôleur partagé `this` (voir [`Controller::update`]) puis réveille le
+    /// futur en attente de la consigne
+    pub fn update_async(this: &RefCell<Self>) {
+        this.borrow_mut().update();
+        Controller::wake_goal(this);
+    }
+
+    /// Réveille le futur en attente de la consigne, après avoir relâché `this`
+    pub fn wake_goal(this: &RefCell<Self>) {
+        let waker = this.borrow_mut().goal.waker.take();
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+
+    /// Ordonne au robot d'avancer de `distance` (en mm) et renvoie un futur terminé à la fin
+    /// du déplacement
+    pub fn forward_async(this: &RefCell<Self>, distance: f32) -> GoalFuture<'_, T> {
+        this.borrow_mut().forward(distance);
+        GoalFuture::new(this)
+    }
+
+    /// Ordonne au robot de reculer de `distance` (en mm) et renvoie un futur terminé à la fin
+    /// du déplacement
+    pub fn backward_async(this: &RefCell<Self>, distance: f32) -> GoalFuture<'_, T> {
+        this.borrow_mut().backward(distance);
+        GoalFuture::new(this)
+    }
+
+    /// Ordonne au robot de tourner de `angle` (en milliradians) et renvoie un futur terminé à
+    /// la fin de la rotation
+    pub fn rotate_async(this: &RefCell<Self>, angle: f32) -> GoalFuture<'_, T> {
+        this.borrow_mut().rotate(angle);
+        GoalFuture::new(this)
+    }
+
+    /// Rejoint le point `target` en tournant vers lui puis en avançant, ou en reculant si c'est
+    /// plus court et que `reverse` l'autorise (voir [`Controller::plan_goto`])
+    pub async fn goto_async(
+        this: &RefCell<Self>,
+        target: Coord,
+        reverse: Reverse,
+    ) -> Result<(), GoalError> {
+        let approach = this.borrow().plan_goto(target, reverse);
+        Controller::approach_async(this, approach).await
+    }
+
+    /// Rejoint la position de `target` comme [`Controller::goto_async`], puis tourne pour prendre
+    /// son orientation
+    pub async fn goto_pose_async(
+        this: &RefCell<Self>,
+        target: Pose,
+        reverse: Reverse,
+    ) -> Result<(), GoalError> {
+        let approach = this.borrow().plan_goto_pose(target, reverse);
+        Controller::approach_async(this, approach).await
+    }
+
+    async fn approach_async(this: &RefCell<Self>, approach: Approach) -> Result<(), GoalError> {
+        Controller::rotate_async(this, approach.rotation.as_milliradians() as f32).await?;
+        Controller::forward_async(this, approach.distance.as_millimeters() as f32).await?;
+        if approach.final_rotation != Angle(0) {
+            Controller::rotate_async(this, approach.final_rotation.as_milliradians() as f32)
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use core::cell::{Cell, RefCell};
+    use core::future::Future;
+    use core::pin::Pin;
+    use core::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+
+    use qei::QeiManager;
+
+    use super::GoalError;
+    use crate::navigation::motor::test::DummyMotor;
+    use crate::navigation::{PIDParameters, RealWorldPid};
+
+    type Pid = RealWorldPid<DummyMotor, DummyMotor>;
+
+    fn noop_raw_waker() -> RawWaker {
+        fn clone(_: *const ()) -> RawWaker {
+            noop_raw_waker()
+        }
+        fn noop(_: *const ()) {}
+        static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, noop, noop, noop);
+        RawWaker::new(core::ptr::null(), &VTABLE)
+    }
+
+    /// Note si le contrôleur pouvait être emprunté au moment du réveil
+    struct Probe {
+        pid: *const RefCell<Pid>,
+        free: Cell<Option<bool>>,
+    }
+
+    #[allow(unsafe_code)]
+    fn probe_waker(probe: &Probe) -> Waker {
+        fn raw(data: *const ()) -> RawWaker {
+            RawWaker::new(data, &VTABLE)
+        }
+        fn wake(data: *const ()) {
+            let probe = unsafe { &*(data as *const Probe) };
+            let free = unsafe { &*probe.pid }.try_borrow_mut().is_ok();
+            probe.free.set(Some(free));
+        }
+        fn drop(_: *const ()) {}
+        static VTABLE: RawWakerVTable = RawWakerVTable::new(raw, wake, wake, drop);
+        unsafe { Waker::from_raw(raw(probe as *const Probe as *const ())) }
+    }
+
+    #[allow(unsafe_code)]
+    fn poll<F: Future + Unpin>(future: &mut F) -> Poll<F::Output> {
+        let waker = unsafe { Waker::from_raw(noop_raw_waker()) };
+        let mut context = Context::from_waker(&waker);
+        Pin::new(future).poll(&mut context)
+    }
+
+    #[test]
+    fn forward_async_completes() {
+        let pid_parameters = PIDParameters {
+            coder_radius: 30.0,
+            left_wheel_coef: 1.0,
+            right_wheel_coef: -1.0,
+            ticks_per_turn: 1024,
+            inter_axial_length: 300.0,
+            pos_kp: 1.0,
+            orient_kp: 1.0,
+            max_output: 100,
+            ..Default::default()
+        };
+        let mut motor_left = DummyMotor::new();
+        let mut motor_right = DummyMotor::new();
+        let qei_left = QeiManager::new(motor_left.clone());
+        let qei_right = QeiManager::new(motor_right.clone());
+        let pid = RefCell::new(RealWorldPid::new(qei_left, qei_right, &pid_parameters));
+
+        let mut goal = RealWorldPid::forward_async(&pid, 188.5);
+        assert_eq!(poll(&mut goal), Poll::Pending);
+        pid.borrow_mut().update();
+        assert_eq!(poll(&mut goal), Poll::Pending);
+
+        motor_left.set_position(1024);
+        motor_right.set_position(-1024);
+        pid.borrow_mut().update();
+        assert_eq!(poll(&mut goal), Poll::Ready(Ok(())));
+
+        let mut goal = RealWorldPid::forward_async(&pid, 100.0).with_timeout(2);
+        pid.borrow_mut().update();
+        assert_eq!(poll(&mut goal), Poll::Pending);
+        pid.borrow_mut().update();
+        assert_eq!(poll(&mut goal), Poll::Ready(Err(GoalError::Timeout)));
+    }
+
+    #[test]
+    fn goal_woken_after_release() {
+        let pid_parameters = PIDParameters {
+            coder_radius: 30.0,
+            right_wheel_coef: -1.0,
+            ticks_per_turn: 1024,
+            max_output: 100,
+            ..Default::default()
+        };
+        let qei_left = QeiManager::new(DummyMotor::new());
+        let qei_right = QeiManager::new(DummyMotor::new());
+        let pid: RefCell<Pid> =
+            RefCell::new(RealWorldPid::new(qei_left, qei_right, &pid_parameters));
+        let probe = Probe {
+            pid: &pid,
+            free: Cell::new(None),
+        };
+        let waker = probe_waker(&probe);
+        let mut context = Context::from_waker(&waker);
+
+        let mut goal = RealWorldPid::forward_async(&pid, 100.0);
+        assert_eq!(Pin::new(&mut goal).poll(&mut context), Poll::Pending);
+        // Une mise à jour pendant que le contrôleur est emprunté ne réveille pas le futur
+        pid.borrow_mut().update();
+        assert_eq!(probe.free.get(), None);
+        RealWorldPid::update_async(&pid);
+        assert_eq!(probe.free.get(), Some(true));
+    }
+}
@@ -0,0 +1,82 @@
+//! Fonctions appelées par la navigation lors de ses changements d'état.
+//!
+//! Plutôt que de vérifier à chaque tour de boucle si la consigne est atteinte, si le robot est
+//! bloqué ou s'il est arrêté d'urgence, le firmware enregistre des fonctions qui sont appelées
+//! une seule fois, au moment où l'état change :
+//!
+//! ```ignore
+//! pid.set_hooks(NavigationHooks {
+//!     on_blocked: Some(|_pose| BUZZER.store(true, Ordering::Relaxed)),
+//!     ..Default::default()
+//! });
+//! ```
+
+use super::Pose;
+
+/// Une fonction appelée lors d'un événement de la navigation, avec la position du robot
+pub type NavigationHook = fn(Pose);
+
+/// Les fonctions appelées lors des événements de la navigation
+#[derive(Debug, Copy, Clone, Default)]
+pub struct NavigationHooks {
+    /// Appelée quand le robot atteint sa consigne
+    pub on_goal_reached: Option<NavigationHook>,
+    /// Appelée quand le robot devient bloqué
+    pub on_blocked: Option<NavigationHook>,
+    /// Appelée lors d'un arrêt d'urgence
+    pub on_emergency_stop: Option<NavigationHook>,
+}
+
+/// L'état de la navigation observé lors de la dernière mise à jour, pour n'appeler les
+/// fonctions qu'au changement d'état
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct Events {
+    pub(crate) hooks: NavigationHooks,
+    /// Précision sur la position en mm pour considérer la consigne atteinte
+    pub(crate) lin_accuracy: f32,
+    /// Précision sur l'angle en milliradians pour considérer la consigne atteinte
+    pub(crate) ang_accuracy: f32,
+    pub(crate) emergency_stopped: bool,
+    goal_reached: bool,
+    blocked: bool,
+}
+
+impl Events {
+    pub(crate) fn new() -> Self {
+        Events {
+            hooks: NavigationHooks::default(),
+            lin_accuracy: 2.0,
+            ang_accuracy: 20.0,
+            emergency_stopped: false,
+            // Pas d'événement au démarrage, quand le robot est immobile sans consigne
+            goal_reached: true,
+            blocked: false,
+        }
+    }
+
+    /// Appelle les fonctions des états qui viennent de devenir vrais
+    pub(crate) fn update(&mut self, goal_reached: bool, blocked: bool, pose: Pose) {
+        if goal_reached && !self.goal_reached {
+            if let Some(hook) = self.hooks.on_goal_reached {
+                hook(pose);
+            }
+        }
+        if blocked && !self.blocked {
+            if let Some(hook) = self.hooks.on_blocked {
+                hook(pose);
+            }
+        }
+        self.goal_reached = goal_reached;
+        self.blocked = blocked;
+    }
+
+    /// Passe en arrêt d'urgence, en appelant la fonction associée si ce n'était pas déjà le cas
+    pub(crate) fn emergency_stop(&mut self, pose: Pose) {
+        if !self.emergency_stopped {
+            self.emergency_stopped = true;
+            if let Some(hook) = self.hooks.on_emergency_stop {
+                hook(pose);
+            }
+        }
+    }
+}
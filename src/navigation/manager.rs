@@ -0,0 +1,476 @@
+//! Mise à jour de plusieurs axes asservis depuis une seule boucle.
+//!
+//! Le déplacement, l'ascenseur et la tourelle ont chacun leur asservissement. Plutôt que
+//! d'entrelacer leurs boucles à la main, l'[`AxisManager`] les met à jour ensemble à chaque
+//! appel périodique, avec pour chaque axe son activation, sa commande maximale et sa détection
+//! du blocage, puis rend l'état de tous les axes dans un seul [`AxesSnapshot`] :
+//!
+//! ```ignore
+//! let mut manager = AxisManager::new();
+//! let drive = manager.add(AxisConfig::default()).unwrap();
+//! let elevator = manager
+//!     .add(AxisConfig {
+//!         max_output: Some(400),
+//!         ..Default::default()
+//!     })
+//!     .unwrap();
+//! // Dans l'interruption du timer
+//! let snapshot = manager.update(&mut [&mut pid, &mut lift], &clock);
+//! if snapshot.axes[elevator].blocked {
+//!     manager.set_enabled(elevator, false);
+//! }
+//! ```
+
+use heapless::consts::U4;
+use heapless::Vec;
+
+use super::blocking::Blocking;
+use super::motor::Command;
+use super::{ControlledAxis, Controller, SamplingError, TickSource};
+use crate::time::{Clock, Instant};
+use embedded_hal::digital::OutputPin;
+use embedded_hal::{PwmPin, Qei};
+
+/// Nombre maximal d'axes gérés par un [`AxisManager`]
+pub const MAX_AXES: usize = 4;
+
+/// Un asservissement que l'[`AxisManager`] peut mettre à jour
+pub trait Axis {
+    /// Échantillonne les capteurs et met à jour l'asservissement comme si l'instant présent
+    /// était `now`
+    fn update_at(&mut self, now: Instant) -> Result<(), SamplingError>;
+
+    /// Active ou coupe l'asservissement : coupé, l'axe ne commande plus ses moteurs. Une fois
+    /// rétabli, l'axe retrouve l'activation de chacune de ses boucles d'avant la coupure.
+    fn set_enabled(&mut self, enabled: bool);
+
+    /// Borne la commande des moteurs de l'axe
+    fn set_output_limit(&mut self, max_output: u16);
+
+    /// Renvoie la commande de chaque moteur de l'axe. Un axe à un seul moteur renvoie une
+    /// seconde commande nulle.
+    fn commands(&self) -> (Command, Command);
+
+    /// Renvoie la position de chaque moteur de l'axe, dans l'unité des seuils de blocage de son
+    /// [`AxisConfig`]
+    fn positions(&self) -> (f32, f32);
+
+    /// Renvoie vrai si l'axe a atteint sa consigne
+    fn is_finished(&self) -> bool;
+}
+
+impl<T: TickSource> Axis for Controller<T> {
+    fn update_at(&mut self, now: Instant) -> Result<(), SamplingError> {
+        self.try_update_at(now)
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.internal_pid.set_suspended(!enabled);
+    }
+
+    fn set_output_limit(&mut self, max_output: u16) {
+        if self.params.max_output != max_output {
+            let mut params = self.params.clone();
+            params.max_output = max_output;
+            self.set_params(&params);
+        }
+    }
+
+    fn commands(&self) -> (Command, Command) {
+        self.get_command()
+    }
+
+    fn positions(&self) -> (f32, f32) {
+        self.get_wheel_dist()
+    }
+
+    fn is_finished(&self) -> bool {
+        self.is_goal_reached(self.events.lin_accuracy, self.events.ang_accuracy)
+    }
+}
+
+impl<Q, MOT, DIR> Axis for ControlledAxis<Q, MOT, DIR>
+where
+    Q: Qei<Count = u16>,
+    MOT: PwmPin<Duty = u16>,
+    DIR: OutputPin,
+{
+    fn update_at(&mut self, _now: Instant) -> Result<(), SamplingError> {
+        self.update()
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        ControlledAxis::set_enabled(self, enabled);
+    }
+
+    fn set_output_limit(&mut self, max_output: u16) {
+        let mut params = self.get_params();
+        params.max_output = max_output;
+        self.set_params(params);
+    }
+
+    fn commands(&self) -> (Command, Command) {
+        (self.get_command(), Command::Front(0))
+    }
+
+    fn positions(&self) -> (f32, f32) {
+        (self.get_ticks() as f32, 0.0)
+    }
+
+    fn is_finished(&self) -> bool {
+        ControlledAxis::is_finished(self)
+    }
+}
+
+/// La configuration d'un axe de l'[`AxisManager`]
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct AxisConfig {
+    /// Vrai si l'axe est asservi
+    pub enabled: bool,
+    /// Commande maximale imposée aux moteurs de l'axe, `None` pour garder celle des paramètres
+    /// de l'axe
+    pub max_output: Option<u16>,
+    /// Commande au delà de laquelle l'axe doit bouger pour ne pas être considéré bloqué
+    pub command_threshold: u16,
+    /// Déplacement minimal de l'axe pendant `stall_window`, dans l'unité de
+    /// [`Axis::positions`]
+    pub distance_threshold: f32,
+    /// Durée de la fenêtre d'observation du blocage, en millisecondes
+    pub stall_window: u32,
+}
+
+impl Default for AxisConfig {
+    fn default() -> Self {
+        AxisConfig {
+            enabled: true,
+            max_output: None,
+            command_threshold: 20,
+            distance_threshold: 1.0,
+            stall_window: 100,
+        }
+    }
+}
+
+/// L'état d'un axe lors de la dernière mise à jour
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct AxisSample {
+    /// Vrai si l'axe est asservi
+    pub enabled: bool,
+    /// La commande de chaque moteur de l'axe
+    pub commands: (Command, Command),
+    /// La position de chaque moteur de l'axe
+    pub positions: (f32, f32),
+    /// Vrai si l'axe a atteint sa consigne
+    pub finished: bool,
+    /// Vrai si l'axe reçoit une commande mais ne bouge pas
+    pub blocked: bool,
+    /// Vrai si les capteurs de l'axe n'ont pas pu être échantillonnés
+    pub sampling_error: bool,
+}
+
+impl Default for AxisSample {
+    fn default() -> Self {
+        AxisSample {
+            enabled: false,
+            commands: (Command::Front(0), Command::Front(0)),
+            positions: (0.0, 0.0),
+            finished: true,
+            blocked: false,
+            sampling_error: false,
+        }
+    }
+}
+
+/// L'état de tous les axes à un même instant
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct AxesSnapshot {
+    /// L'instant de la mise à jour
+    pub time: Instant,
+    /// Le nombre d'axes décrits dans `axes`
+    pub count: u8,
+    /// L'état de chaque axe, dans l'ordre de leur ajout à l'[`AxisManager`]
+    pub axes: [AxisSample; MAX_AXES],
+}
+
+impl AxesSnapshot {
+    /// Renvoie l'état des axes mis à jour
+    pub fn iter(&self) -> impl Iterator<Item = &AxisSample> {
+        self.axes.iter().take(self.count as usize)
+    }
+}
+
+#[derive(Debug)]
+struct Slot {
+    config: AxisConfig,
+    /// Activation et commande maximale déjà imposées à l'axe
+    applied: Option<(bool, Option<u16>)>,
+    blocking: Blocking,
+}
+
+/// Met à jour plusieurs axes asservis indépendants depuis un seul appel périodique
+#[derive(Debug, Default)]
+pub struct AxisManager {
+    slots: Vec<Slot, U4>,
+    snapshot: AxesSnapshot,
+}
+
+impl AxisManager {
+    /// Crée un gestionnaire sans axe
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Ajoute un axe et renvoie son numéro, ou renvoie sa configuration s'il y a déjà
+    /// [`MAX_AXES`] axes
+    pub fn add(&mut self, config: AxisConfig) -> Result<usize, AxisConfig> {
+        let slot = Slot {
+            config,
+            applied: None,
+            blocking: Blocking::new(config.command_threshold, config.distance_threshold)
+                .with_window(config.stall_window),
+        };
+        self.slots.push(slot).map_err(|slot| slot.config)?;
+        Ok(self.slots.len() - 1)
+    }
+
+    /// Renvoie la configuration de l'axe `axis`
+    pub fn config(&self, axis: usize) -> Option<&AxisConfig> {
+        self.slots.get(axis).map(|slot| &slot.config)
+    }
+
+    /// Active ou coupe l'asservissement de l'axe `axis` à partir de la prochaine mise à jour
+    pub fn set_enabled(&mut self, axis: usize, enabled: bool) {
+        if let Some(slot) = self.slots.get_mut(axis) {
+            slot.config.enabled = enabled;
+            slot.blocking.reset();
+        }
+    }
+
+    /// Change la commande maximale de l'axe `axis` à partir de la prochaine mise à jour
+    pub fn set_output_limit(&mut self, axis: usize, max_output: u16) {
+        if let Some(slot) = self.slots.get_mut(axis) {
+            slot.config.max_output = Some(max_output);
+        }
+    }
+
+    /// Met à jour les axes `axes`, dans l'ordre de leur ajout, à l'instant donné par `clock`.
+    /// L'activation et la commande maximale de la configuration, si elle est donnée, sont imposées
+    /// à chaque axe quand elles changent. Les axes en trop sont ignorés. Renvoie l'état de tous
+    /// les axes.
+    pub fn update<C: Clock>(&mut self, axes: &mut [&mut dyn Axis], clock: &C) -> AxesSnapshot {
+        let now = clock.now();
+        let mut snapshot = AxesSnapshot {
+            time: now,
+            ..Default::default()
+        };
+        let pairs = self.slots.iter_mut().zip(axes.iter_mut());
+        for ((slot, axis), sample) in pairs.zip(snapshot.axes.iter_mut()) {
+            let config = slot.config;
+            if slot.applied != Some((config.enabled, config.max_output)) {
+                axis.set_enabled(config.enabled);
+                if let Some(max_output) = config.max_output {
+                    axis.set_output_limit(max_output);
+                }
+                slot.applied = Some((config.enabled, config.max_output));
+            }
+            let sampling_error = axis.update_at(now).is_err();
+            let (commands, positions) = (axis.commands(), axis.positions());
+            if config.enabled {
+                slot.blocking.update_with(commands, positions, &|| now);
+            }
+            *sample = AxisSample {
+                enabled: config.enabled,
+                commands,
+                positions,
+                finished: axis.is_finished(),
+                blocked: config.enabled && slot.blocking.blocked(),
+                sampling_error,
+            };
+            snapshot.count += 1;
+        }
+        self.snapshot = snapshot;
+        snapshot
+    }
+
+    /// Renvoie vrai si l'axe `axis` était bloqué lors de la dernière mise à jour
+    pub fn is_blocked(&self, axis: usize) -> bool {
+        self.snapshot
+            .iter()
+            .nth(axis)
+            .map_or(false, |sample| sample.blocked)
+    }
+
+    /// Renvoie l'état des axes lors de la dernière mise à jour
+    pub fn snapshot(&self) -> &AxesSnapshot {
+        &self.snapshot
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Axis, AxisConfig, AxisManager, MAX_AXES};
+    use crate::navigation::{Command, Controller, PIDParameters, SamplingError, TickSource};
+    use crate::time::Instant;
+    use core::cell::Cell;
+
+    #[derive(Debug)]
+    struct FakeAxis {
+        enabled: bool,
+        max_output: u16,
+        command: u16,
+        position: f32,
+        updates: u32,
+    }
+
+    impl FakeAxis {
+        fn new(command: u16) -> Self {
+            FakeAxis {
+                enabled: false,
+                max_output: u16::max_value(),
+                command,
+                position: 0.0,
+                updates: 0,
+            }
+        }
+    }
+
+    impl Axis for FakeAxis {
+        fn update_at(&mut self, _now: Instant) -> Result<(), SamplingError> {
+            self.updates += 1;
+            Ok(())
+        }
+
+        fn set_enabled(&mut self, enabled: bool) {
+            self.enabled = enabled;
+        }
+
+        fn set_output_limit(&mut self, max_output: u16) {
+            self.max_output = max_output;
+        }
+
+        fn commands(&self) -> (Command, Command) {
+            let command = if self.enabled { self.command } else { 0 };
+            (
+                Command::Front(command.min(self.max_output)),
+                Command::Front(0),
+            )
+        }
+
+        fn positions(&self) -> (f32, f32) {
+            (self.position, 0.0)
+        }
+
+        fn is_finished(&self) -> bool {
+            self.command == 0
+        }
+    }
+
+    #[test]
+    fn independent_axes() {
+        let mut manager = AxisManager::new();
+        let drive = manager.add(AxisConfig::default()).unwrap();
+        let lift = manager
+            .add(AxisConfig {
+                enabled: false,
+                max_output: Some(40),
+                ..Default::default()
+            })
+            .unwrap();
+        for _ in 2..MAX_AXES {
+            manager.add(AxisConfig::default()).unwrap();
+        }
+        assert!(manager.add(AxisConfig::default()).is_err());
+
+        let now = Cell::new(Instant::from_millis(0));
+        let clock = || now.get();
+        let (mut wheels, mut elevator) = (FakeAxis::new(80), FakeAxis::new(80));
+        for millis in (0..=200).step_by(10) {
+            now.set(Instant::from_millis(millis));
+            // Les roues avancent, l'ascenseur est coincé
+            wheels.position += 1.0;
+            manager.update(&mut [&mut wheels, &mut elevator], &clock);
+        }
+        let snapshot = *manager.snapshot();
+        assert_eq!(snapshot.count, 2);
+        assert_eq!(snapshot.time, Instant::from_millis(200));
+        assert_eq!(wheels.updates, 21);
+        // Sans commande maximale dans la configuration, celle de l'axe est gardée
+        assert_eq!(wheels.max_output, u16::max_value());
+        assert_eq!(snapshot.axes[drive].commands.0, Command::Front(80));
+        assert!(!manager.is_blocked(drive));
+        assert!(!snapshot.axes[lift].enabled);
+        assert_eq!(snapshot.axes[lift].commands.0, Command::Front(0));
+        assert!(!manager.is_blocked(lift));
+
+        manager.set_enabled(lift, true);
+        for millis in (210..=400).step_by(10) {
+            now.set(Instant::from_millis(millis));
+            wheels.position += 1.0;
+            manager.update(&mut [&mut wheels, &mut elevator], &clock);
+        }
+        assert_eq!(manager.snapshot().axes[lift].commands.0, Command::Front(40));
+        assert!(manager.is_blocked(lift));
+        assert!(!manager.is_blocked(drive));
+
+        manager.set_output_limit(drive, 50);
+        manager.update(&mut [&mut wheels], &clock);
+        assert_eq!(wheels.max_output, 50);
+        assert_eq!(manager.snapshot().iter().count(), 1);
+    }
+
+    /// Des roues codeuses immobiles, dont l'échantillonnage peut échouer
+    struct StillTicks {
+        fail: bool,
+    }
+
+    impl TickSource for StillTicks {
+        fn sample(&mut self) -> Result<(), SamplingError> {
+            if self.fail {
+                Err(SamplingError)
+            } else {
+                Ok(())
+            }
+        }
+
+        fn ticks(&self) -> (i64, i64) {
+            (0, 0)
+        }
+    }
+
+    #[test]
+    fn controller_axis() {
+        let params = PIDParameters {
+            coder_radius: 30.0,
+            ticks_per_turn: 1024,
+            inter_axial_length: 300.0,
+            ..Default::default()
+        };
+        let mut pid = Controller::with_ticks(StillTicks { fail: false }, &params);
+        pid.enable_asserv(true, false);
+        pid.forward(50.0);
+        pid.rotate(100.0);
+        let now = Instant::from_millis(0);
+        assert!(Axis::update_at(&mut pid, now).is_ok());
+        let (left, right) = pid.commands();
+        assert_ne!(left, Command::Front(0));
+        assert_eq!(left, right);
+
+        Axis::set_enabled(&mut pid, false);
+        Axis::update_at(&mut pid, now + 1).unwrap();
+        assert_eq!(pid.commands(), (Command::Front(0), Command::Front(0)));
+
+        // L'asservissement angulaire coupé avant reste coupé
+        Axis::set_enabled(&mut pid, true);
+        Axis::update_at(&mut pid, now + 2).unwrap();
+        let (left, right) = pid.commands();
+        assert_ne!(left, Command::Front(0));
+        assert_eq!(left, right);
+
+        pid.ticks.fail = true;
+        assert_eq!(Axis::update_at(&mut pid, now + 3), Err(SamplingError));
+        assert_eq!(pid.qei_diagnostics().sampling_errors(), 1);
+    }
+}
@@ -21,28 +21,60 @@
 //!+-----------------------+
 //! ```
 
+mod approach;
+mod axis;
 mod blocking;
+#[cfg(feature = "async")]
+mod goal;
+mod hooks;
+mod manager;
 mod motor;
 mod odometry;
 mod pid;
-
+#[cfg(any(test, feature = "std"))]
+mod replay;
+mod sampler;
+mod soft_qei;
+mod telemetry;
+mod zones;
+
+pub use self::approach::*;
+pub use self::axis::*;
+#[cfg(feature = "async")]
+pub use self::goal::*;
+pub use self::hooks::*;
+pub use self::manager::*;
 pub use self::motor::*;
+pub use self::odometry::OdometryModel;
+#[cfg(any(test, feature = "std"))]
+pub use self::replay::*;
+pub use self::sampler::*;
+pub use self::soft_qei::*;
+pub use self::zones::*;
 
 use self::blocking::Blocking;
+#[cfg(feature = "async")]
+use self::goal::GoalWaker;
+use self::hooks::Events;
 use self::odometry::Odometry;
 use self::pid::*;
-use crate::units::MilliMeter;
+use self::telemetry::Telemetry;
+use crate::time::{Clock, Instant};
+use crate::units::{Angle, Fixed16, MicroMeter, MilliMeter};
 
 use core::f32;
 #[allow(unused_imports)]
 use libm::F32Ext;
 
-use crate::transmission::navigation::NavigationParametersFrame;
+use crate::transmission::navigation::{
+    NavigationParametersAck, NavigationParametersFrame, ParamsStatus, PidTelemetryFrame,
+};
 use embedded_hal::Qei;
 use qei::QeiManager;
 
 /// Les coordonnées x,y d'un point sur la table
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct Coord {
     /// La composante longeur (x)
     pub x: MilliMeter,
@@ -50,61 +82,101 @@ pub struct Coord {
     pub y: MilliMeter,
 }
 
+/// La position et l'orientation du robot sur la table
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Pose {
+    /// La position du robot
+    pub position: Coord,
+    /// L'orientation du robot
+    pub heading: Angle,
+}
+
 /// Le module central de la navigation, qui permet de controller le robot avec les unités du monde
 /// physique, et d'avoir un retour sur la position du robot. Il contient:
 /// * un PID basé sur la distance parcourue par le robot en millimètres
 /// * un module d'odométrie capable de retrouver la position et l'angle du robot
 /// * les informations nécessaires pour passer du monde des ticks de roue codeuses au monde physique
-/// * les qei gauche et droite correspondant aux deux roues codeuses
+/// * la source des ticks des roues codeuses gauche et droite (voir [`TickSource`])
 /// * la commande à appliquer aux moteurs gauche et droit
-pub struct RealWorldPid<L, R>
-where
-    L: Qei<Count = u16>,
-    R: Qei<Count = u16>,
-{
+pub struct Controller<T: TickSource> {
     internal_pid: PolarController,
     odometry: Odometry,
     params: PIDParameters,
-    qei: (QeiManager<L>, QeiManager<R>),
+    ticks: T,
     command: (Command, Command),
     blocking: Blocking,
+    last_update: Option<Instant>,
+    events: Events,
+    qei_diagnostics: QeiDiagnostics,
+    telemetry: Telemetry,
+    speed_zones: SpeedZones,
+    #[cfg(feature = "async")]
+    goal: GoalWaker,
 }
 
-/// Les paramètres d'un PID
-#[derive(Debug, Copy, Clone)]
+/// La navigation lisant directement les qei gauche et droite correspondant aux deux roues
+/// codeuses. Elle peut être séparée en un [`Sampler`] et un [`Controller`] avec
+/// [`RealWorldPid::split`].
+pub type RealWorldPid<L, R> = Controller<(QeiManager<L>, QeiManager<R>)>;
+
+/// Les paramètres d'un PID, qui peuvent être sauvegardés (voir [`crate::persistence`])
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
 pub struct PIDParameters {
     /// Le rayon d'une roue codeuse en mm
+    #[serde(with = "crate::persistence::f32_bits")]
     pub coder_radius: f32,
     /// Coefficient de correction de la roue codeuse gauche, notamment
     /// pour pouvoir supporter le décompte en sens inverse
+    #[serde(with = "crate::persistence::f32_bits")]
     pub left_wheel_coef: f32,
     /// Coefficient de correction de la roue codeuse droite, pour corriger
     /// le sens et l'écart de diamètre entre les deux roues.
+    #[serde(with = "crate::persistence::f32_bits")]
     pub right_wheel_coef: f32,
     /// Le nombre de ticks d'une roue codeuse
     pub ticks_per_turn: u16,
     /// La distance entre les roues codeuses en mm
+    #[serde(with = "crate::persistence::f32_bits")]
     pub inter_axial_length: f32,
     /// Le coefficient proportionnel sur la position
+    #[serde(with = "crate::persistence::f32_bits")]
     pub pos_kp: f32,
     /// Le coefficient dérivé sur la position
+    #[serde(with = "crate::persistence::f32_bits")]
     pub pos_kd: f32,
     /// Le coefficient intégrateur sur la position
+    #[serde(with = "crate::persistence::f32_bits")]
     pub pos_ki: f32,
     /// Le coefficient proportionnel sur l'orientation
+    #[serde(with = "crate::persistence::f32_bits")]
     pub orient_kp: f32,
     /// Le coefficient dérivée sur l'orientation
+    #[serde(with = "crate::persistence::f32_bits")]
     pub orient_kd: f32,
     /// Le coefficient intégral sur l'orientation
+    #[serde(with = "crate::persistence::f32_bits")]
     pub orient_ki: f32,
-    /// La valeur maximale en sortie
+    /// La valeur maximale en sortie, en général [`Motor::max_output`] (voir [`MotorConfig`])
     pub max_output: u16,
     /// Valeur maximale specifique à l'angle
     pub max_angle_output: u16,
+    /// Le gain du couplage croisé entre les roues : quand une roue prend du retard (choc,
+    /// glissement), la commande de l'autre est réduite de ce gain fois l'écart d'angle en mm,
+    /// pour garder le cap même quand les commandes sont saturées. 0 pour le désactiver.
+    #[serde(with = "crate::persistence::f32_bits")]
+    pub cross_coupling: f32,
     /// Seuil de commande pour le bloquage
     pub command_threshold: u16,
     /// Seuil de distance pour le bloquage
+    #[serde(with = "crate::persistence::f32_bits")]
     pub distance_threshold: f32,
+    /// Durée en ms pendant laquelle le robot doit parcourir `distance_threshold` pour ne pas
+    /// être bloqué (voir [`Controller::update_blocking_with`])
+    pub stall_window: u32,
+    /// La période d'échantillonnage nominale du PID en ms, pour laquelle les coefficients ont
+    /// été réglés (voir [`Controller::update_with`])
+    pub sample_period: u32,
 }
 
 impl Default for PIDParameters {
@@ -123,24 +195,19 @@ impl Default for PIDParameters {
             orient_ki: 1.0,
             max_output: 100,
             max_angle_output: 100,
+            cross_coupling: 0.0,
             command_threshold: 100,
             distance_threshold: 0.1,
+            stall_window: 100,
+            sample_period: 1,
         }
     }
 }
 
-impl<L, R> core::fmt::Debug for RealWorldPid<L, R>
-where
-    L: Qei<Count = u16>,
-    R: Qei<Count = u16>,
-{
+impl<T: TickSource> core::fmt::Debug for Controller<T> {
     fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
-        write!(
-            f,
-            "Pid {{ left: {}, right: {} }}",
-            self.qei.0.count(),
-            self.qei.1.count()
-        )
+        let (left, right) = self.ticks.ticks();
+        write!(f, "Pid {{ left: {}, right: {} }}", left, right)
     }
 }
 
@@ -157,22 +224,71 @@ where
     ///     * distance interaxe en mm
     ///     * rayon d'une roue codeuse en mm
     pub fn new(qei_left: QeiManager<L>, qei_right: QeiManager<R>, params: &PIDParameters) -> Self {
-        RealWorldPid {
-            internal_pid: PolarController::new(
-                params.pos_kp,
-                params.pos_kd,
-                params.pos_ki,
-                params.orient_kp,
-                params.orient_kd,
-                params.orient_ki,
-                params.max_output,
-                params.max_angle_output,
-            ),
+        Controller::with_ticks((qei_left, qei_right), params)
+    }
+
+    /// Sépare la navigation en deux parties communiquant sans verrou à travers `snapshot` :
+    /// * un [`Sampler`], qui échantillonne les qei et peut être appelé depuis l'interruption des
+    /// roues codeuses
+    /// * un [`Controller`], qui met à jour le PID et l'odométrie à partir des derniers ticks
+    /// publiés par le `Sampler`
+    ///
+    /// Le `Controller` doit être mis à jour depuis une tâche de priorité inférieure à celle du
+    /// `Sampler` (voir [`TicksSnapshot`]).
+    pub fn split<'a>(
+        self,
+        snapshot: &'a TicksSnapshot,
+    ) -> (Sampler<'a, L, R>, Controller<&'a TicksSnapshot>) {
+        snapshot.publish(self.ticks.ticks());
+        let sampler = Sampler::new(self.ticks, snapshot);
+        let controller = Controller {
+            internal_pid: self.internal_pid,
+            odometry: self.odometry,
+            params: self.params,
+            ticks: snapshot,
+            command: self.command,
+            blocking: self.blocking,
+            last_update: self.last_update,
+            events: self.events,
+            qei_diagnostics: self.qei_diagnostics,
+            telemetry: self.telemetry,
+            speed_zones: self.speed_zones,
+            #[cfg(feature = "async")]
+            goal: self.goal,
+        };
+        (sampler, controller)
+    }
+}
+
+impl<T: TickSource> Controller<T> {
+    /// Crée la navigation lisant les ticks de `ticks`
+    fn with_ticks(ticks: T, params: &PIDParameters) -> Self {
+        let mut internal_pid = PolarController::new(
+            params.pos_kp,
+            params.pos_kd,
+            params.pos_ki,
+            params.orient_kp,
+            params.orient_kd,
+            params.orient_ki,
+            params.max_output,
+            params.max_angle_output,
+        );
+        internal_pid.set_cross_coupling(params.cross_coupling);
+        Controller {
+            internal_pid,
             odometry: Odometry::new(),
             params: params.clone(),
-            qei: (qei_left, qei_right),
+            ticks,
             command: (Command::Front(0), Command::Front(0)),
-            blocking: Blocking::new(params.command_threshold, params.distance_threshold),
+            blocking: Blocking::new(params.command_threshold, params.distance_threshold)
+                .with_window(params.stall_window),
+            last_update: None,
+            events: Events::new(),
+            qei_diagnostics: QeiDiagnostics::new(),
+            telemetry: Telemetry::new(),
+            speed_zones: SpeedZones::new(),
+            #[cfg(feature = "async")]
+            goal: GoalWaker::new(),
         }
     }
 
@@ -184,17 +300,83 @@ where
     /// Met à jour les paramètres du déplacement.
     pub fn set_params(&mut self, params: &PIDParameters) {
         self.params = params.clone();
-        // TODO update PID
+        self.internal_pid.set_params(params);
+    }
+
+    /// Applique une trame de paramètres reçue de l'informatique si elle est valide, et renvoie
+    /// la réponse à lui envoyer avec les paramètres effectivement utilisés.
+    pub fn apply_params_frame(
+        &mut self,
+        frame: &NavigationParametersFrame,
+    ) -> NavigationParametersAck {
+        let status = match frame.validate() {
+            Ok(()) => {
+                let params = PIDParameters::from_frame(&self.params, frame);
+                self.set_params(&params);
+                ParamsStatus::Applied
+            }
+            Err(status) => status,
+        };
+        NavigationParametersAck {
+            params: self.params.to_frame(),
+            status,
+        }
     }
 
-    /// Mets à jour le PID et la position du robot
+    /// Mets à jour le PID et la position du robot, en supposant qu'une période
+    /// d'échantillonnage exactement s'est écoulée depuis la dernière mise à jour
     pub fn update(&mut self) {
-        self.qei.0.sample_unwrap();
-        self.qei.1.sample_unwrap();
+        let _ = self.update_dt(1.0);
+    }
+
+    /// Mets à jour le PID et la position du robot en tenant compte du temps réellement écoulé
+    /// depuis le dernier appel, mesuré par `clock` et rapporté à la période d'échantillonnage
+    /// `sample_period` des paramètres.
+    pub fn update_with<C: Clock>(&mut self, clock: &C) {
+        let _ = self.try_update_at(clock.now());
+    }
+
+    /// Comme [`Controller::update_with`] à l'instant `now`, mais renvoie une erreur si les roues
+    /// codeuses n'ont pas pu être échantillonnées. La mise à jour a tout de même lieu.
+    pub(crate) fn try_update_at(&mut self, now: Instant) -> Result<(), SamplingError> {
+        let dt = match self.last_update {
+            Some(last) => {
+                now.duration_since(last).max(1) as f32 / self.params.sample_period.max(1) as f32
+            }
+            None => 1.0,
+        };
+        self.last_update = Some(now);
+        self.update_dt(dt)
+    }
+
+    fn update_dt(&mut self, dt: f32) -> Result<(), SamplingError> {
+        let sampled = self.ticks.sample();
+        if sampled.is_err() {
+            self.qei_diagnostics.record_sampling_error();
+        }
         let (left_ticks, right_ticks) = self.get_qei_ticks();
+        self.qei_diagnostics.record((left_ticks, right_ticks));
         let (left_dist, right_dist) = self.params.ticks_to_distance(left_ticks, right_ticks);
-        self.command = self.internal_pid.update(left_dist, right_dist);
+        let max_output = match self.speed_cap() {
+            Some(cap) => cap.min(self.params.max_output),
+            None => self.params.max_output,
+        };
+        self.internal_pid.set_max_output(max_output);
+        self.command = if self.events.emergency_stopped {
+            (Command::Front(0), Command::Front(0))
+        } else {
+            self.internal_pid.update_dt(left_dist, right_dist, dt)
+        };
+        self.telemetry
+            .record(dt, self.internal_pid.get_terms(), self.command);
         self.odometry.update(left_ticks, right_ticks, &self.params);
+        let goal_reached = self.is_goal_reached(self.events.lin_accuracy, self.events.ang_accuracy);
+        let blocked = self.is_robot_blocked();
+        let pose = self.get_pose();
+        self.events.update(goal_reached, blocked, pose);
+        #[cfg(feature = "async")]
+        self.goal.count_update();
+        sampled
     }
 
     /// Active ou désactive l'asservissement longitudinal et / ou l'asservissement
@@ -204,24 +386,115 @@ where
         self.internal_pid.enable_control(lin_ctrl, ang_ctrl);
     }
 
+    /// Commande l'axe longitudinal en boucle ouverte avec `command` (positive en avant, saturée
+    /// à `max_output`), pendant que l'asservissement angulaire garde le cap mesuré par
+    /// l'odométrie s'il est actif. C'est le mode « pousseur », quand les roues glissent trop
+    /// pour asservir la position.
+    ///
+    /// `None`, [`Controller::stop`] ou un arrêt d'urgence rendent la main à l'asservissement
+    /// longitudinal, qui garde le robot à la position atteinte.
+    pub fn set_linear_open_loop(&mut self, command: Option<i16>) {
+        self.internal_pid
+            .set_linear_open_loop(command.map(f32::from));
+    }
+
+    /// Renvoie vrai si l'axe longitudinal est commandé en boucle ouverte
+    pub fn is_linear_open_loop(&self) -> bool {
+        self.internal_pid.get_linear_open_loop().is_some()
+    }
+
     /// Renvoie la commande courante
     pub fn get_command(&self) -> (Command, Command) {
         self.command
     }
 
-    /// Renvoie la position
+    /// Renvoie la position, arrondie au millimètre le plus proche
     pub fn get_position(&self) -> Coord {
         self.odometry.get_position()
     }
 
-    /// Renvoie l'angle en milliradians
-    pub fn get_angle(&self) -> i64 {
+    /// Renvoie la position (x, y) au micromètre près, par exemple pour remplir les champs en
+    /// dixièmes de millimètre d'une [`NavigationFrame`](crate::transmission::navigation::NavigationFrame)
+    pub fn get_precise_position(&self) -> (MicroMeter, MicroMeter) {
+        self.odometry.get_precise_position()
+    }
+
+    /// Renvoie l'angle continu du robot, identique à [`Controller::total_rotation`]
+    pub fn get_angle(&self) -> Angle {
+        self.odometry.get_angle()
+    }
+
+    /// Renvoie le cap du robot, ramené dans `[-π, π[`
+    pub fn heading(&self) -> Angle {
+        self.odometry.get_heading()
+    }
+
+    /// Renvoie l'angle dont le robot a tourné depuis l'angle nul, sans le ramener dans
+    /// `[-π, π[` : il augmente de 2π à chaque tour dans le sens trigonométrique
+    pub fn total_rotation(&self) -> Angle {
         self.odometry.get_angle()
     }
 
+    /// Renvoie la position et l'orientation du robot
+    pub fn get_pose(&self) -> Pose {
+        self.odometry.get_pose()
+    }
+
+    /// Change le schéma d'intégration de l'odométrie (par défaut [`OdometryModel::Euler`])
+    pub fn set_odometry_model(&mut self, model: OdometryModel) {
+        self.odometry.set_model(model);
+    }
+
+    /// Renvoie le schéma d'intégration de l'odométrie
+    pub fn odometry_model(&self) -> OdometryModel {
+        self.odometry.get_model()
+    }
+
+    /// Ajoute une zone de la table où la commande des moteurs est limitée, ou la renvoie s'il
+    /// n'y a plus de place (voir [`SpeedZones`])
+    pub fn add_speed_zone(&mut self, zone: SpeedZone) -> Result<(), SpeedZone> {
+        self.speed_zones.add(zone)
+    }
+
+    /// Supprime toutes les zones de vitesse limitée
+    pub fn clear_speed_zones(&mut self) {
+        self.speed_zones.clear();
+    }
+
+    /// Renvoie la commande maximale imposée par les zones à la position actuelle du robot, ou
+    /// `None` s'il n'est dans aucune zone
+    pub fn speed_cap(&self) -> Option<u16> {
+        self.speed_zones.cap_at(self.get_position())
+    }
+
     /// Renvoie les ticks comptés par les roues codeuses
     pub fn get_qei_ticks(&self) -> (i64, i64) {
-        (self.qei.0.count(), self.qei.1.count())
+        self.ticks.ticks()
+    }
+
+    /// Renvoie les statistiques sur la variation des ticks entre deux mises à jour. Après
+    /// [`RealWorldPid::split`], ce sont celles du [`Sampler`] qui correspondent aux
+    /// échantillonnages des qei.
+    pub fn qei_diagnostics(&self) -> &QeiDiagnostics {
+        &self.qei_diagnostics
+    }
+
+    /// Remet à zéro les statistiques sur la variation des ticks
+    pub fn reset_qei_diagnostics(&mut self) {
+        self.qei_diagnostics.reset();
+    }
+
+    /// Produit une [`PidTelemetryFrame`] toutes les `rate` mises à jour du PID, ou plus aucune
+    /// si `rate` vaut 0 (par défaut). À 1 kHz d'échantillonnage, `rate = 10` donne 100 trames
+    /// par seconde.
+    pub fn set_telemetry_rate(&mut self, rate: u16) {
+        self.telemetry.set_rate(rate);
+    }
+
+    /// Renvoie la dernière trame de télémétrie du PID si elle n'a pas déjà été renvoyée. Une
+    /// trame non récupérée avant la suivante est perdue, ce qui se voit à son numéro.
+    pub fn take_pid_telemetry(&mut self) -> Option<PidTelemetryFrame> {
+        self.telemetry.take()
     }
 
     /// Renvoie la distance parcourue par les roues codeuses à gauche et à droite,
@@ -231,9 +504,17 @@ where
         self.params.ticks_to_distance(left_ticks, right_ticks)
     }
 
+    /// Définit la position et l'orientation actuelles de l'odométrie
+    pub fn set_pose(&mut self, pose: Pose) {
+        self.odometry.set_pose(pose);
+    }
+
     /// Définit la position actuelle de l'odométrie
-    pub fn set_position_and_angle(&mut self, position: Coord, angle: i64) {
-        self.odometry.set_position_and_angle(position, angle);
+    pub fn set_position_and_angle(&mut self, position: Coord, angle: Angle) {
+        self.set_pose(Pose {
+            position,
+            heading: angle,
+        });
     }
 
     /// Ordonne au robot d'avancer de `distance` (en mm)
@@ -252,32 +533,66 @@ where
         self.internal_pid.increment_angular_goal(turn_distance);
     }
 
-    /// Ordonne au robot de tourner de façon à s'orienter vers l'angle `angle`
-    /// (en milliradians). Le robot détermine sa position initiale grâce à
-    /// l'odométrie.
-    pub fn rotate_absolute(&mut self, angle: f32) {
-        let current_angle = self.odometry.get_angle() as f32;
-        let mut diff = angle - current_angle;
+    /// Ordonne au robot de tourner de façon à s'orienter vers l'angle `angle`.
+    /// Le robot détermine son cap initial grâce à l'odométrie, et tourne
+    /// dans le sens le plus court quel que soit le nombre de tours déjà effectués.
+    pub fn rotate_absolute(&mut self, angle: Angle) {
+        let diff = (angle.normalize() - self.heading()).normalize();
+        self.rotate(diff.as_milliradians() as f32);
+    }
 
-        // Find the best angle
-        let pi = core::f32::consts::PI * 1000.0;
-        while diff < -pi {
-            diff += pi * 2.0;
-        }
+    /// Calcule la manœuvre pour rejoindre le point `target` depuis la position actuelle, en
+    /// marche arrière si c'est plus court et que `reverse` l'autorise (voir [`Approach`])
+    pub fn plan_goto(&self, target: Coord, reverse: Reverse) -> Approach {
+        Approach::to_point(self.get_pose(), target, reverse)
+    }
 
-        while diff >= pi {
-            diff -= pi * 2.0;
-        }
-        self.rotate(diff);
+    /// Calcule la manœuvre pour rejoindre la position et l'orientation de `target` depuis la
+    /// position actuelle (voir [`Approach`])
+    pub fn plan_goto_pose(&self, target: Pose, reverse: Reverse) -> Approach {
+        Approach::to_pose(self.get_pose(), target, reverse)
     }
 
     /// Ordonne au robot de rester là où il est actuellement
     pub fn stop(&mut self) {
+        self.internal_pid.set_linear_open_loop(None);
         let (left_ticks, right_ticks) = self.get_qei_ticks();
         let (left_dist, right_dist) = self.params.ticks_to_distance(left_ticks, right_ticks);
         self.internal_pid.set_left_right_goal(left_dist, right_dist);
     }
 
+    /// Arrête le robot d'urgence : la consigne devient la position actuelle et les commandes
+    /// des moteurs restent nulles jusqu'à l'appel de `release_emergency_stop`.
+    pub fn emergency_stop(&mut self) {
+        self.stop();
+        self.command = (Command::Front(0), Command::Front(0));
+        let pose = self.get_pose();
+        self.events.emergency_stop(pose);
+    }
+
+    /// Termine l'arrêt d'urgence, le robot reste asservi sur sa position actuelle
+    pub fn release_emergency_stop(&mut self) {
+        self.stop();
+        self.events.emergency_stopped = false;
+    }
+
+    /// Renvoie vrai si le robot est arrêté d'urgence
+    pub fn is_emergency_stopped(&self) -> bool {
+        self.events.emergency_stopped
+    }
+
+    /// Définit les fonctions appelées lors des événements de la navigation
+    pub fn set_hooks(&mut self, hooks: NavigationHooks) {
+        self.events.hooks = hooks;
+    }
+
+    /// Définit la précision (en mm et en milliradians) à partir de laquelle la consigne est
+    /// considérée atteinte pour `on_goal_reached`
+    pub fn set_goal_accuracy(&mut self, lin_accuracy: f32, ang_accuracy: f32) {
+        self.events.lin_accuracy = lin_accuracy;
+        self.events.ang_accuracy = ang_accuracy;
+    }
+
     /// Retourne `true` si le robot est bloqué, c'est à dire s'il reçoit une
     /// commande mais ne change pas de position.
     pub fn is_robot_blocked(&self) -> bool {
@@ -293,6 +608,17 @@ where
         );
     }
 
+    /// Met à jour la detection du bloquage du robot sur une fenêtre de `stall_window` ms
+    /// mesurée par `clock`. Peut être appelée à chaque mise à jour du PID.
+    pub fn update_blocking_with<C: Clock>(&mut self, clock: &C) {
+        let (left_ticks, right_ticks) = self.get_qei_ticks();
+        self.blocking.update_with(
+            self.get_command(),
+            self.params.ticks_to_distance(left_ticks, right_ticks),
+            clock,
+        );
+    }
+
     /// Retourne `true` si le pid a atteind sa consigne en position et angle
     ///
     /// `lin_accuracy`: L'erreur autorisée sur la position du robot en millimètres.
@@ -318,24 +644,43 @@ impl PIDParameters {
         base: &PIDParameters,
         params_frame: &NavigationParametersFrame,
     ) -> PIDParameters {
-        const RADIX: f32 = 65536f32;
         PIDParameters {
             coder_radius: params_frame.coder_radius as f32 / 10.0,
             left_wheel_coef: base.left_wheel_coef,
-            // TODO keep the sign on right_wheel_coef
-            right_wheel_coef: params_frame.right_wheel_coef as f32 / RADIX,
+            right_wheel_coef: params_frame.right_wheel_coef.to_f32(),
             ticks_per_turn: base.ticks_per_turn,
             inter_axial_length: params_frame.inter_axial_length as f32 / 10.0,
-            pos_kp: params_frame.pos_kp as f32 / RADIX,
-            pos_kd: params_frame.pos_kd as f32 / RADIX,
+            pos_kp: params_frame.pos_kp.to_f32(),
+            pos_kd: params_frame.pos_kd.to_f32(),
             pos_ki: 0.0,
-            orient_kp: params_frame.orient_kp as f32 / RADIX,
-            orient_kd: params_frame.orient_kd as f32 / RADIX,
+            orient_kp: params_frame.orient_kp.to_f32(),
+            orient_kd: params_frame.orient_kd.to_f32(),
             orient_ki: 0.0,
             max_output: base.max_output,
-            max_angle_output: base.max_angle_output,
+            max_angle_output: match params_frame.max_angle_output {
+                0 => base.max_angle_output,
+                max_angle_output => max_angle_output,
+            },
+            cross_coupling: base.cross_coupling,
             command_threshold: base.command_threshold,
             distance_threshold: base.distance_threshold,
+            stall_window: base.stall_window,
+            sample_period: base.sample_period,
+        }
+    }
+
+    /// Renvoie la trame de paramètres correspondant à ces paramètres, inverse de
+    /// [`PIDParameters::from_frame`]
+    pub fn to_frame(&self) -> NavigationParametersFrame {
+        NavigationParametersFrame {
+            coder_radius: (self.coder_radius * 10.0).round() as u16,
+            right_wheel_coef: Fixed16::from_f32(self.right_wheel_coef),
+            inter_axial_length: (self.inter_axial_length * 10.0).round() as u16,
+            pos_kp: Fixed16::from_f32(self.pos_kp),
+            pos_kd: Fixed16::from_f32(self.pos_kd),
+            orient_kp: Fixed16::from_f32(self.orient_kp),
+            orient_kd: Fixed16::from_f32(self.orient_kd),
+            max_angle_output: self.max_angle_output,
         }
     }
 
@@ -383,9 +728,15 @@ mod test {
     use qei::QeiManager;
 
     use super::motor::test::DummyMotor;
-    use super::{Coord, PIDParameters, RealWorldPid};
+    use super::{
+        Coord, NavigationHooks, PIDParameters, Pose, RealWorldPid, SpeedZone, TickSource,
+        TicksSnapshot,
+    };
     use crate::navigation::Command;
-    use crate::units::MilliMeter;
+    use crate::time::Instant;
+    use crate::transmission::navigation::{NavigationParametersFrame, ParamsStatus};
+    use crate::units::{Angle, Fixed16, MilliMeter};
+    use core::cell::Cell;
 
     #[test]
     fn test_ticks_to_distance() {
@@ -437,7 +788,152 @@ mod test {
 
     #[test]
     fn test_parameters_from_frame() {
-        // TODO
+        let frame = NavigationParametersFrame {
+            coder_radius: 315,
+            right_wheel_coef: Fixed16::from_f32(-1.5),
+            inter_axial_length: 2800,
+            pos_kp: Fixed16::from_f32(0.25),
+            pos_kd: Fixed16::from_integer(2),
+            orient_kp: Fixed16::ONE,
+            orient_kd: Fixed16(0),
+            max_angle_output: 40,
+        };
+        let base = PIDParameters::default();
+        let params = PIDParameters::from_frame(&base, &frame);
+        assert_eq!(params.coder_radius, 31.5);
+        assert_eq!(params.inter_axial_length, 280.0);
+        assert_eq!(params.right_wheel_coef, -1.5);
+        assert_eq!(params.left_wheel_coef, base.left_wheel_coef);
+        assert_eq!(params.pos_kp, 0.25);
+        assert_eq!(params.pos_kd, 2.0);
+        assert_eq!(params.orient_kp, 1.0);
+        assert_eq!(params.orient_kd, 0.0);
+        assert_eq!(params.max_angle_output, 40);
+        assert_eq!(params.max_output, base.max_output);
+        assert_eq!(params.to_frame(), frame);
+
+        // Sans commande maximale en orientation, celle de `base` est gardée
+        let frame = NavigationParametersFrame {
+            max_angle_output: 0,
+            ..frame
+        };
+        let params = PIDParameters::from_frame(&base, &frame);
+        assert_eq!(params.max_angle_output, base.max_angle_output);
+    }
+
+    #[test]
+    fn test_apply_params_frame() {
+        let motor = DummyMotor::new();
+        let qei_left = QeiManager::new(motor.clone());
+        let qei_right = QeiManager::new(motor.clone());
+        let mut pid = RealWorldPid::new(qei_left, qei_right, &PIDParameters::default());
+        let frame = NavigationParametersFrame {
+            coder_radius: 315,
+            right_wheel_coef: Fixed16::ONE,
+            inter_axial_length: 2800,
+            pos_kp: Fixed16::from_f32(0.5),
+            pos_kd: Fixed16(0),
+            orient_kp: Fixed16::ONE,
+            orient_kd: Fixed16(0),
+            max_angle_output: 100,
+        };
+        let ack = pid.apply_params_frame(&frame);
+        assert_eq!(ack.status, ParamsStatus::Applied);
+        assert_eq!(ack.params, frame);
+        assert_eq!(pid.get_params().pos_kp, 0.5);
+
+        // Une trame refusée ne change pas les paramètres, l'écho donne ceux qui sont utilisés
+        let rejected = NavigationParametersFrame {
+            pos_kp: Fixed16::from_f32(-0.5),
+            coder_radius: 400,
+            ..frame
+        };
+        let ack = pid.apply_params_frame(&rejected);
+        assert_eq!(ack.status, ParamsStatus::NegativeGain);
+        assert!(!ack.status.is_applied());
+        assert_eq!(ack.params, frame);
+        let ack = pid.apply_params_frame(&NavigationParametersFrame {
+            inter_axial_length: 0,
+            ..frame
+        });
+        assert_eq!(ack.status, ParamsStatus::InvalidInterAxialLength);
+    }
+
+    #[test]
+    fn test_linear_open_loop() {
+        let motor = DummyMotor::new();
+        let qei_left = QeiManager::new(motor.clone());
+        let qei_right = QeiManager::new(motor.clone());
+        let mut pid = RealWorldPid::new(qei_left, qei_right, &PIDParameters::default());
+        pid.set_linear_open_loop(Some(-60));
+        pid.update();
+        assert!(pid.is_linear_open_loop());
+        assert_eq!(pid.get_command(), (Command::Back(60), Command::Back(60)));
+
+        pid.stop();
+        assert!(!pid.is_linear_open_loop());
+        pid.update();
+        let (left, right) = pid.get_command();
+        assert_eq!((left.get_value(), right.get_value()), (0, 0));
+    }
+
+    #[test]
+    fn test_speed_zones() {
+        let motor = DummyMotor::new();
+        let qei_left = QeiManager::new(motor.clone());
+        let qei_right = QeiManager::new(motor.clone());
+        let mut pid = RealWorldPid::new(qei_left, qei_right, &PIDParameters::default());
+        let corner = |x, y| Coord {
+            x: MilliMeter(x),
+            y: MilliMeter(y),
+        };
+        pid.add_speed_zone(SpeedZone::new(corner(-100, -100), corner(100, 100), 20))
+            .unwrap();
+        pid.add_speed_zone(SpeedZone::new(corner(500, 500), corner(600, 600), 5))
+            .unwrap();
+        assert_eq!(pid.speed_cap(), Some(20));
+        pid.forward(1000.0);
+        pid.update();
+        assert_eq!(pid.get_command(), (Command::Front(20), Command::Front(20)));
+
+        pid.set_pose(Pose {
+            position: corner(1000, 1000),
+            heading: Angle(0),
+        });
+        assert_eq!(pid.speed_cap(), None);
+        pid.update();
+        assert_eq!(
+            pid.get_command(),
+            (Command::Front(100), Command::Front(100))
+        );
+    }
+
+    #[test]
+    fn test_pid_telemetry() {
+        let motor = DummyMotor::new();
+        let qei_left = QeiManager::new(motor.clone());
+        let qei_right = QeiManager::new(motor.clone());
+        let mut pid = RealWorldPid::new(qei_left, qei_right, &PIDParameters::default());
+        pid.update();
+        assert_eq!(pid.take_pid_telemetry(), None);
+
+        pid.set_telemetry_rate(2);
+        pid.forward(100.0);
+        pid.update();
+        assert_eq!(pid.take_pid_telemetry(), None);
+        pid.update();
+        let frame = pid.take_pid_telemetry().unwrap();
+        assert_eq!(frame.sample, 3);
+        assert_eq!(frame.dt, 100);
+        assert_eq!(frame.lin.goal, 1000);
+        assert_eq!(frame.lin.speed, 0);
+        assert_eq!(frame.lin.error, -1000);
+        assert_eq!(frame.lin.p, -1000);
+        assert_eq!(frame.lin.i, -3000);
+        // La commande est saturée à `max_output`
+        assert_eq!(frame.lin.out, -1000);
+        assert_eq!((frame.left, frame.right), (100, 100));
+        assert_eq!(pid.take_pid_telemetry(), None);
     }
 
     #[test]
@@ -471,8 +967,7 @@ mod test {
         motor_left.set_position(49); // 9 mm
         motor_right.set_position(-65); // 12 mm
 
-        pid.qei.0.sample_unwrap();
-        pid.qei.1.sample_unwrap();
+        pid.ticks.sample().unwrap();
         let (left_ticks, right_ticks) = pid.get_qei_ticks();
         let (left_dist, right_dist) = pid_parameters.ticks_to_distance(left_ticks, right_ticks);
 
@@ -586,6 +1081,142 @@ mod test {
         }
     }
 
+    #[test]
+    fn real_world_pid_split() {
+        let pid_parameters = PIDParameters {
+            coder_radius: 30.0,
+            left_wheel_coef: 1.0,
+            right_wheel_coef: -1.0,
+            ticks_per_turn: 1024,
+            inter_axial_length: 300.0,
+            pos_kp: 1.0,
+            orient_kp: 1.0,
+            max_output: 100,
+            ..Default::default()
+        };
+
+        let mut motor_left = DummyMotor::new();
+        let mut motor_right = DummyMotor::new();
+        let qei_left = QeiManager::new(motor_left.clone());
+        let qei_right = QeiManager::new(motor_right.clone());
+        let mut pid = RealWorldPid::new(qei_left, qei_right, &pid_parameters);
+        motor_left.set_position(10);
+        pid.ticks.sample().unwrap();
+
+        let snapshot = TicksSnapshot::new();
+        let (mut sampler, mut controller) = pid.split(&snapshot);
+        assert_eq!(controller.get_qei_ticks(), (10, 0));
+
+        motor_left.set_position(1024);
+        motor_right.set_position(-1024);
+        controller.update();
+        assert_eq!(controller.get_qei_ticks(), (10, 0));
+
+        sampler.sample().unwrap();
+        assert_eq!(sampler.get_qei_ticks(), (1024, -1024));
+        assert_eq!(sampler.qei_diagnostics().max_delta(), 1024);
+        assert!(!sampler.qei_diagnostics().has_overflowed());
+        controller.update();
+        assert_eq!(controller.get_qei_ticks(), (1024, -1024));
+        assert!(controller.get_position().x.as_millimeters() > 180);
+        match controller.get_command() {
+            (Command::Back(_), Command::Back(_)) => {}
+            command => panic!("{:?} should be going backward.", command),
+        }
+    }
+
+    #[test]
+    fn real_world_pid_update_with_clock() {
+        let pid_parameters = PIDParameters {
+            coder_radius: 30.0,
+            ticks_per_turn: 1024,
+            inter_axial_length: 300.0,
+            sample_period: 2,
+            ..Default::default()
+        };
+
+        let motor_left = DummyMotor::new();
+        let motor_right = DummyMotor::new();
+        let mut fixed = RealWorldPid::new(
+            QeiManager::new(motor_left.clone()),
+            QeiManager::new(motor_right.clone()),
+            &pid_parameters,
+        );
+        let mut timed = RealWorldPid::new(
+            QeiManager::new(motor_left.clone()),
+            QeiManager::new(motor_right.clone()),
+            &pid_parameters,
+        );
+        let now = Cell::new(Instant::from_millis(0));
+        let clock = || now.get();
+
+        fixed.forward(100.0);
+        timed.forward(100.0);
+        for _ in 0..10 {
+            fixed.update();
+            timed.update_with(&clock);
+            now.set(now.get() + 2);
+            assert_eq!(fixed.get_command(), timed.get_command());
+        }
+    }
+
+    #[test]
+    fn real_world_pid_hooks() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        static REACHED: AtomicUsize = AtomicUsize::new(0);
+        static STOPPED: AtomicUsize = AtomicUsize::new(0);
+
+        let pid_parameters = PIDParameters {
+            coder_radius: 30.0,
+            left_wheel_coef: 1.0,
+            right_wheel_coef: -1.0,
+            ticks_per_turn: 1024,
+            inter_axial_length: 300.0,
+            pos_kp: 1.0,
+            orient_kp: 1.0,
+            max_output: 100,
+            ..Default::default()
+        };
+        let mut motor_left = DummyMotor::new();
+        let mut motor_right = DummyMotor::new();
+        let qei_left = QeiManager::new(motor_left.clone());
+        let qei_right = QeiManager::new(motor_right.clone());
+        let mut pid = RealWorldPid::new(qei_left, qei_right, &pid_parameters);
+        pid.set_hooks(NavigationHooks {
+            on_goal_reached: Some(|_| {
+                REACHED.fetch_add(1, Ordering::SeqCst);
+            }),
+            on_emergency_stop: Some(|_| {
+                STOPPED.fetch_add(1, Ordering::SeqCst);
+            }),
+            ..Default::default()
+        });
+
+        pid.update();
+        assert_eq!(REACHED.load(Ordering::SeqCst), 0);
+        pid.forward(188.5);
+        pid.update();
+        assert_eq!(REACHED.load(Ordering::SeqCst), 0);
+        motor_left.set_position(1024);
+        motor_right.set_position(-1024);
+        pid.update();
+        pid.update();
+        assert_eq!(REACHED.load(Ordering::SeqCst), 1);
+
+        pid.forward(100.0);
+        pid.update();
+        assert_ne!(pid.get_command().0.get_value(), 0);
+        pid.emergency_stop();
+        pid.emergency_stop();
+        assert_eq!(STOPPED.load(Ordering::SeqCst), 1);
+        assert!(pid.is_emergency_stopped());
+        pid.forward(100.0);
+        pid.update();
+        assert_eq!(pid.get_command(), (Command::Front(0), Command::Front(0)));
+        pid.release_emergency_stop();
+        assert!(!pid.is_emergency_stopped());
+    }
+
     #[test]
     fn test_real_world_pid_rotate_absolute() {
         let pid_parameters = PIDParameters {
@@ -613,9 +1244,9 @@ mod test {
                 x: MilliMeter(0),
                 y: MilliMeter(0),
             },
-            5890,
+            Angle(5890),
         ); // 15 * pi / 8
-        pid.rotate_absolute(392.0); // rotation relative de pi/4
+        pid.rotate_absolute(Angle(392)); // rotation relative de pi/4
 
         let (goall, goalr) = pid.internal_pid.get_left_right_goal();
 
@@ -627,9 +1258,11 @@ mod test {
                 x: MilliMeter(0),
                 y: MilliMeter(0),
             },
-            9032,
+            Angle(9032),
         ); // 23 * pi / 8
-        pid.rotate_absolute(1963.0); // rotation relative de -pi/4
+        assert_eq!(pid.total_rotation(), Angle(9032));
+        assert_eq!(pid.heading(), Angle(2749)); // 7 * pi / 8
+        pid.rotate_absolute(Angle(1963)); // rotation relative de -pi/4
 
         let (goall1, goalr1) = pid.internal_pid.get_left_right_goal();
 
@@ -637,6 +1270,38 @@ mod test {
         assert!((goalr1 + 0.0).abs() <= 1.0, "{} should be {}", goalr1, 0);
     }
 
+    #[test]
+    fn test_real_world_pid_pose() {
+        let pid_parameters = PIDParameters {
+            coder_radius: 30.0,
+            left_wheel_coef: 1.0,
+            right_wheel_coef: -1.0,
+            ticks_per_turn: 1024,
+            inter_axial_length: 300.0,
+            ..Default::default()
+        };
+
+        let motor_left = DummyMotor::new();
+        let motor_right = DummyMotor::new();
+        let qei_left = QeiManager::new(motor_left.clone());
+        let qei_right = QeiManager::new(motor_right.clone());
+        let mut pid = RealWorldPid::new(qei_left, qei_right, &pid_parameters);
+
+        let pose = Pose {
+            position: Coord {
+                x: MilliMeter(150),
+                y: MilliMeter(-40),
+            },
+            heading: Angle(5890), // 15 * pi / 8
+        };
+        pid.set_pose(pose);
+        assert_eq!(pid.get_pose(), pose);
+
+        pid.set_position_and_angle(pose.position, Angle(392));
+        assert_eq!(pid.get_pose().heading, Angle(392));
+        assert_eq!(pid.get_pose().position, pose.position);
+    }
+
     #[test]
     fn test_full_session() {}
 }
@@ -3,8 +3,12 @@ use core::fmt::{Debug, Display, Formatter, Result};
 use embedded_hal::digital::OutputPin;
 use embedded_hal::PwmPin;
 
+use crate::time::{Clock, Watchdog};
+use crate::units::{DutyCycle, Hertz};
+
 /// Une commande pour un moteur : une direction et une vitesse sur 16 bits (0 : vitesse nulle).
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum Command {
     /// Le moteur doit avancer à la vitesse fournie
     Front(u16),
@@ -54,6 +58,36 @@ impl Display for Command {
     }
 }
 
+/// La configuration de la PWM d'un moteur.
+///
+/// Le rapport cyclique maximal borne toutes les commandes appliquées au moteur : la valeur de
+/// `max_output` des paramètres du PID doit valoir [`MotorConfig::max_output`] pour que toute la
+/// plage de commande soit utilisable.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct MotorConfig {
+    /// La fréquence de la PWM, à utiliser lors de la configuration du timer
+    pub frequency: Hertz,
+    /// Le rapport cyclique maximal applicable au moteur
+    pub max_duty: DutyCycle,
+}
+
+impl MotorConfig {
+    /// Renvoie la commande maximale pour une PWM dont `pwm_max_duty` correspond à 100%
+    pub fn max_output(&self, pwm_max_duty: u16) -> u16 {
+        self.max_duty.of(pwm_max_duty)
+    }
+}
+
+impl Default for MotorConfig {
+    fn default() -> Self {
+        MotorConfig {
+            frequency: Hertz::from_kilohertz(20),
+            max_duty: DutyCycle::FULL,
+        }
+    }
+}
+
 /// Un moteur avec ses deux broches : vitesse et direction.
 pub struct Motor<MOT, DIR>
 where
@@ -62,6 +96,7 @@ where
 {
     pwm: MOT,
     dir: DIR,
+    config: MotorConfig,
 }
 
 impl<MOT, DIR> Debug for Motor<MOT, DIR>
@@ -89,34 +124,129 @@ where
     /// moteur
     /// * la broche d'entrée sortie controle la direction du moteur
     pub fn new(pwm: MOT, dir: DIR) -> Self {
-        Motor { pwm, dir }
+        Motor::with_config(pwm, dir, MotorConfig::default())
+    }
+
+    /// Crée un moteur dont la PWM respecte la configuration `config`
+    pub fn with_config(pwm: MOT, dir: DIR, config: MotorConfig) -> Self {
+        Motor { pwm, dir, config }
+    }
+
+    /// Renvoie la configuration du moteur
+    pub fn config(&self) -> MotorConfig {
+        self.config
+    }
+
+    /// Renvoie la commande maximale que le moteur applique, à utiliser comme `max_output` du PID
+    pub fn max_output(&self) -> u16 {
+        self.config.max_output(self.pwm.get_max_duty())
     }
 
     /// Applique la commande de direction et de vitesse aux moteurs :
     /// * avancer correspond à un état bas sur la broche de direction
     /// * reculer correspond à un état haut sur la broche de direction
+    ///
+    /// La vitesse est bornée par [`Motor::max_output`].
     pub fn apply_command(&mut self, cmd: Command) {
+        let max = self.max_output();
         match cmd {
             Command::Front(pwm) => {
-                self.pwm.set_duty(pwm);
+                self.pwm.set_duty(pwm.min(max));
                 self.dir.set_high();
             }
             Command::Back(pwm) => {
-                self.pwm.set_duty(pwm);
+                self.pwm.set_duty(pwm.min(max));
                 self.dir.set_low();
             }
         }
     }
 }
 
+/// Un moteur qui s'arrête tout seul s'il ne reçoit plus de commande.
+///
+/// Si la boucle principale se bloque alors que le timer continue de générer la PWM, le moteur
+/// garderait sa dernière commande. Ici, [`GuardedMotor::check`] est appelée périodiquement (par
+/// exemple depuis l'interruption d'un timer) et annule la commande si ni
+/// [`GuardedMotor::apply_command`] ni [`GuardedMotor::feed`] n'ont été appelées depuis plus de
+/// `timeout` ms.
+pub struct GuardedMotor<MOT, DIR>
+where
+    MOT: PwmPin<Duty = u16>,
+    DIR: OutputPin,
+{
+    motor: Motor<MOT, DIR>,
+    watchdog: Watchdog,
+    stopped: bool,
+}
+
+impl<MOT, DIR> Debug for GuardedMotor<MOT, DIR>
+where
+    MOT: PwmPin<Duty = u16>,
+    DIR: OutputPin,
+{
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        write!(
+            f,
+            "GuardedMotor {{ {:?}, stopped: {} }}",
+            self.motor, self.stopped
+        )
+    }
+}
+
+impl<MOT, DIR> GuardedMotor<MOT, DIR>
+where
+    MOT: PwmPin<Duty = u16>,
+    DIR: OutputPin,
+{
+    /// Protège `motor`, qui est arrêté s'il ne reçoit pas de commande pendant `timeout` ms
+    pub fn new(motor: Motor<MOT, DIR>, timeout: u32) -> Self {
+        GuardedMotor {
+            motor,
+            watchdog: Watchdog::new(timeout),
+            stopped: true,
+        }
+    }
+
+    /// Applique la commande au moteur et repousse l'arrêt automatique
+    pub fn apply_command<C: Clock>(&mut self, cmd: Command, clock: &C) {
+        self.watchdog.feed(clock);
+        self.stopped = false;
+        self.motor.apply_command(cmd);
+    }
+
+    /// Repousse l'arrêt automatique sans changer la commande, par exemple à chaque mise à jour
+    /// du PID
+    pub fn feed<C: Clock>(&mut self, clock: &C) {
+        self.watchdog.feed(clock);
+    }
+
+    /// Arrête le moteur si le délai est dépassé. Renvoie vrai si le moteur est arrêté par le
+    /// chien de garde.
+    pub fn check<C: Clock>(&mut self, clock: &C) -> bool {
+        if !self.stopped && self.watchdog.is_expired(clock) {
+            self.motor.apply_command(Command::Front(0));
+            self.stopped = true;
+        }
+        self.stopped
+    }
+
+    /// Renvoie le moteur protégé
+    pub fn into_inner(self) -> Motor<MOT, DIR> {
+        self.motor
+    }
+}
+
 #[cfg(test)]
 pub mod test {
     use std::cell::Cell;
     use std::rc::Rc;
 
-    use embedded_hal::Qei;
+    use embedded_hal::digital::OutputPin;
+    use embedded_hal::{PwmPin, Qei};
 
-    use super::Command;
+    use super::{Command, GuardedMotor, Motor, MotorConfig};
+    use crate::time::Instant;
+    use crate::units::{DutyCycle, Hertz};
 
     #[derive(Debug, Clone, Copy)]
     enum Direction {
@@ -197,4 +327,97 @@ pub mod test {
             embedded_hal::Direction::Upcounting
         }
     }
+
+    #[derive(Debug, Clone, Default)]
+    struct DummyPwm {
+        duty: Rc<Cell<u16>>,
+    }
+
+    impl PwmPin for DummyPwm {
+        type Channel = ();
+        type Time = ();
+        type Duty = u16;
+
+        fn disable(&mut self) {}
+
+        fn enable(&mut self) {}
+
+        fn get_period(&self) {}
+
+        fn get_duty(&self) -> u16 {
+            self.duty.get()
+        }
+
+        fn get_max_duty(&self) -> u16 {
+            1000
+        }
+
+        fn set_duty(&mut self, duty: u16) {
+            self.duty.replace(duty);
+        }
+    }
+
+    #[derive(Debug, Clone, Default)]
+    struct DummyDir {
+        high: Rc<Cell<bool>>,
+    }
+
+    impl OutputPin for DummyDir {
+        fn set_low(&mut self) {
+            self.high.replace(false);
+        }
+
+        fn set_high(&mut self) {
+            self.high.replace(true);
+        }
+    }
+
+    #[test]
+    fn motor_clamps_to_max_duty() {
+        let pwm = DummyPwm::default();
+        let dir = DummyDir::default();
+        let config = MotorConfig {
+            frequency: Hertz::from_kilohertz(10),
+            max_duty: DutyCycle::from_percent(80),
+        };
+        let mut motor = Motor::with_config(pwm.clone(), dir.clone(), config);
+        assert_eq!(motor.config(), config);
+        assert_eq!(motor.max_output(), 800);
+
+        motor.apply_command(Command::Front(500));
+        assert_eq!(pwm.duty.get(), 500);
+        assert!(dir.high.get());
+        motor.apply_command(Command::Back(1000));
+        assert_eq!(pwm.duty.get(), 800);
+        assert!(!dir.high.get());
+
+        let motor = Motor::new(pwm, dir);
+        assert_eq!(motor.max_output(), 1000);
+    }
+
+    #[test]
+    fn guarded_motor_stops() {
+        let pwm = DummyPwm::default();
+        let dir = DummyDir::default();
+        let now = Cell::new(Instant::from_millis(0));
+        let clock = || now.get();
+        let mut motor = GuardedMotor::new(Motor::new(pwm.clone(), dir), 20);
+        assert!(motor.check(&clock));
+
+        motor.apply_command(Command::Front(500), &clock);
+        now.set(Instant::from_millis(20));
+        assert!(!motor.check(&clock));
+        motor.feed(&clock);
+        now.set(Instant::from_millis(40));
+        assert!(!motor.check(&clock));
+        assert_eq!(pwm.duty.get(), 500);
+
+        now.set(Instant::from_millis(41));
+        assert!(motor.check(&clock));
+        assert_eq!(pwm.duty.get(), 0);
+
+        motor.apply_command(Command::Back(300), &clock);
+        assert!(!motor.check(&clock));
+        assert_eq!(pwm.duty.get(), 300);
+    }
 }
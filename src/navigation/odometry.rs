@@ -1,11 +1,33 @@
 use core::f32;
 
-use crate::navigation::{Coord, PIDParameters};
-use crate::units::MilliMeter;
+use crate::navigation::{Coord, PIDParameters, Pose};
+use crate::units::{Angle, MicroMeter};
 
 #[allow(unused_imports)]
 use micromath::F32Ext;
 
+/// Le schéma d'intégration de l'odométrie entre deux mises à jour
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum OdometryModel {
+    /// Le robot avance en ligne droite avec l'angle du début de la mise à jour (méthode
+    /// d'Euler). Sous-estime la position dans les virages serrés si les mises à jour sont
+    /// espacées.
+    Euler,
+    /// Le robot avance en ligne droite avec l'angle du milieu de la mise à jour
+    Midpoint,
+    /// Le robot suit un arc de cercle : la distance parcourue est remplacée par la corde de
+    /// l'arc, avec l'angle du milieu de la mise à jour. Exact quand les vitesses des roues
+    /// sont constantes pendant la mise à jour.
+    Arc,
+}
+
+impl Default for OdometryModel {
+    fn default() -> Self {
+        OdometryModel::Euler
+    }
+}
+
 /// Contient la position du robot et peut se mettre à jour en
 /// fonction des informations provenant des roues codeuses
 #[derive(Debug)]
@@ -18,8 +40,9 @@ pub(crate) struct Odometry {
     x: f32,
     /// Coordonnee en y du robot en mm
     y: f32,
-    /// Angle du robot en radians
+    /// Angle du robot en radians, continu : il compte les tours complets
     angle: f32,
+    model: OdometryModel,
 }
 
 impl Odometry {
@@ -32,28 +55,64 @@ impl Odometry {
             x: 0.,
             y: 0.,
             angle: 0.,
+            model: OdometryModel::default(),
         }
     }
 
+    pub(crate) fn set_model(&mut self, model: OdometryModel) {
+        self.model = model;
+    }
+
+    pub(crate) fn get_model(&self) -> OdometryModel {
+        self.model
+    }
+
     /// Définit les informations de position du robot.
-    /// `new_pos` est exprimé en millimètres, `new_angle` est exprimé
-    /// en milliradians
-    pub(crate) fn set_position_and_angle(&mut self, new_pos: Coord, new_angle: i64) {
+    pub(crate) fn set_position_and_angle(&mut self, new_pos: Coord, new_angle: Angle) {
         self.x = new_pos.x.as_millimeters() as f32;
         self.y = new_pos.y.as_millimeters() as f32;
-        self.angle = new_angle as f32 / 1000.0;
+        self.angle = new_angle.as_radians();
     }
 
+    /// Définit la position et l'orientation du robot
+    pub(crate) fn set_pose(&mut self, pose: Pose) {
+        self.set_position_and_angle(pose.position, pose.heading);
+    }
+
+    /// Retourne la position du robot, arrondie au millimètre le plus proche
     pub(crate) fn get_position(&self) -> Coord {
+        let (x, y) = self.get_precise_position();
         Coord {
-            x: MilliMeter(self.x as i64),
-            y: MilliMeter(self.y as i64),
+            x: x.as_millimeters(),
+            y: y.as_millimeters(),
         }
     }
 
-    /// Retourne l'angle du robot en milliradians
-    pub(crate) fn get_angle(&self) -> i64 {
-        (self.angle * 1000.0) as i64
+    /// Retourne la position (x, y) du robot au micromètre près
+    pub(crate) fn get_precise_position(&self) -> (MicroMeter, MicroMeter) {
+        (
+            MicroMeter((self.x * 1000.0).round() as i64),
+            MicroMeter((self.y * 1000.0).round() as i64),
+        )
+    }
+
+    /// Retourne l'angle continu du robot, qui augmente de 2π à chaque tour dans le sens
+    /// trigonométrique
+    pub(crate) fn get_angle(&self) -> Angle {
+        Angle::from_radians(self.angle)
+    }
+
+    /// Retourne le cap du robot, ramené dans `[-π, π[`
+    pub(crate) fn get_heading(&self) -> Angle {
+        self.get_angle().normalize()
+    }
+
+    /// Retourne la position et l'orientation du robot
+    pub(crate) fn get_pose(&self) -> Pose {
+        Pose {
+            position: self.get_position(),
+            heading: self.get_angle(),
+        }
     }
 
     /// Met à jour l'odometrie à partir de la variation des ticks
@@ -65,8 +124,22 @@ impl Odometry {
         let dist_diff = (dist_left + dist_right) / 2.0;
         let angle_diff = (dist_right - dist_left) / params.inter_axial_length;
 
-        let sin = self.angle.sin();
-        let cos = self.angle.cos();
+        let (dist_diff, direction) = match self.model {
+            OdometryModel::Euler => (dist_diff, self.angle),
+            OdometryModel::Midpoint => (dist_diff, self.angle + angle_diff / 2.0),
+            OdometryModel::Arc => {
+                // Corde d'un arc de longueur `dist_diff` et d'angle `angle_diff`
+                let half = angle_diff / 2.0;
+                let chord = if half.abs() < 1e-4 {
+                    dist_diff
+                } else {
+                    dist_diff * half.sin() / half
+                };
+                (chord, self.angle + half)
+            }
+        };
+        let sin = direction.sin();
+        let cos = direction.cos();
         let dxf = dist_diff * cos;
         let dyf = dist_diff * sin;
         self.x += dxf;
@@ -81,10 +154,10 @@ impl Odometry {
 #[cfg(test)]
 mod test {
 
-    use crate::units::MilliMeter;
+    use crate::units::{Angle, MicroMeter, MilliMeter};
 
     use crate::navigation::odometry::*;
-    use crate::navigation::{Coord, PIDParameters};
+    use crate::navigation::{Coord, PIDParameters, Pose};
 
     #[test]
     fn odom_forward() {
@@ -106,6 +179,16 @@ mod test {
 
         assert_eq!(robot_pos.x, MilliMeter(195));
         assert_eq!(robot_pos.y, MilliMeter(0));
+
+        // Un tour de roue : 2 * pi * 31 mm
+        let (x, y) = odom.get_precise_position();
+        assert!(
+            (x.as_micrometers() - 194_779).abs() <= 20,
+            "{} should be 194779 µm",
+            x
+        );
+        assert_eq!(x.as_millimeters(), MilliMeter(195));
+        assert_eq!(y, MicroMeter(0));
     }
 
     #[test]
@@ -148,7 +231,7 @@ mod test {
                 x: MilliMeter(0),
                 y: MilliMeter(0),
             },
-            3141 / 4,
+            Angle(3141 / 4),
         );
 
         for i in 0..1025 {
@@ -158,7 +241,24 @@ mod test {
 
         assert_eq!(robot_pos.x, MilliMeter(138));
         assert_eq!(robot_pos.y, MilliMeter(138));
-        assert_eq!(odom.get_angle(), 3141 / 4);
+        assert_eq!(odom.get_angle(), Angle(3141 / 4));
+    }
+
+    #[test]
+    fn odom_pose() {
+        let mut odom = Odometry::new();
+        let pose = Pose {
+            position: Coord {
+                x: MilliMeter(-250),
+                y: MilliMeter(1200),
+            },
+            heading: Angle(3141 / 4),
+        };
+
+        odom.set_pose(pose);
+        assert_eq!(odom.get_pose(), pose);
+        assert_eq!(odom.get_position(), pose.position);
+        assert_eq!(odom.get_angle(), pose.heading);
     }
 
     #[test]
@@ -193,7 +293,7 @@ mod test {
             0
         );
         assert!(
-            (odom.get_angle() + 1571).abs() <= 3,
+            (odom.get_angle().as_milliradians() + 1571).abs() <= 3,
             "{} should be {}",
             odom.get_angle(),
             -1571
@@ -233,6 +333,51 @@ mod test {
         );
     }
 
+    #[test]
+    fn odom_models_on_tight_arc() {
+        let params = PIDParameters {
+            coder_radius: 31.0,
+            left_wheel_coef: 1.0,
+            right_wheel_coef: 1.0,
+            ticks_per_turn: 1024,
+            inter_axial_length: 223.0,
+            ..Default::default()
+        };
+
+        // Un quart de tour autour de la roue droite en seulement deux mises à jour : le centre
+        // du robot arrive en (111.5, -111.5)
+        let run = |model: OdometryModel| {
+            let mut odom = Odometry::new();
+            odom.set_model(model);
+            odom.update(922, 0, &params);
+            odom.update(1843, 0, &params);
+            let (x, y) = odom.get_precise_position();
+            (x.as_micrometers() / 1000, y.as_micrometers() / 1000)
+        };
+
+        let (x, y) = run(OdometryModel::Euler);
+        assert!(
+            x > 140 && y > -70,
+            "({}, {}) should be far from the arc",
+            x,
+            y
+        );
+        let (x, y) = run(OdometryModel::Midpoint);
+        assert!(
+            (x - 114).abs() <= 2 && (y + 114).abs() <= 2,
+            "({}, {})",
+            x,
+            y
+        );
+        let (x, y) = run(OdometryModel::Arc);
+        assert!(
+            (x - 111).abs() <= 2 && (y + 111).abs() <= 2,
+            "({}, {})",
+            x,
+            y
+        );
+    }
+
     #[test]
     fn odom_front_backward_angle() {
         let mut odom = Odometry::new();
@@ -251,7 +396,7 @@ mod test {
                 x: MilliMeter(0),
                 y: MilliMeter(0),
             },
-            0,
+            Angle(0),
         );
 
         for i in 0..1025 {
@@ -261,7 +406,7 @@ mod test {
 
         assert_eq!(robot_pos.x, MilliMeter(195));
         assert_eq!(robot_pos.y, MilliMeter(0));
-        assert_eq!(odom.get_angle(), 0);
+        assert_eq!(odom.get_angle(), Angle(0));
 
         let val_before = odom.get_position().x.as_millimeters();
         println!("angle before: {}", odom.get_angle());
@@ -292,6 +437,38 @@ mod test {
         );
     }
 
+    #[test]
+    fn odom_heading_wraps() {
+        let mut odom = Odometry::new();
+
+        let params = PIDParameters {
+            coder_radius: 31.0,
+            left_wheel_coef: 1.0,
+            right_wheel_coef: 1.0,
+            ticks_per_turn: 1024,
+            inter_axial_length: 223.0,
+            ..Default::default()
+        };
+
+        // Neuf quarts de tour dans le sens horaire
+        for i in 0..8281 {
+            odom.update(i, -i, &params);
+        }
+
+        assert!(
+            (odom.get_angle().as_milliradians() + 14137).abs() <= 30,
+            "{} should be {}",
+            odom.get_angle(),
+            -14137
+        );
+        assert!(
+            (odom.get_heading().as_milliradians() + 1571).abs() <= 30,
+            "{} should be {}",
+            odom.get_heading(),
+            -1571
+        );
+    }
+
     // #[test]
     fn odom_complex_navigation() {
         let mut odom = Odometry::new();
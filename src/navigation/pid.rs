@@ -3,10 +3,25 @@
 use core::f32;
 
 use crate::navigation::motor::Command;
+use crate::navigation::PIDParameters;
 
 #[allow(unused_imports)]
 use libm::F32Ext;
 
+/// Les grandeurs internes d'un PID lors de sa dernière mise à jour
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub(crate) struct Terms {
+    pub(crate) goal: f32,
+    /// Variation de la consigne par période d'échantillonnage
+    pub(crate) goal_speed: f32,
+    pub(crate) error: f32,
+    pub(crate) p: f32,
+    pub(crate) i: f32,
+    pub(crate) d: f32,
+    /// Commande après saturation par le [`PolarController`]
+    pub(crate) output: f32,
+}
+
 #[allow(non_snake_case)]
 pub(crate) struct PID {
     kp: f32,
@@ -18,6 +33,7 @@ pub(crate) struct PID {
     current_error: f32,
     goal: f32,
     command: f32,
+    terms: Terms,
 }
 
 impl PID {
@@ -31,9 +47,17 @@ impl PID {
             current_error: 0.0,
             goal: 0.0,
             command: 0.0,
+            terms: Terms::default(),
         }
     }
 
+    /// Change les coefficients sans perdre l'intégrale ni l'erreur courante
+    pub(crate) fn set_gains(&mut self, kp: f32, kd: f32, ki: f32) {
+        self.kp = kp;
+        self.kd = kd;
+        self.ki = ki;
+    }
+
     pub(crate) fn set_goal(&mut self, goal: f32) {
         self.goal = goal;
     }
@@ -50,11 +74,30 @@ impl PID {
         self.command
     }
 
+    pub(crate) fn get_terms(&self) -> Terms {
+        self.terms
+    }
+
     pub(crate) fn update(&mut self, val: f32) {
+        self.update_dt(val, 1.0);
+    }
+
+    /// Met à jour le PID quand `dt` périodes d'échantillonnage se sont écoulées depuis la
+    /// dernière mise à jour
+    pub(crate) fn update_dt(&mut self, val: f32, dt: f32) {
         let error = val - self.goal;
-        let d_error = error - self.current_error;
-        self.I += error + self.current_error;
-        self.command = error * self.kp + self.I * self.ki + d_error * self.kd;
+        let d_error = (error - self.current_error) / dt;
+        self.I += (error + self.current_error) * dt;
+        self.terms = Terms {
+            goal: self.goal,
+            goal_speed: (self.goal - self.terms.goal) / dt,
+            error,
+            p: error * self.kp,
+            i: self.I * self.ki,
+            d: d_error * self.kd,
+            output: 0.0,
+        };
+        self.command = self.terms.p + self.terms.i + self.terms.d;
         self.current_error = error;
     }
 }
@@ -70,6 +113,13 @@ pub(crate) struct PolarController {
     linear_control_enabled: bool,
     /// Si `false` le robot n'est pas asservi en angulaire
     angular_control_enabled: bool,
+    /// Si `true` les deux asservissements sont coupés, quelle que soit leur activation
+    suspended: bool,
+    /// Commande longitudinale appliquée en boucle ouverte à la place de l'asservissement
+    /// longitudinal, positive en avant
+    linear_open_loop: Option<f32>,
+    /// Gain du couplage croisé entre les roues
+    cross_coupling: f32,
     pos_kd: f32,
     orient_kd: f32,
 }
@@ -92,6 +142,9 @@ impl PolarController {
             max_angle_output,
             linear_control_enabled: true,
             angular_control_enabled: true,
+            suspended: false,
+            linear_open_loop: None,
+            cross_coupling: 0.0,
             pos_kd,
             orient_kd,
         }
@@ -102,6 +155,38 @@ impl PolarController {
         self.angular_control_enabled = ang_ctrl;
     }
 
+    /// Coupe ou rétablit les deux asservissements sans changer leur activation respective
+    pub(crate) fn set_suspended(&mut self, suspended: bool) {
+        self.suspended = suspended;
+    }
+
+    /// Remplace l'asservissement longitudinal par la commande `command` en boucle ouverte, ou
+    /// le rétablit si `command` vaut `None`
+    pub(crate) fn set_linear_open_loop(&mut self, command: Option<f32>) {
+        self.linear_open_loop = command;
+    }
+
+    pub(crate) fn get_linear_open_loop(&self) -> Option<f32> {
+        self.linear_open_loop
+    }
+
+    /// Change les coefficients et les saturations des deux asservissements
+    pub(crate) fn set_params(&mut self, params: &PIDParameters) {
+        self.linear_control
+            .set_gains(params.pos_kp, params.pos_kd, params.pos_ki);
+        self.angular_control
+            .set_gains(params.orient_kp, params.orient_kd, params.orient_ki);
+        self.pos_kd = params.pos_kd;
+        self.orient_kd = params.orient_kd;
+        self.max_output = params.max_output;
+        self.max_angle_output = params.max_angle_output;
+        self.cross_coupling = params.cross_coupling;
+    }
+
+    pub(crate) fn set_cross_coupling(&mut self, gain: f32) {
+        self.cross_coupling = gain;
+    }
+
     pub(crate) fn set_max_output(&mut self, max_output: u16) {
         self.max_output = max_output;
     }
@@ -139,6 +224,15 @@ impl PolarController {
         )
     }
 
+    /// Renvoie les grandeurs internes des asservissements longitudinal et angulaire lors de la
+    /// dernière mise à jour
+    pub(crate) fn get_terms(&self) -> (Terms, Terms) {
+        (
+            self.linear_control.get_terms(),
+            self.angular_control.get_terms(),
+        )
+    }
+
     pub(crate) fn clamp(val: f32, threshold: f32) -> f32 {
         if val > threshold {
             threshold
@@ -149,13 +243,41 @@ impl PolarController {
         }
     }
 
+    /// Réduit la commande `command` d'une roue en avance de `ahead` mm sur l'autre dans le sens
+    /// où elle avance, sans changer son sens
+    fn slow_down(command: f32, ahead: f32, gain: f32) -> f32 {
+        if command * ahead <= 0.0 {
+            command
+        } else if command > 0.0 {
+            (command - gain * ahead).max(0.0)
+        } else {
+            (command - gain * ahead).min(0.0)
+        }
+    }
+
     pub(crate) fn update(&mut self, left_dist: f32, right_dist: f32) -> (Command, Command) {
+        self.update_dt(left_dist, right_dist, 1.0)
+    }
+
+    /// Met à jour les PID quand `dt` périodes d'échantillonnage se sont écoulées depuis la
+    /// dernière mise à jour
+    pub(crate) fn update_dt(
+        &mut self,
+        left_dist: f32,
+        right_dist: f32,
+        dt: f32,
+    ) -> (Command, Command) {
         // Mise à jour de la mémoire du PID
         let lin_val = (left_dist + right_dist) / 2.0;
         let ang_val = right_dist - left_dist;
 
-        self.linear_control.update(lin_val);
-        self.angular_control.update(ang_val);
+        // En boucle ouverte, la consigne suit le robot pour que l'asservissement reprenne là où
+        // il se trouve, sans avoir accumulé d'erreur
+        if self.linear_open_loop.is_some() {
+            self.linear_control.set_goal(lin_val);
+        }
+        self.linear_control.update_dt(lin_val, dt);
+        self.angular_control.update_dt(ang_val, dt);
 
         self.linear_control.kd = if self.linear_control.current_error.abs() < 5.0 {
             0.0
@@ -168,12 +290,16 @@ impl PolarController {
             self.orient_kd
         };
         // Calcul du PID
-        let position_cmd = if self.linear_control_enabled {
-            Self::clamp(self.linear_control.get_command(), self.max_output as f32)
-        } else {
-            0.0
+        let position_cmd = match self.linear_open_loop {
+            _ if self.suspended => 0.0,
+            // La commande du PID est positive quand le robot doit reculer
+            Some(command) => Self::clamp(-command, self.max_output as f32),
+            None if self.linear_control_enabled => {
+                Self::clamp(self.linear_control.get_command(), self.max_output as f32)
+            }
+            None => 0.0,
         };
-        let orientation_cmd = if self.angular_control_enabled {
+        let orientation_cmd = if self.angular_control_enabled && !self.suspended {
             Self::clamp(
                 self.angular_control.get_command(),
                 self.max_angle_output as f32,
@@ -181,11 +307,30 @@ impl PolarController {
         } else {
             0.0
         };
+        self.linear_control.terms.output = position_cmd;
+        self.angular_control.terms.output = orientation_cmd;
+
+        let max_output = self.max_output as f32;
+        let left = Self::clamp(-position_cmd + orientation_cmd, max_output);
+        let right = Self::clamp(-position_cmd - orientation_cmd, max_output);
+
+        // Couplage croisé : les commandes sont saturées indépendamment, donc la roue en avance
+        // est ralentie pour attendre celle qui a pris du retard
+        let (left, right) = if self.cross_coupling > 0.0 {
+            // Positif quand la roue droite est en avance sur la gauche par rapport à la consigne
+            let lag = self.angular_control.current_error;
+            (
+                Self::slow_down(left, -lag, self.cross_coupling),
+                Self::slow_down(right, lag, self.cross_coupling),
+            )
+        } else {
+            (left, right)
+        };
 
         // Truncate result
         (
-            Command::truncate(-position_cmd + orientation_cmd, self.max_output),
-            Command::truncate(-position_cmd - orientation_cmd, self.max_output),
+            Command::truncate(left, self.max_output),
+            Command::truncate(right, self.max_output),
         )
     }
 }
@@ -196,7 +341,8 @@ mod test {
     use qei::QeiManager;
 
     use crate::navigation::motor::test::DummyMotor;
-    use crate::navigation::pid::PolarController;
+    use crate::navigation::motor::Command;
+    use crate::navigation::pid::{PolarController, PID};
 
     fn get_qei<T>(qei: &mut QeiManager<T>) -> i64
     where
@@ -343,4 +489,97 @@ mod test {
         );
     }
 
+    #[test]
+    fn pid_variable_dt() {
+        let mut fixed = PID::new(1.0, 1.0, 1.0);
+        let mut variable = PID::new(1.0, 1.0, 1.0);
+        fixed.update(3.0);
+        variable.update_dt(3.0, 1.0);
+        assert!((fixed.get_command() - variable.get_command()).abs() < 1e-6);
+
+        let mut integral = PID::new(0.0, 0.0, 1.0);
+        integral.update_dt(2.0, 2.0);
+        assert!((integral.get_command() - 4.0).abs() < 1e-6);
+
+        let mut derivative = PID::new(0.0, 1.0, 0.0);
+        derivative.update_dt(2.0, 2.0);
+        assert!((derivative.get_command() - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn pid_cross_coupling() {
+        // La roue gauche est bloquée par un obstacle pendant que le robot avance
+        let run = |cross_coupling: f32| {
+            let motor_left = DummyMotor::new();
+            let mut motor_right = DummyMotor::new();
+            let mut qei_left = QeiManager::new(motor_left.clone());
+            let mut qei_right = QeiManager::new(motor_right.clone());
+            let mut pid = PolarController::new(1.0, 1.0, 0.1, 1.0, 1.0, 0.1, 800, 50);
+            pid.set_cross_coupling(cross_coupling);
+            pid.set_linear_goal(90000.0);
+            for _ in 0..100 {
+                let (_, cmdr) = pid.update(
+                    get_qei(&mut qei_left) as f32,
+                    get_qei(&mut qei_right) as f32,
+                );
+                motor_right.apply_command(cmdr);
+                motor_right.update();
+            }
+            motor_right.get_real_position()
+        };
+        // Sans couplage, l'asservissement angulaire saturé laisse la roue droite avancer
+        assert!(run(0.0) > 10000, "{} should be more than 10000", run(0.0));
+        // Avec couplage, la roue droite attend la roue gauche
+        assert!(run(10.0) <= 200, "{} should be at most 200", run(10.0));
+    }
+
+    #[test]
+    fn pid_linear_open_loop() {
+        let mut motor_left = DummyMotor::new();
+        let mut motor_right = DummyMotor::new();
+        let mut qei_left = QeiManager::new(motor_left.clone());
+        let mut qei_right = QeiManager::new(motor_right.clone());
+        let mut pid = PolarController::new(1.0, 1.0, 0.1, 1.0, 1.0, 0.1, 800, 800);
+
+        // La roue gauche est en avance, l'asservissement angulaire doit la rattraper pendant
+        // que le robot pousse droit devant lui
+        motor_left.apply_command(Command::Front(500));
+        motor_left.update();
+        pid.set_linear_open_loop(Some(100.0));
+        for _ in 0..999 {
+            let (cmdl, cmdr) = pid.update(
+                get_qei(&mut qei_left) as f32,
+                get_qei(&mut qei_right) as f32,
+            );
+            motor_left.apply_command(cmdl);
+            motor_right.apply_command(cmdr);
+            motor_left.update();
+            motor_right.update();
+        }
+        let (left, right) = (
+            motor_left.get_real_position(),
+            motor_right.get_real_position(),
+        );
+        assert!((left - right).abs() <= 10, "{} should be {}", right, left);
+        assert!(right > 10000, "{} should be more than 10000", right);
+
+        // L'asservissement longitudinal reprend à la position atteinte
+        pid.set_linear_open_loop(None);
+        for _ in 0..200 {
+            let (cmdl, cmdr) = pid.update(
+                get_qei(&mut qei_left) as f32,
+                get_qei(&mut qei_right) as f32,
+            );
+            motor_left.apply_command(cmdl);
+            motor_right.apply_command(cmdr);
+            motor_left.update();
+            motor_right.update();
+        }
+        assert!(
+            (motor_left.get_real_position() - left).abs() <= 30,
+            "{} should be {}",
+            motor_left.get_real_position(),
+            left
+        );
+    }
 }
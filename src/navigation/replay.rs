@@ -0,0 +1,204 @@
+//! Rejeu hors ligne des ticks enregistrés pendant un match.
+//!
+//! La carte déplacement enregistre les ticks des roues codeuses gauche et droite avec l'instant
+//! de leur échantillonnage. Une [`Replay`] les redonne au [`Controller`] à la place des qei, avec
+//! les mêmes instants, pour reproduire un incident de match avec le vrai code de la navigation :
+//!
+//! ```ignore
+//! let trace = Replay::from_csv(&std::fs::read_to_string("match.csv")?)?;
+//! let mut pid = Controller::replay(trace, &params);
+//! pid.forward(500.0);
+//! while let Some(now) = pid.replay_step() {
+//!     println!("{} {:?} {}", now.as_millis(), pid.get_pose(), pid.is_robot_blocked());
+//! }
+//! ```
+
+use super::{Controller, PIDParameters, SamplingError, TickSource};
+use crate::time::Instant;
+use core::fmt;
+use std::vec::Vec;
+
+/// Un échantillonnage enregistré des roues codeuses
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct TickRecord {
+    /// L'instant de l'échantillonnage
+    pub time: Instant,
+    /// Les ticks de la roue codeuse gauche, comptés depuis le démarrage de la carte
+    pub left: i64,
+    /// Les ticks de la roue codeuse droite, comptés depuis le démarrage de la carte
+    pub right: i64,
+}
+
+impl TickRecord {
+    /// Lit une ligne `instant_ms,gauche,droite`. Les champs peuvent aussi être séparés par des
+    /// espaces.
+    pub fn parse(line: &str) -> Option<Self> {
+        let mut fields = line
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|field| !field.is_empty());
+        let record = TickRecord {
+            time: Instant::from_millis(fields.next()?.parse().ok()?),
+            left: fields.next()?.parse().ok()?,
+            right: fields.next()?.parse().ok()?,
+        };
+        match fields.next() {
+            Some(_) => None,
+            None => Some(record),
+        }
+    }
+}
+
+/// Une ligne d'un enregistrement n'a pas pu être lue
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ReplayError {
+    /// Le numéro de la ligne, à partir de 1
+    pub line: usize,
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid tick record at line {}", self.line)
+    }
+}
+
+impl std::error::Error for ReplayError {}
+
+/// Une source de ticks qui rejoue un enregistrement, un échantillonnage à chaque mise à jour du
+/// [`Controller`]
+#[derive(Debug, Default, Clone)]
+pub struct Replay {
+    records: Vec<TickRecord>,
+    next: usize,
+    ticks: (i64, i64),
+}
+
+impl Replay {
+    /// Crée le rejeu des échantillonnages `records`, dans l'ordre
+    pub fn new(records: Vec<TickRecord>) -> Self {
+        Replay {
+            records,
+            ..Default::default()
+        }
+    }
+
+    /// Lit un enregistrement avec un échantillonnage par ligne (voir [`TickRecord::parse`]).
+    /// Les lignes vides et celles qui commencent par `#` sont ignorées.
+    pub fn from_csv(text: &str) -> Result<Self, ReplayError> {
+        let mut records = Vec::new();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let record = TickRecord::parse(line).ok_or(ReplayError { line: index + 1 })?;
+            records.push(record);
+        }
+        Ok(Replay::new(records))
+    }
+
+    /// Renvoie le prochain échantillonnage à rejouer
+    pub fn peek(&self) -> Option<&TickRecord> {
+        self.records.get(self.next)
+    }
+
+    /// Renvoie le nombre d'échantillonnages qui restent à rejouer
+    pub fn remaining(&self) -> usize {
+        self.records.len() - self.next
+    }
+
+    /// Reprend le rejeu au début de l'enregistrement
+    pub fn rewind(&mut self) {
+        self.next = 0;
+        self.ticks = (0, 0);
+    }
+}
+
+impl TickSource for Replay {
+    fn sample(&mut self) -> Result<(), SamplingError> {
+        if let Some(record) = self.records.get(self.next) {
+            self.ticks = (record.left, record.right);
+            self.next += 1;
+        }
+        Ok(())
+    }
+
+    fn ticks(&self) -> (i64, i64) {
+        self.ticks
+    }
+}
+
+impl Controller<Replay> {
+    /// Crée une navigation qui lit ses ticks dans l'enregistrement `trace`
+    pub fn replay(trace: Replay, params: &PIDParameters) -> Self {
+        Controller::with_ticks(trace, params)
+    }
+
+    /// Rejoue le prochain échantillonnage : met à jour le PID, l'odométrie et la détection du
+    /// bloquage comme si l'instant enregistré était l'instant présent. Renvoie cet instant, ou
+    /// `None` à la fin de l'enregistrement.
+    pub fn replay_step(&mut self) -> Option<Instant> {
+        let now = self.ticks.peek()?.time;
+        let clock = || now;
+        self.update_with(&clock);
+        self.update_blocking_with(&clock);
+        Some(now)
+    }
+
+    /// Renvoie l'enregistrement rejoué
+    pub fn trace(&self) -> &Replay {
+        &self.ticks
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Replay, ReplayError, TickRecord};
+    use crate::navigation::{Controller, PIDParameters};
+    use crate::time::Instant;
+    use std::string::String;
+
+    #[test]
+    fn parse_records() {
+        assert_eq!(
+            TickRecord::parse("12, -5 7"),
+            Some(TickRecord {
+                time: Instant::from_millis(12),
+                left: -5,
+                right: 7,
+            })
+        );
+        assert_eq!(TickRecord::parse("12,5"), None);
+        assert_eq!(TickRecord::parse("12,5,7,9"), None);
+        assert_eq!(
+            Replay::from_csv("# t,l,r\n0,0,0\n\n1,2,x\n").unwrap_err(),
+            ReplayError { line: 4 }
+        );
+    }
+
+    #[test]
+    fn replay_is_deterministic() {
+        let trace: String = (0..200)
+            .map(|i| format!("{},{},{}\n", i * 2, i * 10, i * 10))
+            .collect();
+        let trace = Replay::from_csv(&trace).unwrap();
+        let params = PIDParameters {
+            sample_period: 2,
+            ..Default::default()
+        };
+        let run = |trace: Replay| {
+            let mut pid = Controller::replay(trace, &params);
+            pid.forward(100.0);
+            let mut last = None;
+            while let Some(now) = pid.replay_step() {
+                last = Some(now);
+            }
+            assert_eq!(pid.trace().remaining(), 0);
+            (last, pid.get_qei_ticks(), pid.get_pose(), pid.get_command())
+        };
+        let first = run(trace.clone());
+        assert_eq!(first.0, Some(Instant::from_millis(398)));
+        assert_eq!(first.1, (1990, 1990));
+        assert!(first.2.position.x.as_millimeters() > 0);
+        assert_eq!(run(trace), first);
+    }
+}
@@ -0,0 +1,329 @@
+//! Séparation de la navigation entre l'échantillonnage des roues codeuses et l'asservissement.
+//!
+//! Les qei doivent être échantillonnées très souvent (typiquement à 10 kHz dans une interruption)
+//! pour ne pas rater un débordement du compteur matériel, alors que le PID et l'odométrie sont
+//! mis à jour beaucoup moins souvent. Un [`Sampler`] échantillonne les qei et publie les ticks
+//! dans un [`TicksSnapshot`], que le [`Controller`](super::Controller) relit sans jamais bloquer
+//! l'interruption.
+
+#![cfg_attr(
+    not(test),
+    deny(
+        clippy::panic,
+        clippy::unwrap_used,
+        clippy::expect_used,
+        clippy::unreachable
+    )
+)]
+
+use core::sync::atomic::{fence, AtomicU32, Ordering};
+
+use embedded_hal::Qei;
+use qei::QeiManager;
+
+/// Un qei n'a pas pu être échantillonné : son compteur a trop bougé depuis le dernier
+/// échantillonnage pour savoir dans quel sens il a débordé, les ticks sont faux
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SamplingError;
+
+/// Une source de ticks pour les roues codeuses gauche et droite
+pub trait TickSource {
+    /// Met à jour les ticks, appelée au début de chaque mise à jour du
+    /// [`Controller`](super::Controller). Les deux roues sont toujours échantillonnées, même si
+    /// l'une d'elles renvoie une erreur.
+    fn sample(&mut self) -> Result<(), SamplingError>;
+
+    /// Renvoie les ticks comptés par les roues codeuses gauche et droite
+    fn ticks(&self) -> (i64, i64);
+}
+
+impl<L, R> TickSource for (QeiManager<L>, QeiManager<R>)
+where
+    L: Qei<Count = u16>,
+    R: Qei<Count = u16>,
+{
+    fn sample(&mut self) -> Result<(), SamplingError> {
+        let left = self.0.sample();
+        let right = self.1.sample();
+        if left.is_err() || right.is_err() {
+            Err(SamplingError)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn ticks(&self) -> (i64, i64) {
+        (self.0.count(), self.1.count())
+    }
+}
+
+/// Plus grande variation des ticks entre deux échantillonnages considérée comme sûre.
+///
+/// Le [`QeiManager`] étend le compteur 16 bits des qei en supposant qu'il a bougé de moins d'un
+/// demi-tour de compteur (32768 ticks) entre deux échantillonnages. Au delà de la moitié de cette
+/// limite, un échantillonnage en retard suffit à faire compter la distance à l'envers.
+pub const QEI_DELTA_LIMIT: u32 = 1 << 14;
+
+/// Statistiques sur la variation des ticks entre deux échantillonnages, pour détecter une roue
+/// qui tourne trop vite pour la fréquence d'échantillonnage au lieu de calculer une distance
+/// fausse sans le savoir.
+///
+/// Un débordement peut être signalé à l'informatique avec le code
+/// [`ErrorCode::QeiOverflow`](crate::transmission::diagnostic::ErrorCode::QeiOverflow).
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct QeiDiagnostics {
+    last: Option<(i64, i64)>,
+    max_delta: u32,
+    overflows: u32,
+    sampling_errors: u32,
+}
+
+impl QeiDiagnostics {
+    /// Crée des statistiques vides
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Enregistre les ticks gauche et droite lus après un échantillonnage
+    pub fn record(&mut self, (left, right): (i64, i64)) {
+        if let Some((last_left, last_right)) = self.last {
+            let delta = (left - last_left).abs().max((right - last_right).abs());
+            let delta = if delta > i64::from(u32::max_value()) {
+                u32::max_value()
+            } else {
+                delta as u32
+            };
+            self.max_delta = self.max_delta.max(delta);
+            if delta > QEI_DELTA_LIMIT {
+                self.overflows = self.overflows.saturating_add(1);
+            }
+        }
+        self.last = Some((left, right));
+    }
+
+    /// Enregistre un échantillonnage qui a échoué (voir [`SamplingError`])
+    pub fn record_sampling_error(&mut self) {
+        self.sampling_errors = self.sampling_errors.saturating_add(1);
+    }
+
+    /// Renvoie la plus grande variation des ticks observée entre deux échantillonnages
+    pub fn max_delta(&self) -> u32 {
+        self.max_delta
+    }
+
+    /// Renvoie le nombre d'échantillonnages où la variation a dépassé [`QEI_DELTA_LIMIT`]
+    pub fn overflows(&self) -> u32 {
+        self.overflows
+    }
+
+    /// Renvoie le nombre d'échantillonnages qui ont échoué
+    pub fn sampling_errors(&self) -> u32 {
+        self.sampling_errors
+    }
+
+    /// Renvoie vrai si la variation a dépassé [`QEI_DELTA_LIMIT`] ou si un échantillonnage a
+    /// échoué au moins une fois : la distance mesurée n'est alors plus fiable
+    pub fn has_overflowed(&self) -> bool {
+        self.overflows > 0 || self.sampling_errors > 0
+    }
+
+    /// Remet les statistiques à zéro, en gardant les derniers ticks
+    pub fn reset(&mut self) {
+        self.max_delta = 0;
+        self.overflows = 0;
+        self.sampling_errors = 0;
+    }
+}
+
+/// Les derniers ticks publiés par un [`Sampler`], lisibles sans verrou.
+///
+/// L'écriture ne bloque jamais. La lecture recommence tant qu'une écriture est en cours : le
+/// lecteur doit donc pouvoir être interrompu par l'écrivain, c'est à dire avoir une priorité
+/// inférieure à celle du `Sampler`. Il est prévu pour être placé dans une `static` :
+///
+/// ```ignore
+/// static SNAPSHOT: TicksSnapshot = TicksSnapshot::new();
+/// let (sampler, controller) = pid.split(&SNAPSHOT);
+/// ```
+#[derive(Debug, Default)]
+pub struct TicksSnapshot {
+    /// Impair pendant une écriture
+    sequence: AtomicU32,
+    /// Les ticks gauche et droite, découpés en mots de 32 bits (poids faible en premier)
+    words: [AtomicU32; 4],
+}
+
+impl TicksSnapshot {
+    /// Crée une photographie des ticks initialisée à 0
+    pub const fn new() -> Self {
+        TicksSnapshot {
+            sequence: AtomicU32::new(0),
+            words: [
+                AtomicU32::new(0),
+                AtomicU32::new(0),
+                AtomicU32::new(0),
+                AtomicU32::new(0),
+            ],
+        }
+    }
+
+    /// Publie de nouveaux ticks. Il ne doit y avoir qu'un seul écrivain à la fois.
+    pub(crate) fn publish(&self, (left, right): (i64, i64)) {
+        let sequence = self.sequence.load(Ordering::Relaxed);
+        self.sequence
+            .store(sequence.wrapping_add(1), Ordering::Relaxed);
+        fence(Ordering::Release);
+        self.words[0].store(left as u32, Ordering::Relaxed);
+        self.words[1].store((left >> 32) as u32, Ordering::Relaxed);
+        self.words[2].store(right as u32, Ordering::Relaxed);
+        self.words[3].store((right >> 32) as u32, Ordering::Relaxed);
+        self.sequence
+            .store(sequence.wrapping_add(2), Ordering::Release);
+    }
+
+    /// Renvoie les derniers ticks publiés
+    pub fn read(&self) -> (i64, i64) {
+        loop {
+            let before = self.sequence.load(Ordering::Acquire);
+            if before % 2 == 1 {
+                continue;
+            }
+            let words = [
+                self.words[0].load(Ordering::Relaxed),
+                self.words[1].load(Ordering::Relaxed),
+                self.words[2].load(Ordering::Relaxed),
+                self.words[3].load(Ordering::Relaxed),
+            ];
+            fence(Ordering::Acquire);
+            if self.sequence.load(Ordering::Relaxed) == before {
+                return (
+                    (i64::from(words[1]) << 32) | i64::from(words[0]),
+                    (i64::from(words[3]) << 32) | i64::from(words[2]),
+                );
+            }
+        }
+    }
+}
+
+impl<'a> TickSource for &'a TicksSnapshot {
+    fn sample(&mut self) -> Result<(), SamplingError> {
+        Ok(())
+    }
+
+    fn ticks(&self) -> (i64, i64) {
+        self.read()
+    }
+}
+
+/// La partie de la navigation qui échantillonne les qei, obtenue avec
+/// [`RealWorldPid::split`](super::RealWorldPid::split).
+///
+/// Elle ne contient que les qei et une référence vers le [`TicksSnapshot`], et peut donc être
+/// utilisée depuis l'interruption des roues codeuses.
+pub struct Sampler<'a, L, R>
+where
+    L: Qei<Count = u16>,
+    R: Qei<Count = u16>,
+{
+    qei: (QeiManager<L>, QeiManager<R>),
+    snapshot: &'a TicksSnapshot,
+    diagnostics: QeiDiagnostics,
+}
+
+impl<'a, L, R> core::fmt::Debug for Sampler<'a, L, R>
+where
+    L: Qei<Count = u16>,
+    R: Qei<Count = u16>,
+{
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        let (left, right) = self.qei.ticks();
+        write!(f, "Sampler {{ left: {}, right: {} }}", left, right)
+    }
+}
+
+impl<'a, L, R> Sampler<'a, L, R>
+where
+    L: Qei<Count = u16>,
+    R: Qei<Count = u16>,
+{
+    pub(crate) fn new(qei: (QeiManager<L>, QeiManager<R>), snapshot: &'a TicksSnapshot) -> Self {
+        let mut diagnostics = QeiDiagnostics::new();
+        diagnostics.record(qei.ticks());
+        Sampler {
+            qei,
+            snapshot,
+            diagnostics,
+        }
+    }
+
+    /// Échantillonne les qei et publie les ticks pour le [`Controller`](super::Controller).
+    /// Les ticks sont publiés même si l'échantillonnage échoue, l'erreur est comptée dans
+    /// [`Sampler::qei_diagnostics`].
+    pub fn sample(&mut self) -> Result<(), SamplingError> {
+        let result = self.qei.sample();
+        if result.is_err() {
+            self.diagnostics.record_sampling_error();
+        }
+        let ticks = self.qei.ticks();
+        self.diagnostics.record(ticks);
+        self.snapshot.publish(ticks);
+        result
+    }
+
+    /// Renvoie les ticks comptés par les roues codeuses
+    pub fn get_qei_ticks(&self) -> (i64, i64) {
+        self.qei.ticks()
+    }
+
+    /// Renvoie les statistiques sur la variation des ticks entre deux échantillonnages
+    pub fn qei_diagnostics(&self) -> &QeiDiagnostics {
+        &self.diagnostics
+    }
+
+    /// Remet à zéro les statistiques sur la variation des ticks
+    pub fn reset_qei_diagnostics(&mut self) {
+        self.diagnostics.reset();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{QeiDiagnostics, TicksSnapshot, QEI_DELTA_LIMIT};
+
+    #[test]
+    fn snapshot_round_trip() {
+        let snapshot = TicksSnapshot::new();
+        assert_eq!(snapshot.read(), (0, 0));
+        snapshot.publish((-1, 1 << 40));
+        assert_eq!(snapshot.read(), (-1, 1 << 40));
+        snapshot.publish((i64::min_value(), 12));
+        assert_eq!(snapshot.read(), (i64::min_value(), 12));
+    }
+
+    #[test]
+    fn qei_diagnostics_overflow() {
+        let mut diagnostics = QeiDiagnostics::new();
+        diagnostics.record((0, 0));
+        diagnostics.record((100, -250));
+        assert_eq!(diagnostics.max_delta(), 250);
+        assert!(!diagnostics.has_overflowed());
+
+        let limit = i64::from(QEI_DELTA_LIMIT);
+        diagnostics.record((100 + limit + 1, -250));
+        assert_eq!(diagnostics.max_delta(), QEI_DELTA_LIMIT + 1);
+        assert_eq!(diagnostics.overflows(), 1);
+        assert!(diagnostics.has_overflowed());
+
+        diagnostics.reset();
+        diagnostics.record_sampling_error();
+        assert_eq!(diagnostics.sampling_errors(), 1);
+        assert!(diagnostics.has_overflowed());
+
+        diagnostics.reset();
+        diagnostics.record((100 + limit + 11, -250));
+        assert_eq!(diagnostics.max_delta(), 10);
+        assert!(!diagnostics.has_overflowed());
+    }
+}
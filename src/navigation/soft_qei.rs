@@ -0,0 +1,163 @@
+//! Décodeur de quadrature logiciel.
+//!
+//! Sur les cartes dont les timers sont déjà utilisés pour les PWM, les voies A et B d'une roue
+//! codeuse sont branchées sur des broches d'interruption (EXTI). À chaque front, l'interruption
+//! lit le niveau des deux voies et le donne au [`SoftQei`], qui compte les ticks. Il implémente
+//! [`Qei`] et s'utilise donc avec un [`QeiManager`](qei::QeiManager) comme un décodeur matériel :
+//!
+//! ```ignore
+//! static LEFT: SoftQei = SoftQei::new();
+//!
+//! #[interrupt]
+//! fn EXTI0() {
+//!     LEFT.poll(&pin_a, &pin_b);
+//! }
+//!
+//! let qei = QeiManager::new(&LEFT);
+//! ```
+
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicU8, Ordering};
+
+use embedded_hal::digital::InputPin;
+use embedded_hal::{Direction, Qei};
+
+/// Le déplacement correspondant à chaque transition `(ancien état << 2) | nouvel état`, où un
+/// état vaut `(A << 1) | B`. Les transitions où les deux voies changent valent 0 : un front a
+/// été raté et le sens n'est pas connu.
+const TRANSITIONS: [i8; 16] = [0, -1, 1, 0, 1, 0, 0, -1, -1, 0, 0, 1, 0, 1, -1, 0];
+
+/// Un décodeur de quadrature alimenté par les fronts des voies A et B.
+///
+/// Toutes les méthodes prennent `&self` pour qu'il puisse être placé dans une `static`. Les
+/// appels à [`SoftQei::update`] et [`SoftQei::poll`] doivent venir d'interruptions de même
+/// priorité, qui ne s'interrompent pas entre elles.
+#[derive(Debug, Default)]
+pub struct SoftQei {
+    /// L'état `(A << 1) | B` lors du dernier front
+    state: AtomicU8,
+    count: AtomicU32,
+    upcounting: AtomicBool,
+    /// Nombre de transitions invalides, où les deux voies ont changé en même temps
+    missed: AtomicU32,
+}
+
+impl SoftQei {
+    /// Crée un décodeur dont les deux voies sont à l'état bas
+    pub const fn new() -> Self {
+        SoftQei {
+            state: AtomicU8::new(0),
+            count: AtomicU32::new(0),
+            upcounting: AtomicBool::new(true),
+            missed: AtomicU32::new(0),
+        }
+    }
+
+    /// Met à jour le compteur avec le niveau des voies A et B après un front
+    pub fn update(&self, a: bool, b: bool) {
+        let new = (u8::from(a) << 1) | u8::from(b);
+        let old = self.state.swap(new, Ordering::Relaxed);
+        if old == new {
+            return;
+        }
+        match TRANSITIONS[usize::from((old << 2) | new)] {
+            1 => {
+                self.count.fetch_add(1, Ordering::Relaxed);
+                self.upcounting.store(true, Ordering::Relaxed);
+            }
+            -1 => {
+                self.count.fetch_sub(1, Ordering::Relaxed);
+                self.upcounting.store(false, Ordering::Relaxed);
+            }
+            _ => {
+                self.missed.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Lit les voies A et B et met à jour le compteur, depuis une interruption ou en scrutation
+    pub fn poll<A: InputPin, B: InputPin>(&self, a: &A, b: &B) {
+        self.update(a.is_high(), b.is_high());
+    }
+
+    /// Renvoie le nombre de transitions invalides depuis la création du décodeur. S'il augmente,
+    /// les interruptions ne suivent pas la vitesse de la roue.
+    pub fn missed_edges(&self) -> u32 {
+        self.missed.load(Ordering::Relaxed)
+    }
+}
+
+impl Qei for SoftQei {
+    type Count = u16;
+
+    fn count(&self) -> u16 {
+        self.count.load(Ordering::Relaxed) as u16
+    }
+
+    fn direction(&self) -> Direction {
+        if self.upcounting.load(Ordering::Relaxed) {
+            Direction::Upcounting
+        } else {
+            Direction::Downcounting
+        }
+    }
+}
+
+impl<'a> Qei for &'a SoftQei {
+    type Count = u16;
+
+    fn count(&self) -> u16 {
+        (**self).count()
+    }
+
+    fn direction(&self) -> Direction {
+        (**self).direction()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::SoftQei;
+    use embedded_hal::{Direction, Qei};
+    use qei::QeiManager;
+
+    /// Les états successifs des voies (A, B) pour un tour dans le sens positif
+    const FORWARD: [(bool, bool); 4] = [(true, false), (true, true), (false, true), (false, false)];
+
+    #[test]
+    fn soft_qei_counts() {
+        let qei = SoftQei::new();
+        for _ in 0..3 {
+            for &(a, b) in FORWARD.iter() {
+                qei.update(a, b);
+            }
+        }
+        assert_eq!(qei.count(), 12);
+        assert_eq!(qei.direction(), Direction::Upcounting);
+
+        for &(a, b) in FORWARD.iter().rev().skip(1) {
+            qei.update(a, b);
+        }
+        assert_eq!(qei.count(), 9);
+        assert_eq!(qei.direction(), Direction::Downcounting);
+
+        // Les deux voies changent : un front a été raté
+        qei.update(false, true);
+        assert_eq!(qei.count(), 9);
+        assert_eq!(qei.missed_edges(), 1);
+
+        // Un rebond sans changement d'état ne compte pas
+        qei.update(false, true);
+        assert_eq!(qei.count(), 9);
+    }
+
+    #[test]
+    fn soft_qei_with_manager() {
+        let qei = SoftQei::new();
+        let mut manager = QeiManager::new(&qei);
+        for &(a, b) in FORWARD.iter().rev().cycle().skip(1).take(8) {
+            qei.update(a, b);
+        }
+        manager.sample_unwrap();
+        assert_eq!(manager.count(), -8);
+    }
+}
@@ -0,0 +1,84 @@
+//! Télémétrie des grandeurs internes du PID, décimée pour ne pas saturer la liaison.
+
+use super::motor::Command;
+use super::pid::Terms;
+use crate::transmission::navigation::{PidTelemetryFrame, PidTerms};
+
+#[allow(unused_imports)]
+use libm::F32Ext;
+
+/// Convertit en dixièmes, en saturant
+fn tenths(value: f32) -> i16 {
+    (value * 10.0)
+        .round()
+        .max(f32::from(i16::min_value()))
+        .min(f32::from(i16::max_value())) as i16
+}
+
+impl From<Terms> for PidTerms {
+    fn from(terms: Terms) -> Self {
+        PidTerms {
+            goal: tenths(terms.goal),
+            speed: tenths(terms.goal_speed),
+            error: tenths(terms.error),
+            p: tenths(terms.p),
+            i: tenths(terms.i),
+            d: tenths(terms.d),
+            out: tenths(terms.output),
+        }
+    }
+}
+
+fn signed(command: Command) -> i32 {
+    match command {
+        Command::Front(value) => i32::from(value),
+        Command::Back(value) => -i32::from(value),
+    }
+}
+
+/// Garde une trame de télémétrie toutes les `rate` mises à jour du PID, jusqu'à ce qu'elle soit
+/// envoyée
+#[derive(Debug, Default, Copy, Clone)]
+pub(crate) struct Telemetry {
+    /// 0 si la télémétrie est désactivée
+    rate: u16,
+    /// Mises à jour depuis la dernière trame
+    skipped: u16,
+    samples: u16,
+    pending: Option<PidTelemetryFrame>,
+}
+
+impl Telemetry {
+    pub(crate) fn new() -> Self {
+        Default::default()
+    }
+
+    pub(crate) fn set_rate(&mut self, rate: u16) {
+        self.rate = rate;
+        self.skipped = 0;
+        self.pending = None;
+    }
+
+    /// Compte une mise à jour du PID, et prépare une trame si c'est la `rate`-ième depuis la
+    /// dernière trame
+    pub(crate) fn record(&mut self, dt: f32, terms: (Terms, Terms), command: (Command, Command)) {
+        self.samples = self.samples.wrapping_add(1);
+        self.skipped = self.skipped.saturating_add(1);
+        if self.rate == 0 || self.skipped < self.rate {
+            return;
+        }
+        self.skipped = 0;
+        self.pending = Some(PidTelemetryFrame {
+            sample: self.samples,
+            dt: (dt * 100.0).round() as u16,
+            lin: terms.0.into(),
+            ang: terms.1.into(),
+            left: signed(command.0),
+            right: signed(command.1),
+        });
+    }
+
+    pub(crate) fn take(&mut self) -> Option<PidTelemetryFrame> {
+        self.pending.take()
+    }
+}
@@ -0,0 +1,140 @@
+//! Zones de la table où la vitesse du robot est limitée.
+//!
+//! Près des bordures ou des éléments fragiles, le robot doit ralentir quelle que soit la
+//! commande donnée par la stratégie. Les zones sont enregistrées une fois pour toutes au début du
+//! match, puis le [`Controller`](super::Controller) limite sa commande automatiquement selon la
+//! position donnée par l'odométrie :
+//!
+//! ```ignore
+//! // Ralentir à moins de 150 mm de la bordure x = 0
+//! pid.add_speed_zone(SpeedZone::new(
+//!     Coord { x: MilliMeter(0), y: MilliMeter(0) },
+//!     Coord { x: MilliMeter(150), y: MilliMeter(2000) },
+//!     40,
+//! ))?;
+//! ```
+
+use super::Coord;
+use crate::units::MilliMeter;
+use heapless::consts::U8;
+use heapless::Vec;
+
+/// Nombre maximal de zones enregistrées
+pub const MAX_SPEED_ZONES: usize = 8;
+
+/// Un rectangle de la table, aux côtés parallèles aux axes, où la commande des moteurs est
+/// limitée
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SpeedZone {
+    /// Le coin aux plus petites coordonnées
+    pub min: Coord,
+    /// Le coin aux plus grandes coordonnées
+    pub max: Coord,
+    /// La commande maximale des moteurs dans la zone, comme `max_output` dans les
+    /// [`PIDParameters`](super::PIDParameters)
+    pub max_output: u16,
+}
+
+impl SpeedZone {
+    /// Crée la zone entre deux coins opposés `a` et `b`, donnés dans n'importe quel ordre
+    pub fn new(a: Coord, b: Coord, max_output: u16) -> Self {
+        SpeedZone {
+            min: Coord {
+                x: MilliMeter(a.x.0.min(b.x.0)),
+                y: MilliMeter(a.y.0.min(b.y.0)),
+            },
+            max: Coord {
+                x: MilliMeter(a.x.0.max(b.x.0)),
+                y: MilliMeter(a.y.0.max(b.y.0)),
+            },
+            max_output,
+        }
+    }
+
+    /// Renvoie vrai si le point `position` est dans la zone, bords compris. Seul le centre du
+    /// robot est testé : la zone doit être agrandie de la taille du robot si besoin.
+    pub fn contains(&self, position: Coord) -> bool {
+        self.min.x <= position.x
+            && position.x <= self.max.x
+            && self.min.y <= position.y
+            && position.y <= self.max.y
+    }
+}
+
+/// Les zones de vitesse limitée de la table
+#[derive(Debug, Default, Clone)]
+pub struct SpeedZones {
+    zones: Vec<SpeedZone, U8>,
+}
+
+impl SpeedZones {
+    /// Crée une carte sans zone
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Ajoute une zone, ou la renvoie s'il y en a déjà [`MAX_SPEED_ZONES`]
+    pub fn add(&mut self, zone: SpeedZone) -> Result<(), SpeedZone> {
+        self.zones.push(zone)
+    }
+
+    /// Supprime toutes les zones
+    pub fn clear(&mut self) {
+        self.zones.clear();
+    }
+
+    /// Renvoie les zones enregistrées
+    pub fn iter(&self) -> impl Iterator<Item = &SpeedZone> {
+        self.zones.iter()
+    }
+
+    /// Renvoie la commande maximale la plus basse des zones qui contiennent `position`, ou
+    /// `None` si aucune ne la contient
+    pub fn cap_at(&self, position: Coord) -> Option<u16> {
+        self.zones
+            .iter()
+            .filter(|zone| zone.contains(position))
+            .map(|zone| zone.max_output)
+            .min()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{SpeedZone, SpeedZones, MAX_SPEED_ZONES};
+    use crate::navigation::Coord;
+    use crate::units::MilliMeter;
+
+    fn coord(x: i64, y: i64) -> Coord {
+        Coord {
+            x: MilliMeter(x),
+            y: MilliMeter(y),
+        }
+    }
+
+    #[test]
+    fn overlapping_zones() {
+        let mut zones = SpeedZones::new();
+        zones
+            .add(SpeedZone::new(coord(150, 2000), coord(0, 0), 40))
+            .unwrap();
+        zones
+            .add(SpeedZone::new(coord(0, 0), coord(500, 500), 60))
+            .unwrap();
+        assert_eq!(zones.cap_at(coord(1000, 1000)), None);
+        assert_eq!(zones.cap_at(coord(100, 1000)), Some(40));
+        assert_eq!(zones.cap_at(coord(400, 400)), Some(60));
+        assert_eq!(zones.cap_at(coord(150, 500)), Some(40));
+
+        for _ in 2..MAX_SPEED_ZONES {
+            zones
+                .add(SpeedZone::new(coord(0, 0), coord(1, 1), 10))
+                .unwrap();
+        }
+        let extra = SpeedZone::new(coord(0, 0), coord(2, 2), 5);
+        assert_eq!(zones.add(extra), Err(extra));
+        zones.clear();
+        assert_eq!(zones.iter().count(), 0);
+    }
+}
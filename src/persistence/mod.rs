@@ -0,0 +1,303 @@
+//! Sauvegarde des paramètres réglés dans une mémoire non volatile (flash, EEPROM).
+//!
+//! Chaque type [`Persistent`] est enregistré dans son propre bloc d'un [`ParamStore`], sous la
+//! forme :
+//!
+//! ```text
+//! +---------+--------------+----------------+-----------------+
+//! | version | taille (LE)  | données (JSON) | CRC-16 (LE)     |
+//! | 1 octet | 2 octets     | taille octets  | 2 octets        |
+//! +---------+--------------+----------------+-----------------+
+//! ```
+//!
+//! Le CRC couvre la version, la taille et les données. Un bloc vide, corrompu ou écrit par une
+//! autre version du type est refusé par [`load`], et la carte garde alors ses paramètres par
+//! défaut :
+//!
+//! ```ignore
+//! let params = persistence::load::<PIDParameters, _, U512>(&mut eeprom).unwrap_or(defaults);
+//! ```
+
+use crate::navigation::PIDParameters;
+use crate::profile::RobotProfile;
+use crate::transmission::servo::dynamixel::crc16;
+use crate::transmission::servo::ServoCalibrations;
+use crate::transmission::Error;
+use heapless::{ArrayLength, String, Vec};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json_core::de::from_slice;
+use serde_json_core::ser::{to_string, Error as SError};
+
+/// La taille de l'en-tête d'un bloc : version et taille des données
+pub const HEADER_SIZE: usize = 3;
+/// La taille du CRC à la fin d'un bloc
+pub const CRC_SIZE: usize = 2;
+
+/// Une mémoire non volatile découpée en blocs
+pub trait ParamStore {
+    /// L'erreur renvoyée par la mémoire
+    type Error;
+
+    /// Lit `buffer.len()` octets du bloc `block` à partir de `offset`
+    fn read(&mut self, block: u8, offset: usize, buffer: &mut [u8]) -> Result<(), Self::Error>;
+
+    /// Remplace le contenu du bloc `block` par `data`
+    fn write(&mut self, block: u8, data: &[u8]) -> Result<(), Self::Error>;
+}
+
+/// Un type qui peut être sauvegardé dans un [`ParamStore`]
+pub trait Persistent: Serialize + DeserializeOwned {
+    /// Le bloc dans lequel le type est sauvegardé
+    const BLOCK: u8;
+    /// La version du format du type, à incrémenter à chaque changement de ses champs
+    const VERSION: u8;
+}
+
+impl Persistent for PIDParameters {
+    const BLOCK: u8 = 0;
+    const VERSION: u8 = 2;
+}
+
+impl Persistent for ServoCalibrations {
+    const BLOCK: u8 = 1;
+    const VERSION: u8 = 2;
+}
+
+impl Persistent for RobotProfile {
+    const BLOCK: u8 = 2;
+    const VERSION: u8 = 3;
+}
+
+/// (Dé)sérialisation d'un `f32` par ses bits, pour le relire à l'identique, à utiliser avec
+/// `#[serde(with = "crate::persistence::f32_bits")]`
+pub(crate) mod f32_bits {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub(crate) fn serialize<S: Serializer>(value: &f32, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u32(value.to_bits())
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f32, D::Error> {
+        u32::deserialize(deserializer).map(f32::from_bits)
+    }
+}
+
+/// Les erreurs qui peuvent survenir lors de la sauvegarde ou du chargement
+#[derive(Debug)]
+pub enum PersistError<E> {
+    /// La mémoire n'a pas pu être lue ou écrite
+    Store(E),
+    /// Les données ne tiennent pas dans le buffer, ou les données lues ne sont pas un JSON
+    /// valide
+    Json(Error),
+    /// Le bloc a été écrit par une autre version du type
+    Version(u8),
+    /// La taille enregistrée dépasse le buffer, le bloc est vide ou corrompu
+    InvalidSize(u16),
+    /// Le CRC du bloc est faux
+    Checksum,
+}
+
+#[cfg(feature = "defmt")]
+impl<E: defmt::Format> defmt::Format for PersistError<E> {
+    fn format(&self, f: defmt::Formatter) {
+        match self {
+            PersistError::Store(e) => defmt::write!(f, "Store({})", e),
+            PersistError::Json(e) => defmt::write!(f, "Json({})", e),
+            PersistError::Version(version) => defmt::write!(f, "Version({})", version),
+            PersistError::InvalidSize(size) => defmt::write!(f, "InvalidSize({})", size),
+            PersistError::Checksum => defmt::write!(f, "Checksum"),
+        }
+    }
+}
+
+/// Sauvegarde `value` dans son bloc de `store`.
+///
+/// `B` est la taille du buffer utilisé : il doit contenir le JSON de `value`, l'en-tête et le CRC.
+pub fn save<T, S, B>(store: &mut S, value: &T) -> Result<(), PersistError<S::Error>>
+where
+    T: Persistent,
+    S: ParamStore,
+    B: ArrayLength<u8>,
+{
+    let json: String<B> = to_string(value).map_err(|e| PersistError::Json(e.into()))?;
+    let mut block: Vec<u8, B> = Vec::new();
+    let size = json.len() as u16;
+    let overflow = || PersistError::Json(Error::Serialization(SError::BufferFull));
+    block.push(T::VERSION).map_err(|_| overflow())?;
+    block
+        .extend_from_slice(&size.to_le_bytes())
+        .map_err(|_| overflow())?;
+    block
+        .extend_from_slice(json.as_bytes())
+        .map_err(|_| overflow())?;
+    let crc = crc16(&block);
+    block
+        .extend_from_slice(&crc.to_le_bytes())
+        .map_err(|_| overflow())?;
+    store.write(T::BLOCK, &block).map_err(PersistError::Store)
+}
+
+/// Charge la valeur sauvegardée dans le bloc de `T`.
+///
+/// `B` est la taille du buffer utilisé, qui doit être celle utilisée par [`save`].
+pub fn load<T, S, B>(store: &mut S) -> Result<T, PersistError<S::Error>>
+where
+    T: Persistent,
+    S: ParamStore,
+    B: ArrayLength<u8>,
+{
+    let mut block: Vec<u8, B> = Vec::new();
+    block
+        .resize(HEADER_SIZE, 0)
+        .map_err(|_| PersistError::InvalidSize(0))?;
+    store
+        .read(T::BLOCK, 0, &mut block)
+        .map_err(PersistError::Store)?;
+    let size = u16::from_le_bytes([block[1], block[2]]);
+    let total = HEADER_SIZE + usize::from(size) + CRC_SIZE;
+    block
+        .resize(total, 0)
+        .map_err(|_| PersistError::InvalidSize(size))?;
+    store
+        .read(T::BLOCK, HEADER_SIZE, &mut block[HEADER_SIZE..])
+        .map_err(PersistError::Store)?;
+
+    let (content, crc) = block.split_at(total - CRC_SIZE);
+    if crc16(content) != u16::from_le_bytes([crc[0], crc[1]]) {
+        return Err(PersistError::Checksum);
+    }
+    if content[0] != T::VERSION {
+        return Err(PersistError::Version(content[0]));
+    }
+    from_slice(&content[HEADER_SIZE..]).map_err(|e| PersistError::Json(e.into()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{load, save, ParamStore, PersistError, Persistent};
+    use crate::navigation::PIDParameters;
+    use crate::profile::{RobotGeometry, RobotProfile};
+    use crate::transmission::servo::{ServoCalibration, ServoCalibrations};
+    use crate::transmission::Error;
+    use heapless::consts::{U1024, U32};
+    use std::vec::Vec;
+
+    #[derive(Debug, Default)]
+    struct MemoryStore {
+        blocks: [Vec<u8>; 3],
+    }
+
+    impl ParamStore for MemoryStore {
+        type Error = ();
+
+        fn read(&mut self, block: u8, offset: usize, buffer: &mut [u8]) -> Result<(), ()> {
+            let data = &self.blocks[usize::from(block)];
+            for (index, byte) in buffer.iter_mut().enumerate() {
+                // Une mémoire effacée contient des 0xFF
+                *byte = data.get(offset + index).cloned().unwrap_or(0xFF);
+            }
+            Ok(())
+        }
+
+        fn write(&mut self, block: u8, data: &[u8]) -> Result<(), ()> {
+            self.blocks[usize::from(block)] = data.to_vec();
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn save_and_load() {
+        let mut store = MemoryStore::default();
+        let params = PIDParameters {
+            coder_radius: 31.5,
+            pos_kp: 0.25,
+            stall_window: 150,
+            cross_coupling: 2.5,
+            ..Default::default()
+        };
+        save::<_, _, U1024>(&mut store, &params).unwrap();
+        let loaded: PIDParameters = load::<_, _, U1024>(&mut store).unwrap();
+        assert_eq!(loaded.coder_radius, params.coder_radius);
+        assert_eq!(loaded.pos_kp, params.pos_kp);
+        assert_eq!(loaded.stall_window, params.stall_window);
+        assert_eq!(loaded.cross_coupling, params.cross_coupling);
+
+        let mut calibrations = ServoCalibrations::default();
+        calibrations.servos[0] = ServoCalibration {
+            id: 4,
+            offset: 12,
+            min_position: 0,
+            max_position: 1023,
+            inverted: true,
+        };
+        save::<_, _, U1024>(&mut store, &calibrations).unwrap();
+        assert_eq!(
+            load::<ServoCalibrations, _, U1024>(&mut store).unwrap(),
+            calibrations
+        );
+        assert_eq!(store.blocks[0][0], PIDParameters::VERSION);
+
+        let profile = RobotProfile {
+            pid: Some(PIDParameters {
+                coder_radius: 31.5,
+                ..Default::default()
+            }),
+            geometry: Some(RobotGeometry {
+                width: 250,
+                length: 280,
+                front_offset: 120,
+            }),
+            ..RobotProfile::secondary()
+        };
+        save::<_, _, U1024>(&mut store, &profile).unwrap();
+        let loaded: RobotProfile = load::<_, _, U1024>(&mut store).unwrap();
+        assert_eq!(loaded.subnet, profile.subnet);
+        assert_eq!(loaded.modules, profile.modules);
+        assert_eq!(loaded.geometry, profile.geometry);
+        assert_eq!(loaded.pid.unwrap().coder_radius, 31.5);
+    }
+
+    #[test]
+    fn load_rejects_invalid_blocks() {
+        let mut store = MemoryStore::default();
+        match load::<ServoCalibrations, _, U1024>(&mut store) {
+            Err(PersistError::InvalidSize(0xFFFF)) => {}
+            other => panic!("{:?}", other),
+        }
+
+        save::<_, _, U1024>(&mut store, &ServoCalibrations::default()).unwrap();
+        store.blocks[1][5] ^= 0x01;
+        match load::<ServoCalibrations, _, U1024>(&mut store) {
+            Err(PersistError::Checksum) => {}
+            other => panic!("{:?}", other),
+        }
+
+        save::<_, _, U1024>(&mut store, &ServoCalibrations::default()).unwrap();
+        store.blocks[1][0] = ServoCalibrations::VERSION + 1;
+        let size = store.blocks[1].len();
+        let crc = super::crc16(&store.blocks[1][..size - 2]).to_le_bytes();
+        store.blocks[1][size - 2..].copy_from_slice(&crc);
+        match load::<ServoCalibrations, _, U1024>(&mut store) {
+            Err(PersistError::Version(version)) => {
+                assert_eq!(version, ServoCalibrations::VERSION + 1)
+            }
+            other => panic!("{:?}", other),
+        }
+
+        let mut block = vec![ServoCalibrations::VERSION, 1, 0, b'{'];
+        let crc = super::crc16(&block).to_le_bytes();
+        block.extend_from_slice(&crc);
+        store.blocks[1] = block;
+        match load::<ServoCalibrations, _, U1024>(&mut store) {
+            Err(PersistError::Json(Error::Deserialization(_))) => {}
+            other => panic!("{:?}", other),
+        }
+
+        match save::<_, _, U32>(&mut store, &ServoCalibrations::default()) {
+            Err(PersistError::Json(Error::Serialization(_))) => {}
+            other => panic!("{:?}", other),
+        }
+    }
+}
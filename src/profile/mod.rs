@@ -0,0 +1,140 @@
+//! Configuration propre à chaque robot, choisie au démarrage de la carte.
+//!
+//! Un [`RobotProfile`] regroupe tout ce qui change d'un robot à l'autre : le sous-réseau, l'IP
+//! de l'ordinateur embarqué, les cartes présentes, les paramètres par défaut du PID et les
+//! dimensions du robot. Un même firmware peut donc tourner sur les deux robots : il lit son
+//! profil dans la mémoire non volatile (voir [`crate::persistence`]) et garde celui fourni par
+//! les features `primary` ou `secondary` si aucun n'y est enregistré :
+//!
+//! ```ignore
+//! let profile = persistence::load::<RobotProfile, _, U1024>(&mut eeprom).unwrap_or_default();
+//! PortMap::default().validate(profile.modules())?;
+//! eth::try_init_eth_with(&profile, &mut w5500, &mut spi, mac, ip)?;
+//! let pid = RealWorldPid::new(qei_left, qei_right, &profile.pid.unwrap_or_default());
+//! ```
+//!
+//! Les profils intégrés ne reprennent que ce que le firmware fixait déjà pour chaque robot (le
+//! sous-réseau, l'ordinateur embarqué et les cartes) : les paramètres du PID et les dimensions
+//! propres à chaque robot sont à enregistrer dans son profil sauvegardé.
+
+use crate::navigation::PIDParameters;
+use crate::transmission::id::{ID_COLOR, ID_IO, ID_NAVIGATION, ID_PNEUMATIC, ID_SERVO};
+
+/// Nombre maximal de cartes dans un [`RobotProfile`]
+pub const MAX_MODULES: usize = 8;
+
+/// Les cartes des deux robots
+const BOARDS: [u16; 5] = [ID_NAVIGATION, ID_SERVO, ID_IO, ID_PNEUMATIC, ID_COLOR];
+
+/// Les dimensions du robot en mm
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct RobotGeometry {
+    /// La largeur du robot, perpendiculairement à l'axe des roues codeuses
+    pub width: u16,
+    /// La longueur du robot, dans l'axe des roues codeuses
+    pub length: u16,
+    /// La distance entre le centre des roues codeuses et l'avant du robot
+    pub front_offset: u16,
+}
+
+/// La configuration d'un robot
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+pub struct RobotProfile {
+    /// Le numéro du robot, 1 pour le robot principal
+    pub robot: u8,
+    /// Le sous-réseau du robot (192.168.x.0)
+    pub subnet: u8,
+    /// Le dernier octet de l'IP de l'ordinateur embarqué (192.168.x.y)
+    pub main_computer: u8,
+    /// Les identifiants des cartes du robot (voir [`crate::transmission::id`]), 0 pour une
+    /// place libre
+    pub modules: [u16; MAX_MODULES],
+    /// Les paramètres du PID à utiliser si aucun n'a été sauvegardé, `None` pour
+    /// `PIDParameters::default()`
+    pub pid: Option<PIDParameters>,
+    /// Les dimensions du robot, `None` si elles n'ont pas été renseignées
+    pub geometry: Option<RobotGeometry>,
+}
+
+impl RobotProfile {
+    fn with_boards(robot: u8, subnet: u8) -> Self {
+        let mut modules = [0; MAX_MODULES];
+        modules[..BOARDS.len()].copy_from_slice(&BOARDS);
+        RobotProfile {
+            robot,
+            subnet,
+            main_computer: 254,
+            modules,
+            pid: None,
+            geometry: None,
+        }
+    }
+
+    /// Le profil du robot principal, sur le sous-réseau 192.168.1.0
+    pub fn primary() -> Self {
+        RobotProfile::with_boards(1, 1)
+    }
+
+    /// Le profil du robot secondaire, sur le sous-réseau 192.168.2.0
+    pub fn secondary() -> Self {
+        RobotProfile::with_boards(2, 2)
+    }
+
+    /// Renvoie les identifiants des cartes du robot
+    pub fn modules(&self) -> &[u16] {
+        let count = self.modules.iter().take_while(|&&id| id != 0).count();
+        &self.modules[..count]
+    }
+
+    /// Renvoie vrai si la carte `id` fait partie du robot
+    pub fn has_module(&self, id: u16) -> bool {
+        id != 0 && self.modules().contains(&id)
+    }
+}
+
+impl Default for RobotProfile {
+    /// Le profil choisi par les features : celui du robot secondaire avec la feature
+    /// `secondary` seule, celui du robot principal sinon
+    fn default() -> Self {
+        if cfg!(all(feature = "secondary", not(feature = "primary"))) {
+            RobotProfile::secondary()
+        } else {
+            RobotProfile::primary()
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::RobotProfile;
+    use crate::transmission::id::{ID_ARM, ID_NAVIGATION, ID_SERVO};
+    use crate::transmission::ports::PortMap;
+
+    #[test]
+    fn default_profile() {
+        let profile = RobotProfile::default();
+        if cfg!(all(feature = "secondary", not(feature = "primary"))) {
+            assert_eq!(profile.subnet, 2);
+        } else {
+            assert_eq!(profile.subnet, 1);
+        }
+        assert_ne!(
+            RobotProfile::primary().subnet,
+            RobotProfile::secondary().subnet
+        );
+    }
+
+    #[test]
+    fn profile_modules() {
+        let profile = RobotProfile::primary();
+        assert_eq!(profile.modules().len(), 5);
+        assert_eq!(&profile.modules()[..2], &[ID_NAVIGATION, ID_SERVO]);
+        assert!(profile.has_module(ID_SERVO));
+        assert!(!profile.has_module(ID_ARM));
+        assert!(!profile.has_module(0));
+        assert!(PortMap::default().validate(profile.modules()).is_ok());
+        assert!(profile.pid.is_none());
+        assert!(profile.geometry.is_none());
+    }
+}
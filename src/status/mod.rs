@@ -0,0 +1,284 @@
+//! LED d'état commune à toutes les cartes.
+//!
+//! La [`Heartbeat`] fait clignoter une LED selon l'état de la carte : lien avec l'informatique,
+//! asservissement, blocage, match en cours ou arrêt d'urgence. Elle est mise à jour avec les
+//! mêmes événements que le reste de la carte, puis appelée régulièrement dans la boucle
+//! principale :
+//!
+//! ```ignore
+//! heartbeat.on_event(event.event);
+//! heartbeat.set_link(!watchdog.is_expired(&clock));
+//! heartbeat.set_blocked(blocking.is_blocked());
+//! heartbeat.update(&clock);
+//! ```
+
+use crate::time::{Clock, Instant};
+use crate::transmission::event::MatchEvent;
+use embedded_hal::digital::OutputPin;
+
+/// Les motifs de clignotement, du plus prioritaire au moins prioritaire
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Pattern {
+    /// Arrêt d'urgence : clignotement rapide
+    Emergency,
+    /// Le robot est bloqué : double flash
+    Blocked,
+    /// Pas de lien avec l'informatique : clignotement lent
+    LinkDown,
+    /// Le match est en cours : allumée en continu
+    MatchRunning,
+    /// L'asservissement est actif : battement rapide
+    AsservEnabled,
+    /// La carte attend : battement lent
+    Idle,
+}
+
+impl Pattern {
+    /// Les durées en millisecondes des phases du motif, alternativement allumée et éteinte en
+    /// commençant par allumée
+    pub fn phases(self) -> &'static [u32] {
+        match self {
+            Pattern::Emergency => &[100, 100],
+            Pattern::Blocked => &[100, 100, 100, 700],
+            Pattern::LinkDown => &[500, 500],
+            Pattern::MatchRunning => &[1000, 0],
+            Pattern::AsservEnabled => &[100, 400],
+            Pattern::Idle => &[100, 900],
+        }
+    }
+
+    /// Renvoie vrai si la LED est allumée `elapsed` ms après le début du motif
+    pub fn is_lit(self, elapsed: u32) -> bool {
+        let phases = self.phases();
+        let period: u32 = phases.iter().sum();
+        let mut t = elapsed % period;
+        for (index, &duration) in phases.iter().enumerate() {
+            if t < duration {
+                return index % 2 == 0;
+            }
+            t -= duration;
+        }
+        false
+    }
+}
+
+/// L'état de la carte affiché par la LED
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SystemState {
+    /// Le lien avec l'informatique est établi
+    pub link_alive: bool,
+    /// L'asservissement est actif
+    pub asserv_enabled: bool,
+    /// Le robot est bloqué
+    pub blocked: bool,
+    /// Le match est en cours
+    pub match_running: bool,
+    /// L'arrêt d'urgence est enclenché
+    pub emergency: bool,
+}
+
+impl SystemState {
+    /// Renvoie le motif correspondant à l'état le plus prioritaire
+    pub fn pattern(&self) -> Pattern {
+        if self.emergency {
+            Pattern::Emergency
+        } else if self.blocked {
+            Pattern::Blocked
+        } else if !self.link_alive {
+            Pattern::LinkDown
+        } else if self.match_running {
+            Pattern::MatchRunning
+        } else if self.asserv_enabled {
+            Pattern::AsservEnabled
+        } else {
+            Pattern::Idle
+        }
+    }
+
+    /// Met à jour l'état avec un événement de match
+    pub fn on_event(&mut self, event: MatchEvent) {
+        match event {
+            MatchEvent::EmergencyStop => self.emergency = true,
+            MatchEvent::EmergencyRelease => self.emergency = false,
+            MatchEvent::MatchStart => self.match_running = true,
+            MatchEvent::MatchEnd => self.match_running = false,
+        }
+    }
+}
+
+/// Une LED d'état pilotée par une broche de sortie
+pub struct Heartbeat<P: OutputPin> {
+    pin: P,
+    active_low: bool,
+    state: SystemState,
+    pattern: Pattern,
+    start: Option<Instant>,
+    lit: Option<bool>,
+}
+
+impl<P: OutputPin> core::fmt::Debug for Heartbeat<P> {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        write!(
+            f,
+            "Heartbeat {{ state: {:?}, pattern: {:?} }}",
+            self.state, self.pattern
+        )
+    }
+}
+
+impl<P: OutputPin> Heartbeat<P> {
+    /// Crée une LED d'état allumée quand `pin` est à l'état haut
+    pub fn new(pin: P) -> Self {
+        let state = SystemState::default();
+        Heartbeat {
+            pin,
+            active_low: false,
+            state,
+            pattern: state.pattern(),
+            start: None,
+            lit: None,
+        }
+    }
+
+    /// La LED est allumée quand la broche est à l'état bas
+    pub fn active_low(mut self) -> Self {
+        self.active_low = true;
+        self
+    }
+
+    /// Indique si le lien avec l'informatique est établi
+    pub fn set_link(&mut self, alive: bool) {
+        self.state.link_alive = alive;
+    }
+
+    /// Indique si l'asservissement est actif
+    pub fn set_asserv(&mut self, enabled: bool) {
+        self.state.asserv_enabled = enabled;
+    }
+
+    /// Indique si le robot est bloqué
+    pub fn set_blocked(&mut self, blocked: bool) {
+        self.state.blocked = blocked;
+    }
+
+    /// Met à jour l'état avec un événement de match
+    pub fn on_event(&mut self, event: MatchEvent) {
+        self.state.on_event(event);
+    }
+
+    /// Renvoie l'état affiché
+    pub fn state(&self) -> SystemState {
+        self.state
+    }
+
+    /// Renvoie le motif affiché
+    pub fn pattern(&self) -> Pattern {
+        self.pattern
+    }
+
+    /// Allume ou éteint la LED selon l'instant donné par `clock`. Un changement de motif le
+    /// reprend à son début, pour que le premier flash ne soit pas perdu.
+    pub fn update<C: Clock>(&mut self, clock: &C) {
+        let now = clock.now();
+        let pattern = self.state.pattern();
+        let start = match self.start {
+            Some(start) if pattern == self.pattern => start,
+            _ => {
+                self.pattern = pattern;
+                self.start = Some(now);
+                now
+            }
+        };
+        let lit = pattern.is_lit(now.duration_since(start));
+        if self.lit != Some(lit) {
+            if lit != self.active_low {
+                self.pin.set_high();
+            } else {
+                self.pin.set_low();
+            }
+            self.lit = Some(lit);
+        }
+    }
+
+    /// Libère la broche
+    pub fn release(self) -> P {
+        self.pin
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Heartbeat, Pattern};
+    use crate::time::Instant;
+    use crate::transmission::event::MatchEvent;
+    use core::cell::Cell;
+    use embedded_hal::digital::OutputPin;
+
+    #[derive(Debug, Default)]
+    struct Led {
+        high: bool,
+        writes: usize,
+    }
+
+    impl OutputPin for Led {
+        fn set_low(&mut self) {
+            self.high = false;
+            self.writes += 1;
+        }
+
+        fn set_high(&mut self) {
+            self.high = true;
+            self.writes += 1;
+        }
+    }
+
+    #[test]
+    fn patterns() {
+        assert!(Pattern::Blocked.is_lit(0));
+        assert!(!Pattern::Blocked.is_lit(150));
+        assert!(Pattern::Blocked.is_lit(250));
+        assert!(!Pattern::Blocked.is_lit(900));
+        assert!(Pattern::Blocked.is_lit(1050));
+        assert!(Pattern::MatchRunning.is_lit(999));
+        assert!(Pattern::MatchRunning.is_lit(1000));
+    }
+
+    #[test]
+    fn heartbeat_follows_state() {
+        let now = Cell::new(Instant::from_millis(0));
+        let clock = || now.get();
+        let mut heartbeat = Heartbeat::new(Led::default());
+        heartbeat.update(&clock);
+        assert_eq!(heartbeat.pattern(), Pattern::LinkDown);
+
+        heartbeat.set_link(true);
+        now.set(now.get() + 200);
+        heartbeat.update(&clock);
+        assert_eq!(heartbeat.pattern(), Pattern::Idle);
+        assert!(heartbeat.pin.high);
+        now.set(now.get() + 150);
+        heartbeat.update(&clock);
+        assert!(!heartbeat.pin.high);
+        heartbeat.update(&clock);
+        assert_eq!(heartbeat.pin.writes, 2);
+
+        heartbeat.set_asserv(true);
+        heartbeat.on_event(MatchEvent::MatchStart);
+        heartbeat.update(&clock);
+        assert_eq!(heartbeat.pattern(), Pattern::MatchRunning);
+        heartbeat.on_event(MatchEvent::EmergencyStop);
+        heartbeat.update(&clock);
+        assert_eq!(heartbeat.pattern(), Pattern::Emergency);
+        heartbeat.on_event(MatchEvent::EmergencyRelease);
+        heartbeat.on_event(MatchEvent::MatchEnd);
+        heartbeat.update(&clock);
+        assert_eq!(heartbeat.pattern(), Pattern::AsservEnabled);
+
+        let mut inverted = Heartbeat::new(Led::default()).active_low();
+        inverted.set_link(true);
+        inverted.update(&clock);
+        assert!(!inverted.release().high);
+    }
+}
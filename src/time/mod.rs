@@ -0,0 +1,136 @@
+//! Horloge monotone à la milliseconde utilisée dans toute la librairie.
+//!
+//! Plutôt que de supposer que l'appelant est appelé exactement toutes les `te` ms, les modules
+//! qui dépendent du temps lisent un [`Clock`]. Une horloge peut être n'importe quelle fonction
+//! renvoyant un [`Instant`], par exemple un compteur incrémenté par le `SysTick` :
+//!
+//! ```ignore
+//! let clock = || Instant::from_millis(TICKS.load(Ordering::Relaxed));
+//! pid.update_with(&clock);
+//! ```
+
+use core::fmt::{Display, Formatter, Result};
+use core::ops::Add;
+
+/// Un instant en millisecondes depuis le démarrage de la carte.
+///
+/// Le compteur déborde au bout d'environ 49 jours : les durées sont calculées modulo 2^32 et
+/// restent justes tant qu'elles sont inférieures à cette période.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Instant(u32);
+
+impl Instant {
+    /// Crée un instant à partir d'un nombre de millisecondes
+    pub fn from_millis(millis: u32) -> Self {
+        Instant(millis)
+    }
+
+    /// Renvoie le nombre de millisecondes de l'instant
+    pub fn as_millis(self) -> u32 {
+        self.0
+    }
+
+    /// Renvoie le temps écoulé depuis `earlier`, en millisecondes
+    pub fn duration_since(self, earlier: Instant) -> u32 {
+        self.0.wrapping_sub(earlier.0)
+    }
+}
+
+impl Add<u32> for Instant {
+    type Output = Instant;
+
+    fn add(self, millis: u32) -> Instant {
+        Instant(self.0.wrapping_add(millis))
+    }
+}
+
+impl Display for Instant {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        write!(f, "{} ms", self.0)
+    }
+}
+
+/// Une horloge monotone à la milliseconde
+pub trait Clock {
+    /// Renvoie l'instant actuel
+    fn now(&self) -> Instant;
+}
+
+impl<F: Fn() -> Instant> Clock for F {
+    fn now(&self) -> Instant {
+        self()
+    }
+}
+
+/// Chien de garde : expire si il n'a pas été nourri depuis plus de `timeout` ms.
+///
+/// Il sert par exemple à arrêter le robot quand l'informatique ne communique plus (nourri à
+/// chaque trame reçue) ou quand aucun nouvel ordre n'a été reçu depuis trop longtemps.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Watchdog {
+    timeout: u32,
+    last_feed: Option<Instant>,
+}
+
+impl Watchdog {
+    /// Crée un chien de garde de délai `timeout` ms, expiré tant qu'il n'a jamais été nourri
+    pub fn new(timeout: u32) -> Self {
+        Watchdog {
+            timeout,
+            last_feed: None,
+        }
+    }
+
+    /// Nourrit le chien de garde à l'instant donné par `clock`
+    pub fn feed<C: Clock>(&mut self, clock: &C) {
+        self.last_feed = Some(clock.now());
+    }
+
+    /// Renvoie vrai si le chien de garde n'a pas été nourri depuis plus de `timeout` ms
+    pub fn is_expired<C: Clock>(&self, clock: &C) -> bool {
+        match self.last_feed {
+            Some(last) => clock.now().duration_since(last) > self.timeout,
+            None => true,
+        }
+    }
+
+    /// Renvoie le dernier instant auquel le chien de garde a été nourri
+    pub fn last_feed(&self) -> Option<Instant> {
+        self.last_feed
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Clock, Instant, Watchdog};
+    use core::cell::Cell;
+
+    #[test]
+    fn instant_wraps() {
+        let start = Instant::from_millis(u32::max_value() - 5);
+        let later = start + 10;
+        assert_eq!(later.as_millis(), 4);
+        assert_eq!(later.duration_since(start), 10);
+        assert_eq!(format!("{}", later), "4 ms");
+    }
+
+    #[test]
+    fn watchdog_expires() {
+        let now = Cell::new(Instant::from_millis(1000));
+        let clock = || now.get();
+        assert_eq!(clock.now(), Instant::from_millis(1000));
+
+        let mut watchdog = Watchdog::new(100);
+        assert!(watchdog.is_expired(&clock));
+        watchdog.feed(&clock);
+        now.set(now.get() + 100);
+        assert!(!watchdog.is_expired(&clock));
+        now.set(now.get() + 1);
+        assert!(watchdog.is_expired(&clock));
+        watchdog.feed(&clock);
+        assert!(!watchdog.is_expired(&clock));
+        assert_eq!(watchdog.last_feed(), Some(Instant::from_millis(1101)));
+    }
+}
@@ -0,0 +1,225 @@
+//! Annonce des modules de chaque carte, envoyée à son démarrage.
+//!
+//! Au démarrage, chaque carte envoie une [`AnnounceFrame`] listant les identifiants des modules
+//! qu'elle gère (voir [`crate::transmission::id`]), le hash git de son firmware et la version du
+//! protocole. L'informatique les donne à une [`Discovery`] pour savoir quelle carte héberge
+//! chaque module, au lieu de le coder en dur :
+//!
+//! ```ignore
+//! let announce = AnnounceFrame::new(ip, &[ID_SERVO, ID_IO], short_hash(env!("GIT_HASH")));
+//! channel.send(&announce)?;
+//! ```
+
+use crate::transmission::Jsonizable;
+use heapless::consts::U32;
+use heapless::Vec;
+
+/// La version du protocole, à incrémenter à chaque changement incompatible d'une trame
+pub const PROTOCOL_VERSION: u16 = 1;
+
+/// Nombre maximal de modules annoncés par une carte
+pub const MAX_MODULES: usize = 8;
+
+/// Trame annonçant les modules gérés par une carte
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Deserialize, Serialize, Jsonizable)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct AnnounceFrame {
+    /// Le dernier octet de l'IP de la carte
+    pub board: u8,
+    /// Les identifiants des modules gérés par la carte, 0 pour un emplacement vide
+    pub modules: [u16; MAX_MODULES],
+    /// Les 32 premiers bits du hash git du firmware
+    pub git_hash: u32,
+    /// La version du protocole utilisée par la carte (voir [`PROTOCOL_VERSION`])
+    pub protocol: u16,
+}
+
+impl AnnounceFrame {
+    /// Crée l'annonce de la carte `board` qui gère les modules `modules` (au plus
+    /// [`MAX_MODULES`]) avec le firmware `git_hash`
+    pub fn new(board: u8, modules: &[u16], git_hash: u32) -> Self {
+        let mut frame = AnnounceFrame {
+            board,
+            git_hash,
+            protocol: PROTOCOL_VERSION,
+            ..Default::default()
+        };
+        for (slot, &module) in frame.modules.iter_mut().zip(modules) {
+            *slot = module;
+        }
+        frame
+    }
+
+    /// Renvoie les identifiants des modules annoncés
+    pub fn modules(&self) -> impl Iterator<Item = u16> + '_ {
+        self.modules.iter().cloned().filter(|&module| module != 0)
+    }
+
+    /// Renvoie vrai si la carte utilise la même version du protocole que la librairie
+    pub fn is_compatible(&self) -> bool {
+        self.protocol == PROTOCOL_VERSION
+    }
+}
+
+/// Renvoie les 32 premiers bits d'un hash git écrit en hexadécimal, ou 0 s'il n'est pas valide
+pub fn short_hash(hash: &str) -> u32 {
+    hash.get(..8)
+        .and_then(|prefix| u32::from_str_radix(prefix, 16).ok())
+        .unwrap_or(0)
+}
+
+/// Une carte connue par la [`Discovery`]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct BoardInfo {
+    /// Le dernier octet de l'IP de la carte
+    pub board: u8,
+    /// Les 32 premiers bits du hash git du firmware
+    pub git_hash: u32,
+    /// La version du protocole utilisée par la carte
+    pub protocol: u16,
+}
+
+/// Il n'y a plus de place pour enregistrer les modules d'une annonce
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct DiscoveryFull;
+
+/// Associe chaque module à la carte qui l'a annoncé en dernier
+#[derive(Debug, Default)]
+pub struct Discovery {
+    modules: Vec<(u16, BoardInfo), U32>,
+}
+
+impl Discovery {
+    /// Crée un annuaire vide
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Enregistre l'annonce `frame` : les modules qu'elle liste sont maintenant hébergés par sa
+    /// carte, et ceux que la carte annonçait auparavant mais plus maintenant sont oubliés.
+    /// L'annuaire n'est pas modifié s'il n'a pas la place d'enregistrer l'annonce.
+    pub fn record(&mut self, frame: &AnnounceFrame) -> Result<(), DiscoveryFull> {
+        let info = BoardInfo {
+            board: frame.board,
+            git_hash: frame.git_hash,
+            protocol: frame.protocol,
+        };
+        let keep = |&(module, host): &(u16, BoardInfo)| {
+            host.board != info.board && frame.modules().all(|m| m != module)
+        };
+        let kept = self.modules.iter().filter(|entry| keep(entry)).count();
+        if kept + frame.modules().count() > self.modules.capacity() {
+            return Err(DiscoveryFull);
+        }
+        self.modules.retain(keep);
+        for module in frame.modules() {
+            self.modules
+                .push((module, info))
+                .map_err(|_| DiscoveryFull)?;
+        }
+        Ok(())
+    }
+
+    /// Renvoie la carte qui héberge le module `module`, si elle s'est annoncée
+    pub fn board_of(&self, module: u16) -> Option<BoardInfo> {
+        self.modules
+            .iter()
+            .find(|&&(known, _)| known == module)
+            .map(|&(_, info)| info)
+    }
+
+    /// Renvoie les modules hébergés par une carte dont le protocole n'est pas celui de la
+    /// librairie
+    pub fn incompatible(&self) -> impl Iterator<Item = (u16, BoardInfo)> + '_ {
+        self.modules
+            .iter()
+            .cloned()
+            .filter(|&(_, info)| info.protocol != PROTOCOL_VERSION)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        short_hash, AnnounceFrame, Discovery, DiscoveryFull, MAX_MODULES, PROTOCOL_VERSION,
+    };
+    use crate::transmission::id::{ID_IO, ID_NAVIGATION, ID_SERVO};
+    use crate::transmission::Jsonizable;
+    use heapless::consts::U256;
+    use heapless::String;
+
+    #[test]
+    fn ser_deser_announce() {
+        let frame = AnnounceFrame::new(3, &[ID_SERVO, ID_IO], short_hash("2710ceb9a1"));
+        assert_eq!(frame.git_hash, 0x2710_ceb9);
+        assert_eq!(
+            frame.modules().collect::<std::vec::Vec<_>>(),
+            [ID_SERVO, ID_IO]
+        );
+        let strd: String<U256> = frame.to_string().unwrap();
+        let data = "{\"board\":3,\"modules\":[2,4,0,0,0,0,0,0],\"git_hash\":655412921,\
+                    \"protocol\":1}";
+        assert_eq!(strd, data);
+        assert_eq!(
+            AnnounceFrame::from_json_slice(data.as_bytes()).unwrap(),
+            frame
+        );
+        assert_eq!(short_hash("dirty"), 0);
+    }
+
+    #[test]
+    fn discovery() {
+        let mut discovery = Discovery::new();
+        discovery
+            .record(&AnnounceFrame::new(3, &[ID_SERVO, ID_IO], 1))
+            .unwrap();
+        discovery
+            .record(&AnnounceFrame::new(4, &[ID_NAVIGATION], 2))
+            .unwrap();
+        assert_eq!(discovery.board_of(ID_IO).unwrap().board, 3);
+
+        // Le module IO a été déplacé sur la carte 4, la carte 3 a redémarré sans lui
+        discovery
+            .record(&AnnounceFrame::new(4, &[ID_NAVIGATION, ID_IO], 2))
+            .unwrap();
+        discovery
+            .record(&AnnounceFrame::new(3, &[ID_SERVO], 3))
+            .unwrap();
+        assert_eq!(discovery.board_of(ID_IO).unwrap().board, 4);
+        assert_eq!(discovery.board_of(ID_SERVO).unwrap().git_hash, 3);
+        assert_eq!(discovery.incompatible().count(), 0);
+
+        let mut old = AnnounceFrame::new(5, &[ID_SERVO], 4);
+        old.protocol = PROTOCOL_VERSION + 1;
+        discovery.record(&old).unwrap();
+        assert_eq!(discovery.incompatible().next().unwrap().0, ID_SERVO);
+    }
+
+    #[test]
+    fn full_discovery_unchanged() {
+        let mut discovery = Discovery::new();
+        for board in 0..4u16 {
+            let modules: std::vec::Vec<u16> = (1..=MAX_MODULES as u16)
+                .map(|i| board * MAX_MODULES as u16 + i)
+                .collect();
+            discovery
+                .record(&AnnounceFrame::new(board as u8, &modules, 1))
+                .unwrap();
+        }
+
+        assert_eq!(
+            discovery.record(&AnnounceFrame::new(4, &[1, 100], 2)),
+            Err(DiscoveryFull)
+        );
+        assert_eq!(discovery.board_of(1).unwrap().board, 0);
+        assert_eq!(discovery.board_of(25).unwrap().board, 3);
+        assert!(discovery.board_of(100).is_none());
+
+        // La carte 3 libère la place de ses anciens modules en s'annonçant à nouveau
+        discovery.record(&AnnounceFrame::new(3, &[100], 2)).unwrap();
+        assert_eq!(discovery.board_of(100).unwrap().git_hash, 2);
+        assert!(discovery.board_of(25).is_none());
+    }
+}
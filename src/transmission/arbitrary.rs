@@ -0,0 +1,304 @@
+//! Génération aléatoire de trames pour les tests par propriétés, avec la feature `arbitrary`.
+//!
+//! Les trames implémentent [`Arbitrary`] : `any::<ServoGroup>()` génère des groupes de servos
+//! quelconques, que l'on peut utiliser dans les tests [`proptest`] de la librairie comme dans
+//! ceux du code de l'informatique :
+//!
+//! ```ignore
+//! proptest! {
+//!     #[test]
+//!     fn round_trip(group in any::<ServoGroup>()) {
+//!         let json: String<U4096> = group.to_string().unwrap();
+//!         prop_assert_eq!(ServoGroup::from_json_slice(json.as_bytes()).unwrap(), group);
+//!     }
+//! }
+//! ```
+
+use crate::transmission::io::{BuzzerState, IOState, Pneumatic, TriggerState, IO};
+use crate::transmission::io::{NB_PUMPS, NB_VALVES};
+use crate::transmission::navigation::{NavigationCommand, NavigationFrame};
+use crate::transmission::servo::{BlockingMode, Color, Control, Rotation, Servo, ServoGroup};
+use crate::units::{Celsius, MilliVolt};
+use proptest::prelude::*;
+
+/// Implémente `Arbitrary` pour une énumération sans données, en choisissant une variante au
+/// hasard
+macro_rules! arbitrary_enum {
+    ($name:ident, $($variant:ident),+) => {
+        impl Arbitrary for $name {
+            type Parameters = ();
+            type Strategy = BoxedStrategy<Self>;
+
+            fn arbitrary_with(_: ()) -> Self::Strategy {
+                prop_oneof![$(Just($name::$variant)),+].boxed()
+            }
+        }
+    };
+}
+
+arbitrary_enum!(BlockingMode, Unblocking, HoldOnBlock);
+arbitrary_enum!(Rotation, CounterClockwise, Clockwise);
+arbitrary_enum!(Control, Speed, Position);
+arbitrary_enum!(Color, Black, Red, Green, Yellow, Blue, Magenta, Cyan, White);
+arbitrary_enum!(TriggerState, Triggered, Waiting);
+arbitrary_enum!(IOState, On, Off);
+arbitrary_enum!(
+    BuzzerState,
+    Rest,
+    PlayErrorSound,
+    PlaySuccessSound,
+    PlayBlockedSound,
+    PlayLowBatterySound,
+    PlayMatchEndSound,
+    PlayMelody
+);
+arbitrary_enum!(
+    NavigationCommand,
+    GoForward,
+    GoBackward,
+    TurnRelative,
+    TurnAbsolute,
+    DoNothing,
+    EmergencyStop,
+    Stop
+);
+
+impl Arbitrary for Servo {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        let command = (
+            any::<u8>(),
+            any::<u16>(),
+            any::<Control>(),
+            any::<Rotation>(),
+            any::<u16>(),
+            any::<bool>(),
+            any::<BlockingMode>(),
+            any::<Color>(),
+        );
+        let state = (
+            any::<u16>(),
+            any::<u16>(),
+            any::<u16>(),
+            any::<u8>(),
+            any::<i16>(),
+            any::<i16>(),
+            any::<i32>(),
+            any::<u8>(),
+        );
+        (command, state)
+            .prop_map(|(command, state)| {
+                let (id, known_position, control, rotation, data, blocked, mode, color) = command;
+                let (
+                    max_speed,
+                    max_acceleration,
+                    torque_limit,
+                    compliance_margin,
+                    load,
+                    temperature,
+                    voltage,
+                    error,
+                ) = state;
+                Servo {
+                    id,
+                    known_position,
+                    control,
+                    rotation,
+                    data,
+                    blocked,
+                    mode,
+                    color,
+                    max_speed,
+                    max_acceleration,
+                    torque_limit,
+                    compliance_margin,
+                    load,
+                    temperature: Celsius(temperature),
+                    voltage: MilliVolt(voltage),
+                    error,
+                }
+            })
+            .boxed()
+    }
+}
+
+impl Arbitrary for ServoGroup {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        (
+            any::<[Servo; 9]>(),
+            any::<u16>(),
+            any::<bool>(),
+            any::<bool>(),
+            any::<u16>(),
+            any::<u8>(),
+        )
+            .prop_map(
+                |(servos, mask, prepare, trigger, counter, bus)| ServoGroup {
+                    servos,
+                    mask,
+                    prepare,
+                    trigger,
+                    counter,
+                    bus,
+                },
+            )
+            .boxed()
+    }
+}
+
+impl Arbitrary for NavigationFrame {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        let state = (
+            any::<i32>(),
+            any::<i32>(),
+            any::<i32>(),
+            any::<i32>(),
+            any::<i32>(),
+            any::<bool>(),
+            any::<bool>(),
+        );
+        let settings = (
+            any::<[bool; 4]>(),
+            any::<u16>(),
+            any::<u16>(),
+            any::<u16>(),
+            any::<u16>(),
+        );
+        let command = (
+            any::<NavigationCommand>(),
+            any::<u16>(),
+            any::<u16>(),
+            any::<u16>(),
+        );
+        (state, settings, command)
+            .prop_map(|(state, settings, command)| {
+                let (x, y, angle, left_dist, right_dist, blocked, moving_done) = state;
+                let (flags, max_lin_speed, max_ang_speed, lin_accuracy, ang_accuracy) = settings;
+                let (command, args_cmd1, args_cmd2, counter) = command;
+                NavigationFrame {
+                    x,
+                    y,
+                    angle,
+                    left_dist,
+                    right_dist,
+                    blocked,
+                    moving_done,
+                    asserv_lin: flags[0],
+                    asserv_ang: flags[1],
+                    led: flags[2],
+                    reset: flags[3],
+                    max_lin_speed,
+                    max_ang_speed,
+                    lin_accuracy,
+                    ang_accuracy,
+                    command,
+                    args_cmd1,
+                    args_cmd2,
+                    counter,
+                }
+            })
+            .boxed()
+    }
+}
+
+impl Arbitrary for IO {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        (
+            any::<BuzzerState>(),
+            any::<TriggerState>(),
+            any::<[TriggerState; 6]>(),
+            any::<u16>(),
+        )
+            .prop_map(|(buzzer, tirette, limits, counter)| IO {
+                buzzer,
+                tirette,
+                limit_left_down: limits[0],
+                limit_left_middle: limits[1],
+                limit_left_high: limits[2],
+                limit_right_down: limits[3],
+                limit_right_middle: limits[4],
+                limit_right_high: limits[5],
+                counter,
+            })
+            .boxed()
+    }
+}
+
+impl Arbitrary for Pneumatic {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        (
+            any::<[IOState; NB_PUMPS]>(),
+            any::<[IOState; NB_VALVES]>(),
+            any::<[u16; NB_VALVES]>(),
+            any::<[u16; NB_PUMPS]>(),
+            any::<u16>(),
+        )
+            .prop_map(
+                |(pumps, valves, valve_pulses, pressures, counter)| Pneumatic {
+                    pumps,
+                    valves,
+                    valve_pulses,
+                    pressures,
+                    counter,
+                },
+            )
+            .boxed()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::transmission::io::{Pneumatic, IO};
+    use crate::transmission::navigation::NavigationFrame;
+    use crate::transmission::servo::{Servo, ServoGroup};
+    use crate::transmission::Jsonizable;
+    use heapless::consts::{U1024, U4096};
+    use heapless::String;
+    use proptest::prelude::*;
+
+    proptest! {
+        #[test]
+        fn servo_round_trip(servo in any::<Servo>()) {
+            let json: String<U1024> = servo.to_string().unwrap();
+            prop_assert_eq!(Servo::from_json_slice(json.as_bytes()).unwrap(), servo);
+        }
+
+        #[test]
+        fn servo_group_round_trip(group in any::<ServoGroup>()) {
+            let json: String<U4096> = group.to_string().unwrap();
+            prop_assert_eq!(ServoGroup::from_json_slice(json.as_bytes()).unwrap(), group);
+        }
+
+        #[test]
+        fn navigation_round_trip(frame in any::<NavigationFrame>()) {
+            let json: String<U1024> = frame.to_string().unwrap();
+            prop_assert_eq!(NavigationFrame::from_json_slice(json.as_bytes()).unwrap(), frame);
+        }
+
+        #[test]
+        fn io_round_trip(io in any::<IO>()) {
+            let json: String<U1024> = io.to_string().unwrap();
+            prop_assert_eq!(IO::from_json_slice(json.as_bytes()).unwrap(), io);
+        }
+
+        #[test]
+        fn pneumatic_round_trip(pneumatic in any::<Pneumatic>()) {
+            let json: String<U1024> = pneumatic.to_string().unwrap();
+            prop_assert_eq!(Pneumatic::from_json_slice(json.as_bytes()).unwrap(), pneumatic);
+        }
+    }
+}
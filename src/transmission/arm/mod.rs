@@ -0,0 +1,133 @@
+//! Représentation haut-niveau d'un bras robotisé à plusieurs articulations.
+//!
+//! Contrairement à [`crate::transmission::servo::ServoGroup`], les articulations sont regroupées
+//! par chaîne cinématique : une trame décrit un bras entier, de la base vers l'effecteur.
+
+use crate::transmission::Jsonizable;
+
+/// Nombre maximal d'articulations d'un bras
+pub const MAX_JOINTS: usize = 6;
+
+/// Etat du couple d'une articulation
+#[derive(Debug, PartialEq, Copy, Clone, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum TorqueStatus {
+    /// L'articulation est libre
+    Off,
+    /// L'articulation est asservie
+    On,
+    /// L'articulation force au delà de sa limite
+    Overload,
+}
+
+impl Default for TorqueStatus {
+    fn default() -> Self {
+        TorqueStatus::Off
+    }
+}
+
+/// Une articulation du bras
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Joint {
+    /// Identifiant de l'articulation. `id == 0` veut dire qu'il n'y a pas d'articulation.
+    pub id: u8,
+    /// Angle à atteindre en milliradians
+    pub target_angle: i16,
+    /// Vitesse maximale pour atteindre la cible en milliradians/s
+    pub speed: u16,
+    /// Angle mesuré en milliradians
+    pub present_angle: i16,
+    /// Etat du couple
+    pub torque: TorqueStatus,
+}
+
+/// Trame décrivant un bras, ses articulations étant rangées de la base vers l'effecteur
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Deserialize, Serialize, Jsonizable)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ArmFrame {
+    /// Identifiant du bras sur la carte
+    pub arm: u8,
+    /// Les articulations du bras
+    pub joints: [Joint; MAX_JOINTS],
+}
+
+impl ArmFrame {
+    /// Renvoie l'articulation d'identifiant `id`
+    pub fn joint(&self, id: u8) -> Option<&Joint> {
+        self.joints.iter().find(|j| id != 0 && j.id == id)
+    }
+
+    /// Renvoie l'articulation d'identifiant `id` pour la modifier
+    pub fn joint_mut(&mut self, id: u8) -> Option<&mut Joint> {
+        self.joints.iter_mut().find(|j| id != 0 && j.id == id)
+    }
+
+    /// Retourne vrai si toutes les articulations présentes sont à moins de `tolerance`
+    /// milliradians de leur cible
+    pub fn is_target_reached(&self, tolerance: u16) -> bool {
+        self.joints.iter().filter(|j| j.id != 0).all(|j| {
+            (i32::from(j.target_angle) - i32::from(j.present_angle)).abs() <= i32::from(tolerance)
+        })
+    }
+
+    /// Retourne vrai si une articulation est en surcharge
+    pub fn is_overloaded(&self) -> bool {
+        self.joints
+            .iter()
+            .any(|j| j.id != 0 && j.torque == TorqueStatus::Overload)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ArmFrame, Joint, TorqueStatus};
+    use crate::transmission::Jsonizable;
+    use heapless::consts::U1024;
+    use heapless::String;
+    type N = U1024;
+
+    fn arm() -> ArmFrame {
+        let mut arm = ArmFrame {
+            arm: 1,
+            ..Default::default()
+        };
+        arm.joints[0] = Joint {
+            id: 1,
+            target_angle: 1570,
+            speed: 500,
+            present_angle: 1565,
+            torque: TorqueStatus::On,
+        };
+        arm.joints[1] = Joint {
+            id: 2,
+            target_angle: -300,
+            speed: 200,
+            present_angle: -250,
+            torque: TorqueStatus::On,
+        };
+        arm
+    }
+
+    #[test]
+    fn ser_deser_arm() {
+        let arm = arm();
+        let strd: String<N> = arm.to_string().unwrap();
+        let arm2 = ArmFrame::from_json_slice(strd.as_bytes()).unwrap();
+        assert_eq!(arm, arm2);
+    }
+
+    #[test]
+    fn arm_target_reached() {
+        let mut arm = arm();
+        assert!(!arm.is_target_reached(10));
+        assert!(arm.is_target_reached(50));
+        assert!(!arm.is_overloaded());
+
+        arm.joint_mut(2).unwrap().torque = TorqueStatus::Overload;
+        assert!(arm.is_overloaded());
+        assert_eq!(arm.joint(1).unwrap().speed, 500);
+        assert!(arm.joint(0).is_none());
+        assert!(arm.joint(3).is_none());
+    }
+}
@@ -0,0 +1,110 @@
+//! Transport en boucle locale : chaque message envoyé est relu par la carte elle-même.
+
+use super::Transport;
+use core::fmt;
+use heapless::{ArrayLength, Vec};
+
+/// Les erreurs du transport en boucle locale
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum LoopbackError {
+    /// Un message attend déjà d'être relu
+    Full,
+    /// Le message ne tient pas dans le buffer
+    TooLarge,
+}
+
+impl fmt::Display for LoopbackError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoopbackError::Full => write!(f, "a message is already pending"),
+            LoopbackError::TooLarge => write!(f, "message too large for the buffer"),
+        }
+    }
+}
+
+#[cfg(any(test, feature = "std"))]
+impl std::error::Error for LoopbackError {}
+
+/// Transport qui conserve le dernier message envoyé jusqu'à ce qu'il soit relu.
+///
+/// Il n'y a de la place que pour un seul message de taille au plus `B`. Il permet de tester
+/// toute la pile de transmission sans matériel (voir [`crate::transmission::selftest`]).
+#[derive(Debug)]
+pub struct LoopbackTransport<B: ArrayLength<u8>> {
+    pending: Option<(u16, Vec<u8, B>)>,
+}
+
+impl<B: ArrayLength<u8>> LoopbackTransport<B> {
+    /// Crée un transport vide
+    pub fn new() -> Self {
+        LoopbackTransport { pending: None }
+    }
+
+    /// Renvoie vrai si un message attend d'être relu
+    pub fn is_pending(&self) -> bool {
+        self.pending.is_some()
+    }
+}
+
+impl<B: ArrayLength<u8>> Default for LoopbackTransport<B> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<B: ArrayLength<u8>> Transport for LoopbackTransport<B> {
+    type Error = LoopbackError;
+
+    fn send(&mut self, id: u16, data: &[u8]) -> Result<(), LoopbackError> {
+        if self.pending.is_some() {
+            return Err(LoopbackError::Full);
+        }
+        let message = Vec::from_slice(data).map_err(|_| LoopbackError::TooLarge)?;
+        self.pending = Some((id, message));
+        Ok(())
+    }
+
+    fn receive(&mut self, id: u16, buffer: &mut [u8]) -> Result<Option<usize>, LoopbackError> {
+        match self.pending.take() {
+            Some((pending_id, message)) if pending_id == id => {
+                if message.len() > buffer.len() {
+                    return Err(LoopbackError::TooLarge);
+                }
+                buffer[..message.len()].copy_from_slice(&message);
+                Ok(Some(message.len()))
+            }
+            other => {
+                self.pending = other;
+                Ok(None)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{LoopbackError, LoopbackTransport};
+    use crate::transmission::channel::Transport;
+    use heapless::consts::U8;
+
+    #[test]
+    fn loopback_round_trip() {
+        let mut transport: LoopbackTransport<U8> = LoopbackTransport::new();
+        let mut buffer = [0; 8];
+        assert_eq!(transport.receive(2, &mut buffer), Ok(None));
+
+        transport.send(2, b"abc").unwrap();
+        assert_eq!(transport.send(2, b"def"), Err(LoopbackError::Full));
+        assert_eq!(transport.receive(4, &mut buffer), Ok(None));
+        assert!(transport.is_pending());
+        assert_eq!(transport.receive(2, &mut buffer), Ok(Some(3)));
+        assert_eq!(&buffer[..3], b"abc");
+        assert!(!transport.is_pending());
+
+        assert_eq!(
+            transport.send(2, b"too large"),
+            Err(LoopbackError::TooLarge)
+        );
+    }
+}
@@ -0,0 +1,88 @@
+//! Authentification des messages par une clé partagée.
+//!
+//! Sur les réseaux partagés des compétitions, il arrive de recevoir des paquets égarés venant du
+//! robot d'une autre équipe. Quand une [`MacKey`] est configurée sur un canal, chaque message est
+//! suivi d'une étiquette SipHash-2-4 tronquée à `TAG_SIZE` octets, calculée sur l'identifiant de
+//! la carte et le contenu du message. Les messages dont l'étiquette est fausse sont ignorés.
+//!
+//! Ce n'est pas une protection contre un attaquant : la clé est courte et il n'y a pas de
+//! protection contre le rejeu. Il s'agit seulement d'écarter les paquets qui ne nous sont pas
+//! destinés.
+
+use core::hash::{Hasher, SipHasher};
+
+/// Taille de l'étiquette ajoutée à la fin de chaque message
+pub const TAG_SIZE: usize = 4;
+
+/// Clé partagée entre l'électronique et l'informatique d'une équipe
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct MacKey {
+    k0: u64,
+    k1: u64,
+}
+
+impl MacKey {
+    /// Crée une clé à partir de ses 16 octets
+    pub fn new(key: [u8; 16]) -> Self {
+        let mut k0 = [0; 8];
+        let mut k1 = [0; 8];
+        k0.copy_from_slice(&key[..8]);
+        k1.copy_from_slice(&key[8..]);
+        MacKey {
+            k0: u64::from_le_bytes(k0),
+            k1: u64::from_le_bytes(k1),
+        }
+    }
+
+    /// Calcule l'étiquette du message `data` destiné à la carte `id`
+    pub fn tag(&self, id: u16, data: &[u8]) -> [u8; TAG_SIZE] {
+        let mut hasher = SipHasher::new_with_keys(self.k0, self.k1);
+        hasher.write(&id.to_le_bytes());
+        hasher.write(data);
+        let mut tag = [0; TAG_SIZE];
+        tag.copy_from_slice(&hasher.finish().to_le_bytes()[..TAG_SIZE]);
+        tag
+    }
+
+    /// Vérifie l'étiquette à la fin de `message` et renvoie le contenu du message si elle est
+    /// correcte
+    pub fn verify<'a>(&self, id: u16, message: &'a [u8]) -> Option<&'a [u8]> {
+        if message.len() < TAG_SIZE {
+            return None;
+        }
+        let (data, tag) = message.split_at(message.len() - TAG_SIZE);
+        let expected = self.tag(id, data);
+        // Comparaison en temps constant
+        let diff = expected
+            .iter()
+            .zip(tag.iter())
+            .fold(0, |diff, (a, b)| diff | (a ^ b));
+        if diff == 0 {
+            Some(data)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{MacKey, TAG_SIZE};
+    use std::vec::Vec;
+
+    #[test]
+    fn tag_and_verify() {
+        let key = MacKey::new(*b"club robot insat");
+        let other = MacKey::new(*b"another team key");
+        let mut message: Vec<u8> = b"\"Blue\"".to_vec();
+        let tag = key.tag(6, &message);
+        assert_ne!(tag, other.tag(6, &message));
+        assert_ne!(tag, key.tag(7, &message));
+        message.extend_from_slice(&tag);
+
+        assert_eq!(key.verify(6, &message), Some(&b"\"Blue\""[..]));
+        assert_eq!(key.verify(7, &message), None);
+        assert_eq!(other.verify(6, &message), None);
+        assert_eq!(key.verify(6, &message[..TAG_SIZE - 1]), None);
+    }
+}
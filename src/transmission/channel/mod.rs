@@ -0,0 +1,418 @@
+//! Canal de communication typé entre l'électronique et l'informatique.
+//!
+//! Un [`Channel`] associe l'identifiant d'une carte (voir [`crate::transmission::id`]), un
+//! [`Transport`] et les buffers nécessaires à la (dé)sérialisation. Il suffit ensuite d'envoyer et
+//! de recevoir directement des structures [`Jsonizable`] :
+//!
+//! ```ignore
+//! let mut channel: Channel<ServoGroup, _, U512> = Channel::new(ID_SERVO, transport);
+//! if let Some(group) = channel.try_recv() {
+//!     // Gestion du message
+//!     channel.send(&group).unwrap();
+//! }
+//! ```
+
+#![cfg_attr(
+    not(test),
+    deny(
+        clippy::panic,
+        clippy::unwrap_used,
+        clippy::expect_used,
+        clippy::unreachable
+    )
+)]
+
+mod loopback;
+mod mac;
+#[cfg(any(test, feature = "std"))]
+mod queue;
+mod rate;
+#[cfg(any(test, feature = "std"))]
+mod script;
+mod stream;
+
+pub use self::loopback::{LoopbackError, LoopbackTransport};
+pub use self::mac::{MacKey, TAG_SIZE};
+#[cfg(any(test, feature = "std"))]
+pub use self::queue::{QueueError, QueueTransport};
+pub use self::rate::TokenBucket;
+#[cfg(any(test, feature = "std"))]
+pub use self::script::{ScriptError, ScriptedTransport, SentMessage};
+pub use self::stream::{header, Chunk, FrameDecoder, StreamError, StreamTransport};
+pub use self::stream::{CHUNK_SIZE, HEADER_SIZE, SYNC};
+
+use crate::transmission::health::{ParseFailures, TransmissionCounters};
+use crate::transmission::{Error, Jsonizable, MessageKind};
+use core::fmt;
+use core::marker::PhantomData;
+use heapless::{ArrayLength, String, Vec};
+use serde_json_core::ser::Error as SError;
+
+/// Un moyen de transport des messages (UDP, UART...).
+pub trait Transport {
+    /// L'erreur renvoyée par le transport
+    type Error;
+
+    /// Envoie `data` à destination de la carte `id`
+    fn send(&mut self, id: u16, data: &[u8]) -> Result<(), Self::Error>;
+
+    /// Copie dans `buffer` le prochain message reçu pour la carte `id` et renvoie sa taille,
+    /// ou `None` si aucun message n'est disponible
+    fn receive(&mut self, id: u16, buffer: &mut [u8]) -> Result<Option<usize>, Self::Error>;
+}
+
+/// Les erreurs qui peuvent survenir lors de l'envoi d'un message
+#[derive(Debug)]
+pub enum ChannelError<E> {
+    /// Le message ne tient pas dans le buffer de sérialisation
+    Serialization(Error),
+    /// Le transport n'a pas pu envoyer le message
+    Transport(E),
+    /// Le débit maximal du canal est atteint, le message n'a pas été envoyé
+    RateLimited,
+}
+
+impl<E: fmt::Display> fmt::Display for ChannelError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ChannelError::Serialization(e) => write!(f, "{}", e),
+            ChannelError::Transport(e) => write!(f, "transport error: {}", e),
+            ChannelError::RateLimited => write!(f, "rate limited"),
+        }
+    }
+}
+
+#[cfg(any(test, feature = "std"))]
+impl<E: std::error::Error> std::error::Error for ChannelError<E> {}
+
+#[cfg(feature = "defmt")]
+impl<E: defmt::Format> defmt::Format for ChannelError<E> {
+    fn format(&self, f: defmt::Formatter) {
+        match self {
+            ChannelError::Serialization(e) => defmt::write!(f, "Serialization({})", e),
+            ChannelError::Transport(e) => defmt::write!(f, "Transport({})", e),
+            ChannelError::RateLimited => defmt::write!(f, "RateLimited"),
+        }
+    }
+}
+
+/// Canal typé permettant d'échanger des `T` avec l'informatique.
+///
+/// `B` est la taille des buffers utilisés pour (dé)sérialiser les messages.
+pub struct Channel<T, N, B>
+where
+    T: Jsonizable,
+    N: Transport,
+    B: ArrayLength<u8>,
+{
+    id: u16,
+    transport: N,
+    buffer: Vec<u8, B>,
+    counters: TransmissionCounters,
+    key: Option<MacKey>,
+    limiter: Option<TokenBucket>,
+    _message: PhantomData<T>,
+}
+
+impl<T, N, B> core::fmt::Debug for Channel<T, N, B>
+where
+    T: Jsonizable,
+    N: Transport,
+    B: ArrayLength<u8>,
+{
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        write!(f, "Channel {{ id: {} }}", self.id)
+    }
+}
+
+impl<T, N, B> Channel<T, N, B>
+where
+    T: Jsonizable,
+    N: Transport,
+    B: ArrayLength<u8>,
+{
+    /// Crée un canal pour la carte `id` utilisant le transport `transport`
+    pub fn new(id: u16, transport: N) -> Self {
+        let mut buffer: Vec<u8, B> = Vec::new();
+        let capacity = buffer.capacity();
+        // Ne peut pas échouer : la taille demandée est la capacité du buffer
+        let _ = buffer.resize_default(capacity);
+        Channel {
+            id,
+            transport,
+            buffer,
+            counters: TransmissionCounters::default(),
+            key: None,
+            limiter: None,
+            _message: PhantomData,
+        }
+    }
+
+    /// Authentifie les messages envoyés et reçus sur ce canal avec la clé `key` (voir
+    /// [`MacKey`])
+    pub fn with_key(mut self, key: MacKey) -> Self {
+        self.key = Some(key);
+        self
+    }
+
+    /// Limite le débit d'envoi de ce canal avec `bucket` (voir [`Channel::send_at`])
+    pub fn with_rate_limit(mut self, bucket: TokenBucket) -> Self {
+        self.limiter = Some(bucket);
+        self
+    }
+
+    /// Renvoie l'identifiant de la carte associée à ce canal
+    pub fn id(&self) -> u16 {
+        self.id
+    }
+
+    /// Renvoie le transport utilisé par le canal
+    pub fn transport(&mut self) -> &mut N {
+        &mut self.transport
+    }
+
+    /// Renvoie les compteurs des messages échangés sur ce canal
+    pub fn counters(&self) -> TransmissionCounters {
+        self.counters
+    }
+
+    /// Libère le transport
+    pub fn release(self) -> N {
+        self.transport
+    }
+
+    /// Sérialise `message` et l'envoie à l'informatique.
+    ///
+    /// L'heure n'étant pas connue, la limite de débit du canal ne s'applique pas : une boucle
+    /// qui envoie régulièrement doit utiliser [`Channel::send_at`].
+    pub fn send(&mut self, message: &T) -> Result<(), ChannelError<N::Error>> {
+        self.send_unlimited(message)
+    }
+
+    /// Envoie `message` à l'heure `now` (en millisecondes) si le débit maximal du canal n'est
+    /// pas atteint. Sans limite de débit, c'est équivalent à [`Channel::send`].
+    pub fn send_at(&mut self, message: &T, now: u32) -> Result<(), ChannelError<N::Error>> {
+        if let Some(limiter) = self.limiter.as_mut() {
+            if !limiter.try_take(now) {
+                self.counters.rate_limited = self.counters.rate_limited.wrapping_add(1);
+                return Err(ChannelError::RateLimited);
+            }
+        }
+        self.send_unlimited(message)
+    }
+
+    fn send_unlimited(&mut self, message: &T) -> Result<(), ChannelError<N::Error>> {
+        let json: String<B> = message.to_string().map_err(ChannelError::Serialization)?;
+        let data = match self.key {
+            Some(key) => {
+                let size = json.len();
+                if size + TAG_SIZE > self.buffer.len() {
+                    return Err(ChannelError::Serialization(Error::Serialization(
+                        SError::BufferFull,
+                    )));
+                }
+                self.buffer[..size].copy_from_slice(json.as_bytes());
+                self.buffer[size..size + TAG_SIZE]
+                    .copy_from_slice(&key.tag(self.id, json.as_bytes()));
+                &self.buffer[..size + TAG_SIZE]
+            }
+            None => json.as_bytes(),
+        };
+        match self.transport.send(self.id, data) {
+            Ok(()) => {
+                self.counters.sent = self.counters.sent.wrapping_add(1);
+                Ok(())
+            }
+            Err(e) => {
+                self.counters.send_errors = self.counters.send_errors.wrapping_add(1);
+                Err(ChannelError::Transport(e))
+            }
+        }
+    }
+
+    /// Renvoie le prochain message reçu, s'il y en a un et qu'il est valide
+    pub fn try_recv(&mut self) -> Option<T> {
+        self.recv_inner(None)
+    }
+
+    /// Comme [`Channel::try_recv`], mais un message illisible est aussi enregistré dans
+    /// `failures`, avec le type de message associé à la carte du canal
+    pub fn try_recv_recording(&mut self, failures: &mut ParseFailures) -> Option<T> {
+        self.recv_inner(Some(failures))
+    }
+
+    fn recv_inner(&mut self, failures: Option<&mut ParseFailures>) -> Option<T> {
+        let size = match self.transport.receive(self.id, &mut self.buffer) {
+            Ok(Some(size)) => size,
+            _ => return None,
+        };
+        let data = match self.key {
+            Some(key) => match key.verify(self.id, &self.buffer[..size]) {
+                Some(data) => data,
+                None => {
+                    self.counters.unauthenticated = self.counters.unauthenticated.wrapping_add(1);
+                    return None;
+                }
+            },
+            None => &self.buffer[..size],
+        };
+        match T::from_json_slice(data) {
+            Ok(message) => {
+                self.counters.received = self.counters.received.wrapping_add(1);
+                Some(message)
+            }
+            Err(_) => {
+                self.counters.invalid = self.counters.invalid.wrapping_add(1);
+                if let (Some(failures), Ok(kind)) = (failures, MessageKind::from_id(self.id)) {
+                    failures.record::<T>(kind, data);
+                }
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use super::{Channel, ChannelError, MacKey, TokenBucket, Transport};
+    use crate::transmission::color::Color;
+    use crate::transmission::health::ParseFailures;
+    use crate::transmission::id::ID_COLOR;
+    use crate::transmission::MessageKind;
+    use heapless::consts::U64;
+    use std::vec::Vec;
+
+    /// Transport de test : les messages envoyés sont stockés, les messages reçus sont ceux
+    /// qu'on a ajoutés au préalable dans `incoming`.
+    #[derive(Debug, Default)]
+    pub(crate) struct DummyTransport {
+        pub(crate) sent: Vec<(u16, Vec<u8>)>,
+        pub(crate) incoming: Vec<Vec<u8>>,
+    }
+
+    impl Transport for DummyTransport {
+        type Error = ();
+
+        fn send(&mut self, id: u16, data: &[u8]) -> Result<(), ()> {
+            self.sent.push((id, data.to_vec()));
+            Ok(())
+        }
+
+        fn receive(&mut self, _id: u16, buffer: &mut [u8]) -> Result<Option<usize>, ()> {
+            if self.incoming.is_empty() {
+                return Ok(None);
+            }
+            let message = self.incoming.remove(0);
+            buffer[..message.len()].copy_from_slice(&message);
+            Ok(Some(message.len()))
+        }
+    }
+
+    #[test]
+    fn channel_send() {
+        let mut channel: Channel<Color, _, U64> = Channel::new(6, DummyTransport::default());
+        channel.send(&Color::Blue).unwrap();
+        let transport = channel.release();
+        assert_eq!(transport.sent, vec![(6, b"\"Blue\"".to_vec())]);
+    }
+
+    #[test]
+    fn channel_try_recv() {
+        let mut transport = DummyTransport::default();
+        transport.incoming.push(b"\"Green\"".to_vec());
+        transport.incoming.push(b"\"Purple\"".to_vec());
+        transport.incoming.push(b"\"Red\"".to_vec());
+        let mut channel: Channel<Color, _, U64> = Channel::new(6, transport);
+
+        match channel.try_recv() {
+            Some(Color::Green) => {}
+            other => panic!("{:?} should be Green", other),
+        }
+        assert!(channel.try_recv().is_none());
+        match channel.try_recv() {
+            Some(Color::Red) => {}
+            other => panic!("{:?} should be Red", other),
+        }
+        assert!(channel.try_recv().is_none());
+        assert_eq!(channel.counters().received, 2);
+        assert_eq!(channel.counters().invalid, 1);
+    }
+
+    #[test]
+    fn channel_try_recv_recording() {
+        let mut transport = DummyTransport::default();
+        transport.incoming.push(b"\"Purple\"".to_vec());
+        transport.incoming.push(b"\"Red\"".to_vec());
+        let mut channel: Channel<Color, _, U64> = Channel::new(ID_COLOR, transport);
+        let mut failures = ParseFailures::new();
+
+        assert!(channel.try_recv_recording(&mut failures).is_none());
+        assert_eq!(channel.try_recv_recording(&mut failures), Some(Color::Red));
+        assert_eq!(failures.count(MessageKind::Color), 1);
+        assert_eq!(failures.last().unwrap().size, 8);
+    }
+
+    #[test]
+    fn channel_authentication() {
+        let key = MacKey::new(*b"club robot insat");
+        let mut sender: Channel<Color, _, U64> =
+            Channel::new(6, DummyTransport::default()).with_key(key);
+        sender.send(&Color::Red).unwrap();
+        let sent = sender.release().sent;
+        assert_eq!(sent[0].1.len(), b"\"Red\"".len() + super::TAG_SIZE);
+
+        let mut transport = DummyTransport::default();
+        transport.incoming.push(sent[0].1.clone());
+        transport.incoming.push(b"\"Green\"".to_vec());
+        let mut receiver: Channel<Color, _, U64> = Channel::new(6, transport).with_key(key);
+        match receiver.try_recv() {
+            Some(Color::Red) => {}
+            other => panic!("{:?} should be Red", other),
+        }
+        assert!(receiver.try_recv().is_none());
+        assert_eq!(receiver.counters().received, 1);
+        assert_eq!(receiver.counters().unauthenticated, 1);
+    }
+
+    #[test]
+    fn channel_rate_limit() {
+        let mut channel: Channel<Color, _, U64> =
+            Channel::new(6, DummyTransport::default()).with_rate_limit(TokenBucket::new(1, 100));
+        channel.send_at(&Color::Red, 0).unwrap();
+        match channel.send_at(&Color::Red, 5) {
+            Err(ChannelError::RateLimited) => {}
+            other => panic!("{:?} should be rate limited", other),
+        }
+        channel.send_at(&Color::Red, 10).unwrap();
+        assert_eq!(channel.counters().sent, 2);
+        assert_eq!(channel.counters().rate_limited, 1);
+
+        // `send` ne connait pas l'heure et ne consomme pas de jeton
+        channel.send(&Color::Red).unwrap();
+        assert_eq!(channel.counters().sent, 3);
+        assert_eq!(channel.counters().rate_limited, 1);
+        channel.send_at(&Color::Red, 20).unwrap();
+    }
+
+    #[test]
+    fn channel_rate_limit_refills() {
+        let mut channel: Channel<Color, _, U64> =
+            Channel::new(6, DummyTransport::default()).with_rate_limit(TokenBucket::new(3, 10));
+        for _ in 0..3 {
+            channel.send_at(&Color::Red, 1000).unwrap();
+        }
+        for now in &[1000, 1050] {
+            match channel.send_at(&Color::Red, *now) {
+                Err(ChannelError::RateLimited) => {}
+                other => panic!("{:?} should be rate limited", other),
+            }
+        }
+
+        // Le seau s'est rechargé de 3 jetons en 300 ms
+        for _ in 0..3 {
+            channel.send_at(&Color::Red, 1300).unwrap();
+        }
+        assert_eq!(channel.counters().sent, 6);
+        assert_eq!(channel.counters().rate_limited, 2);
+    }
+}
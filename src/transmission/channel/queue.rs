@@ -0,0 +1,144 @@
+//! Transport utilisant des files de la bibliothèque standard, pour la Raspberry Pi ou un PC.
+//!
+//! Le [`QueueTransport`] ne fait aucune entrée-sortie : le programme hôte donne au transport les
+//! messages lus sur sa socket avec [`QueueTransport::push_received`], et envoie ceux récupérés
+//! avec [`QueueTransport::pop_sent`]. Les trames sont ainsi lues et écrites par exactement le même
+//! code que sur les cartes :
+//!
+//! ```ignore
+//! let mut channel: Channel<ServoGroup, _, U512> = Channel::new(ID_SERVO, QueueTransport::new());
+//! channel.transport().push_received(ID_SERVO, &datagram);
+//! let group = channel.try_recv();
+//! ```
+
+use super::Transport;
+use core::fmt;
+use std::collections::VecDeque;
+use std::vec::Vec;
+
+/// Les erreurs du transport à files
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum QueueError {
+    /// Le message reçu ne tient pas dans le buffer du canal, il a été abandonné
+    TooLarge,
+}
+
+impl fmt::Display for QueueError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            QueueError::TooLarge => write!(f, "message too large for the buffer"),
+        }
+    }
+}
+
+impl std::error::Error for QueueError {}
+
+/// Transport qui conserve les messages envoyés et reçus dans des files de taille illimitée
+#[derive(Debug, Default)]
+pub struct QueueTransport {
+    received: VecDeque<(u16, Vec<u8>)>,
+    sent: VecDeque<(u16, Vec<u8>)>,
+}
+
+impl QueueTransport {
+    /// Crée un transport aux files vides
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Ajoute un message reçu pour la carte `id`, qui sera lu par le prochain `receive`
+    pub fn push_received(&mut self, id: u16, data: &[u8]) {
+        self.received.push_back((id, data.to_vec()));
+    }
+
+    /// Retire le plus ancien message envoyé et renvoie la carte à laquelle il est destiné
+    pub fn pop_sent(&mut self) -> Option<(u16, Vec<u8>)> {
+        self.sent.pop_front()
+    }
+
+    /// Renvoie le nombre de messages envoyés qui n'ont pas encore été retirés
+    pub fn sent_len(&self) -> usize {
+        self.sent.len()
+    }
+}
+
+impl Transport for QueueTransport {
+    type Error = QueueError;
+
+    fn send(&mut self, id: u16, data: &[u8]) -> Result<(), QueueError> {
+        self.sent.push_back((id, data.to_vec()));
+        Ok(())
+    }
+
+    fn receive(&mut self, id: u16, buffer: &mut [u8]) -> Result<Option<usize>, QueueError> {
+        let index = match self.received.iter().position(|(target, _)| *target == id) {
+            Some(index) => index,
+            None => return Ok(None),
+        };
+        let message = match self.received.remove(index) {
+            Some((_, message)) => message,
+            None => return Ok(None),
+        };
+        // Le message trop grand est retiré pour ne pas bloquer les suivants
+        if message.len() > buffer.len() {
+            return Err(QueueError::TooLarge);
+        }
+        buffer[..message.len()].copy_from_slice(&message);
+        Ok(Some(message.len()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{QueueError, QueueTransport};
+    use crate::transmission::channel::{Channel, Transport};
+    use crate::transmission::color::Color;
+    use heapless::consts::U64;
+
+    #[test]
+    fn queue_transport_round_trip() {
+        let mut transport = QueueTransport::new();
+        transport.push_received(1, b"first");
+        transport.push_received(2, b"other");
+        transport.push_received(1, b"second");
+
+        let mut buffer = [0; 8];
+        assert_eq!(transport.receive(1, &mut buffer), Ok(Some(5)));
+        assert_eq!(&buffer[..5], b"first");
+        assert_eq!(transport.receive(1, &mut buffer), Ok(Some(6)));
+        assert_eq!(&buffer[..6], b"second");
+        assert_eq!(transport.receive(1, &mut buffer), Ok(None));
+        assert_eq!(
+            transport.receive(2, &mut buffer[..2]),
+            Err(QueueError::TooLarge)
+        );
+        assert_eq!(transport.receive(2, &mut buffer), Ok(None));
+
+        transport.send(3, b"out").unwrap();
+        assert_eq!(transport.sent_len(), 1);
+        assert_eq!(transport.pop_sent(), Some((3, b"out".to_vec())));
+        assert_eq!(transport.pop_sent(), None);
+    }
+
+    #[test]
+    fn oversized_message_dropped() {
+        let mut transport = QueueTransport::new();
+        transport.push_received(1, b"much too long");
+        transport.push_received(1, b"fits");
+        let mut buffer = [0; 8];
+        assert_eq!(transport.receive(1, &mut buffer), Err(QueueError::TooLarge));
+        assert_eq!(transport.receive(1, &mut buffer), Ok(Some(4)));
+        assert_eq!(&buffer[..4], b"fits");
+    }
+
+    #[test]
+    fn queue_transport_channel() {
+        let mut channel: Channel<Color, _, U64> = Channel::new(7, QueueTransport::new());
+        channel.send(&Color::Green).unwrap();
+        let (id, data) = channel.transport().pop_sent().unwrap();
+        assert_eq!(id, 7);
+        channel.transport().push_received(id, &data);
+        assert_eq!(channel.try_recv(), Some(Color::Green));
+    }
+}
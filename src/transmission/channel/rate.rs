@@ -0,0 +1,75 @@
+//! Limitation du débit d'envoi.
+//!
+//! Une boucle qui envoie trop vite (par exemple des `NavigationFrame` à 2 kHz) peut saturer le
+//! lien et empêcher les autres messages de passer. Un [`TokenBucket`] associé à un canal limite
+//! le nombre de messages envoyés : chaque envoi consomme un jeton, et les jetons se rechargent à
+//! débit constant jusqu'à la capacité du seau.
+
+/// Seau à jetons limitant le débit d'un canal
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct TokenBucket {
+    /// Nombre maximal de jetons, c'est-à-dire de messages envoyés d'affilée
+    capacity: u32,
+    /// Nombre de jetons rechargés par seconde
+    rate: u32,
+    /// Jetons disponibles, en millièmes de jeton
+    tokens: u32,
+    /// Heure de la dernière recharge en millisecondes
+    last: Option<u32>,
+}
+
+impl TokenBucket {
+    /// Crée un seau plein de `capacity` jetons, rechargé de `rate` jetons par seconde
+    pub fn new(capacity: u32, rate: u32) -> Self {
+        TokenBucket {
+            capacity,
+            rate,
+            tokens: capacity.saturating_mul(1000),
+            last: None,
+        }
+    }
+
+    /// Renvoie le nombre de jetons entiers disponibles
+    pub fn available(&self) -> u32 {
+        self.tokens / 1000
+    }
+
+    /// Recharge le seau à l'heure `now` (en millisecondes) et consomme un jeton s'il y en a un.
+    /// Renvoie `true` si le message peut être envoyé.
+    pub fn try_take(&mut self, now: u32) -> bool {
+        if let Some(last) = self.last {
+            let elapsed = now.wrapping_sub(last);
+            // Le taux est en jetons par seconde, soit en millièmes de jeton par milliseconde
+            let refill = elapsed.saturating_mul(self.rate);
+            self.tokens = self
+                .tokens
+                .saturating_add(refill)
+                .min(self.capacity.saturating_mul(1000));
+        }
+        self.last = Some(now);
+        if self.tokens >= 1000 {
+            self.tokens -= 1000;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::TokenBucket;
+
+    #[test]
+    fn token_bucket() {
+        let mut bucket = TokenBucket::new(2, 10);
+        assert!(bucket.try_take(0));
+        assert!(bucket.try_take(0));
+        assert!(!bucket.try_take(50));
+        assert!(bucket.try_take(100));
+        assert!(!bucket.try_take(100));
+        // Le seau ne dépasse pas sa capacité
+        assert!(bucket.try_take(10_000));
+        assert_eq!(bucket.available(), 1);
+    }
+}
@@ -0,0 +1,357 @@
+//! Transport rejouant un échange scripté, pour les tests d'intégration sans carte.
+//!
+//! Le script décrit les messages que la carte reçoit et l'instant à partir duquel ils sont
+//! disponibles. Un message peut être corrompu ou perdu en route, et un envoi peut être perdu ou
+//! échouer, pour vérifier le comportement de la pile de transmission quand la liaison est mauvaise :
+//!
+//! ```ignore
+//! let transport = ScriptedTransport::new()
+//!     .receive(0, ID_NAVIGATION, forward.as_bytes())
+//!     .receive_corrupted(10, ID_NAVIGATION, stop.as_bytes(), 0)
+//!     .drop_send(0);
+//! let mut channel: Channel<NavigationFrame, _, U512> = Channel::new(ID_NAVIGATION, transport);
+//! channel.transport().set_time(Instant::from_millis(10));
+//! ```
+
+use super::Transport;
+use crate::time::Instant;
+use core::fmt;
+use std::collections::VecDeque;
+use std::vec::Vec;
+
+/// Les erreurs du transport scripté
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ScriptError {
+    /// Le script a demandé que cet envoi échoue
+    SendFailed,
+    /// Le message reçu ne tient pas dans le buffer du canal, il a été abandonné
+    TooLarge,
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ScriptError::SendFailed => write!(f, "scripted send failure"),
+            ScriptError::TooLarge => write!(f, "message too large for the buffer"),
+        }
+    }
+}
+
+impl std::error::Error for ScriptError {}
+
+/// Ce qui arrive à un message du script
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Fate {
+    Deliver,
+    Drop,
+}
+
+#[derive(Debug)]
+struct ScriptedMessage {
+    at: Instant,
+    id: u16,
+    data: Vec<u8>,
+    fate: Fate,
+}
+
+/// Un message envoyé par la carte à travers un [`ScriptedTransport`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SentMessage {
+    /// L'instant du script auquel le message a été envoyé
+    pub at: Instant,
+    /// La carte à laquelle le message est destiné
+    pub id: u16,
+    /// Le contenu du message
+    pub data: Vec<u8>,
+}
+
+/// Transport qui délivre les messages d'un script au fil du temps et enregistre les messages
+/// envoyés
+#[derive(Debug, Default)]
+pub struct ScriptedTransport {
+    now: Instant,
+    incoming: VecDeque<ScriptedMessage>,
+    sent: Vec<SentMessage>,
+    sends: usize,
+    dropped_sends: Vec<usize>,
+    failed_sends: Vec<usize>,
+    lost: usize,
+}
+
+impl ScriptedTransport {
+    /// Crée un transport avec un script vide, à l'instant 0
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    fn push(mut self, at: u32, id: u16, data: &[u8], fate: Fate) -> Self {
+        self.incoming.push_back(ScriptedMessage {
+            at: Instant::from_millis(at),
+            id,
+            data: data.to_vec(),
+            fate,
+        });
+        self
+    }
+
+    /// Ajoute au script le message `data` pour la carte `id`, disponible à partir de `at` ms
+    pub fn receive(self, at: u32, id: u16, data: &[u8]) -> Self {
+        self.push(at, id, data, Fate::Deliver)
+    }
+
+    /// Comme [`ScriptedTransport::receive`], mais les bits de l'octet `index` sont inversés
+    pub fn receive_corrupted(self, at: u32, id: u16, data: &[u8], index: usize) -> Self {
+        let mut data = data.to_vec();
+        if let Some(byte) = data.get_mut(index) {
+            *byte = !*byte;
+        }
+        self.push(at, id, &data, Fate::Deliver)
+    }
+
+    /// Ajoute au script un message perdu en route : il n'est jamais délivré
+    pub fn receive_dropped(self, at: u32, id: u16, data: &[u8]) -> Self {
+        self.push(at, id, data, Fate::Drop)
+    }
+
+    /// Le `n`-ième envoi (à partir de 0) est perdu sans que la carte le sache
+    pub fn drop_send(mut self, n: usize) -> Self {
+        self.dropped_sends.push(n);
+        self
+    }
+
+    /// Le `n`-ième envoi (à partir de 0) échoue avec [`ScriptError::SendFailed`]
+    pub fn fail_send(mut self, n: usize) -> Self {
+        self.failed_sends.push(n);
+        self
+    }
+
+    /// Avance l'instant du script jusqu'à `now`
+    pub fn set_time(&mut self, now: Instant) {
+        self.now = now;
+    }
+
+    /// Avance l'instant du script de `millis` ms
+    pub fn advance(&mut self, millis: u32) {
+        self.now = self.now + millis;
+    }
+
+    /// Renvoie les messages envoyés et arrivés à destination
+    pub fn sent(&self) -> &[SentMessage] {
+        &self.sent
+    }
+
+    /// Renvoie le nombre de messages du script perdus en route
+    pub fn lost(&self) -> usize {
+        self.lost
+    }
+
+    /// Renvoie vrai si tous les messages du script ont été délivrés ou perdus
+    pub fn is_done(&self) -> bool {
+        self.incoming.is_empty()
+    }
+
+    fn is_due(&self, message: &ScriptedMessage) -> bool {
+        message.at.as_millis() <= self.now.as_millis()
+    }
+}
+
+impl Transport for ScriptedTransport {
+    type Error = ScriptError;
+
+    fn send(&mut self, id: u16, data: &[u8]) -> Result<(), ScriptError> {
+        let n = self.sends;
+        self.sends += 1;
+        if self.failed_sends.contains(&n) {
+            return Err(ScriptError::SendFailed);
+        }
+        if !self.dropped_sends.contains(&n) {
+            self.sent.push(SentMessage {
+                at: self.now,
+                id,
+                data: data.to_vec(),
+            });
+        }
+        Ok(())
+    }
+
+    fn receive(&mut self, id: u16, buffer: &mut [u8]) -> Result<Option<usize>, ScriptError> {
+        let mut index = 0;
+        while index < self.incoming.len() {
+            let message = &self.incoming[index];
+            if !self.is_due(message) || message.id != id {
+                index += 1;
+                continue;
+            }
+            if message.fate == Fate::Drop {
+                self.incoming.remove(index);
+                self.lost += 1;
+                continue;
+            }
+            if message.data.len() > buffer.len() {
+                // Le message trop grand est retiré pour ne pas bloquer les suivants
+                self.incoming.remove(index);
+                return Err(ScriptError::TooLarge);
+            }
+            return Ok(self.incoming.remove(index).map(|message| {
+                buffer[..message.data.len()].copy_from_slice(&message.data);
+                message.data.len()
+            }));
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ScriptError, ScriptedTransport};
+    use crate::navigation::{PIDParameters, RealWorldPid, SoftQei};
+    use crate::time::Instant;
+    use crate::transmission::channel::{Channel, ChannelError, Transport};
+    use crate::transmission::navigation::{
+        NavigationCommand, NavigationFrame, NavigationParametersAck, NavigationParametersFrame,
+        ParamsStatus,
+    };
+    use crate::transmission::Jsonizable;
+    use crate::units::Fixed16;
+    use heapless::consts::U512;
+    use heapless::String;
+    use qei::QeiManager;
+    use std::vec::Vec;
+
+    fn command(command: NavigationCommand, counter: u16) -> String<U512> {
+        let frame = NavigationFrame {
+            command,
+            args_cmd1: 500,
+            counter,
+            ..Default::default()
+        };
+        frame.to_string().unwrap()
+    }
+
+    #[test]
+    fn scripted_exchange() {
+        let forward = command(NavigationCommand::GoForward, 1);
+        let backward = command(NavigationCommand::GoBackward, 2);
+        let stop = command(NavigationCommand::Stop, 3);
+        let transport = ScriptedTransport::new()
+            .receive(0, 5, forward.as_bytes())
+            .receive_corrupted(10, 5, backward.as_bytes(), 0)
+            .receive_dropped(20, 5, stop.as_bytes())
+            .receive(30, 5, stop.as_bytes())
+            .drop_send(1)
+            .fail_send(2);
+        let mut channel: Channel<NavigationFrame, _, U512> = Channel::new(5, transport);
+
+        let frame = channel.try_recv().unwrap();
+        assert_eq!(frame.command, NavigationCommand::GoForward);
+        assert_eq!(channel.try_recv(), None);
+
+        // Le message corrompu est rejeté, le suivant est perdu en route
+        channel.transport().set_time(Instant::from_millis(25));
+        assert_eq!(channel.try_recv(), None);
+        assert_eq!(channel.counters().invalid, 1);
+        assert_eq!(channel.try_recv(), None);
+        assert_eq!(channel.transport().lost(), 1);
+
+        channel.transport().advance(5);
+        let frame = channel.try_recv().unwrap();
+        assert_eq!(frame.command, NavigationCommand::Stop);
+        assert!(channel.transport().is_done());
+
+        channel.send(&frame).unwrap();
+        channel.send(&frame).unwrap();
+        match channel.send(&frame) {
+            Err(ChannelError::Transport(ScriptError::SendFailed)) => {}
+            other => panic!("{:?}", other),
+        }
+        let transport = channel.release();
+        assert_eq!(transport.sent().len(), 1);
+        assert_eq!(transport.sent()[0].at, Instant::from_millis(30));
+        assert_eq!(transport.sent()[0].data, stop.as_bytes());
+    }
+
+    #[test]
+    fn oversized_message_dropped() {
+        let mut transport = ScriptedTransport::new()
+            .receive(0, 5, b"much too long")
+            .receive(0, 5, b"fits");
+        let mut buffer = [0; 8];
+        assert_eq!(
+            transport.receive(5, &mut buffer),
+            Err(ScriptError::TooLarge)
+        );
+        assert_eq!(transport.receive(5, &mut buffer), Ok(Some(4)));
+        assert_eq!(&buffer[..4], b"fits");
+        assert!(transport.is_done());
+    }
+
+    #[test]
+    fn scripted_parameters_update() {
+        let applied = NavigationParametersFrame {
+            coder_radius: 315,
+            right_wheel_coef: Fixed16::ONE,
+            inter_axial_length: 2800,
+            pos_kp: Fixed16::from_f32(0.5),
+            pos_kd: Fixed16(0),
+            orient_kp: Fixed16::ONE,
+            orient_kd: Fixed16(0),
+            max_angle_output: 100,
+        };
+        let rejected = NavigationParametersFrame {
+            pos_kp: Fixed16::from_f32(-0.5),
+            ..applied
+        };
+        let lost = NavigationParametersFrame {
+            pos_kp: Fixed16::from_integer(3),
+            ..applied
+        };
+        let applied_json: String<U512> = applied.to_string().unwrap();
+        let rejected_json: String<U512> = rejected.to_string().unwrap();
+        let lost_json: String<U512> = lost.to_string().unwrap();
+        let transport = ScriptedTransport::new()
+            .receive_corrupted(0, 5, applied_json.as_bytes(), 0)
+            .receive(10, 5, applied_json.as_bytes())
+            .receive_dropped(20, 5, lost_json.as_bytes())
+            .receive(30, 5, rejected_json.as_bytes());
+        let mut channel: Channel<NavigationParametersFrame, _, U512> = Channel::new(5, transport);
+
+        let qei_left = QeiManager::new(SoftQei::new());
+        let qei_right = QeiManager::new(SoftQei::new());
+        let mut pid = RealWorldPid::new(qei_left, qei_right, &PIDParameters::default());
+
+        // La boucle de la carte déplacement : chaque trame reçue est appliquée au PID
+        let mut acks = Vec::new();
+        for now in (0..40).step_by(10) {
+            channel.transport().set_time(Instant::from_millis(now));
+            while let Some(frame) = channel.try_recv() {
+                acks.push(pid.apply_params_frame(&frame));
+            }
+            if now == 0 {
+                assert_eq!(channel.counters().invalid, 1);
+            }
+        }
+        assert!(channel.transport().is_done());
+        assert_eq!(channel.transport().lost(), 1);
+
+        let statuses: Vec<ParamsStatus> = acks.iter().map(|ack| ack.status).collect();
+        assert_eq!(
+            statuses,
+            vec![ParamsStatus::Applied, ParamsStatus::NegativeGain]
+        );
+        assert_eq!(pid.get_params().pos_kp, 0.5);
+        assert_eq!(pid.get_params().to_frame(), applied);
+
+        // Les réponses repartent vers l'informatique sur le même transport
+        let mut replies: Channel<NavigationParametersAck, _, U512> =
+            Channel::new(5, channel.release());
+        for ack in &acks {
+            replies.send(ack).unwrap();
+        }
+        let transport = replies.release();
+        assert_eq!(transport.sent().len(), 2);
+        let echo = NavigationParametersAck::from_json_slice(&transport.sent()[1].data).unwrap();
+        assert_eq!(echo.status, ParamsStatus::NegativeGain);
+        assert_eq!(echo.params, applied);
+    }
+}
@@ -0,0 +1,535 @@
+//! Transport sur une liaison série, alimenté par blocs depuis une interruption ou le DMA.
+//!
+//! À 921600 bauds, traiter les octets un par un dans l'interruption de l'UART coûte trop de
+//! cycles. L'interruption (ou la fin d'un transfert DMA) place donc les octets reçus dans un
+//! [`Chunk`] et le met dans une file `heapless::spsc`. Le [`StreamTransport`] vide la file depuis
+//! la boucle principale et reconstitue les messages en copiant les données bloc par bloc :
+//!
+//! ```ignore
+//! static mut CHUNKS: Queue<Chunk, U16> = Queue(heapless::i::Queue::new());
+//! let (producer, consumer) = unsafe { CHUNKS.split() };
+//!
+//! #[interrupt]
+//! fn DMA1_STREAM5() {
+//!     // `dma_chunk` est le bloc que le DMA vient de remplir
+//!     producer.enqueue(dma_chunk).ok();
+//! }
+//!
+//! let transport: StreamTransport<_, _, U512> = StreamTransport::new(tx, consumer);
+//! ```
+//!
+//! Sur la liaison, chaque message est précédé d'un en-tête : l'octet [`SYNC`], puis
+//! l'identifiant de la carte, la taille du message, le CRC-16 de l'identifiant et de la taille,
+//! et le CRC-16 de l'identifiant, de la taille et du message, en little endian sur 2 octets
+//! chacun. Un message dont un CRC est faux est ignoré : un octet [`SYNC`] trouvé au milieu d'un
+//! message n'est ainsi jamais pris pour un début de message valide. Le CRC de l'en-tête est
+//! vérifié avant de lire le message, pour qu'une fausse taille n'avale pas le message suivant.
+
+use super::Transport;
+use crate::transmission::servo::dynamixel::crc16_update;
+use core::fmt;
+use embedded_hal::serial::Write;
+use heapless::spsc::Consumer;
+use heapless::{ArrayLength, Vec};
+use nb::block;
+
+/// La taille maximale d'un bloc en octets
+pub const CHUNK_SIZE: usize = 32;
+
+/// L'octet qui marque le début d'un message
+pub const SYNC: u8 = 0x7E;
+
+/// La taille de l'en-tête d'un message : [`SYNC`], identifiant, taille, CRC de l'en-tête et CRC
+/// du message
+pub const HEADER_SIZE: usize = 9;
+
+/// Un bloc d'octets reçus sur la liaison série
+#[derive(Debug, Copy, Clone)]
+pub struct Chunk {
+    len: u8,
+    data: [u8; CHUNK_SIZE],
+}
+
+impl Default for Chunk {
+    fn default() -> Self {
+        Chunk {
+            len: 0,
+            data: [0; CHUNK_SIZE],
+        }
+    }
+}
+
+impl Chunk {
+    /// Crée un bloc contenant les [`CHUNK_SIZE`] premiers octets de `data`
+    pub fn from_slice(data: &[u8]) -> Self {
+        let mut chunk = Chunk::default();
+        let len = data.len().min(CHUNK_SIZE);
+        chunk.data[..len].copy_from_slice(&data[..len]);
+        chunk.len = len as u8;
+        chunk
+    }
+
+    /// Renvoie le buffer du bloc, dans lequel le DMA peut écrire directement
+    pub fn buffer_mut(&mut self) -> &mut [u8; CHUNK_SIZE] {
+        &mut self.data
+    }
+
+    /// Indique que les `len` premiers octets du buffer ont été reçus
+    pub fn set_len(&mut self, len: usize) {
+        self.len = len.min(CHUNK_SIZE) as u8;
+    }
+
+    /// Renvoie les octets reçus
+    pub fn as_slice(&self) -> &[u8] {
+        &self.data[..usize::from(self.len)]
+    }
+}
+
+/// Calcule le CRC-16 de l'en-tête et du contenu d'un message
+fn checksum(id: u16, len: u16, data: &[u8]) -> u16 {
+    let crc = crc16_update(0, &id.to_le_bytes());
+    let crc = crc16_update(crc, &len.to_le_bytes());
+    crc16_update(crc, data)
+}
+
+/// Renvoie l'en-tête à envoyer avant le message `data` pour la carte `id`. `data` doit faire au
+/// plus 65535 octets.
+pub fn header(id: u16, data: &[u8]) -> [u8; HEADER_SIZE] {
+    let len = data.len() as u16;
+    let [id_low, id_high] = id.to_le_bytes();
+    let [len_low, len_high] = len.to_le_bytes();
+    let [header_low, header_high] = checksum(id, len, &[]).to_le_bytes();
+    let [crc_low, crc_high] = checksum(id, len, data).to_le_bytes();
+    [
+        SYNC,
+        id_low,
+        id_high,
+        len_low,
+        len_high,
+        header_low,
+        header_high,
+        crc_low,
+        crc_high,
+    ]
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum State {
+    Sync,
+    Header(usize),
+    Data,
+    Skip(usize),
+}
+
+/// Reconstitue les messages à partir des octets reçus, bloc par bloc.
+///
+/// Les messages plus grands que `B` sont sautés en entier avant de chercher le prochain
+/// [`SYNC`], et les messages dont le CRC est faux sont ignorés. Si le CRC de l'en-tête est faux,
+/// le prochain [`SYNC`] est cherché à partir de l'octet qui suit le faux début de message.
+#[derive(Debug)]
+pub struct FrameDecoder<B: ArrayLength<u8>> {
+    state: State,
+    header: [u8; HEADER_SIZE - 1],
+    id: u16,
+    len: usize,
+    crc: u16,
+    buffer: Vec<u8, B>,
+    dropped: u32,
+    corrupted: u32,
+}
+
+impl<B: ArrayLength<u8>> Default for FrameDecoder<B> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<B: ArrayLength<u8>> FrameDecoder<B> {
+    /// Crée un décodeur qui attend le début d'un message
+    pub fn new() -> Self {
+        FrameDecoder {
+            state: State::Sync,
+            header: [0; HEADER_SIZE - 1],
+            id: 0,
+            len: 0,
+            crc: 0,
+            buffer: Vec::new(),
+            dropped: 0,
+            corrupted: 0,
+        }
+    }
+
+    /// Lit les octets de `data` jusqu'à la fin du prochain message.
+    ///
+    /// Renvoie le nombre d'octets lus et, si un message est complet, l'identifiant de sa carte
+    /// et son contenu. Les octets restants de `data` doivent être redonnés au prochain appel.
+    pub fn push(&mut self, data: &[u8]) -> (usize, Option<(u16, &[u8])>) {
+        let mut index = 0;
+        while index < data.len() {
+            match self.state {
+                State::Sync => match data[index..].iter().position(|&byte| byte == SYNC) {
+                    Some(position) => {
+                        index += position + 1;
+                        self.state = State::Header(0);
+                    }
+                    None => return (data.len(), None),
+                },
+                State::Header(read) => {
+                    self.header[read] = data[index];
+                    index += 1;
+                    if read + 1 < self.header.len() {
+                        self.state = State::Header(read + 1);
+                    } else {
+                        self.start_data();
+                    }
+                }
+                State::Data => {
+                    let take = (self.len - self.buffer.len()).min(data.len() - index);
+                    // Ne peut pas échouer : `start_data` a vérifié la taille du message
+                    let _ = self.buffer.extend_from_slice(&data[index..index + take]);
+                    index += take;
+                }
+                State::Skip(remaining) => {
+                    let take = remaining.min(data.len() - index);
+                    index += take;
+                    self.state = if take == remaining {
+                        State::Sync
+                    } else {
+                        State::Skip(remaining - take)
+                    };
+                }
+            }
+            if self.state == State::Data && self.buffer.len() == self.len {
+                self.state = State::Sync;
+                if checksum(self.id, self.len as u16, &self.buffer) == self.crc {
+                    return (index, Some((self.id, &self.buffer[..])));
+                }
+                self.corrupted = self.corrupted.wrapping_add(1);
+            }
+        }
+        (index, None)
+    }
+
+    fn start_data(&mut self) {
+        let [id_low, id_high, len_low, len_high, header_low, header_high, crc_low, crc_high] =
+            self.header;
+        let id = u16::from_le_bytes([id_low, id_high]);
+        let len = u16::from_le_bytes([len_low, len_high]);
+        if checksum(id, len, &[]) != u16::from_le_bytes([header_low, header_high]) {
+            // Faux début de message : les octets de l'en-tête peuvent contenir le vrai `SYNC`
+            self.corrupted = self.corrupted.wrapping_add(1);
+            self.state = match self.header.iter().position(|&byte| byte == SYNC) {
+                Some(position) => {
+                    self.header.copy_within(position + 1.., 0);
+                    State::Header(self.header.len() - position - 1)
+                }
+                None => State::Sync,
+            };
+            return;
+        }
+        self.id = id;
+        self.len = usize::from(len);
+        self.crc = u16::from_le_bytes([crc_low, crc_high]);
+        self.buffer.clear();
+        if self.len > self.buffer.capacity() {
+            // Le contenu du message est sauté : il pourrait contenir l'octet `SYNC`
+            self.dropped = self.dropped.wrapping_add(1);
+            self.state = State::Skip(self.len);
+        } else {
+            self.state = State::Data;
+        }
+    }
+
+    /// Renvoie le nombre de messages ignorés car trop grands
+    pub fn dropped(&self) -> u32 {
+        self.dropped
+    }
+
+    /// Renvoie le nombre de messages ignorés car le CRC de leur en-tête ou de leur contenu est
+    /// faux
+    pub fn corrupted(&self) -> u32 {
+        self.corrupted
+    }
+}
+
+/// Les erreurs du transport série
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum StreamError<E> {
+    /// L'écriture sur la liaison série a échoué
+    Serial(E),
+    /// Le message ne tient pas dans le buffer, ou sa taille dépasse 65535 octets
+    TooLarge,
+}
+
+impl<E: fmt::Debug> fmt::Display for StreamError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StreamError::Serial(e) => write!(f, "serial error: {:?}", e),
+            StreamError::TooLarge => write!(f, "message too large for the buffer"),
+        }
+    }
+}
+
+#[cfg(any(test, feature = "std"))]
+impl<E: fmt::Debug> std::error::Error for StreamError<E> {}
+
+/// Transport sur une liaison série : les messages sont envoyés octet par octet sur `serial` et
+/// reçus par blocs depuis la file `chunks`.
+///
+/// Les messages reçus pour une autre carte que celle du canal sont ignorés.
+pub struct StreamTransport<'q, W, N, B>
+where
+    W: Write<u8>,
+    N: ArrayLength<Chunk>,
+    B: ArrayLength<u8>,
+{
+    serial: W,
+    chunks: Consumer<'q, Chunk, N>,
+    current: Option<(Chunk, usize)>,
+    decoder: FrameDecoder<B>,
+}
+
+impl<'q, W, N, B> fmt::Debug for StreamTransport<'q, W, N, B>
+where
+    W: Write<u8>,
+    N: ArrayLength<Chunk>,
+    B: ArrayLength<u8>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "StreamTransport {{ dropped: {}, corrupted: {} }}",
+            self.decoder.dropped(),
+            self.decoder.corrupted()
+        )
+    }
+}
+
+impl<'q, W, N, B> StreamTransport<'q, W, N, B>
+where
+    W: Write<u8>,
+    N: ArrayLength<Chunk>,
+    B: ArrayLength<u8>,
+{
+    /// Crée un transport qui envoie sur `serial` et reçoit les blocs de `chunks`
+    pub fn new(serial: W, chunks: Consumer<'q, Chunk, N>) -> Self {
+        StreamTransport {
+            serial,
+            chunks,
+            current: None,
+            decoder: FrameDecoder::new(),
+        }
+    }
+
+    /// Renvoie le décodeur des messages reçus
+    pub fn decoder(&self) -> &FrameDecoder<B> {
+        &self.decoder
+    }
+}
+
+impl<'q, W, N, B> Transport for StreamTransport<'q, W, N, B>
+where
+    W: Write<u8>,
+    N: ArrayLength<Chunk>,
+    B: ArrayLength<u8>,
+{
+    type Error = StreamError<W::Error>;
+
+    fn send(&mut self, id: u16, data: &[u8]) -> Result<(), Self::Error> {
+        if data.len() > usize::from(u16::max_value()) {
+            return Err(StreamError::TooLarge);
+        }
+        for &byte in header(id, data).iter().chain(data) {
+            block!(self.serial.write(byte)).map_err(StreamError::Serial)?;
+        }
+        Ok(())
+    }
+
+    fn receive(&mut self, id: u16, buffer: &mut [u8]) -> Result<Option<usize>, Self::Error> {
+        loop {
+            let chunks = &mut self.chunks;
+            let (chunk, offset) = match self
+                .current
+                .take()
+                .or_else(|| chunks.dequeue().map(|chunk| (chunk, 0)))
+            {
+                Some(current) => current,
+                None => return Ok(None),
+            };
+            let (read, frame) = self.decoder.push(&chunk.as_slice()[offset..]);
+            if offset + read < chunk.as_slice().len() {
+                self.current = Some((chunk, offset + read));
+            }
+            match frame {
+                Some((frame_id, frame)) if frame_id == id => {
+                    if frame.len() > buffer.len() {
+                        return Err(StreamError::TooLarge);
+                    }
+                    buffer[..frame.len()].copy_from_slice(frame);
+                    return Ok(Some(frame.len()));
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{header, Chunk, FrameDecoder, StreamTransport, CHUNK_SIZE, HEADER_SIZE, SYNC};
+    use crate::transmission::channel::Channel;
+    use crate::transmission::color::Color;
+    use crate::transmission::Jsonizable;
+    use embedded_hal::serial::Write;
+    use heapless::consts::{U16, U64, U8};
+    use heapless::spsc::Queue;
+    use heapless::String;
+    use std::vec::Vec;
+
+    #[derive(Debug, Default)]
+    struct SerialOutput {
+        bytes: Vec<u8>,
+    }
+
+    impl Write<u8> for SerialOutput {
+        type Error = ();
+
+        fn write(&mut self, byte: u8) -> nb::Result<(), ()> {
+            self.bytes.push(byte);
+            Ok(())
+        }
+
+        fn flush(&mut self) -> nb::Result<(), ()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn frame_decoder() {
+        let mut stream = vec![0x00, 0x42];
+        stream.extend_from_slice(&header(3, b"abcd"));
+        stream.extend_from_slice(b"abcd");
+        stream.extend_from_slice(&header(4, &[0; 20]));
+        stream.extend_from_slice(&[0; 20]);
+        stream.extend_from_slice(&header(5, b""));
+
+        let mut decoder: FrameDecoder<U8> = FrameDecoder::new();
+        let (read, frame) = decoder.push(&stream);
+        assert_eq!(frame, Some((3, &b"abcd"[..])));
+        // Le message trop grand est ignoré, puis le message vide est lu
+        let (rest, frame) = decoder.push(&stream[read..]);
+        assert_eq!(frame, Some((5, &b""[..])));
+        assert_eq!(read + rest, stream.len());
+        assert_eq!(decoder.dropped(), 1);
+    }
+
+    #[test]
+    fn oversized_frame_skipped() {
+        // Le message trop grand contient un en-tête valide, qui ne doit pas être lu
+        let mut inner = Vec::new();
+        inner.extend_from_slice(&header(6, b"no"));
+        inner.extend_from_slice(b"no");
+        inner.resize(20, 0);
+        let mut stream = Vec::new();
+        stream.extend_from_slice(&header(4, &inner));
+        stream.extend_from_slice(&inner);
+        stream.extend_from_slice(&header(5, b"ok"));
+        stream.extend_from_slice(b"ok");
+
+        let mut decoder: FrameDecoder<U8> = FrameDecoder::new();
+        // Le message trop grand est sauté même s'il arrive en plusieurs morceaux
+        let (read, frame) = decoder.push(&stream[..10]);
+        assert_eq!((read, frame), (10, None));
+        let (rest, frame) = decoder.push(&stream[10..]);
+        assert_eq!(frame, Some((5, &b"ok"[..])));
+        assert_eq!(10 + rest, stream.len());
+        assert_eq!(decoder.dropped(), 1);
+    }
+
+    #[test]
+    fn corrupted_frame_ignored() {
+        let mut stream = Vec::new();
+        stream.extend_from_slice(&header(3, b"abcd"));
+        stream.extend_from_slice(b"abXd");
+        // Un octet `SYNC` isolé suivi d'octets quelconques ne forme pas un message valide
+        stream.extend_from_slice(&[SYNC, 3, 0, 2, 0, 0, 0, b'x', b'y']);
+        stream.extend_from_slice(&header(3, b"abcd"));
+        stream.extend_from_slice(b"abcd");
+
+        let mut decoder: FrameDecoder<U8> = FrameDecoder::new();
+        let (read, frame) = decoder.push(&stream);
+        assert_eq!(frame, Some((3, &b"abcd"[..])));
+        assert_eq!(read, stream.len());
+        assert_eq!(decoder.corrupted(), 2);
+        assert_eq!(decoder.dropped(), 0);
+
+        let mut bad_header = header(3, b"abcd");
+        bad_header[HEADER_SIZE - 1] ^= 0x01;
+        let mut stream = bad_header.to_vec();
+        stream.extend_from_slice(b"abcd");
+        assert_eq!(decoder.push(&stream), (stream.len(), None));
+        assert_eq!(decoder.corrupted(), 3);
+    }
+
+    #[test]
+    fn corrupted_header_does_not_swallow_next_frame() {
+        // Une fausse taille de 8 octets avalerait le message suivant s'il était lu
+        let mut bad = header(3, b"abcdefgh");
+        bad[5] ^= 0x01;
+        let mut stream = bad.to_vec();
+        stream.extend_from_slice(&header(5, b"ok"));
+        stream.extend_from_slice(b"ok");
+
+        let mut decoder: FrameDecoder<U8> = FrameDecoder::new();
+        let (read, frame) = decoder.push(&stream);
+        assert_eq!(frame, Some((5, &b"ok"[..])));
+        assert_eq!(read, stream.len());
+        assert_eq!(decoder.corrupted(), 1);
+
+        // Un message interrompu au milieu de son en-tête : le vrai `SYNC` est dans l'en-tête lu
+        let mut stream = header(3, b"abcd")[..3].to_vec();
+        stream.extend_from_slice(&header(5, b"ok"));
+        stream.extend_from_slice(b"ok");
+        let mut frames = Vec::new();
+        for part in stream.chunks(4) {
+            let (read, frame) = decoder.push(part);
+            assert_eq!(read, part.len());
+            frames.extend(frame.map(|(id, data)| (id, data.to_vec())));
+        }
+        assert_eq!(frames, [(5, b"ok".to_vec())]);
+        assert_eq!(decoder.corrupted(), 2);
+    }
+
+    #[test]
+    fn stream_transport() {
+        let mut stream = Vec::new();
+        for &(id, color) in [(7, Color::Red), (6, Color::Blue)].iter() {
+            let json: String<U64> = color.to_string().unwrap();
+            stream.extend_from_slice(&header(id, json.as_bytes()));
+            stream.extend_from_slice(json.as_bytes());
+        }
+
+        let mut queue: Queue<Chunk, U16> = Queue::new();
+        let (mut producer, consumer) = queue.split();
+        for part in stream.chunks(5) {
+            producer.enqueue(Chunk::from_slice(part)).unwrap();
+        }
+        let transport: StreamTransport<_, _, U64> =
+            StreamTransport::new(SerialOutput::default(), consumer);
+        let mut receiver: Channel<Color, _, U64> = Channel::new(6, transport);
+        assert_eq!(receiver.try_recv(), Some(Color::Blue));
+        assert_eq!(receiver.try_recv(), None);
+
+        receiver.send(&Color::Green).unwrap();
+        let sent = receiver.release().serial.bytes;
+        assert_eq!(sent[..HEADER_SIZE], header(6, b"\"Green\""));
+        assert_eq!(&sent[HEADER_SIZE..], b"\"Green\"");
+
+        let mut chunk = Chunk::default();
+        chunk.buffer_mut()[..2].copy_from_slice(b"ab");
+        chunk.set_len(2);
+        assert_eq!(chunk.as_slice(), b"ab");
+        assert_eq!(Chunk::from_slice(&[0; 40]).as_slice().len(), CHUNK_SIZE);
+    }
+}
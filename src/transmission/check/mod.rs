@@ -0,0 +1,656 @@
+//! Auto-test des actionneurs au démarrage.
+//!
+//! Un servo débranché ou un codeur mort n'est découvert qu'au début du match. Au démarrage, une
+//! carte peut faire bouger chacun de ses actionneurs un court instant et vérifier qu'il réagit :
+//!
+//! * chaque servo va un peu plus loin que sa position actuelle, sans sortir de ses butées, puis
+//!   y revient ;
+//! * chaque vanne est ouverte le temps d'une impulsion et la pression doit changer ;
+//! * chaque moteur reçoit une petite commande et son codeur doit compter.
+//!
+//! Le matériel est accessible à travers le trait [`ActuatorBench`]. L'[`ActuatorSelfCheck`]
+//! enchaîne les tests sans bloquer la boucle principale. Une fois tous les tests terminés, il
+//! rend les résultats à envoyer à l'informatique : pour tenir dans un message, ils sont découpés
+//! en pages de [`CHECKS_PER_FRAME`] résultats, une [`ActuatorCheckFrame`] par appel.
+//!
+//! ```ignore
+//! let mut check = ActuatorSelfCheck::new();
+//! check.add(ActuatorTest::Motor { id: 1, power: 150, duration: 200, min_ticks: 50 })?;
+//! loop {
+//!     if let Some(frame) = check.update(&mut bench, &clock) {
+//!         channel.send(&frame)?;
+//!     }
+//! }
+//! ```
+
+use crate::time::{Clock, Instant};
+use crate::transmission::Jsonizable;
+use heapless::consts::U16;
+use heapless::Vec;
+
+/// Nombre maximal d'actionneurs testés par une carte
+pub const MAX_ACTUATOR_CHECKS: usize = 16;
+
+/// Nombre de résultats par trame : une trame pleine tient dans
+/// [`FRAME_MAX_SIZE`](crate::transmission::FRAME_MAX_SIZE) octets
+pub const CHECKS_PER_FRAME: usize = 4;
+
+/// Le type d'un actionneur testé
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Actuator {
+    /// Un servo-moteur
+    Servo,
+    /// Une vanne de la carte pneumatique
+    Valve,
+    /// Un moteur avec sa roue codeuse
+    Motor,
+}
+
+impl Default for Actuator {
+    fn default() -> Self {
+        Actuator::Servo
+    }
+}
+
+/// Le résultat du test d'un actionneur
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Verdict {
+    /// L'actionneur a réagi comme prévu
+    Passed,
+    /// L'actionneur ou son capteur ne répond pas
+    NoResponse,
+    /// L'actionneur a été commandé mais aucun effet n'a été mesuré
+    NoEffect,
+    /// L'actionneur a été commandé mais aucun capteur ne permet de vérifier l'effet
+    Unverified,
+}
+
+impl Default for Verdict {
+    fn default() -> Self {
+        Verdict::Unverified
+    }
+}
+
+impl Verdict {
+    /// Renvoie vrai si l'actionneur est défaillant
+    pub fn is_failure(self) -> bool {
+        match self {
+            Verdict::NoResponse | Verdict::NoEffect => true,
+            Verdict::Passed | Verdict::Unverified => false,
+        }
+    }
+}
+
+/// Le résultat du test d'un actionneur
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ActuatorCheck {
+    /// Le type de l'actionneur
+    pub actuator: Actuator,
+    /// L'identifiant du servo ou du moteur, ou le numéro de la vanne à partir de 1. `id == 0`
+    /// veut dire qu'il n'y a pas de résultat.
+    pub id: u8,
+    /// Le résultat du test
+    pub verdict: Verdict,
+}
+
+/// Trame regroupant une page des résultats de l'auto-test des actionneurs d'une carte
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, Deserialize, Serialize, Jsonizable)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ActuatorCheckFrame {
+    /// Le numéro de la page, à partir de 0
+    pub page: u8,
+    /// Le nombre de pages des résultats
+    pub pages: u8,
+    /// Les résultats
+    pub checks: [ActuatorCheck; CHECKS_PER_FRAME],
+}
+
+impl ActuatorCheckFrame {
+    /// Ajoute un résultat dans le premier emplacement libre. Renvoie le résultat si la trame est
+    /// pleine ou si `check.id` vaut 0.
+    pub fn push(&mut self, check: ActuatorCheck) -> Result<(), ActuatorCheck> {
+        if check.id == 0 {
+            return Err(check);
+        }
+        match self.checks.iter_mut().find(|c| c.id == 0) {
+            Some(slot) => {
+                *slot = check;
+                Ok(())
+            }
+            None => Err(check),
+        }
+    }
+
+    /// Renvoie un itérateur sur les résultats présents
+    pub fn iter(&self) -> impl Iterator<Item = &ActuatorCheck> {
+        self.checks.iter().filter(|c| c.id != 0)
+    }
+
+    /// Renvoie un itérateur sur les actionneurs défaillants
+    pub fn failures(&self) -> impl Iterator<Item = &ActuatorCheck> {
+        self.iter().filter(|c| c.verdict.is_failure())
+    }
+
+    /// Renvoie vrai si aucun actionneur de la page n'est défaillant
+    pub fn is_ok(&self) -> bool {
+        self.failures().next().is_none()
+    }
+
+    /// Renvoie vrai si c'est la dernière page des résultats
+    pub fn is_last(&self) -> bool {
+        self.page + 1 >= self.pages
+    }
+}
+
+/// L'accès au matériel de la carte pendant l'auto-test
+pub trait ActuatorBench {
+    /// Renvoie la position du servo `id`, ou `None` s'il ne répond pas
+    fn servo_position(&mut self, id: u8) -> Option<u16>;
+
+    /// Envoie le servo `id` à la position `position`
+    fn set_servo_position(&mut self, id: u8, position: u16);
+
+    /// Ouvre ou ferme la vanne `valve`
+    fn set_valve(&mut self, valve: u8, open: bool);
+
+    /// Renvoie la pression en millibars du circuit de la vanne `valve`, ou `None` si le circuit
+    /// n'a pas de capteur
+    fn pressure(&mut self, valve: u8) -> Option<u16>;
+
+    /// Commande le moteur `id`, de `-1000` (pleine vitesse en arrière) à `1000`
+    fn set_motor(&mut self, id: u8, power: i16);
+
+    /// Renvoie la position en ticks de la roue codeuse du moteur `id`, ou `None` si elle n'a pas
+    /// pu être lue
+    fn encoder_ticks(&mut self, id: u8) -> Option<i64>;
+}
+
+/// Le test d'un actionneur
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ActuatorTest {
+    /// Déplace le servo `id` de `amplitude` sans sortir de `[min_position, max_position]`,
+    /// attend `settle` ms et vérifie qu'il a parcouru au moins la moitié du chemin
+    Servo {
+        /// L'identifiant du servo
+        id: u8,
+        /// Le déplacement demandé
+        amplitude: u16,
+        /// La butée basse du servo
+        min_position: u16,
+        /// La butée haute du servo
+        max_position: u16,
+        /// Le temps laissé au servo pour bouger, en millisecondes
+        settle: u16,
+    },
+    /// Ouvre la vanne `valve` pendant `pulse` ms et vérifie que la pression a changé d'au moins
+    /// `min_pressure_change` millibars
+    Valve {
+        /// Le numéro de la vanne, à partir de 1
+        valve: u8,
+        /// La durée de l'impulsion, en millisecondes
+        pulse: u16,
+        /// La variation de pression attendue, en millibars
+        min_pressure_change: u16,
+    },
+    /// Commande le moteur `id` à `power` pendant `duration` ms et vérifie que son codeur a compté
+    /// au moins `min_ticks` ticks
+    Motor {
+        /// L'identifiant du moteur
+        id: u8,
+        /// La commande appliquée, voir [`ActuatorBench::set_motor`]
+        power: i16,
+        /// La durée de la commande, en millisecondes
+        duration: u16,
+        /// Le déplacement attendu, en ticks
+        min_ticks: u32,
+    },
+}
+
+impl ActuatorTest {
+    fn actuator(&self) -> (Actuator, u8) {
+        match *self {
+            ActuatorTest::Servo { id, .. } => (Actuator::Servo, id),
+            ActuatorTest::Valve { valve, .. } => (Actuator::Valve, valve),
+            ActuatorTest::Motor { id, .. } => (Actuator::Motor, id),
+        }
+    }
+
+    fn duration(&self) -> u32 {
+        match *self {
+            ActuatorTest::Servo { settle, .. } => u32::from(settle),
+            ActuatorTest::Valve { pulse, .. } => u32::from(pulse),
+            ActuatorTest::Motor { duration, .. } => u32::from(duration),
+        }
+    }
+
+    /// Lit l'état de l'actionneur et le met en mouvement. Renvoie la lecture, ou le résultat
+    /// du test s'il est déjà connu.
+    fn start<B: ActuatorBench>(&self, bench: &mut B) -> Result<Option<i64>, Verdict> {
+        match *self {
+            ActuatorTest::Servo {
+                id,
+                amplitude,
+                min_position,
+                max_position,
+                ..
+            } => {
+                let before = bench.servo_position(id).ok_or(Verdict::NoResponse)?;
+                let target = servo_target(before, amplitude, min_position, max_position);
+                if target == before {
+                    return Err(Verdict::Unverified);
+                }
+                bench.set_servo_position(id, target);
+                Ok(Some(i64::from(before)))
+            }
+            ActuatorTest::Valve { valve, .. } => {
+                let before = bench.pressure(valve);
+                bench.set_valve(valve, true);
+                Ok(before.map(i64::from))
+            }
+            ActuatorTest::Motor { id, power, .. } => {
+                let before = bench.encoder_ticks(id).ok_or(Verdict::NoResponse)?;
+                bench.set_motor(id, power);
+                Ok(Some(before))
+            }
+        }
+    }
+
+    /// Mesure l'effet du test commencé avec la lecture `before` et remet l'actionneur au repos
+    fn finish<B: ActuatorBench>(&self, bench: &mut B, before: Option<i64>) -> Verdict {
+        match *self {
+            ActuatorTest::Servo {
+                id,
+                amplitude,
+                min_position,
+                max_position,
+                ..
+            } => {
+                let before = before.unwrap_or(0) as u16;
+                let after = bench.servo_position(id);
+                bench.set_servo_position(id, before);
+                let target = servo_target(before, amplitude, min_position, max_position);
+                let expected = i64::from(target) - i64::from(before);
+                match after {
+                    None => Verdict::NoResponse,
+                    Some(after) => moved(i64::from(after) - i64::from(before), expected / 2),
+                }
+            }
+            ActuatorTest::Valve {
+                valve,
+                min_pressure_change,
+                ..
+            } => {
+                let after = bench.pressure(valve);
+                bench.set_valve(valve, false);
+                match (before, after) {
+                    (Some(before), Some(after)) => {
+                        moved(after - before, i64::from(min_pressure_change))
+                    }
+                    _ => Verdict::Unverified,
+                }
+            }
+            ActuatorTest::Motor { id, min_ticks, .. } => {
+                bench.set_motor(id, 0);
+                match (before, bench.encoder_ticks(id)) {
+                    (Some(before), Some(after)) => moved(after - before, i64::from(min_ticks)),
+                    _ => Verdict::NoResponse,
+                }
+            }
+        }
+    }
+
+    /// Remet l'actionneur au repos sans mesurer l'effet du test
+    fn stop<B: ActuatorBench>(&self, bench: &mut B, before: Option<i64>) {
+        match *self {
+            ActuatorTest::Servo { id, .. } => {
+                if let Some(before) = before {
+                    bench.set_servo_position(id, before as u16);
+                }
+            }
+            ActuatorTest::Valve { valve, .. } => bench.set_valve(valve, false),
+            ActuatorTest::Motor { id, .. } => bench.set_motor(id, 0),
+        }
+    }
+}
+
+/// Renvoie la position à atteindre depuis `position` : `amplitude` plus loin si la butée haute
+/// le permet, sinon `amplitude` en arrière, sans dépasser la butée basse
+fn servo_target(position: u16, amplitude: u16, min_position: u16, max_position: u16) -> u16 {
+    match position.checked_add(amplitude) {
+        Some(target) if target <= max_position => target,
+        _ => position.saturating_sub(amplitude).max(min_position),
+    }
+}
+
+/// Renvoie [`Verdict::Passed`] si `change` va au moins aussi loin que `expected`, dans le même
+/// sens s'il est négatif
+fn moved(change: i64, expected: i64) -> Verdict {
+    let passed = if expected < 0 {
+        change <= expected
+    } else {
+        change.abs() >= expected
+    };
+    if passed {
+        Verdict::Passed
+    } else {
+        Verdict::NoEffect
+    }
+}
+
+/// Enchaîne les tests des actionneurs d'une carte
+#[derive(Debug, Default)]
+pub struct ActuatorSelfCheck {
+    tests: Vec<ActuatorTest, U16>,
+    next: usize,
+    /// Début et lecture initiale du test en cours
+    running: Option<(Instant, Option<i64>)>,
+    results: Vec<ActuatorCheck, U16>,
+    done: bool,
+    /// Nombre de pages des résultats déjà rendues
+    sent_pages: u8,
+}
+
+impl ActuatorSelfCheck {
+    /// Crée un auto-test sans actionneur
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Ajoute le test d'un actionneur. Renvoie le test s'il y en a déjà
+    /// [`MAX_ACTUATOR_CHECKS`] ou si l'identifiant de l'actionneur vaut 0.
+    pub fn add(&mut self, test: ActuatorTest) -> Result<(), ActuatorTest> {
+        if test.actuator().1 == 0 {
+            return Err(test);
+        }
+        self.tests.push(test)
+    }
+
+    /// Fait avancer l'auto-test à l'instant donné par `clock` : commence le test suivant ou
+    /// termine le test en cours une fois sa durée écoulée. Une fois le dernier test terminé,
+    /// renvoie une page des résultats à chaque appel, puis `None`.
+    pub fn update<B, C>(&mut self, bench: &mut B, clock: &C) -> Option<ActuatorCheckFrame>
+    where
+        B: ActuatorBench,
+        C: Clock,
+    {
+        if !self.done {
+            self.step(bench, clock.now());
+        }
+        if !self.done {
+            return None;
+        }
+        let frame = self.page(self.sent_pages)?;
+        self.sent_pages += 1;
+        Some(frame)
+    }
+
+    fn step<B: ActuatorBench>(&mut self, bench: &mut B, now: Instant) {
+        if let Some(test) = self.tests.get(self.next).copied() {
+            let verdict = match self.running {
+                None => match test.start(bench) {
+                    Ok(before) => {
+                        self.running = Some((now, before));
+                        None
+                    }
+                    Err(verdict) => Some(verdict),
+                },
+                Some((start, before)) if now.duration_since(start) >= test.duration() => {
+                    self.running = None;
+                    Some(test.finish(bench, before))
+                }
+                Some(_) => None,
+            };
+            if let Some(verdict) = verdict {
+                let (actuator, id) = test.actuator();
+                let check = ActuatorCheck {
+                    actuator,
+                    id,
+                    verdict,
+                };
+                // Il y a autant d'emplacements que de tests
+                self.results.push(check).ok();
+                self.next += 1;
+            }
+        }
+        self.done = self.next >= self.tests.len();
+    }
+
+    /// Interrompt l'auto-test, par exemple au début du match, et remet l'actionneur en cours de
+    /// test au repos. Les tests restants ne sont pas faits.
+    pub fn abort<B: ActuatorBench>(&mut self, bench: &mut B) {
+        if let (Some(test), Some((_, before))) = (self.tests.get(self.next), self.running) {
+            test.stop(bench, before);
+        }
+        self.running = None;
+        self.done = true;
+        self.sent_pages = self.pages();
+    }
+
+    /// Renvoie vrai une fois tous les tests terminés ou l'auto-test interrompu
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+
+    /// Renvoie les résultats des tests déjà terminés
+    pub fn results(&self) -> &[ActuatorCheck] {
+        &self.results
+    }
+
+    /// Renvoie le nombre de pages des résultats, au moins une
+    pub fn pages(&self) -> u8 {
+        let pages = (self.results.len() + CHECKS_PER_FRAME - 1) / CHECKS_PER_FRAME;
+        pages.max(1) as u8
+    }
+
+    /// Renvoie la page `page` des résultats des tests déjà terminés, si elle existe
+    pub fn page(&self, page: u8) -> Option<ActuatorCheckFrame> {
+        let pages = self.pages();
+        if page >= pages {
+            return None;
+        }
+        let mut frame = ActuatorCheckFrame {
+            page,
+            pages,
+            ..Default::default()
+        };
+        let start = usize::from(page) * CHECKS_PER_FRAME;
+        for &check in self.results.iter().skip(start).take(CHECKS_PER_FRAME) {
+            frame.push(check).ok();
+        }
+        Some(frame)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        Actuator, ActuatorBench, ActuatorCheck, ActuatorCheckFrame, ActuatorSelfCheck,
+        ActuatorTest, Verdict, CHECKS_PER_FRAME,
+    };
+    use crate::time::Instant;
+    use crate::transmission::{Jsonizable, FRAME_MAX_SIZE};
+    use core::cell::Cell;
+    use heapless::consts::U2048;
+    use heapless::String;
+    use std::vec::Vec;
+
+    /// Servo 1 et moteur 3 fonctionnent, servo 2 ne répond pas, moteur 4 est bloqué
+    #[derive(Debug, Default)]
+    struct Bench {
+        servo: u16,
+        valve_open: bool,
+        pressure: u16,
+        motor_power: i16,
+        ticks: i64,
+        log: Vec<&'static str>,
+    }
+
+    impl Bench {
+        fn step(&mut self) {
+            if self.valve_open {
+                self.pressure = self.pressure.saturating_sub(50);
+            }
+            self.ticks += i64::from(self.motor_power / 10);
+        }
+    }
+
+    impl ActuatorBench for Bench {
+        fn servo_position(&mut self, id: u8) -> Option<u16> {
+            if id == 1 {
+                Some(self.servo)
+            } else {
+                None
+            }
+        }
+
+        fn set_servo_position(&mut self, _id: u8, position: u16) {
+            self.servo = position;
+            self.log.push("servo");
+        }
+
+        fn set_valve(&mut self, _valve: u8, open: bool) {
+            self.valve_open = open;
+            self.log.push(if open { "open" } else { "close" });
+        }
+
+        fn pressure(&mut self, _valve: u8) -> Option<u16> {
+            Some(self.pressure)
+        }
+
+        fn set_motor(&mut self, id: u8, power: i16) {
+            if id == 3 {
+                self.motor_power = power;
+            }
+            self.log.push("motor");
+        }
+
+        fn encoder_ticks(&mut self, _id: u8) -> Option<i64> {
+            Some(self.ticks)
+        }
+    }
+
+    fn servo(id: u8) -> ActuatorTest {
+        ActuatorTest::Servo {
+            id,
+            amplitude: 40,
+            min_position: 100,
+            max_position: 520,
+            settle: 50,
+        }
+    }
+
+    fn motor(id: u8) -> ActuatorTest {
+        ActuatorTest::Motor {
+            id,
+            power: 100,
+            duration: 50,
+            min_ticks: 20,
+        }
+    }
+
+    #[test]
+    fn boot_check() {
+        let mut bench = Bench {
+            servo: 500,
+            pressure: 800,
+            ..Default::default()
+        };
+        let mut check = ActuatorSelfCheck::new();
+        let valve = ActuatorTest::Valve {
+            valve: 1,
+            pulse: 100,
+            min_pressure_change: 200,
+        };
+        for &test in [servo(1), servo(2), valve, motor(3), motor(4)].iter() {
+            check.add(test).unwrap();
+        }
+        assert_eq!(check.add(motor(0)), Err(motor(0)));
+
+        let now = Cell::new(Instant::from_millis(0));
+        let clock = || now.get();
+        let mut frames = Vec::new();
+        for millis in (0..1000).step_by(10) {
+            now.set(Instant::from_millis(millis));
+            bench.step();
+            if let Some(frame) = check.update(&mut bench, &clock) {
+                frames.push(frame);
+            }
+        }
+        assert!(check.is_done());
+        // Les 5 résultats tiennent dans 2 pages, rendues une par appel
+        assert_eq!(frames.len(), 2);
+        assert_eq!((frames[0].page, frames[0].pages), (0, 2));
+        assert!(!frames[0].is_last());
+        assert!(frames[1].is_last());
+        assert_eq!(frames[1].iter().count(), 1);
+        let verdicts: Vec<(Actuator, u8, Verdict)> = frames
+            .iter()
+            .flat_map(|frame| frame.iter())
+            .map(|c| (c.actuator, c.id, c.verdict))
+            .collect();
+        assert_eq!(
+            verdicts,
+            vec![
+                (Actuator::Servo, 1, Verdict::Passed),
+                (Actuator::Servo, 2, Verdict::NoResponse),
+                (Actuator::Valve, 1, Verdict::Passed),
+                (Actuator::Motor, 3, Verdict::Passed),
+                (Actuator::Motor, 4, Verdict::NoEffect),
+            ]
+        );
+        assert!(!frames[0].is_ok());
+        assert_eq!(frames[0].failures().count(), 1);
+        assert_eq!(frames[1].failures().count(), 1);
+        assert_eq!(check.results().len(), 5);
+        assert_eq!(check.page(1), Some(frames[1]));
+        assert_eq!(check.page(2), None);
+        // Le servo est allé vers sa butée basse puis est revenu
+        assert_eq!(bench.servo, 500);
+        assert!(!bench.valve_open);
+        assert_eq!(bench.motor_power, 0);
+
+        let json: String<U2048> = frames[0].to_string().unwrap();
+        assert_eq!(
+            ActuatorCheckFrame::from_json_slice(json.as_bytes()).unwrap(),
+            frames[0]
+        );
+    }
+
+    #[test]
+    fn full_page_fits_in_a_message() {
+        let check = ActuatorCheck {
+            actuator: Actuator::Servo,
+            id: u8::max_value(),
+            verdict: Verdict::NoResponse,
+        };
+        let frame = ActuatorCheckFrame {
+            page: u8::max_value(),
+            pages: u8::max_value(),
+            checks: [check; CHECKS_PER_FRAME],
+        };
+        let json: String<U2048> = frame.to_string().unwrap();
+        assert!(json.len() <= FRAME_MAX_SIZE, "{} bytes", json.len());
+    }
+
+    #[test]
+    fn abort_stops_actuator() {
+        let mut bench = Bench::default();
+        let mut check = ActuatorSelfCheck::new();
+        check.add(motor(3)).unwrap();
+        check.add(servo(1)).unwrap();
+        check.update(&mut bench, &|| Instant::from_millis(0));
+        assert_eq!(bench.motor_power, 100);
+        check.abort(&mut bench);
+        assert_eq!(bench.motor_power, 0);
+        assert!(check.is_done());
+        assert!(check
+            .update(&mut bench, &|| Instant::from_millis(100))
+            .is_none());
+        assert_eq!(check.results().len(), 0);
+        assert_eq!(bench.log, vec!["motor", "motor"]);
+    }
+}
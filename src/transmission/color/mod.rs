@@ -1,12 +1,15 @@
 //! Module permettant l'envoi d'une couleur au robot
+//!
+//! Le capteur de couleur fournit des valeurs brutes ([`ColorReading`]) qui sont classées en une
+//! [`Color`] par la méthode du plus proche centroïde : chaque lecture est normalisée par sa
+//! luminosité totale, puis comparée aux couleurs de référence d'une [`ColorCalibration`].
+//! L'informatique peut envoyer une nouvelle calibration pour s'adapter à l'éclairage de la table.
 
 use crate::transmission::Jsonizable;
-use heapless::{ArrayLength, String};
-use serde_json_core::de::{from_slice, Error as DError};
-use serde_json_core::ser::{to_string, Error as SError};
 
 /// La couleur vue par le robot
-#[derive(Debug, Copy, Clone, Deserialize, Serialize)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize, Serialize, Jsonizable)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum Color {
     /// Rouge
     Red,
@@ -17,21 +20,95 @@ pub enum Color {
 }
 
 impl Color {
-    fn from_rgb_values(red: u8, green: u8, blue: u8) -> Color {
-        unimplemented!()
+    /// Classe une couleur RGB 8 bits avec la calibration par défaut
+    pub fn from_rgb_values(red: u8, green: u8, blue: u8) -> Color {
+        let reading = ColorReading {
+            red: u16::from(red),
+            green: u16::from(green),
+            blue: u16::from(blue),
+            clear: u16::from(red) + u16::from(green) + u16::from(blue),
+        };
+        ColorCalibration::default().classify(&reading)
     }
 }
 
-impl Jsonizable for Color {
-    fn from_json_slice(slice: &[u8]) -> Result<Self, DError> {
-        from_slice(slice)
+/// Valeurs brutes lues par le capteur de couleur
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Deserialize, Serialize, Jsonizable)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ColorReading {
+    /// Composante rouge
+    pub red: u16,
+    /// Composante verte
+    pub green: u16,
+    /// Composante bleue
+    pub blue: u16,
+    /// Luminosité totale (canal sans filtre)
+    pub clear: u16,
+}
+
+impl ColorReading {
+    /// Renvoie la proportion de chaque composante en millièmes de la somme des trois composantes,
+    /// ce qui rend la lecture indépendante de la luminosité
+    pub fn chromaticity(&self) -> (i32, i32, i32) {
+        let sum = i32::from(self.red) + i32::from(self.green) + i32::from(self.blue);
+        if sum == 0 {
+            return (0, 0, 0);
+        }
+        (
+            i32::from(self.red) * 1000 / sum,
+            i32::from(self.green) * 1000 / sum,
+            i32::from(self.blue) * 1000 / sum,
+        )
+    }
+
+    fn distance_to(&self, other: &ColorReading) -> i32 {
+        let (r1, g1, b1) = self.chromaticity();
+        let (r2, g2, b2) = other.chromaticity();
+        (r1 - r2) * (r1 - r2) + (g1 - g2) * (g1 - g2) + (b1 - b2) * (b1 - b2)
     }
+}
+
+/// Trame de calibration : la lecture de référence de chaque couleur
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize, Serialize, Jsonizable)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ColorCalibration {
+    /// Lecture de référence du rouge
+    pub red: ColorReading,
+    /// Lecture de référence du vert
+    pub green: ColorReading,
+    /// Lecture de référence du bleu
+    pub blue: ColorReading,
+}
 
-    fn to_string<B>(&self) -> Result<String<B>, SError>
-    where
-        B: ArrayLength<u8>,
-    {
-        to_string(self)
+impl Default for ColorCalibration {
+    fn default() -> Self {
+        let reference = |red: u16, green: u16, blue: u16| ColorReading {
+            red,
+            green,
+            blue,
+            clear: red + green + blue,
+        };
+        ColorCalibration {
+            red: reference(200, 30, 30),
+            green: reference(30, 200, 30),
+            blue: reference(30, 30, 200),
+        }
+    }
+}
+
+impl ColorCalibration {
+    /// Renvoie la couleur de référence la plus proche de `reading`
+    pub fn classify(&self, reading: &ColorReading) -> Color {
+        let candidates = [
+            (Color::Red, &self.red),
+            (Color::Green, &self.green),
+            (Color::Blue, &self.blue),
+        ];
+        candidates
+            .iter()
+            .min_by_key(|(_, reference)| reading.distance_to(reference))
+            .map(|(color, _)| *color)
+            .unwrap_or(Color::Red)
     }
 }
 
@@ -53,4 +130,60 @@ mod tests {
         assert_eq!(color.to_string::<U2048>().unwrap(), expected_value);
     }
 
+    #[test]
+    fn color_classification() {
+        match Color::from_rgb_values(180, 40, 20) {
+            Color::Red => {}
+            other => panic!("{:?} should be Red", other),
+        }
+        match Color::from_rgb_values(10, 90, 30) {
+            Color::Green => {}
+            other => panic!("{:?} should be Green", other),
+        }
+        match Color::from_rgb_values(20, 60, 120) {
+            Color::Blue => {}
+            other => panic!("{:?} should be Blue", other),
+        }
+    }
+
+    #[test]
+    fn color_calibration() {
+        // Sous cet éclairage, le bleu paraît cyan et le vert paraît jaune
+        let calibration = ColorCalibration::from_json_slice(
+            "{\"red\":{\"red\":900,\"green\":300,\"blue\":200,\"clear\":1400},\
+             \"green\":{\"red\":500,\"green\":800,\"blue\":100,\"clear\":1400},\
+             \"blue\":{\"red\":100,\"green\":600,\"blue\":700,\"clear\":1400}}"
+                .as_bytes(),
+        )
+        .unwrap();
+        let reading = ColorReading {
+            red: 120,
+            green: 580,
+            blue: 690,
+            clear: 1390,
+        };
+        match calibration.classify(&reading) {
+            Color::Blue => {}
+            other => panic!("{:?} should be Blue", other),
+        }
+        match ColorCalibration::default().classify(&reading) {
+            Color::Blue => {}
+            other => panic!("{:?} should be Blue", other),
+        }
+        let reading = ColorReading {
+            red: 480,
+            green: 750,
+            blue: 90,
+            clear: 1320,
+        };
+        match calibration.classify(&reading) {
+            Color::Green => {}
+            other => panic!("{:?} should be Green", other),
+        }
+        assert_eq!(
+            calibration,
+            ColorCalibration::from_json_slice(calibration.to_string::<U2048>().unwrap().as_bytes())
+                .unwrap()
+        );
+    }
 }
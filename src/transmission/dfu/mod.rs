@@ -0,0 +1,400 @@
+//! Mise à jour du firmware d'une carte à travers le lien de communication.
+//!
+//! Le protocole est volontairement simple :
+//! 1. l'informatique envoie `Enter` avec la taille et le CRC de l'image ;
+//! 2. elle envoie l'image morceau par morceau (`Chunk`), chaque morceau ayant son propre CRC ;
+//! 3. elle envoie `Commit` : la carte vérifie le CRC de l'image complète avant de la valider.
+//!
+//! La carte répond à chaque trame par une [`DfuReply`] contenant le prochain offset attendu.
+//! [`DfuReceiver`] implémente la machine à états côté carte, l'écriture en flash étant déléguée à
+//! un [`FlashWriter`].
+
+use crate::transmission::Jsonizable;
+use heapless::consts::U32;
+use heapless::Vec;
+
+/// Taille maximale des données d'un morceau d'image
+pub type DfuChunkSize = U32;
+
+/// Calcule le CRC-32 (IEEE 802.3) de `data`
+pub fn crc32(data: &[u8]) -> u32 {
+    !crc32_update(!0, data)
+}
+
+/// Met à jour un CRC-32 en cours de calcul (non inversé) avec `data`
+fn crc32_update(mut crc: u32, data: &[u8]) -> u32 {
+    for byte in data {
+        crc ^= u32::from(*byte);
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    crc
+}
+
+/// Les commandes du protocole de mise à jour
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DfuCommand {
+    /// Passage en mode mise à jour ; `size` et `crc` décrivent l'image complète
+    Enter,
+    /// Morceau de l'image à écrire à `offset` ; `crc` est celui de `data`
+    Chunk,
+    /// Validation de l'image
+    Commit,
+    /// Abandon de la mise à jour
+    Abort,
+}
+
+/// Trame envoyée par l'informatique pour mettre à jour une carte
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize, Jsonizable)]
+pub struct DfuFrame {
+    /// La commande
+    pub command: DfuCommand,
+    /// Taille de l'image (`Enter` uniquement)
+    #[serde(default)]
+    pub size: u32,
+    /// Position du morceau dans l'image (`Chunk` uniquement)
+    #[serde(default)]
+    pub offset: u32,
+    /// Données du morceau (`Chunk` uniquement)
+    #[serde(default)]
+    pub data: Vec<u8, DfuChunkSize>,
+    /// CRC de l'image pour `Enter`, du morceau pour `Chunk`
+    #[serde(default)]
+    pub crc: u32,
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for DfuFrame {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(
+            f,
+            "DfuFrame {{ command: {}, size: {}, offset: {}, data: {=[u8]}, crc: {=u32:#x} }}",
+            self.command,
+            self.size,
+            self.offset,
+            &self.data[..],
+            self.crc
+        )
+    }
+}
+
+impl DfuFrame {
+    /// Crée la trame de passage en mode mise à jour pour l'image `image`
+    pub fn enter(image: &[u8]) -> Self {
+        DfuFrame {
+            command: DfuCommand::Enter,
+            size: image.len() as u32,
+            offset: 0,
+            data: Vec::new(),
+            crc: crc32(image),
+        }
+    }
+
+    /// Crée la trame contenant `data` à la position `offset`, ou `None` si `data` est trop long
+    pub fn chunk(offset: u32, data: &[u8]) -> Option<Self> {
+        let mut chunk = Vec::new();
+        chunk.extend_from_slice(data).ok()?;
+        Some(DfuFrame {
+            command: DfuCommand::Chunk,
+            size: 0,
+            offset,
+            data: chunk,
+            crc: crc32(data),
+        })
+    }
+
+    /// Crée une trame sans paramètre (`Commit` ou `Abort`)
+    pub fn command(command: DfuCommand) -> Self {
+        DfuFrame {
+            command,
+            size: 0,
+            offset: 0,
+            data: Vec::new(),
+            crc: 0,
+        }
+    }
+}
+
+/// Le résultat d'une commande de mise à jour
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DfuStatus {
+    /// La carte est prête à recevoir l'image
+    Ready,
+    /// Le morceau a été écrit
+    Ack,
+    /// Le morceau n'est pas à l'offset attendu
+    OutOfOrder,
+    /// Le CRC du morceau ou de l'image est faux
+    CrcError,
+    /// Le morceau dépasse la taille annoncée de l'image
+    Overflow,
+    /// L'image n'a pas été reçue entièrement
+    Incomplete,
+    /// La carte n'est pas en mode mise à jour
+    NotReady,
+    /// L'écriture en flash a échoué
+    FlashError,
+    /// L'image a été validée
+    Committed,
+    /// La mise à jour a été abandonnée
+    Aborted,
+}
+
+/// Réponse de la carte à une [`DfuFrame`]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize, Serialize, Jsonizable)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct DfuReply {
+    /// Le résultat de la commande
+    pub status: DfuStatus,
+    /// Le prochain offset attendu par la carte
+    pub next_offset: u32,
+}
+
+/// Accès à la zone de flash qui reçoit la nouvelle image
+pub trait FlashWriter {
+    /// L'erreur renvoyée par la flash
+    type Error;
+
+    /// Efface la zone de réception pour une image de `size` octets
+    fn erase(&mut self, size: u32) -> Result<(), Self::Error>;
+
+    /// Écrit `data` à la position `offset` de la zone de réception
+    fn write(&mut self, offset: u32, data: &[u8]) -> Result<(), Self::Error>;
+
+    /// Marque l'image reçue comme valide pour le prochain démarrage
+    fn commit(&mut self) -> Result<(), Self::Error>;
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum DfuState {
+    Idle,
+    Receiving { size: u32, crc: u32 },
+}
+
+/// Machine à états de la mise à jour côté carte
+#[derive(Debug)]
+pub struct DfuReceiver<F: FlashWriter> {
+    flash: F,
+    state: DfuState,
+    offset: u32,
+    running_crc: u32,
+}
+
+impl<F: FlashWriter> DfuReceiver<F> {
+    /// Crée une machine à états écrivant dans `flash`
+    pub fn new(flash: F) -> Self {
+        DfuReceiver {
+            flash,
+            state: DfuState::Idle,
+            offset: 0,
+            running_crc: !0,
+        }
+    }
+
+    /// Renvoie `true` si une mise à jour est en cours
+    pub fn is_updating(&self) -> bool {
+        self.state != DfuState::Idle
+    }
+
+    /// Libère la flash
+    pub fn release(self) -> F {
+        self.flash
+    }
+
+    fn reply(&self, status: DfuStatus) -> DfuReply {
+        DfuReply {
+            status,
+            next_offset: self.offset,
+        }
+    }
+
+    fn reset(&mut self) {
+        self.state = DfuState::Idle;
+        self.offset = 0;
+        self.running_crc = !0;
+    }
+
+    /// Traite une trame reçue et renvoie la réponse à envoyer
+    pub fn handle(&mut self, frame: &DfuFrame) -> DfuReply {
+        match (frame.command, self.state) {
+            (DfuCommand::Enter, _) => {
+                self.reset();
+                if self.flash.erase(frame.size).is_err() {
+                    return self.reply(DfuStatus::FlashError);
+                }
+                self.state = DfuState::Receiving {
+                    size: frame.size,
+                    crc: frame.crc,
+                };
+                self.reply(DfuStatus::Ready)
+            }
+            (DfuCommand::Abort, _) => {
+                self.reset();
+                self.reply(DfuStatus::Aborted)
+            }
+            (_, DfuState::Idle) => self.reply(DfuStatus::NotReady),
+            (DfuCommand::Chunk, DfuState::Receiving { size, .. }) => {
+                if frame.offset != self.offset {
+                    return self.reply(DfuStatus::OutOfOrder);
+                }
+                if crc32(&frame.data) != frame.crc {
+                    return self.reply(DfuStatus::CrcError);
+                }
+                let end = match self.offset.checked_add(frame.data.len() as u32) {
+                    Some(end) if end <= size => end,
+                    _ => return self.reply(DfuStatus::Overflow),
+                };
+                if self.flash.write(self.offset, &frame.data).is_err() {
+                    return self.reply(DfuStatus::FlashError);
+                }
+                self.running_crc = crc32_update(self.running_crc, &frame.data);
+                self.offset = end;
+                self.reply(DfuStatus::Ack)
+            }
+            (DfuCommand::Commit, DfuState::Receiving { size, crc }) => {
+                if self.offset != size {
+                    return self.reply(DfuStatus::Incomplete);
+                }
+                if !self.running_crc != crc {
+                    return self.reply(DfuStatus::CrcError);
+                }
+                if self.flash.commit().is_err() {
+                    return self.reply(DfuStatus::FlashError);
+                }
+                let reply = self.reply(DfuStatus::Committed);
+                self.reset();
+                reply
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{crc32, DfuCommand, DfuFrame, DfuReceiver, DfuStatus, FlashWriter};
+    use crate::transmission::Jsonizable;
+    use heapless::consts::U256;
+    use heapless::String;
+    use std::vec::Vec;
+
+    #[derive(Debug, Default)]
+    struct DummyFlash {
+        image: Vec<u8>,
+        committed: bool,
+    }
+
+    impl FlashWriter for DummyFlash {
+        type Error = ();
+
+        fn erase(&mut self, size: u32) -> Result<(), ()> {
+            self.image = vec![0xFF; size as usize];
+            self.committed = false;
+            Ok(())
+        }
+
+        fn write(&mut self, offset: u32, data: &[u8]) -> Result<(), ()> {
+            let offset = offset as usize;
+            self.image[offset..offset + data.len()].copy_from_slice(data);
+            Ok(())
+        }
+
+        fn commit(&mut self) -> Result<(), ()> {
+            self.committed = true;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn crc() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn ser_deser_dfu() {
+        let frame = DfuFrame::chunk(32, &[1, 2, 3]).unwrap();
+        let strd: String<U256> = frame.to_string().unwrap();
+        let data =
+            "{\"command\":\"Chunk\",\"size\":0,\"offset\":32,\"data\":[1,2,3],\"crc\":1438416925}";
+        assert_eq!(strd, data);
+        assert_eq!(DfuFrame::from_json_slice(data.as_bytes()).unwrap(), frame);
+        let commit = DfuFrame::from_json_slice(b"{\"command\":\"Commit\"}").unwrap();
+        assert_eq!(commit, DfuFrame::command(DfuCommand::Commit));
+        assert!(DfuFrame::chunk(0, &[0; 33]).is_none());
+    }
+
+    #[test]
+    fn dfu_update() {
+        let image: Vec<u8> = (0..70).collect();
+        let mut receiver = DfuReceiver::new(DummyFlash::default());
+
+        let commit = DfuFrame::command(DfuCommand::Commit);
+        assert_eq!(receiver.handle(&commit).status, DfuStatus::NotReady);
+        assert_eq!(
+            receiver.handle(&DfuFrame::enter(&image)).status,
+            DfuStatus::Ready
+        );
+
+        let first = DfuFrame::chunk(0, &image[..32]).unwrap();
+        let second = DfuFrame::chunk(32, &image[32..64]).unwrap();
+        let last = DfuFrame::chunk(64, &image[64..]).unwrap();
+        assert_eq!(receiver.handle(&second).status, DfuStatus::OutOfOrder);
+        let mut corrupted = first.clone();
+        corrupted.data[3] = 0;
+        assert_eq!(receiver.handle(&corrupted).status, DfuStatus::CrcError);
+
+        let reply = receiver.handle(&first);
+        assert_eq!(reply.status, DfuStatus::Ack);
+        assert_eq!(reply.next_offset, 32);
+        assert_eq!(receiver.handle(&commit).status, DfuStatus::Incomplete);
+        assert_eq!(receiver.handle(&second).status, DfuStatus::Ack);
+        assert_eq!(receiver.handle(&last).status, DfuStatus::Ack);
+        assert_eq!(receiver.handle(&commit).status, DfuStatus::Committed);
+        assert!(!receiver.is_updating());
+
+        let flash = receiver.release();
+        assert!(flash.committed);
+        assert_eq!(flash.image, image);
+    }
+
+    /// Flash qui accepte toutes les écritures sans les garder
+    #[derive(Debug, Default)]
+    struct NullFlash;
+
+    impl FlashWriter for NullFlash {
+        type Error = ();
+
+        fn erase(&mut self, _size: u32) -> Result<(), ()> {
+            Ok(())
+        }
+
+        fn write(&mut self, _offset: u32, _data: &[u8]) -> Result<(), ()> {
+            Ok(())
+        }
+
+        fn commit(&mut self) -> Result<(), ()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn chunk_past_u32_max() {
+        let mut receiver = DfuReceiver::new(NullFlash);
+        let mut enter = DfuFrame::command(DfuCommand::Enter);
+        enter.size = u32::max_value();
+        assert_eq!(receiver.handle(&enter).status, DfuStatus::Ready);
+
+        // Envoyer 4 Go de morceaux serait trop long : la position est placée directement
+        receiver.offset = u32::max_value() - 1;
+        let chunk = DfuFrame::chunk(u32::max_value() - 1, &[1, 2, 3]).unwrap();
+        let reply = receiver.handle(&chunk);
+        assert_eq!(reply.status, DfuStatus::Overflow);
+        assert_eq!(reply.next_offset, u32::max_value() - 1);
+    }
+}
@@ -0,0 +1,210 @@
+//! Trames de diagnostic émises par les cartes lorsqu'un problème survient.
+//!
+//! Le [`DiagnosticReporter`] compte les occurrences de chaque erreur et construit les
+//! [`DiagnosticFrame`] à envoyer à l'informatique.
+
+use crate::transmission::Jsonizable;
+
+/// Les erreurs qu'une carte peut signaler
+#[derive(Debug, PartialEq, Copy, Clone, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ErrorCode {
+    /// Un QEI a débordé entre deux échantillonnages
+    QeiOverflow,
+    /// Une trame reçue n'a pas pu être lue
+    ParseFailure,
+    /// Un servo-moteur force au delà de sa limite
+    ServoOverload,
+    /// Un servo-moteur est en surchauffe
+    ServoOverheat,
+    /// Un actionneur est bloqué
+    Blocked,
+    /// La batterie est faible
+    LowBattery,
+    /// La boucle principale n'a pas été exécutée à temps
+    Watchdog,
+    /// La liaison avec l'informatique est perdue
+    LinkLost,
+    /// Le test d'un élément de la carte a échoué
+    SelfTestFailed,
+    /// Erreur non répertoriée, doit rester le dernier code
+    Other,
+}
+
+impl ErrorCode {
+    /// Nombre de codes d'erreur différents
+    pub const COUNT: usize = ErrorCode::Other as usize + 1;
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// La gravité d'une erreur
+#[derive(Debug, PartialEq, PartialOrd, Copy, Clone, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Severity {
+    /// Information, pas d'action nécessaire
+    Info,
+    /// Comportement dégradé
+    Warning,
+    /// Une fonction de la carte ne marche plus
+    Error,
+    /// La carte ne peut plus fonctionner
+    Critical,
+}
+
+/// Trame signalant une erreur survenue sur une carte
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize, Serialize, Jsonizable)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct DiagnosticFrame {
+    /// L'identifiant du module concerné (voir [`crate::transmission::id`])
+    pub module: u16,
+    /// Le code de l'erreur
+    pub code: ErrorCode,
+    /// La gravité de l'erreur
+    pub severity: Severity,
+    /// Nombre d'occurrences de cette erreur depuis le démarrage de la carte
+    pub occurrences: u16,
+    /// Valeur donnant le contexte de l'erreur (identifiant du servo, valeur lue...)
+    pub context: Option<i32>,
+}
+
+/// Compte les erreurs d'un module et construit les trames de diagnostic correspondantes
+#[derive(Debug)]
+pub struct DiagnosticReporter {
+    module: u16,
+    occurrences: [u16; ErrorCode::COUNT],
+}
+
+impl DiagnosticReporter {
+    /// Crée un compteur d'erreurs pour le module `module`
+    pub fn new(module: u16) -> Self {
+        DiagnosticReporter {
+            module,
+            occurrences: [0; ErrorCode::COUNT],
+        }
+    }
+
+    /// Enregistre une occurrence de l'erreur `code` et renvoie la trame à envoyer
+    pub fn report(
+        &mut self,
+        code: ErrorCode,
+        severity: Severity,
+        context: Option<i32>,
+    ) -> DiagnosticFrame {
+        let count = &mut self.occurrences[code.index()];
+        *count = count.saturating_add(1);
+        DiagnosticFrame {
+            module: self.module,
+            code,
+            severity,
+            occurrences: *count,
+            context,
+        }
+    }
+
+    /// Construit la trame de l'erreur `code` sans compter de nouvelle occurrence, par exemple
+    /// pour signaler sa disparition
+    pub fn notice(
+        &self,
+        code: ErrorCode,
+        severity: Severity,
+        context: Option<i32>,
+    ) -> DiagnosticFrame {
+        DiagnosticFrame {
+            module: self.module,
+            code,
+            severity,
+            occurrences: self.occurrences[code.index()],
+            context,
+        }
+    }
+
+    /// Renvoie le nombre d'occurrences de l'erreur `code`
+    pub fn occurrences(&self, code: ErrorCode) -> u16 {
+        self.occurrences[code.index()]
+    }
+
+    /// Remet à zéro les compteurs
+    pub fn reset(&mut self) {
+        self.occurrences = [0; ErrorCode::COUNT];
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{DiagnosticFrame, DiagnosticReporter, ErrorCode, Severity};
+    use crate::transmission::id::ID_SERVO;
+    use crate::transmission::Jsonizable;
+    use heapless::consts::U256;
+    use heapless::String;
+    type N = U256;
+
+    #[test]
+    fn error_code_count() {
+        let codes = [
+            ErrorCode::QeiOverflow,
+            ErrorCode::ParseFailure,
+            ErrorCode::ServoOverload,
+            ErrorCode::ServoOverheat,
+            ErrorCode::Blocked,
+            ErrorCode::LowBattery,
+            ErrorCode::Watchdog,
+            ErrorCode::LinkLost,
+            ErrorCode::SelfTestFailed,
+            ErrorCode::Other,
+        ];
+        assert_eq!(codes.len(), ErrorCode::COUNT);
+        for (index, &code) in codes.iter().enumerate() {
+            // Ne compile plus si un code est ajouté sans compléter la liste
+            match code {
+                ErrorCode::QeiOverflow
+                | ErrorCode::ParseFailure
+                | ErrorCode::ServoOverload
+                | ErrorCode::ServoOverheat
+                | ErrorCode::Blocked
+                | ErrorCode::LowBattery
+                | ErrorCode::Watchdog
+                | ErrorCode::LinkLost
+                | ErrorCode::SelfTestFailed
+                | ErrorCode::Other => assert_eq!(code.index(), index),
+            }
+        }
+    }
+
+    #[test]
+    fn ser_deser_diagnostic() {
+        let frame = DiagnosticFrame {
+            module: ID_SERVO,
+            code: ErrorCode::ServoOverload,
+            severity: Severity::Error,
+            occurrences: 3,
+            context: Some(5),
+        };
+        let strd: String<N> = frame.to_string().unwrap();
+        let data = "{\"module\":2,\"code\":\"ServoOverload\",\"severity\":\"Error\",\
+                    \"occurrences\":3,\"context\":5}";
+        assert_eq!(strd, data);
+        assert_eq!(
+            DiagnosticFrame::from_json_slice(data.as_bytes()).unwrap(),
+            frame
+        );
+    }
+
+    #[test]
+    fn diagnostic_counters() {
+        let mut reporter = DiagnosticReporter::new(ID_SERVO);
+        reporter.report(ErrorCode::ParseFailure, Severity::Warning, None);
+        let frame = reporter.report(ErrorCode::ParseFailure, Severity::Warning, Some(12));
+        assert_eq!(frame.occurrences, 2);
+        assert_eq!(frame.module, ID_SERVO);
+        assert_eq!(reporter.occurrences(ErrorCode::ParseFailure), 2);
+        assert_eq!(reporter.occurrences(ErrorCode::Other), 0);
+        let frame = reporter.report(ErrorCode::Other, Severity::Info, None);
+        assert_eq!(frame.occurrences, 1);
+        reporter.reset();
+        assert_eq!(reporter.occurrences(ErrorCode::ParseFailure), 0);
+        assert!(Severity::Critical > Severity::Warning);
+    }
+}
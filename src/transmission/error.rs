@@ -0,0 +1,95 @@
+//! Le type d'erreur commun à tout le module `transmission`.
+
+use crate::transmission::motor::MotorFrameError;
+use crate::transmission::servo::binary::ServoFrameError;
+use core::fmt;
+use serde_json_core::de::Error as DError;
+use serde_json_core::ser::Error as SError;
+
+/// Les erreurs qui peuvent survenir lors de la lecture ou de l'écriture d'une trame
+#[derive(Debug)]
+pub enum Error {
+    /// Le JSON reçu n'a pas pu être lu
+    Deserialization(DError),
+    /// La trame ne tient pas dans le buffer de sérialisation
+    Serialization(SError),
+    /// Le type de message n'existe pas
+    UnknownMessageKind(u8),
+    /// L'identifiant de carte ne correspond à aucun type de message
+    UnknownModuleId(u16),
+    /// La trame binaire des servos est invalide
+    ServoFrame(ServoFrameError),
+    /// La trame binaire des moteurs est invalide
+    MotorFrame(MotorFrameError),
+    /// Les deux cartes utilisent le même port, voir
+    /// [`PortMap::validate`](crate::transmission::ports::PortMap::validate)
+    PortCollision(u16, u16),
+    /// Le port de la carte dépasse 65535
+    PortOverflow(u16),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Deserialization(e) => write!(f, "invalid json: {:?}", e),
+            Error::Serialization(e) => write!(f, "serialization failed: {:?}", e),
+            Error::UnknownMessageKind(kind) => write!(f, "unknown message kind {}", kind),
+            Error::UnknownModuleId(id) => write!(f, "unknown module id {}", id),
+            Error::ServoFrame(e) => write!(f, "invalid servo frame: {:?}", e),
+            Error::MotorFrame(e) => write!(f, "invalid motor frame: {:?}", e),
+            Error::PortCollision(first, second) => {
+                write!(f, "modules {} and {} share a port", first, second)
+            }
+            Error::PortOverflow(id) => write!(f, "port of module {} overflows", id),
+        }
+    }
+}
+
+#[cfg(any(test, feature = "std"))]
+impl std::error::Error for Error {}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for Error {
+    fn format(&self, f: defmt::Formatter) {
+        match self {
+            Error::Deserialization(e) => {
+                defmt::write!(f, "invalid json: {}", defmt::Debug2Format(e))
+            }
+            Error::Serialization(e) => {
+                defmt::write!(f, "serialization failed: {}", defmt::Debug2Format(e))
+            }
+            Error::UnknownMessageKind(kind) => defmt::write!(f, "unknown message kind {}", kind),
+            Error::UnknownModuleId(id) => defmt::write!(f, "unknown module id {}", id),
+            Error::ServoFrame(e) => defmt::write!(f, "invalid servo frame: {}", e),
+            Error::MotorFrame(e) => defmt::write!(f, "invalid motor frame: {}", e),
+            Error::PortCollision(first, second) => {
+                defmt::write!(f, "modules {} and {} share a port", first, second)
+            }
+            Error::PortOverflow(id) => defmt::write!(f, "port of module {} overflows", id),
+        }
+    }
+}
+
+impl From<DError> for Error {
+    fn from(e: DError) -> Self {
+        Error::Deserialization(e)
+    }
+}
+
+impl From<SError> for Error {
+    fn from(e: SError) -> Self {
+        Error::Serialization(e)
+    }
+}
+
+impl From<ServoFrameError> for Error {
+    fn from(e: ServoFrameError) -> Self {
+        Error::ServoFrame(e)
+    }
+}
+
+impl From<MotorFrameError> for Error {
+    fn from(e: MotorFrameError) -> Self {
+        Error::MotorFrame(e)
+    }
+}
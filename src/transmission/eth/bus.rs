@@ -0,0 +1,128 @@
+//! Accès au bus SPI du W5500, éventuellement partagé avec d'autres composants.
+//!
+//! Une opération du W5500 est une suite de transferts sur le bus pendant laquelle sa broche CS
+//! reste basse : aucun autre composant ne doit utiliser le bus entre temps. Le [`SpiAccess`]
+//! donne un accès exclusif au bus pour toute la durée d'une opération. Si le bus n'est utilisé
+//! que par le W5500, on donne simplement `&mut spi` ; s'il est partagé, par exemple avec la
+//! centrale inertielle, on donne un `&RefCell<Spi>` ou un type qui implémente [`SpiAccess`]
+//! avec une section critique. Si le bus est déjà emprunté, l'opération échoue avec
+//! [`BusError::Busy`] au lieu de paniquer :
+//!
+//! ```ignore
+//! let bus = RefCell::new(spi2);
+//! let mut transport = NetTransport::w5500(&mut w5500, &bus, SOCKET_UDP);
+//! // La centrale inertielle emprunte le bus entre deux opérations du W5500
+//! let accel = imu.read_accel(&mut *bus.borrow_mut())?;
+//! ```
+
+use core::cell::RefCell;
+use embedded_hal::spi::FullDuplex;
+
+/// Le bus SPI est déjà emprunté par un autre composant
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct BusBusy;
+
+/// Les erreurs d'une opération sur un bus SPI éventuellement partagé
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum BusError<E> {
+    /// Un transfert sur le bus a échoué
+    Spi(E),
+    /// Le bus est déjà emprunté, l'opération n'a pas été commencée
+    Busy,
+}
+
+impl<E> From<E> for BusError<E> {
+    fn from(e: E) -> Self {
+        BusError::Spi(e)
+    }
+}
+
+/// Donne un accès exclusif à un bus SPI
+pub trait SpiAccess {
+    /// Le bus SPI
+    type Bus: FullDuplex<u8>;
+
+    /// Appelle `f` avec le bus, qu'aucun autre composant ne peut utiliser pendant l'appel.
+    /// Renvoie [`BusBusy`] sans appeler `f` si le bus est déjà emprunté.
+    fn lock<R, F: FnOnce(&mut Self::Bus) -> R>(&mut self, f: F) -> Result<R, BusBusy>;
+}
+
+impl<'a, S: FullDuplex<u8>> SpiAccess for &'a mut S {
+    type Bus = S;
+
+    fn lock<R, F: FnOnce(&mut S) -> R>(&mut self, f: F) -> Result<R, BusBusy> {
+        Ok(f(&mut **self))
+    }
+}
+
+/// Le bus est partagé entre des composants utilisés dans le même contexte (la boucle principale
+/// ou une même interruption) : il ne peut être déjà emprunté que si `f` utilise lui-même le bus,
+/// ou si un composant garde l'emprunt entre deux opérations.
+impl<'a, S: FullDuplex<u8>> SpiAccess for &'a RefCell<S> {
+    type Bus = S;
+
+    fn lock<R, F: FnOnce(&mut S) -> R>(&mut self, f: F) -> Result<R, BusBusy> {
+        let mut bus = self.try_borrow_mut().map_err(|_| BusBusy)?;
+        Ok(f(&mut *bus))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{BusBusy, SpiAccess};
+    use core::cell::RefCell;
+    use embedded_hal::spi::FullDuplex;
+
+    #[derive(Debug, Default)]
+    struct CountingSpi {
+        transfers: usize,
+    }
+
+    impl FullDuplex<u8> for CountingSpi {
+        type Error = ();
+
+        fn read(&mut self) -> nb::Result<u8, ()> {
+            Ok(0)
+        }
+
+        fn send(&mut self, _word: u8) -> nb::Result<(), ()> {
+            self.transfers += 1;
+            Ok(())
+        }
+    }
+
+    fn transfer<B: SpiAccess>(mut bus: B) -> Result<(), BusBusy> {
+        bus.lock(|spi| spi.send(0x55).ok()).map(|_| ())
+    }
+
+    #[test]
+    fn spi_access() {
+        let mut spi = CountingSpi::default();
+        assert_eq!(transfer(&mut spi), Ok(()));
+        assert_eq!(spi.transfers, 1);
+
+        let shared = RefCell::new(spi);
+        assert_eq!(transfer(&shared), Ok(()));
+        assert_eq!(transfer(&shared), Ok(()));
+        assert_eq!(shared.borrow().transfers, 3);
+    }
+
+    #[test]
+    fn busy_shared_bus() {
+        let shared = RefCell::new(CountingSpi::default());
+        {
+            // Un autre composant garde le bus emprunté
+            let _imu = shared.borrow_mut();
+            assert_eq!(transfer(&shared), Err(BusBusy));
+        }
+        assert_eq!(shared.borrow().transfers, 0);
+        // L'opération qui utilise elle-même le bus échoue sans paniquer
+        let mut bus = &shared;
+        let nested = bus.lock(|_| transfer(&shared));
+        assert_eq!(nested, Ok(Err(BusBusy)));
+        assert_eq!(transfer(&shared), Ok(()));
+        assert_eq!(shared.borrow().transfers, 1);
+    }
+}
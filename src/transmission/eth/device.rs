@@ -0,0 +1,173 @@
+//! Abstraction du contrôleur ethernet.
+//!
+//! Le [`NetTransport`](super::NetTransport) n'utilise le contrôleur qu'à travers le trait
+//! [`NetDevice`], qui représente une socket UDP. Le W5500 l'implémente avec [`W5500Device`] ; un
+//! ENC28J60 ou une pile smoltcp n'ont qu'à l'implémenter pour être utilisés à sa place.
+
+use super::bus::{BusError, SpiAccess};
+use super::TX_BUFFER_SIZE;
+use embedded_hal::spi::FullDuplex;
+use w5500::*;
+
+/// Une adresse IPv4, indépendante du contrôleur ethernet
+pub type Ipv4 = [u8; 4];
+
+/// Une socket UDP d'un contrôleur ethernet
+pub trait NetDevice {
+    /// L'erreur renvoyée par le contrôleur
+    type Error;
+
+    /// Réinitialise le contrôleur avec l'adresse MAC `mac`, l'IP `ip` sur un sous-réseau /24 et
+    /// la passerelle `gateway`
+    fn configure(&mut self, mac: [u8; 6], ip: Ipv4, gateway: Ipv4) -> Result<(), Self::Error>;
+
+    /// Écoute les messages UDP reçus sur le port `port`
+    fn listen(&mut self, port: u16) -> Result<(), Self::Error>;
+
+    /// Envoie `data` depuis le port `local_port` vers `ip:port`. Renvoie
+    /// `nb::Error::WouldBlock` si le contrôleur n'a pas la place de le stocker.
+    fn send_to(
+        &mut self,
+        local_port: u16,
+        ip: Ipv4,
+        port: u16,
+        data: &[u8],
+    ) -> nb::Result<(), Self::Error>;
+
+    /// Copie le prochain message reçu dans `buffer` et renvoie sa taille, s'il y en a un
+    fn receive(&mut self, buffer: &mut [u8]) -> Result<Option<usize>, Self::Error>;
+
+    /// Renvoie vrai si le lien avec le switch est établi
+    fn link_up(&mut self) -> Result<bool, Self::Error>;
+
+    /// Renvoie `nb::Error::WouldBlock` tant que les messages envoyés n'ont pas tous quitté le
+    /// contrôleur
+    fn flush(&mut self) -> nb::Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+/// L'erreur du bus SPI donné par `B`
+pub type SpiError<B> = <<B as SpiAccess>::Bus as FullDuplex<u8>>::Error;
+
+/// L'erreur d'une opération du W5500 sur le bus donné par `B`
+pub type W5500Error<B> = BusError<SpiError<B>>;
+
+/// Une socket UDP du W5500, dont le bus SPI est donné par `B` (voir [`SpiAccess`])
+pub struct W5500Device<'a, 'b, B>
+where
+    B: SpiAccess,
+{
+    eth: &'a mut W5500<'b>,
+    bus: B,
+    socket: Socket,
+}
+
+impl<'a, 'b, B> core::fmt::Debug for W5500Device<'a, 'b, B>
+where
+    B: SpiAccess,
+{
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        write!(f, "W5500Device")
+    }
+}
+
+impl<'a, 'b, B> W5500Device<'a, 'b, B>
+where
+    B: SpiAccess,
+{
+    /// Utilise la socket `socket` du W5500 `eth`, branché sur le bus `bus`
+    pub fn new(eth: &'a mut W5500<'b>, bus: B, socket: Socket) -> Self {
+        W5500Device { eth, bus, socket }
+    }
+
+    /// Appelle `f` avec le W5500, la socket et le bus, ou renvoie [`BusError::Busy`] si le bus
+    /// est déjà emprunté
+    fn with_bus<R, F>(&mut self, f: F) -> Result<R, W5500Error<B>>
+    where
+        F: FnOnce(&mut W5500<'b>, Socket, &mut B::Bus) -> Result<R, W5500Error<B>>,
+    {
+        let (eth, socket) = (&mut *self.eth, self.socket);
+        self.bus
+            .lock(|spi| f(eth, socket, spi))
+            .unwrap_or(Err(BusError::Busy))
+    }
+
+    /// Renvoie la place libre dans le buffer d'envoi de la socket, en octets
+    pub fn tx_free_size(&mut self) -> Result<u16, W5500Error<B>> {
+        self.with_bus(|eth, socket, spi| {
+            Ok(eth.read_u16(spi, socket.at(SocketRegister::TxFreeSize))?)
+        })
+    }
+}
+
+pub(super) fn ip_address(ip: Ipv4) -> IpAddress {
+    IpAddress::new(ip[0], ip[1], ip[2], ip[3])
+}
+
+impl<'a, 'b, B> NetDevice for W5500Device<'a, 'b, B>
+where
+    B: SpiAccess,
+{
+    type Error = W5500Error<B>;
+
+    fn configure(&mut self, mac: [u8; 6], ip: Ipv4, gateway: Ipv4) -> Result<(), Self::Error> {
+        let mac = MacAddress::new(mac[0], mac[1], mac[2], mac[3], mac[4], mac[5]);
+        self.with_bus(|eth, _socket, spi| {
+            eth.init(&mut *spi)?;
+            eth.set_mode(&mut *spi, false, false, false, true)?;
+            eth.set_mac(&mut *spi, &mac)?;
+            eth.set_ip(&mut *spi, &ip_address(ip))?;
+            eth.set_subnet(&mut *spi, &IpAddress::new(255, 255, 255, 0))?;
+            Ok(eth.set_gateway(&mut *spi, &ip_address(gateway))?)
+        })
+    }
+
+    fn listen(&mut self, port: u16) -> Result<(), Self::Error> {
+        self.with_bus(|eth, socket, spi| Ok(eth.listen_udp(spi, socket, port)?))
+    }
+
+    fn send_to(
+        &mut self,
+        local_port: u16,
+        ip: Ipv4,
+        port: u16,
+        data: &[u8],
+    ) -> nb::Result<(), Self::Error> {
+        let (eth, socket) = (&mut *self.eth, self.socket);
+        // La place libre est lue sans relâcher le bus, pour qu'elle soit encore valable à l'envoi
+        self.bus
+            .lock(|spi| {
+                let free = eth
+                    .read_u16(&mut *spi, socket.at(SocketRegister::TxFreeSize))
+                    .map_err(BusError::Spi)?;
+                if usize::from(free) < data.len() {
+                    return Err(nb::Error::WouldBlock);
+                }
+                eth.send_udp(spi, socket, local_port, &ip_address(ip), port, data)
+                    .map_err(BusError::Spi)?;
+                Ok(())
+            })
+            .unwrap_or(Err(nb::Error::Other(BusError::Busy)))
+    }
+
+    fn receive(&mut self, buffer: &mut [u8]) -> Result<Option<usize>, Self::Error> {
+        self.with_bus(|eth, socket, spi| {
+            Ok(eth
+                .try_receive_udp(spi, socket, buffer)?
+                .map(|(_ip, _port, size)| size))
+        })
+    }
+
+    fn link_up(&mut self) -> Result<bool, Self::Error> {
+        self.with_bus(|eth, _socket, spi| Ok(super::try_link_up(eth, spi)?))
+    }
+
+    fn flush(&mut self) -> nb::Result<(), Self::Error> {
+        if self.tx_free_size()? < TX_BUFFER_SIZE {
+            Err(nb::Error::WouldBlock)
+        } else {
+            Ok(())
+        }
+    }
+}
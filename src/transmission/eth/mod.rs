@@ -1,57 +1,481 @@
 //! Module pour la communication ethernet
+//!
+//! Aucune fonction de ce module ne panique en dehors de [`init_eth`] et [`listen_on`], gardées
+//! pour la compatibilité : une erreur du W5500 pendant un match est renvoyée à l'appelant, qui
+//! peut réessayer au lieu de figer le robot.
 
+#![cfg_attr(
+    not(test),
+    deny(
+        clippy::panic,
+        clippy::unwrap_used,
+        clippy::expect_used,
+        clippy::unreachable
+    )
+)]
+
+use crate::profile::RobotProfile;
+use crate::transmission::channel::Transport;
+use crate::transmission::id::ID_BROADCAST;
+use crate::transmission::ports::PortMap;
 use embedded_hal::spi::FullDuplex;
+use heapless::consts::U8;
+use heapless::Vec;
 use w5500::*;
 
+mod bus;
+mod device;
+
+pub use self::bus::{BusBusy, BusError, SpiAccess};
+pub use self::device::{Ipv4, NetDevice, SpiError, W5500Device, W5500Error};
+
 /// La socket utilisee pour l'UDP
 pub const SOCKET_UDP: Socket = Socket::Socket0;
 
-fn get_subnet() -> u8 {
-    if cfg!(feature = "primary") {
-        1
-    } else if cfg!(feature = "secondary") {
-        2
-    } else {
-        unreachable!()
-    }
+/// La taille du buffer d'envoi d'une socket du W5500 après sa réinitialisation, en octets
+pub const TX_BUFFER_SIZE: u16 = 2048;
+
+fn main_computer_address(profile: &RobotProfile) -> Ipv4 {
+    [192, 168, profile.subnet, profile.main_computer]
+}
+
+fn broadcast_address(profile: &RobotProfile) -> Ipv4 {
+    [192, 168, profile.subnet, 255]
+}
+
+/// Renvoie l'adresse MAC de la carte d'identifiant `mac` sur le robot décrit par `profile`
+pub fn board_mac(profile: &RobotProfile, mac: u8) -> [u8; 6] {
+    // using a 'locally administered' MAC address
+    [
+        0x02,
+        0x01,
+        0x02,
+        0x03,
+        0x04u8.wrapping_add(profile.subnet),
+        mac,
+    ]
+}
+
+/// Renvoie l'IP de l'ordinateur embarqué du robot décrit par `profile` (192.168.x.254)
+pub fn main_computer_ip(profile: &RobotProfile) -> IpAddress {
+    device::ip_address(main_computer_address(profile))
+}
+
+/// Renvoie l'adresse de broadcast du sous-réseau du robot décrit par `profile` (192.168.x.255)
+pub fn broadcast_ip(profile: &RobotProfile) -> IpAddress {
+    device::ip_address(broadcast_address(profile))
 }
 
-/// Renvoie l'IP de l'ordinateur embarqué (192.168.x.254)
+/// Renvoie l'IP de l'ordinateur embarqué du profil par défaut, voir [`main_computer_ip`]
 pub fn get_main_computer_ip() -> IpAddress {
-    IpAddress::new(192, 168, get_subnet(), 254)
+    main_computer_ip(&RobotProfile::default())
+}
+
+/// Renvoie l'adresse de broadcast du profil par défaut, voir [`broadcast_ip`]
+pub fn get_broadcast_ip() -> IpAddress {
+    broadcast_ip(&RobotProfile::default())
 }
 
 /// Initialise la connexion ethernet pour permettre une communication
 /// a l'aide de la librairie W5500. La socket a utiliser pour lire
 /// les message est eth::SOCKET_UDP
-pub fn init_eth<E: core::fmt::Debug>(
+///
+/// Le robot est celui du profil par défaut, voir [`try_init_eth_with`] pour en choisir un autre.
+///
+/// Renvoie la première erreur du bus SPI, l'initialisation peut alors être recommencée.
+pub fn try_init_eth<E>(
     eth: &mut W5500,
     spi: &mut FullDuplex<u8, Error = E>,
     mac: u8,
     ip: u8,
-) {
-    let ip = IpAddress::new(192, 168, get_subnet(), ip);
-    let mac = MacAddress::new(0x02, 0x01, 0x02, 0x03, 0x04 + get_subnet(), mac);
-    //eth.set_mode(spi,false, false, false, true).unwrap();
-    // using a 'locally administered' MAC address
-    eth.init(spi).expect("Failed to initialize w5500");
-    eth.set_mode(spi, false, false, false, true).unwrap();
-    eth.set_mac(spi, &mac).unwrap();
-    eth.set_ip(spi, &ip).unwrap();
-    eth.set_subnet(spi, &IpAddress::new(255, 255, 255, 0))
-        .unwrap();
-    eth.set_gateway(spi, &IpAddress::new(192, 168, get_subnet(), 254))
-        .unwrap();
+) -> Result<(), E> {
+    try_init_eth_with(&RobotProfile::default(), eth, spi, mac, ip)
+}
+
+/// Initialise la connexion ethernet sur le sous-réseau du robot décrit par `profile`, voir
+/// [`try_init_eth`]
+pub fn try_init_eth_with<E>(
+    profile: &RobotProfile,
+    eth: &mut W5500,
+    spi: &mut FullDuplex<u8, Error = E>,
+    mac: u8,
+    ip: u8,
+) -> Result<(), E> {
+    let ip = IpAddress::new(192, 168, profile.subnet, ip);
+    let [a, b, c, d, e, f] = board_mac(profile, mac);
+    let mac = MacAddress::new(a, b, c, d, e, f);
+    eth.init(spi)?;
+    eth.set_mode(spi, false, false, false, true)?;
+    eth.set_mac(spi, &mac)?;
+    eth.set_ip(spi, &ip)?;
+    eth.set_subnet(spi, &IpAddress::new(255, 255, 255, 0))?;
+    eth.set_gateway(spi, &main_computer_ip(profile))?;
     //eth.reset_interrupt(spi, SOCKET_UDP, Interrupt::Received)
     //    .expect("Failed ot reset interrupts for W5500");
+    Ok(())
+}
+
+/// Initialise le contrôleur `device` sur le sous-réseau du robot décrit par `profile`, comme
+/// [`try_init_eth_with`] pour le W5500
+pub fn try_init_device<D: NetDevice>(
+    device: &mut D,
+    profile: &RobotProfile,
+    mac: u8,
+    ip: u8,
+) -> Result<(), D::Error> {
+    device.configure(
+        board_mac(profile, mac),
+        [192, 168, profile.subnet, ip],
+        main_computer_address(profile),
+    )
+}
+
+/// Initialise la connexion ethernet, voir [`try_init_eth`].
+///
+/// # Panics
+///
+/// Panique si le W5500 ne répond pas.
+#[deprecated(note = "utiliser `try_init_eth`, qui ne panique pas")]
+#[allow(clippy::expect_used)]
+pub fn init_eth<E: core::fmt::Debug>(
+    eth: &mut W5500,
+    spi: &mut FullDuplex<u8, Error = E>,
+    mac: u8,
+    ip: u8,
+) {
+    try_init_eth(eth, spi, mac, ip).expect("Failed to initialize w5500");
 }
 
 /// Ecoute sur un port avec un socket donné
+pub fn try_listen_on<E>(
+    eth: &mut W5500,
+    spi: &mut FullDuplex<u8, Error = E>,
+    port: u16,
+    socket: Socket,
+) -> Result<(), E> {
+    eth.listen_udp(spi, socket, port)
+}
+
+/// Ecoute sur le port de la carte `id` donné par `ports`, voir [`try_listen_on`]
+pub fn try_listen_module<E>(
+    eth: &mut W5500,
+    spi: &mut FullDuplex<u8, Error = E>,
+    ports: &PortMap,
+    id: u16,
+    socket: Socket,
+) -> Result<(), E> {
+    try_listen_on(eth, spi, ports.elec_port(id), socket)
+}
+
+/// Ecoute sur un port avec un socket donné, voir [`try_listen_on`].
+///
+/// # Panics
+///
+/// Panique si le W5500 ne répond pas.
+#[deprecated(note = "utiliser `try_listen_on`, qui ne panique pas")]
+#[allow(clippy::expect_used)]
 pub fn listen_on<E: core::fmt::Debug>(
     eth: &mut W5500,
     spi: &mut FullDuplex<u8, Error = E>,
     port: u16,
     socket: Socket,
 ) {
-    eth.listen_udp(spi, socket, port).expect("Failed to listen");
+    try_listen_on(eth, spi, port, socket).expect("Failed to listen");
+}
+
+/// Le registre de configuration du PHY (PHYCFGR), dont le bit 0 indique si le lien est établi
+const PHY_CONFIG: u16 = 0x002E;
+
+/// Renvoie vrai si le câble ethernet est branché et le lien établi avec le switch
+pub fn try_link_up<E>(eth: &mut W5500, spi: &mut FullDuplex<u8, Error = E>) -> Result<bool, E> {
+    Ok(eth.read_u8(spi, Register::CommonRegister(PHY_CONFIG))? & 0x01 != 0)
+}
+
+/// Un changement d'état du lien ethernet, renvoyé par [`LinkMonitor::poll`]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum LinkEvent {
+    /// Le lien est perdu : plus aucun message n'est échangé
+    Down,
+    /// Le lien est rétabli et les sockets écoutent de nouveau
+    Up,
+}
+
+/// Surveille le lien ethernet et reconfigure le W5500 quand il est rétabli.
+///
+/// Quand le switch redémarre, le W5500 perd sa configuration : à chaque appel de
+/// [`LinkMonitor::poll`], le moniteur lit l'état du lien et, s'il revient, réinitialise le W5500
+/// (voir [`try_init_eth_with`]) et remet les sockets en écoute. L'application est prévenue par le
+/// [`LinkEvent`] renvoyé, par exemple pour envoyer un diagnostic `LinkLost` :
+///
+/// ```ignore
+/// let mut link = LinkMonitor::new(&profile, mac, ip).listen(servo_port, SOCKET_UDP);
+/// if let Some(LinkEvent::Up) = link.poll(&mut w5500, &mut spi)? {
+///     channel.send(&reporter.report(ErrorCode::LinkLost, Severity::Warning, None))?;
+/// }
+/// ```
+pub struct LinkMonitor {
+    profile: RobotProfile,
+    mac: u8,
+    ip: u8,
+    listens: Vec<(u16, Socket), U8>,
+    up: bool,
+}
+
+impl core::fmt::Debug for LinkMonitor {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        write!(f, "LinkMonitor {{ up: {} }}", self.up)
+    }
+}
+
+impl LinkMonitor {
+    /// Crée un moniteur pour une carte configurée avec `try_init_eth_with(profile, .., mac, ip)`.
+    /// Le lien est supposé établi.
+    pub fn new(profile: &RobotProfile, mac: u8, ip: u8) -> Self {
+        LinkMonitor {
+            profile: *profile,
+            mac,
+            ip,
+            listens: Vec::new(),
+            up: true,
+        }
+    }
+
+    /// Remet la socket `socket` en écoute sur `port` quand le lien est rétabli
+    pub fn listen(mut self, port: u16, socket: Socket) -> Self {
+        // Le W5500 n'a que 8 sockets : une neuvième écoute en remplacerait forcément une autre
+        let _ = self.listens.push((port, socket));
+        self
+    }
+
+    /// Renvoie vrai si le lien était établi lors du dernier appel à [`LinkMonitor::poll`]
+    pub fn is_up(&self) -> bool {
+        self.up
+    }
+
+    /// Lit l'état du lien et renvoie le changement depuis le dernier appel, s'il y en a un.
+    ///
+    /// En cas d'erreur du bus SPI pendant la reconfiguration, le lien reste considéré comme
+    /// perdu et la reconfiguration est recommencée au prochain appel.
+    pub fn poll<E>(
+        &mut self,
+        eth: &mut W5500,
+        spi: &mut FullDuplex<u8, Error = E>,
+    ) -> Result<Option<LinkEvent>, E> {
+        let up = try_link_up(eth, spi)?;
+        if up == self.up {
+            return Ok(None);
+        }
+        if up {
+            try_init_eth_with(&self.profile, eth, spi, self.mac, self.ip)?;
+            for &(port, socket) in self.listens.iter() {
+                try_listen_on(eth, spi, port, socket)?;
+            }
+        }
+        self.up = up;
+        Ok(Some(if up { LinkEvent::Up } else { LinkEvent::Down }))
+    }
+}
+
+/// Transport UDP au-dessus d'un contrôleur ethernet : les messages pour la carte `id` sont
+/// envoyés depuis son port d'écoute vers son port d'envoi de l'ordinateur embarqué (voir
+/// [`PortMap`]).
+///
+/// Les messages pour `ID_BROADCAST` sont envoyés à toutes les cartes, sur l'adresse de broadcast
+/// et le port d'écoute de `ID_BROADCAST`.
+///
+/// La socket doit au préalable écouter sur le port de la carte (voir [`NetDevice::listen`]).
+///
+/// Si le contrôleur n'a pas la place pour un message, l'envoi renvoie `nb::Error::WouldBlock` au
+/// lieu d'écraser les messages en attente : il faut attendre qu'il les ait envoyés avec
+/// [`NetTransport::flush`] puis recommencer.
+#[derive(Debug)]
+pub struct NetTransport<D: NetDevice> {
+    device: D,
+    main_computer: Ipv4,
+    broadcast: Ipv4,
+    ports: PortMap,
+}
+
+/// Transport UDP au-dessus d'une socket du W5500, seul sur son bus SPI
+pub type UdpTransport<'a, 'b, S> = NetTransport<W5500Device<'a, 'b, &'a mut S>>;
+
+impl<'a, 'b, S> UdpTransport<'a, 'b, S>
+where
+    S: FullDuplex<u8>,
+{
+    /// Crée un transport UDP utilisant la socket `socket` du W5500, vers l'ordinateur embarqué
+    /// du profil par défaut
+    pub fn new(eth: &'a mut W5500<'b>, spi: &'a mut S, socket: Socket) -> Self {
+        NetTransport::w5500(eth, spi, socket)
+    }
+}
+
+impl<'a, 'b, B> NetTransport<W5500Device<'a, 'b, B>>
+where
+    B: SpiAccess,
+{
+    /// Crée un transport UDP utilisant la socket `socket` du W5500, dont le bus SPI est
+    /// éventuellement partagé (voir [`SpiAccess`])
+    pub fn w5500(eth: &'a mut W5500<'b>, bus: B, socket: Socket) -> Self {
+        NetTransport::with_device(W5500Device::new(eth, bus, socket))
+    }
+
+    /// Renvoie la place libre dans le buffer d'envoi de la socket, en octets
+    pub fn tx_free_size(&mut self) -> Result<u16, W5500Error<B>> {
+        self.device.tx_free_size()
+    }
+}
+
+impl<D: NetDevice> NetTransport<D> {
+    /// Crée un transport UDP utilisant `device`, vers l'ordinateur embarqué du profil par défaut
+    pub fn with_device(device: D) -> Self {
+        NetTransport {
+            device,
+            main_computer: main_computer_address(&RobotProfile::default()),
+            broadcast: broadcast_address(&RobotProfile::default()),
+            ports: PortMap::default(),
+        }
+    }
+
+    /// Utilise les ports de `ports` au lieu de ceux par défaut
+    pub fn with_ports(mut self, ports: PortMap) -> Self {
+        self.ports = ports;
+        self
+    }
+
+    /// Envoie les messages à l'ordinateur embarqué du robot décrit par `profile`
+    pub fn with_profile(mut self, profile: &RobotProfile) -> Self {
+        self.main_computer = main_computer_address(profile);
+        self.broadcast = broadcast_address(profile);
+        self
+    }
+
+    /// Renvoie le contrôleur utilisé par le transport
+    pub fn device(&mut self) -> &mut D {
+        &mut self.device
+    }
+
+    /// Renvoie `nb::Error::WouldBlock` tant que le contrôleur n'a pas envoyé tous les messages,
+    /// à utiliser avec `nb::block!(transport.flush())`
+    pub fn flush(&mut self) -> nb::Result<(), D::Error> {
+        self.device.flush()
+    }
+}
+
+impl<D: NetDevice> Transport for NetTransport<D> {
+    type Error = nb::Error<D::Error>;
+
+    fn send(&mut self, id: u16, data: &[u8]) -> Result<(), Self::Error> {
+        let (ip, port) = if id == ID_BROADCAST {
+            (self.broadcast, self.ports.elec_port(id))
+        } else {
+            (self.main_computer, self.ports.info_port(id))
+        };
+        self.device
+            .send_to(self.ports.elec_port(id), ip, port, data)
+    }
+
+    fn receive(&mut self, _id: u16, buffer: &mut [u8]) -> Result<Option<usize>, Self::Error> {
+        self.device.receive(buffer).map_err(nb::Error::Other)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{board_mac, try_init_device, Ipv4, NetDevice, NetTransport};
+    use crate::profile::RobotProfile;
+    use crate::transmission::channel::{Channel, ChannelError};
+    use crate::transmission::color::Color;
+    use crate::transmission::id::{ID_BROADCAST, ID_COLOR};
+    use crate::transmission::ports::PortMap;
+    use heapless::consts::U64;
+    use std::vec::Vec;
+
+    /// Contrôleur de test qui n'a de la place que pour `capacity` octets
+    #[derive(Debug, Default)]
+    struct FakeDevice {
+        configuration: Option<([u8; 6], Ipv4, Ipv4)>,
+        sent: Vec<(u16, Ipv4, u16, Vec<u8>)>,
+        capacity: usize,
+    }
+
+    impl NetDevice for FakeDevice {
+        type Error = ();
+
+        fn configure(&mut self, mac: [u8; 6], ip: Ipv4, gateway: Ipv4) -> Result<(), ()> {
+            self.configuration = Some((mac, ip, gateway));
+            Ok(())
+        }
+
+        fn listen(&mut self, _port: u16) -> Result<(), ()> {
+            Ok(())
+        }
+
+        fn send_to(
+            &mut self,
+            local_port: u16,
+            ip: Ipv4,
+            port: u16,
+            data: &[u8],
+        ) -> nb::Result<(), ()> {
+            if data.len() > self.capacity {
+                return Err(nb::Error::WouldBlock);
+            }
+            self.capacity -= data.len();
+            self.sent.push((local_port, ip, port, data.to_vec()));
+            Ok(())
+        }
+
+        fn receive(&mut self, _buffer: &mut [u8]) -> Result<Option<usize>, ()> {
+            Ok(None)
+        }
+
+        fn link_up(&mut self) -> Result<bool, ()> {
+            Ok(true)
+        }
+    }
+
+    #[test]
+    fn net_transport() {
+        let profile = RobotProfile::secondary();
+        let mut device = FakeDevice {
+            capacity: 8,
+            ..Default::default()
+        };
+        try_init_device(&mut device, &profile, 3, 10).unwrap();
+        let (mac, ip, gateway) = device.configuration.unwrap();
+        assert_eq!(mac[4], 0x06);
+        let last = RobotProfile {
+            subnet: 255,
+            ..profile
+        };
+        assert_eq!(board_mac(&last, 3)[4], 0x03);
+        assert_eq!(ip, [192, 168, 2, 10]);
+        assert_eq!(gateway, [192, 168, 2, 254]);
+
+        let transport = NetTransport::with_device(device)
+            .with_profile(&profile)
+            .with_ports(PortMap::default());
+        let mut channel: Channel<Color, _, U64> = Channel::new(ID_COLOR, transport);
+        channel.send(&Color::Red).unwrap();
+        match channel.send(&Color::Blue) {
+            Err(ChannelError::Transport(nb::Error::WouldBlock)) => {}
+            other => panic!("{:?}", other),
+        }
+        let sent = &channel.transport().device().sent;
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].0, 50 + ID_COLOR);
+        assert_eq!(sent[0].1, [192, 168, 2, 254]);
+        assert_eq!(sent[0].2, 5000 + ID_COLOR);
+
+        // Le contrôleur a envoyé les messages en attente
+        channel.transport().device().capacity = 8;
+        let mut broadcast: Channel<Color, _, U64> =
+            Channel::new(ID_BROADCAST, channel.release().with_profile(&profile));
+        broadcast.send(&Color::Red).unwrap();
+        let sent = &broadcast.transport().device().sent;
+        assert_eq!(sent[1].1, [192, 168, 2, 255]);
+        assert_eq!(sent[1].2, 50 + ID_BROADCAST);
+    }
 }
@@ -0,0 +1,78 @@
+//! Événements de match diffusés à toutes les cartes.
+//!
+//! L'arrêt d'urgence et le départ du match doivent atteindre toutes les cartes en même temps :
+//! une seule [`EventFrame`] est envoyée sur l'identifiant de diffusion
+//! [`ID_BROADCAST`](crate::transmission::id::ID_BROADCAST), vers l'adresse de broadcast du
+//! sous-réseau du robot (voir [`get_broadcast_ip`](crate::transmission::eth::get_broadcast_ip)).
+//! Chaque carte doit donc écouter sur le port d'écoute de `ID_BROADCAST` (voir
+//! [`PortMap::elec_port`](crate::transmission::ports::PortMap::elec_port)) en plus du sien.
+
+use crate::transmission::id::ID_BROADCAST;
+use crate::transmission::Jsonizable;
+
+/// Les événements de match
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum MatchEvent {
+    /// Arrêt d'urgence : tous les actionneurs doivent s'arrêter
+    EmergencyStop,
+    /// Fin de l'arrêt d'urgence
+    EmergencyRelease,
+    /// Départ du match
+    MatchStart,
+    /// Fin du match : plus rien ne doit bouger
+    MatchEnd,
+}
+
+/// Trame d'événement, destinée à une carte ou à toutes
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize, Serialize, Jsonizable)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct EventFrame {
+    /// L'événement
+    pub event: MatchEvent,
+    /// L'identifiant de la carte destinataire, ou `ID_BROADCAST` pour toutes les cartes
+    pub target: u16,
+    /// Heure de l'émetteur en millisecondes
+    pub timestamp: u32,
+}
+
+impl EventFrame {
+    /// Crée un événement destiné à toutes les cartes
+    pub fn broadcast(event: MatchEvent, timestamp: u32) -> Self {
+        EventFrame {
+            event,
+            target: ID_BROADCAST,
+            timestamp,
+        }
+    }
+
+    /// Renvoie vrai si l'événement concerne la carte `id`
+    pub fn is_for(&self, id: u16) -> bool {
+        self.target == ID_BROADCAST || self.target == id
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{EventFrame, MatchEvent};
+    use crate::transmission::id::{ID_BROADCAST, ID_NAVIGATION, ID_SERVO};
+    use crate::transmission::Jsonizable;
+    use heapless::consts::U64;
+
+    #[test]
+    fn ser_deser_event() {
+        let event = EventFrame::broadcast(MatchEvent::EmergencyStop, 42);
+        let data = "{\"event\":\"EmergencyStop\",\"target\":255,\"timestamp\":42}";
+        assert_eq!(event.to_string::<U64>().unwrap(), data);
+        assert_eq!(EventFrame::from_json_slice(data.as_bytes()).unwrap(), event);
+        assert_eq!(event.target, ID_BROADCAST);
+        assert!(event.is_for(ID_SERVO));
+
+        let event = EventFrame {
+            target: ID_NAVIGATION,
+            ..event
+        };
+        assert!(event.is_for(ID_NAVIGATION));
+        assert!(!event.is_for(ID_SERVO));
+    }
+}
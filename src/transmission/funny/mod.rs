@@ -0,0 +1,221 @@
+//! Actions de fin de match (funny action).
+//!
+//! Le règlement impose que la funny action soit réalisée de façon autonome : l'informatique envoie
+//! la liste des actions avant le match dans une [`FunnyActionFrame`], puis le
+//! [`FunnyActionScheduler`] de la carte les exécute à partir de son propre [`MatchTimer`], même
+//! si le lien avec la Raspberry Pi est coupé.
+//!
+//! ```ignore
+//! scheduler.update(&timer, &clock, |action| match action.kind {
+//!     FunnyActionKind::Servo => servos.set_position(action.target, action.value),
+//!     _ => {}
+//! });
+//! ```
+
+use crate::time::Clock;
+use crate::transmission::io::tirette::MatchTimer;
+use crate::transmission::Jsonizable;
+
+/// Nombre maximal d'actions dans une [`FunnyActionFrame`]
+pub const MAX_FUNNY_ACTIONS: usize = 8;
+
+/// Le type d'actionneur commandé par une action
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum FunnyActionKind {
+    /// Emplacement vide
+    Nothing,
+    /// Position d'un servo-moteur : `target` est son identifiant, `value` la position
+    Servo,
+    /// Pompe de la carte pneumatique : `target` est son indice, `value` vaut 1 pour l'allumer
+    Pump,
+    /// Vanne de la carte pneumatique : `target` est son indice, `value` vaut 1 pour l'ouvrir
+    Valve,
+    /// Broche d'un [`GpioBank`](crate::transmission::io::GpioBank) : `target` est la broche,
+    /// `value` vaut 1 pour l'état haut
+    Gpio,
+}
+
+impl Default for FunnyActionKind {
+    fn default() -> Self {
+        FunnyActionKind::Nothing
+    }
+}
+
+/// Une commande d'actionneur à exécuter à un instant donné par rapport à la fin du match
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct FunnyAction {
+    /// L'actionneur commandé
+    pub kind: FunnyActionKind,
+    /// L'identifiant ou l'indice de l'actionneur
+    pub target: u8,
+    /// La valeur à appliquer
+    pub value: u16,
+    /// Instant d'exécution en millisecondes par rapport à la fin du match (négatif : avant la fin)
+    pub offset: i32,
+}
+
+/// Trame envoyée par l'informatique pour configurer les actions de fin de match
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Deserialize, Serialize, Jsonizable)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct FunnyActionFrame {
+    /// Les actions, les emplacements inutilisés sont de type `Nothing`
+    pub actions: [FunnyAction; MAX_FUNNY_ACTIONS],
+}
+
+/// Exécute les actions d'une [`FunnyActionFrame`] à partir du chronomètre du match
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct FunnyActionScheduler {
+    frame: FunnyActionFrame,
+    /// Bit `i` : l'action `i` a déjà été exécutée
+    done: u8,
+}
+
+impl FunnyActionScheduler {
+    /// Crée un ordonnanceur sans action
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Remplace les actions à exécuter. Les actions modifiées dont l'instant est déjà passé
+    /// seront exécutées au prochain `update` ; une action identique à celle de son emplacement
+    /// n'est pas rejouée si elle a déjà été exécutée.
+    pub fn configure(&mut self, frame: &FunnyActionFrame) {
+        for i in 0..MAX_FUNNY_ACTIONS {
+            if self.frame.actions[i] != frame.actions[i] {
+                self.done &= !(1 << i);
+            }
+        }
+        self.frame = *frame;
+    }
+
+    /// Appelle `apply` pour chaque action dont l'instant est atteint et qui n'a pas encore été
+    /// exécutée. Ne fait rien tant que le match n'a pas commencé.
+    pub fn update<C, F>(&mut self, timer: &MatchTimer, clock: &C, mut apply: F)
+    where
+        C: Clock,
+        F: FnMut(&FunnyAction),
+    {
+        let elapsed = match timer.elapsed(clock) {
+            Some(elapsed) => i64::from(elapsed),
+            None => return,
+        };
+        let end = i64::from(timer.duration());
+        for (index, action) in self.frame.actions.iter().enumerate() {
+            let bit = 1 << index;
+            if action.kind == FunnyActionKind::Nothing || self.done & bit != 0 {
+                continue;
+            }
+            if elapsed >= end + i64::from(action.offset) {
+                self.done |= bit;
+                apply(action);
+            }
+        }
+    }
+
+    /// Renvoie vrai si toutes les actions ont été exécutées
+    pub fn is_done(&self) -> bool {
+        self.frame
+            .actions
+            .iter()
+            .enumerate()
+            .all(|(index, action)| {
+                action.kind == FunnyActionKind::Nothing || self.done & (1 << index) != 0
+            })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{FunnyAction, FunnyActionFrame, FunnyActionKind, FunnyActionScheduler};
+    use crate::time::Instant;
+    use crate::transmission::io::tirette::MatchTimer;
+    use crate::transmission::Jsonizable;
+    use core::cell::Cell;
+    use heapless::consts::U1024;
+    use heapless::String;
+    use std::vec::Vec;
+
+    fn frame() -> FunnyActionFrame {
+        let mut frame = FunnyActionFrame::default();
+        frame.actions[0] = FunnyAction {
+            kind: FunnyActionKind::Servo,
+            target: 3,
+            value: 512,
+            offset: 500,
+        };
+        frame.actions[2] = FunnyAction {
+            kind: FunnyActionKind::Pump,
+            target: 0,
+            value: 1,
+            offset: -1000,
+        };
+        frame
+    }
+
+    #[test]
+    fn ser_deser_funny_action() {
+        let frame = frame();
+        let json: String<U1024> = frame.to_string().unwrap();
+        assert_eq!(
+            FunnyActionFrame::from_json_slice(json.as_bytes()).unwrap(),
+            frame
+        );
+    }
+
+    #[test]
+    fn scheduler_runs_actions() {
+        let now = Cell::new(Instant::from_millis(0));
+        let clock = || now.get();
+        let mut timer = MatchTimer::new(10_000);
+        let mut scheduler = FunnyActionScheduler::new();
+        scheduler.configure(&frame());
+        let mut applied = Vec::new();
+
+        scheduler.update(&timer, &clock, |action| applied.push(*action));
+        assert!(applied.is_empty());
+
+        timer.start(&clock);
+        now.set(Instant::from_millis(8_999));
+        scheduler.update(&timer, &clock, |action| applied.push(*action));
+        assert!(applied.is_empty());
+
+        now.set(Instant::from_millis(9_000));
+        scheduler.update(&timer, &clock, |action| applied.push(*action));
+        assert_eq!(applied, [frame().actions[2]]);
+        assert!(!scheduler.is_done());
+
+        now.set(Instant::from_millis(11_000));
+        scheduler.update(&timer, &clock, |action| applied.push(*action));
+        scheduler.update(&timer, &clock, |action| applied.push(*action));
+        assert_eq!(applied, [frame().actions[2], frame().actions[0]]);
+        assert!(scheduler.is_done());
+    }
+
+    #[test]
+    fn same_frame_not_replayed() {
+        let now = Cell::new(Instant::from_millis(0));
+        let clock = || now.get();
+        let mut timer = MatchTimer::new(10_000);
+        let mut scheduler = FunnyActionScheduler::new();
+        scheduler.configure(&frame());
+        let mut applied = Vec::new();
+
+        timer.start(&clock);
+        now.set(Instant::from_millis(9_000));
+        scheduler.update(&timer, &clock, |action| applied.push(*action));
+        assert_eq!(applied, [frame().actions[2]]);
+
+        scheduler.configure(&frame());
+        scheduler.update(&timer, &clock, |action| applied.push(*action));
+        assert_eq!(applied, [frame().actions[2]]);
+
+        let mut changed = frame();
+        changed.actions[2].value = 0;
+        scheduler.configure(&changed);
+        scheduler.update(&timer, &clock, |action| applied.push(*action));
+        assert_eq!(applied, [frame().actions[2], changed.actions[2]]);
+    }
+}
@@ -0,0 +1,129 @@
+//! État des éléments de jeu et des mécanismes, envoyé par les cartes à l'informatique.
+//!
+//! L'estimateur de score de l'informatique lit directement quels éléments le robot tient, combien
+//! sont stockés et dans quel état sont les mécanismes, au lieu de le déduire des positions des
+//! servo-moteurs. La signification des emplacements, des stockages et des mécanismes est propre à
+//! chaque robot et à chaque année.
+
+use crate::transmission::color::Color;
+use crate::transmission::Jsonizable;
+
+/// Nombre d'emplacements dans lesquels le robot peut tenir un élément de jeu
+pub const MAX_HELD: usize = 4;
+/// Nombre de stockages dont le contenu est compté
+pub const MAX_STORAGES: usize = 4;
+/// Nombre de mécanismes dont l'état est envoyé
+pub const MAX_MECHANISMS: usize = 6;
+
+/// Un élément de jeu tenu par le robot
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum GameElement {
+    /// L'emplacement est vide
+    Empty,
+    /// Un élément est présent mais sa couleur n'est pas connue
+    Unknown,
+    /// Un élément rouge
+    Red,
+    /// Un élément vert
+    Green,
+    /// Un élément bleu
+    Blue,
+}
+
+impl Default for GameElement {
+    fn default() -> Self {
+        GameElement::Empty
+    }
+}
+
+impl From<Color> for GameElement {
+    fn from(color: Color) -> Self {
+        match color {
+            Color::Red => GameElement::Red,
+            Color::Green => GameElement::Green,
+            Color::Blue => GameElement::Blue,
+        }
+    }
+}
+
+/// L'état d'un mécanisme
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum MechanismState {
+    /// L'état n'est pas connu, ou le mécanisme n'existe pas
+    Unknown,
+    /// Le mécanisme est rangé
+    Retracted,
+    /// Le mécanisme est déployé
+    Deployed,
+    /// Le mécanisme est en mouvement
+    Moving,
+    /// Le mécanisme est en défaut (bloqué, servo-moteur en erreur...)
+    Fault,
+}
+
+impl Default for MechanismState {
+    fn default() -> Self {
+        MechanismState::Unknown
+    }
+}
+
+/// Trame décrivant les éléments de jeu tenus et stockés par le robot
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Deserialize, Serialize, Jsonizable)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct GameStatusFrame {
+    /// Les éléments tenus, un par emplacement
+    pub held: [GameElement; MAX_HELD],
+    /// Le nombre d'éléments dans chaque stockage
+    pub storage: [u8; MAX_STORAGES],
+    /// L'état de chaque mécanisme
+    pub mechanisms: [MechanismState; MAX_MECHANISMS],
+}
+
+impl GameStatusFrame {
+    /// Renvoie le nombre d'éléments tenus
+    pub fn held_count(&self) -> usize {
+        self.held
+            .iter()
+            .filter(|element| **element != GameElement::Empty)
+            .count()
+    }
+
+    /// Renvoie le nombre total d'éléments dans les stockages
+    pub fn stored_count(&self) -> u16 {
+        self.storage.iter().map(|count| u16::from(*count)).sum()
+    }
+
+    /// Renvoie vrai si un mécanisme est en défaut
+    pub fn has_fault(&self) -> bool {
+        self.mechanisms.contains(&MechanismState::Fault)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{GameElement, GameStatusFrame, MechanismState};
+    use crate::transmission::color::Color;
+    use crate::transmission::Jsonizable;
+    use heapless::consts::U512;
+    use heapless::String;
+
+    #[test]
+    fn ser_deser_game_status() {
+        let mut frame = GameStatusFrame::default();
+        frame.held[0] = Color::Green.into();
+        frame.held[2] = GameElement::Unknown;
+        frame.storage = [3, 0, 2, 0];
+        frame.mechanisms[1] = MechanismState::Deployed;
+        assert_eq!(frame.held_count(), 2);
+        assert_eq!(frame.stored_count(), 5);
+        assert!(!frame.has_fault());
+
+        let json: String<U512> = frame.to_string().unwrap();
+        assert_eq!(
+            GameStatusFrame::from_json_slice(json.as_bytes()).unwrap(),
+            frame
+        );
+    }
+}
@@ -0,0 +1,362 @@
+//! Trame de santé envoyée périodiquement par les cartes.
+//!
+//! Elle permet de vérifier que la boucle de contrôle tient sa période quand on ajoute des
+//! fonctionnalités : le [`LoopMonitor`] mesure la gigue et la marge de calcul de la boucle, les
+//! [`TransmissionCounters`] comptent les messages échangés par un
+//! [`Channel`](crate::transmission::channel::Channel).
+//!
+//! Les [`ParseFailures`] comptent les trames JSON illisibles pour chaque type de message : si
+//! l'informatique et l'électronique n'utilisent pas la même version d'une trame, le compteur
+//! augmente et [`ParseFailures::report`] le signale dans une
+//! [`DiagnosticFrame`](crate::transmission::diagnostic::DiagnosticFrame).
+
+use crate::transmission::diagnostic::{DiagnosticFrame, ErrorCode, Severity};
+use crate::transmission::{Error, Jsonizable, MessageKind};
+use serde_json_core::de::Error as DError;
+
+/// Compteurs des messages échangés sur un canal
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct TransmissionCounters {
+    /// Nombre de messages envoyés
+    pub sent: u32,
+    /// Nombre de messages reçus et correctement lus
+    pub received: u32,
+    /// Nombre de messages reçus qui n'ont pas pu être lus
+    pub invalid: u32,
+    /// Nombre d'envois qui ont échoué
+    pub send_errors: u32,
+    /// Nombre de messages reçus rejetés car leur authentification est fausse
+    #[serde(default)]
+    pub unauthenticated: u32,
+    /// Nombre de messages non envoyés car le débit maximal du canal était atteint
+    #[serde(default)]
+    pub rate_limited: u32,
+}
+
+/// Trame décrivant l'état de santé d'une carte
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Deserialize, Serialize, Jsonizable)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct HealthFrame {
+    /// Période nominale de la boucle de contrôle en microsecondes
+    pub loop_period: u32,
+    /// Plus grand écart mesuré entre la période réelle et la période nominale en microsecondes
+    pub max_jitter: u32,
+    /// Plus petite marge de calcul mesurée, en pourcents de la période
+    pub cpu_headroom: u8,
+    /// Place libre sur la pile en octets
+    pub free_stack: u32,
+    /// Compteurs de transmission
+    pub transmission: TransmissionCounters,
+}
+
+/// Mesure la gigue et la marge de calcul d'une boucle périodique.
+///
+/// Il faut appeler [`LoopMonitor::begin`] au début de chaque itération et [`LoopMonitor::end`] à
+/// la fin, avec l'heure courante en microsecondes.
+#[derive(Debug)]
+pub struct LoopMonitor {
+    period: u32,
+    last_begin: Option<u32>,
+    current_begin: u32,
+    max_jitter: u32,
+    min_headroom: u8,
+}
+
+impl LoopMonitor {
+    /// Crée un moniteur pour une boucle de période nominale `period` microsecondes
+    pub fn new(period: u32) -> Self {
+        LoopMonitor {
+            period,
+            last_begin: None,
+            current_begin: 0,
+            max_jitter: 0,
+            min_headroom: 100,
+        }
+    }
+
+    /// Signale le début d'une itération à l'heure `now`
+    pub fn begin(&mut self, now: u32) {
+        if let Some(last) = self.last_begin {
+            let actual = now.wrapping_sub(last);
+            let jitter = if actual > self.period {
+                actual - self.period
+            } else {
+                self.period - actual
+            };
+            self.max_jitter = self.max_jitter.max(jitter);
+        }
+        self.last_begin = Some(now);
+        self.current_begin = now;
+    }
+
+    /// Signale la fin d'une itération à l'heure `now`
+    pub fn end(&mut self, now: u32) {
+        let busy = u64::from(now.wrapping_sub(self.current_begin));
+        let period = u64::from(self.period.max(1));
+        let headroom = if busy >= period {
+            0
+        } else {
+            ((period - busy) * 100 / period) as u8
+        };
+        self.min_headroom = self.min_headroom.min(headroom);
+    }
+
+    /// Renvoie la plus grande gigue mesurée en microsecondes
+    pub fn max_jitter(&self) -> u32 {
+        self.max_jitter
+    }
+
+    /// Renvoie la plus petite marge de calcul mesurée en pourcents
+    pub fn cpu_headroom(&self) -> u8 {
+        self.min_headroom
+    }
+
+    /// Construit la trame de santé et remet à zéro les mesures
+    pub fn report(&mut self, free_stack: u32, transmission: TransmissionCounters) -> HealthFrame {
+        let frame = HealthFrame {
+            loop_period: self.period,
+            max_jitter: self.max_jitter,
+            cpu_headroom: self.min_headroom,
+            free_stack,
+            transmission,
+        };
+        self.max_jitter = 0;
+        self.min_headroom = 100;
+        frame
+    }
+}
+
+/// La dernière trame illisible reçue
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ParseFailure {
+    /// Le type de la trame
+    pub kind: MessageKind,
+    /// La position du premier octet fautif dans la trame
+    pub offset: u16,
+    /// La taille de la trame en octets
+    pub size: u16,
+}
+
+/// Renvoie vrai si l'erreur indique seulement que la trame s'arrête trop tôt
+fn is_eof(error: &Error) -> bool {
+    match error {
+        Error::Deserialization(DError::EofWhileParsingList)
+        | Error::Deserialization(DError::EofWhileParsingObject)
+        | Error::Deserialization(DError::EofWhileParsingString)
+        | Error::Deserialization(DError::EofWhileParsingValue) => true,
+        _ => false,
+    }
+}
+
+/// Renvoie la position du premier octet fautif de `data`, qui n'a pas pu être lu comme un `T`.
+///
+/// Les erreurs de `serde_json_core` ne donnent pas de position : on cherche par dichotomie le
+/// plus petit début de `data` dont la lecture échoue autrement que parce qu'il est incomplet. Si
+/// la trame est seulement tronquée, la position renvoyée est sa taille.
+pub fn error_offset<T: Jsonizable>(data: &[u8]) -> usize {
+    let fails = |size: usize| match T::from_json_slice(&data[..size]) {
+        Ok(_) => false,
+        Err(e) => !is_eof(&e),
+    };
+    if !fails(data.len()) {
+        return data.len();
+    }
+    let (mut low, mut high) = (0, data.len());
+    while low + 1 < high {
+        let middle = low + (high - low) / 2;
+        if fails(middle) {
+            high = middle;
+        } else {
+            low = middle;
+        }
+    }
+    high - 1
+}
+
+/// Compte les trames JSON illisibles pour chaque type de message
+#[derive(Debug)]
+pub struct ParseFailures {
+    counts: [u16; MessageKind::COUNT],
+    offsets: [u16; MessageKind::COUNT],
+    last: Option<ParseFailure>,
+}
+
+impl Default for ParseFailures {
+    fn default() -> Self {
+        ParseFailures::new()
+    }
+}
+
+impl ParseFailures {
+    /// Crée des compteurs à zéro
+    pub fn new() -> Self {
+        ParseFailures {
+            counts: [0; MessageKind::COUNT],
+            offsets: [0; MessageKind::COUNT],
+            last: None,
+        }
+    }
+
+    /// Lit `data` comme un `T` de type `kind` et enregistre l'échec s'il n'est pas valide
+    pub fn parse<T: Jsonizable>(&mut self, kind: MessageKind, data: &[u8]) -> Result<T, Error> {
+        T::from_json_slice(data).map_err(|e| {
+            self.record::<T>(kind, data);
+            e
+        })
+    }
+
+    /// Enregistre que la trame `data` de type `kind` n'a pas pu être lue comme un `T`
+    pub fn record<T: Jsonizable>(&mut self, kind: MessageKind, data: &[u8]) {
+        let offset = error_offset::<T>(data).min(usize::from(u16::max_value())) as u16;
+        let size = data.len().min(usize::from(u16::max_value())) as u16;
+        let count = &mut self.counts[kind.index()];
+        *count = count.saturating_add(1);
+        self.offsets[kind.index()] = offset;
+        self.last = Some(ParseFailure { kind, offset, size });
+    }
+
+    /// Renvoie le nombre de trames de type `kind` illisibles
+    pub fn count(&self, kind: MessageKind) -> u16 {
+        self.counts[kind.index()]
+    }
+
+    /// Renvoie le nombre total de trames illisibles
+    pub fn total(&self) -> u32 {
+        self.counts.iter().map(|&count| u32::from(count)).sum()
+    }
+
+    /// Renvoie la dernière trame illisible, tous types confondus
+    pub fn last(&self) -> Option<ParseFailure> {
+        self.last
+    }
+
+    /// Construit la trame de diagnostic des échecs de lecture des trames de type `kind`, avec
+    /// la position du dernier octet fautif en contexte. Renvoie `None` s'il n'y en a pas eu.
+    pub fn report(&self, kind: MessageKind) -> Option<DiagnosticFrame> {
+        match self.count(kind) {
+            0 => None,
+            occurrences => Some(DiagnosticFrame {
+                module: kind.id(),
+                code: ErrorCode::ParseFailure,
+                severity: Severity::Warning,
+                occurrences,
+                context: Some(i32::from(self.offsets[kind.index()])),
+            }),
+        }
+    }
+
+    /// Remet à zéro les compteurs
+    pub fn reset(&mut self) {
+        *self = ParseFailures::new();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{error_offset, HealthFrame, LoopMonitor, ParseFailures, TransmissionCounters};
+    use crate::transmission::diagnostic::ErrorCode;
+    use crate::transmission::navigation::NavigationFrame;
+    use crate::transmission::servo::Servo;
+    use crate::transmission::{Jsonizable, MessageKind};
+    use heapless::consts::U256;
+    use heapless::String;
+    type N = U256;
+
+    #[test]
+    fn loop_monitor() {
+        let mut monitor = LoopMonitor::new(1000);
+        monitor.begin(0);
+        monitor.end(200);
+        monitor.begin(1050);
+        monitor.end(1800);
+        monitor.begin(1990);
+        monitor.end(2000);
+        assert_eq!(monitor.max_jitter(), 60);
+        assert_eq!(monitor.cpu_headroom(), 25);
+
+        let frame = monitor.report(512, TransmissionCounters::default());
+        assert_eq!(frame.max_jitter, 60);
+        assert_eq!(frame.cpu_headroom, 25);
+        assert_eq!(monitor.max_jitter(), 0);
+        assert_eq!(monitor.cpu_headroom(), 100);
+    }
+
+    #[test]
+    fn ser_deser_health() {
+        let frame = HealthFrame {
+            loop_period: 1000,
+            max_jitter: 12,
+            cpu_headroom: 40,
+            free_stack: 2048,
+            transmission: TransmissionCounters {
+                sent: 10,
+                received: 9,
+                invalid: 1,
+                send_errors: 0,
+                unauthenticated: 0,
+                rate_limited: 0,
+            },
+        };
+        let strd: String<N> = frame.to_string().unwrap();
+        let data =
+            "{\"loop_period\":1000,\"max_jitter\":12,\"cpu_headroom\":40,\"free_stack\":2048,\
+                    \"transmission\":{\"sent\":10,\"received\":9,\"invalid\":1,\"send_errors\":0,\
+                    \"unauthenticated\":0,\"rate_limited\":0}}";
+        assert_eq!(strd, data);
+        assert_eq!(
+            HealthFrame::from_json_slice(data.as_bytes()).unwrap(),
+            frame
+        );
+    }
+
+    #[test]
+    fn parse_error_offset() {
+        let frame = NavigationFrame::default();
+        let json: String<N> = frame.to_string().unwrap();
+        let data = json.as_bytes();
+        assert_eq!(error_offset::<NavigationFrame>(data), data.len());
+
+        // Une valeur du mauvais type
+        let mut wrong = std::vec::Vec::from(data);
+        let colon = data.iter().position(|&c| c == b':').unwrap();
+        wrong[colon + 1] = b'"';
+        assert_eq!(error_offset::<NavigationFrame>(&wrong), colon + 1);
+
+        // Une trame tronquée
+        assert_eq!(error_offset::<NavigationFrame>(&data[..10]), 10);
+    }
+
+    #[test]
+    fn parse_failures() {
+        let mut failures = ParseFailures::new();
+        let json: String<N> = NavigationFrame::default().to_string().unwrap();
+        assert!(failures
+            .parse::<NavigationFrame>(MessageKind::Navigation, json.as_bytes())
+            .is_ok());
+        assert!(failures.report(MessageKind::Navigation).is_none());
+
+        assert!(failures
+            .parse::<NavigationFrame>(MessageKind::Navigation, b"{\"x\":true}")
+            .is_err());
+        assert!(failures
+            .parse::<Servo>(MessageKind::Servo, b"{\"id\":1")
+            .is_err());
+        assert_eq!(failures.count(MessageKind::Navigation), 1);
+        assert_eq!(failures.count(MessageKind::Servo), 1);
+        assert_eq!(failures.total(), 2);
+        let last = failures.last().unwrap();
+        assert_eq!(last.kind, MessageKind::Servo);
+        assert_eq!((last.offset, last.size), (7, 7));
+
+        let report = failures.report(MessageKind::Navigation).unwrap();
+        assert_eq!(report.code, ErrorCode::ParseFailure);
+        assert_eq!(report.occurrences, 1);
+        assert_eq!(report.context, Some(5));
+
+        failures.reset();
+        assert_eq!(failures.total(), 0);
+        assert!(failures.last().is_none());
+    }
+}
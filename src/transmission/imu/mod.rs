@@ -0,0 +1,73 @@
+//! Décrit les données remontées par la centrale inertielle (IMU) de la carte déplacement
+
+use crate::transmission::Jsonizable;
+
+/// Un vecteur à trois composantes, dans le repère du capteur
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Vector3 {
+    /// Composante x
+    pub x: i32,
+    /// Composante y
+    pub y: i32,
+    /// Composante z
+    pub z: i32,
+}
+
+/// Trame contenant une mesure de la centrale inertielle
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, Deserialize, Serialize, Jsonizable)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ImuFrame {
+    /// Accélération en mm/s²
+    pub accel: Vector3,
+    /// Vitesse angulaire en milliradians/s
+    pub gyro: Vector3,
+    /// Champ magnétique en dixièmes de microtesla, si le capteur a un magnétomètre
+    pub mag: Option<Vector3>,
+    /// Température du capteur en dixièmes de degrés Celsius
+    pub temperature: i16,
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ImuFrame, Vector3};
+    use crate::transmission::Jsonizable;
+    use heapless::consts::U256;
+    use heapless::String;
+    type N = U256;
+
+    #[test]
+    fn ser_deser_imu() {
+        let imu = ImuFrame {
+            accel: Vector3 {
+                x: 12,
+                y: -40,
+                z: 9810,
+            },
+            gyro: Vector3 {
+                x: 0,
+                y: 1,
+                z: -350,
+            },
+            mag: None,
+            temperature: 254,
+        };
+        let strd: String<N> = imu.to_string().unwrap();
+        let imu2 = ImuFrame::from_json_slice(strd.as_bytes()).unwrap();
+        assert_eq!(imu, imu2);
+
+        let data =
+            "{\"accel\":{\"x\":12,\"y\":-40,\"z\":9810},\"gyro\":{\"x\":0,\"y\":1,\"z\":-350},\
+                    \"mag\":{\"x\":200,\"y\":-10,\"z\":430},\"temperature\":254}";
+        let imu3 = ImuFrame::from_json_slice(data.as_bytes()).unwrap();
+        assert_eq!(
+            imu3.mag,
+            Some(Vector3 {
+                x: 200,
+                y: -10,
+                z: 430
+            })
+        );
+        assert_eq!(imu3.gyro, imu.gyro);
+    }
+}
@@ -0,0 +1,235 @@
+//! Affichage lisible des trames brutes, pour le débug.
+//!
+//! Une [`FrameDump`] reconnaît l'en-tête de la liaison série (voir
+//! [`header`](crate::transmission::channel::header)), le type du message et résume son contenu :
+//! le début du JSON, les servos d'une trame binaire, ou à défaut les premiers octets en
+//! hexadécimal. Elle s'affiche avec `{}` comme avec `{:?}`, dans les logs de la carte comme dans
+//! les tests :
+//!
+//! ```ignore
+//! // Sur la liaison série, l'identifiant est lu dans l'en-tête
+//! log::info!("{}", FrameDump::new(&bytes));
+//! // En UDP, l'identifiant est donné par le port
+//! log::info!("{}", FrameDump::with_id(&buffer[..size], ports.module_id(port)?));
+//! ```
+//! affiche par exemple `[7e id=1 len=13] Navigation {"x":0,"y":0}`.
+
+use crate::transmission::channel::{HEADER_SIZE, SYNC, TAG_SIZE};
+use crate::transmission::{Message, MessageKind, ServoGroup, FRAME_MAX_SIZE};
+use core::fmt;
+use core::str;
+
+/// Nombre maximal de caractères du JSON affichés
+pub const MAX_JSON: usize = 64;
+
+/// Nombre maximal d'octets affichés en hexadécimal
+pub const MAX_HEX: usize = 16;
+
+/// Une trame brute, affichée de manière lisible
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub struct FrameDump<'a> {
+    header: Option<(u16, u16)>,
+    id: Option<u16>,
+    payload: &'a [u8],
+}
+
+impl<'a> FrameDump<'a> {
+    /// Lit une trame qui commence éventuellement par l'en-tête de la liaison série. Sans
+    /// en-tête, le type du message n'est pas connu.
+    pub fn new(bytes: &'a [u8]) -> Self {
+        let header = if bytes.len() >= HEADER_SIZE && bytes[0] == SYNC {
+            Some((
+                u16::from_le_bytes([bytes[1], bytes[2]]),
+                u16::from_le_bytes([bytes[3], bytes[4]]),
+            ))
+        } else {
+            None
+        };
+        match header {
+            Some((id, len)) if usize::from(len) <= bytes.len() - HEADER_SIZE => FrameDump {
+                header,
+                id: Some(id),
+                payload: &bytes[HEADER_SIZE..HEADER_SIZE + usize::from(len)],
+            },
+            _ => FrameDump {
+                header: None,
+                id: None,
+                payload: bytes,
+            },
+        }
+    }
+
+    /// Lit un message sans en-tête destiné à la carte `id`, par exemple reçu en UDP
+    pub fn with_id(bytes: &'a [u8], id: u16) -> Self {
+        FrameDump {
+            header: None,
+            id: Some(id),
+            payload: bytes,
+        }
+    }
+
+    /// Renvoie l'identifiant et la taille lus dans l'en-tête de la liaison série
+    pub fn header(&self) -> Option<(u16, u16)> {
+        self.header
+    }
+
+    /// Renvoie le type du message, s'il est connu
+    pub fn kind(&self) -> Option<MessageKind> {
+        self.id.and_then(|id| MessageKind::from_id(id).ok())
+    }
+
+    /// Renvoie le contenu du message, sans l'en-tête
+    pub fn payload(&self) -> &'a [u8] {
+        self.payload
+    }
+
+    /// Renvoie le JSON du message et vrai s'il est suivi d'une étiquette d'authentification
+    /// (voir [`MacKey`](crate::transmission::channel::MacKey))
+    pub fn json(&self) -> Option<(&'a str, bool)> {
+        let is_json = |data: &'a [u8]| match (data.first().cloned(), data.last().cloned()) {
+            (Some(b'{'), Some(b'}')) | (Some(b'['), Some(b']')) | (Some(b'"'), Some(b'"')) => {
+                str::from_utf8(data).ok()
+            }
+            _ => None,
+        };
+        if let Some(json) = is_json(self.payload) {
+            return Some((json, false));
+        }
+        let end = self.payload.len().checked_sub(TAG_SIZE)?;
+        is_json(&self.payload[..end]).map(|json| (json, true))
+    }
+
+    fn fmt_json(f: &mut fmt::Formatter, json: &str) -> fmt::Result {
+        match json.char_indices().nth(MAX_JSON) {
+            Some((cut, _)) => write!(f, "{}… (+{} B)", &json[..cut], json.len() - cut),
+            None => write!(f, "{}", json),
+        }
+    }
+
+    fn fmt_servos(&self, f: &mut fmt::Formatter) -> Option<fmt::Result> {
+        if self.kind() != Some(MessageKind::Servo) || self.payload.len() > FRAME_MAX_SIZE {
+            return None;
+        }
+        let message: Message = self.payload.iter().cloned().collect();
+        let group = ServoGroup::new(message).ok()?;
+        let mut write = || -> fmt::Result {
+            write!(f, "{} servos", group.updated().count())?;
+            for servo in group.updated() {
+                write!(
+                    f,
+                    " #{} {}->{} {:?}",
+                    servo.id, servo.known_position, servo.data, servo.control
+                )?;
+            }
+            Ok(())
+        };
+        Some(write())
+    }
+
+    fn fmt_hex(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let shown = self.payload.len().min(MAX_HEX);
+        for (index, byte) in self.payload[..shown].iter().enumerate() {
+            if index > 0 {
+                write!(f, " ")?;
+            }
+            write!(f, "{:02x}", byte)?;
+        }
+        if self.payload.len() > shown {
+            write!(f, "… (+{} B)", self.payload.len() - shown)?;
+        }
+        Ok(())
+    }
+}
+
+impl<'a> fmt::Display for FrameDump<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some((id, len)) = self.header {
+            write!(f, "[{:02x} id={} len={}] ", SYNC, id, len)?;
+        }
+        match (self.kind(), self.id) {
+            (Some(kind), _) => write!(f, "{:?} ", kind)?,
+            (None, Some(id)) => write!(f, "id {}? ", id)?,
+            (None, None) => {}
+        }
+        if self.payload.is_empty() {
+            return write!(f, "(vide)");
+        }
+        if let Some((json, tagged)) = self.json() {
+            FrameDump::fmt_json(f, json)?;
+            return if tagged { write!(f, " +tag") } else { Ok(()) };
+        }
+        if let Some(result) = self.fmt_servos(f) {
+            return result;
+        }
+        self.fmt_hex(f)
+    }
+}
+
+impl<'a> fmt::Debug for FrameDump<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl<'a> defmt::Format for FrameDump<'a> {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(f, "{}", defmt::Display2Format(self))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::FrameDump;
+    use crate::transmission::channel::{header, MacKey};
+    use crate::transmission::id::{ID_NAVIGATION, ID_SERVO};
+    use crate::transmission::MessageKind;
+    use std::string::ToString;
+    use std::vec::Vec;
+
+    #[test]
+    fn dump_stream_frame() {
+        let json = b"{\"x\":0,\"y\":0}";
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&header(ID_NAVIGATION, json));
+        bytes.extend_from_slice(json);
+        let dump = FrameDump::new(&bytes);
+        assert_eq!(dump.kind(), Some(MessageKind::Navigation));
+        assert_eq!(dump.payload(), &json[..]);
+        assert_eq!(
+            dump.to_string(),
+            "[7e id=1 len=13] Navigation {\"x\":0,\"y\":0}"
+        );
+
+        let long = format!("{{\"log\":\"{}\"}}", "a".repeat(90));
+        assert_eq!(
+            format!("{:?}", FrameDump::with_id(long.as_bytes(), 999)),
+            format!("id 999? {{\"log\":\"{}… (+36 B)", "a".repeat(56))
+        );
+    }
+
+    #[test]
+    fn dump_binary_and_tagged() {
+        #[rustfmt::skip]
+        let servos = [
+            1,
+            3, 0x01, 0x2C, 0x02, 0x00, 0b0000_0010, 0x00, 0x00, 0x00,
+        ];
+        assert_eq!(
+            FrameDump::with_id(&servos, ID_SERVO).to_string(),
+            "Servo 1 servos #3 300->512 Position"
+        );
+
+        let key = MacKey::new([7; 16]);
+        let mut tagged = b"{\"id\":3}".to_vec();
+        tagged.extend_from_slice(&key.tag(ID_SERVO, &tagged));
+        assert_eq!(
+            FrameDump::with_id(&tagged, ID_SERVO).to_string(),
+            "Servo {\"id\":3} +tag"
+        );
+
+        let raw = [0xAC, 0xDC, 0xAB, 0xBA, 0x01];
+        assert_eq!(FrameDump::new(&raw).to_string(), "ac dc ab ba 01");
+        assert_eq!(FrameDump::new(&[]).to_string(), "(vide)");
+    }
+}
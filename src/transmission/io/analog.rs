@@ -0,0 +1,112 @@
+//! Echantillonnage des entrées analogiques à l'aide d'un ADC de `embedded_hal`.
+
+use super::AnalogChannel;
+use embedded_hal::adc::{Channel, OneShot};
+
+/// Une entrée analogique : une broche d'ADC et la conversion de ses valeurs en millivolts
+#[derive(Debug)]
+pub struct AnalogInput<PIN> {
+    id: u8,
+    pin: PIN,
+    reference_mv: u16,
+    max_value: u16,
+    last: Option<AnalogChannel>,
+}
+
+impl<PIN> AnalogInput<PIN> {
+    /// Crée une entrée analogique d'identifiant `id` sur la broche `pin`.
+    ///
+    /// `reference_mv` est la tension de référence de l'ADC en millivolts et `max_value` la valeur
+    /// brute correspondante (par exemple 4095 pour un ADC 12 bits).
+    pub fn new(id: u8, pin: PIN, reference_mv: u16, max_value: u16) -> Self {
+        AnalogInput {
+            id,
+            pin,
+            reference_mv,
+            max_value,
+            last: None,
+        }
+    }
+
+    /// Renvoie la dernière mesure effectuée
+    pub fn last(&self) -> Option<AnalogChannel> {
+        self.last
+    }
+
+    /// Convertit une valeur brute de l'ADC en millivolts
+    pub fn to_millivolts(&self, raw: u16) -> u16 {
+        if self.max_value == 0 {
+            return 0;
+        }
+        (u32::from(raw.min(self.max_value)) * u32::from(self.reference_mv)
+            / u32::from(self.max_value)) as u16
+    }
+
+    /// Prend en compte une nouvelle valeur brute : la moyenne glissante est mise à jour avec un
+    /// poids de 1/8 pour la nouvelle mesure
+    pub fn push_raw(&mut self, raw: u16) -> AnalogChannel {
+        let millivolts = self.to_millivolts(raw);
+        let average = match self.last {
+            Some(last) => ((u32::from(last.average) * 7 + u32::from(millivolts)) / 8) as u16,
+            None => millivolts,
+        };
+        let channel = AnalogChannel {
+            id: self.id,
+            millivolts,
+            average,
+        };
+        self.last = Some(channel);
+        channel
+    }
+
+    /// Lance une conversion sur l'ADC `adc` et met à jour la mesure
+    pub fn sample<ADC, A>(&mut self, adc: &mut A) -> nb::Result<AnalogChannel, A::Error>
+    where
+        PIN: Channel<ADC>,
+        A: OneShot<ADC, u16, PIN>,
+    {
+        let raw = adc.read(&mut self.pin)?;
+        Ok(self.push_raw(raw))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::AnalogInput;
+    use embedded_hal::adc::{Channel, OneShot};
+
+    struct DummyAdc;
+    struct DummyPin(u16);
+
+    impl Channel<DummyAdc> for DummyPin {
+        type ID = u8;
+
+        fn channel() -> u8 {
+            0
+        }
+    }
+
+    impl OneShot<DummyAdc, u16, DummyPin> for DummyAdc {
+        type Error = ();
+
+        fn read(&mut self, pin: &mut DummyPin) -> nb::Result<u16, ()> {
+            Ok(pin.0)
+        }
+    }
+
+    #[test]
+    fn analog_sampling() {
+        let mut input = AnalogInput::new(3, DummyPin(2048), 3300, 4095);
+        let mut adc = DummyAdc;
+        let channel = input.sample(&mut adc).unwrap();
+        assert_eq!(channel.id, 3);
+        assert_eq!(channel.millivolts, 1650);
+        assert_eq!(channel.average, 1650);
+
+        let channel = input.push_raw(4095);
+        assert_eq!(channel.millivolts, 3300);
+        assert_eq!(channel.average, (1650 * 7 + 3300) / 8);
+        assert_eq!(input.to_millivolts(5000), 3300);
+        assert_eq!(input.last(), Some(channel));
+    }
+}
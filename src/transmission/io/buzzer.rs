@@ -0,0 +1,167 @@
+//! Générateur de sons pour le buzzer de la carte IO.
+//!
+//! Le [`MelodyPlayer`] doit être mis à jour régulièrement avec l'heure courante ; il pilote une
+//! [`ToneOutput`], par exemple une sortie PWM via [`PwmTone`].
+
+use super::{Melody, Note};
+use embedded_hal::Pwm;
+
+/// Une sortie capable de générer un signal carré à une fréquence donnée
+pub trait ToneOutput {
+    /// Joue un son à la fréquence `frequency` (en Hz)
+    fn play(&mut self, frequency: u16);
+
+    /// Arrête le son
+    fn mute(&mut self);
+}
+
+/// Génère les sons sur une voie d'un timer PWM avec un rapport cyclique de 50%.
+///
+/// La période du timer est donnée en Hz : `P::Time` doit pouvoir être construit depuis un `u32`
+/// représentant une fréquence.
+pub struct PwmTone<P>
+where
+    P: Pwm,
+{
+    pwm: P,
+    channel: P::Channel,
+}
+
+impl<P> core::fmt::Debug for PwmTone<P>
+where
+    P: Pwm,
+{
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        write!(f, "PwmTone")
+    }
+}
+
+impl<P> PwmTone<P>
+where
+    P: Pwm<Duty = u16>,
+    P::Channel: Copy,
+    P::Time: From<u32>,
+{
+    /// Crée une sortie sonore sur la voie `channel` du timer `pwm`
+    pub fn new(pwm: P, channel: P::Channel) -> Self {
+        PwmTone { pwm, channel }
+    }
+
+    /// Libère le timer
+    pub fn release(self) -> P {
+        self.pwm
+    }
+}
+
+impl<P> ToneOutput for PwmTone<P>
+where
+    P: Pwm<Duty = u16>,
+    P::Channel: Copy,
+    P::Time: From<u32>,
+{
+    fn play(&mut self, frequency: u16) {
+        self.pwm.set_period(u32::from(frequency));
+        let max_duty = self.pwm.get_max_duty();
+        self.pwm.set_duty(self.channel, max_duty / 2);
+        self.pwm.enable(self.channel);
+    }
+
+    fn mute(&mut self) {
+        self.pwm.disable(self.channel);
+    }
+}
+
+/// Joue une mélodie sur une [`ToneOutput`]
+#[derive(Debug, Default)]
+pub struct MelodyPlayer {
+    melody: Melody,
+    start: u32,
+    current: Option<usize>,
+    playing: bool,
+}
+
+impl MelodyPlayer {
+    /// Crée un lecteur à l'arrêt
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Commence à jouer `melody` à partir de l'instant `now` (en millisecondes)
+    pub fn start(&mut self, melody: Melody, now: u32) {
+        self.melody = melody;
+        self.start = now;
+        self.current = None;
+        self.playing = true;
+    }
+
+    /// Arrête la mélodie en cours
+    pub fn stop<T: ToneOutput>(&mut self, output: &mut T) {
+        self.playing = false;
+        self.current = None;
+        output.mute();
+    }
+
+    /// Retourne vrai si une mélodie est en train d'être jouée
+    pub fn is_playing(&self) -> bool {
+        self.playing
+    }
+
+    /// Met à jour la sortie sonore à l'instant `now` (en millisecondes). La sortie n'est modifiée
+    /// qu'aux changements de note.
+    pub fn update<T: ToneOutput>(&mut self, now: u32, output: &mut T) {
+        if !self.playing {
+            return;
+        }
+        let elapsed = now.wrapping_sub(self.start);
+        match self.melody.note_at(elapsed) {
+            Some((index, &Note { frequency, .. })) => {
+                if self.current != Some(index) {
+                    self.current = Some(index);
+                    if frequency == 0 {
+                        output.mute();
+                    } else {
+                        output.play(frequency);
+                    }
+                }
+            }
+            None => self.stop(output),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{MelodyPlayer, ToneOutput};
+    use crate::transmission::io::Melody;
+    use std::vec::Vec;
+
+    #[derive(Debug, Default)]
+    struct DummyTone {
+        events: Vec<Option<u16>>,
+    }
+
+    impl ToneOutput for DummyTone {
+        fn play(&mut self, frequency: u16) {
+            self.events.push(Some(frequency));
+        }
+
+        fn mute(&mut self) {
+            self.events.push(None);
+        }
+    }
+
+    #[test]
+    fn play_melody() {
+        let mut output = DummyTone::default();
+        let mut player = MelodyPlayer::new();
+        player.update(0, &mut output);
+        assert!(output.events.is_empty());
+
+        player.start(Melody::from_notes(&[(440, 100), (0, 50), (880, 200)]), 1000);
+        for now in (1000..1400).step_by(10) {
+            player.update(now, &mut output);
+        }
+        assert_eq!(output.events, vec![Some(440), None, Some(880), None]);
+        assert!(!player.is_playing());
+    }
+}
@@ -0,0 +1,179 @@
+//! Fronts des fins de course et de la tirette.
+//!
+//! La trame [`IO`] donne l'état des interrupteurs au moment où elle est envoyée : un appui plus
+//! court que la période d'envoi passe inaperçu. L'[`EdgeDetector`] compare les états lus par
+//! l'elec à chaque échantillonnage et produit une [`EdgeFrame`] horodatée pour chaque
+//! changement, envoyée en plus de la trame `IO` :
+//!
+//! ```ignore
+//! io.tirette = tirette.trigger_state();
+//! edges.update(&io, &clock, |frame| edge_channel.send(&frame).unwrap_or(()));
+//! ```
+
+use super::{TriggerState, IO};
+use crate::time::Clock;
+use crate::transmission::Jsonizable;
+
+/// Les identifiants des interrupteurs de la trame [`IO`]
+pub mod pin {
+    /// La tirette
+    pub const TIRETTE: u8 = 1;
+    /// Le fin de course en bas à gauche
+    pub const LIMIT_LEFT_DOWN: u8 = 2;
+    /// Le fin de course au milieu à gauche
+    pub const LIMIT_LEFT_MIDDLE: u8 = 3;
+    /// Le fin de course en haut à gauche
+    pub const LIMIT_LEFT_HIGH: u8 = 4;
+    /// Le fin de course en bas à droite
+    pub const LIMIT_RIGHT_DOWN: u8 = 5;
+    /// Le fin de course au milieu à droite
+    pub const LIMIT_RIGHT_MIDDLE: u8 = 6;
+    /// Le fin de course en haut à droite
+    pub const LIMIT_RIGHT_HIGH: u8 = 7;
+}
+
+/// Nombre d'interrupteurs de la trame [`IO`]
+const NB_TRIGGERS: usize = 7;
+
+/// Le sens d'un changement d'état d'un interrupteur
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Edge {
+    /// L'interrupteur passe à [`TriggerState::Triggered`]
+    Triggered,
+    /// L'interrupteur revient à [`TriggerState::Waiting`]
+    Released,
+}
+
+/// Trame signalant le changement d'état d'un interrupteur
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize, Serialize, Jsonizable)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct EdgeFrame {
+    /// L'identifiant de l'interrupteur, voir le module [`pin`]
+    pub pin: u8,
+    /// Le sens du changement
+    pub edge: Edge,
+    /// Heure de l'elec au moment du changement, en millisecondes
+    pub timestamp: u32,
+}
+
+impl IO {
+    /// Renvoie l'identifiant et l'état de chaque interrupteur
+    fn triggers(&self) -> [(u8, TriggerState); NB_TRIGGERS] {
+        [
+            (pin::TIRETTE, self.tirette),
+            (pin::LIMIT_LEFT_DOWN, self.limit_left_down),
+            (pin::LIMIT_LEFT_MIDDLE, self.limit_left_middle),
+            (pin::LIMIT_LEFT_HIGH, self.limit_left_high),
+            (pin::LIMIT_RIGHT_DOWN, self.limit_right_down),
+            (pin::LIMIT_RIGHT_MIDDLE, self.limit_right_middle),
+            (pin::LIMIT_RIGHT_HIGH, self.limit_right_high),
+        ]
+    }
+}
+
+/// Détecte les changements d'état des interrupteurs entre deux lectures
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct EdgeDetector {
+    previous: Option<[(u8, TriggerState); NB_TRIGGERS]>,
+}
+
+impl EdgeDetector {
+    /// Crée un détecteur qui n'a encore rien lu
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Compare les interrupteurs de `io` à la lecture précédente, et appelle `report` avec une
+    /// trame horodatée par `clock` pour chaque interrupteur qui a changé. La première lecture
+    /// sert de référence et ne produit aucune trame.
+    pub fn update<C: Clock, F: FnMut(EdgeFrame)>(&mut self, io: &IO, clock: &C, mut report: F) {
+        let triggers = io.triggers();
+        if let Some(previous) = self.previous {
+            let timestamp = clock.now().as_millis();
+            for (&(_, before), &(pin, current)) in previous.iter().zip(triggers.iter()) {
+                if before == current {
+                    continue;
+                }
+                let edge = match current {
+                    TriggerState::Triggered => Edge::Triggered,
+                    TriggerState::Waiting => Edge::Released,
+                };
+                report(EdgeFrame {
+                    pin,
+                    edge,
+                    timestamp,
+                });
+            }
+        }
+        self.previous = Some(triggers);
+    }
+
+    /// Oublie la dernière lecture : la prochaine servira de référence
+    pub fn reset(&mut self) {
+        self.previous = None;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{pin, Edge, EdgeDetector, EdgeFrame};
+    use crate::time::Instant;
+    use crate::transmission::io::{BuzzerState, TriggerState, IO};
+    use crate::transmission::Jsonizable;
+    use heapless::consts::U64;
+    use std::vec::Vec;
+
+    #[test]
+    fn ser_deser_edge() {
+        let frame = EdgeFrame {
+            pin: pin::TIRETTE,
+            edge: Edge::Triggered,
+            timestamp: 1234,
+        };
+        let data = "{\"pin\":1,\"edge\":\"Triggered\",\"timestamp\":1234}";
+        assert_eq!(frame.to_string::<U64>().unwrap(), data);
+        assert_eq!(EdgeFrame::from_json_slice(data.as_bytes()).unwrap(), frame);
+    }
+
+    #[test]
+    fn short_press() {
+        let mut io = IO {
+            buzzer: BuzzerState::Rest,
+            tirette: TriggerState::Waiting,
+            limit_left_down: TriggerState::Waiting,
+            limit_left_middle: TriggerState::Waiting,
+            limit_left_high: TriggerState::Waiting,
+            limit_right_down: TriggerState::Waiting,
+            limit_right_middle: TriggerState::Waiting,
+            limit_right_high: TriggerState::Waiting,
+            counter: 0,
+        };
+        let mut detector = EdgeDetector::new();
+        let mut frames = Vec::new();
+        let mut read = |detector: &mut EdgeDetector, io: &IO, millis| {
+            detector.update(io, &|| Instant::from_millis(millis), |frame| {
+                frames.push((frame.pin, frame.edge, frame.timestamp))
+            });
+        };
+
+        read(&mut detector, &io, 0);
+        io.limit_right_middle = TriggerState::Triggered;
+        read(&mut detector, &io, 2);
+        read(&mut detector, &io, 4);
+        io.limit_right_middle = TriggerState::Waiting;
+        io.tirette = TriggerState::Triggered;
+        read(&mut detector, &io, 6);
+        detector.reset();
+        read(&mut detector, &io, 8);
+        assert_eq!(
+            frames,
+            vec![
+                (pin::LIMIT_RIGHT_MIDDLE, Edge::Triggered, 2),
+                (pin::TIRETTE, Edge::Triggered, 6),
+                (pin::LIMIT_RIGHT_MIDDLE, Edge::Released, 6),
+            ]
+        );
+    }
+}
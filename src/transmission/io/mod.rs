@@ -1,12 +1,16 @@
 //! Décrit l'API pour interagir avec la carte IO
 
+pub mod analog;
+pub mod buzzer;
+pub mod edge;
+pub mod pneumatic;
+pub mod tirette;
+
 use crate::transmission::Jsonizable;
-use heapless::{ArrayLength, String};
-use serde_json_core::de::{from_slice, Error as DError};
-use serde_json_core::ser::{to_string, Error as SError};
 
 /// L'état d'un interrupteur : en attente d'activation, ou activé
-#[derive(Debug, Copy, Clone, Deserialize, Serialize)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum TriggerState {
     /// En attente d'activation
     Triggered,
@@ -15,7 +19,8 @@ pub enum TriggerState {
 }
 
 /// L'état d'un port IO : On ou Off
-#[derive(Debug, Copy, Clone, Deserialize, Serialize)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum IOState {
     /// Le port est activé (état haut)
     On,
@@ -24,7 +29,8 @@ pub enum IOState {
 }
 
 /// Représente l'état du buzzer
-#[derive(Debug, Copy, Clone, Deserialize, Serialize)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum BuzzerState {
     /// Au repos
     Rest,
@@ -34,10 +40,104 @@ pub enum BuzzerState {
 
     /// Joue un son indiquant un succès
     PlaySuccessSound,
+
+    /// Joue le son indiquant que le robot est bloqué
+    PlayBlockedSound,
+
+    /// Joue le son indiquant que la batterie est faible
+    PlayLowBatterySound,
+
+    /// Joue le son de fin de match
+    PlayMatchEndSound,
+
+    /// Joue la dernière mélodie reçue (voir [`Melody`])
+    PlayMelody,
+}
+
+impl BuzzerState {
+    /// Renvoie la mélodie prédéfinie associée à cet état, s'il y en a une
+    pub fn melody(self) -> Option<Melody> {
+        let notes: &[(u16, u16)] = match self {
+            BuzzerState::Rest | BuzzerState::PlayMelody => return None,
+            BuzzerState::PlayErrorSound => &[(220, 300), (0, 100), (220, 300)],
+            BuzzerState::PlaySuccessSound => &[(523, 100), (659, 100), (784, 200)],
+            BuzzerState::PlayBlockedSound => &[(880, 80), (0, 80), (880, 80), (0, 80), (880, 80)],
+            BuzzerState::PlayLowBatterySound => &[(784, 200), (523, 200), (330, 400)],
+            BuzzerState::PlayMatchEndSound => &[
+                (523, 150),
+                (659, 150),
+                (784, 150),
+                (1047, 300),
+                (784, 150),
+                (1047, 600),
+            ],
+        };
+        Some(Melody::from_notes(notes))
+    }
+}
+
+/// Nombre maximal de notes d'une mélodie
+pub const MAX_NOTES: usize = 16;
+
+/// Une note jouée par le buzzer
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Note {
+    /// Fréquence de la note en Hz. `0` correspond à un silence.
+    pub frequency: u16,
+    /// Durée de la note en millisecondes. `0` marque la fin de la mélodie.
+    pub duration: u16,
+}
+
+/// Une mélodie : une suite d'au plus `MAX_NOTES` notes
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Deserialize, Serialize, Jsonizable)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Melody {
+    /// Les notes de la mélodie, terminées par une note de durée nulle
+    pub notes: [Note; MAX_NOTES],
+}
+
+impl Melody {
+    /// Crée une mélodie à partir de couples (fréquence en Hz, durée en ms). Les notes au delà de
+    /// `MAX_NOTES` sont ignorées.
+    pub fn from_notes(notes: &[(u16, u16)]) -> Melody {
+        let mut melody = Melody::default();
+        for (slot, &(frequency, duration)) in melody.notes.iter_mut().zip(notes.iter()) {
+            *slot = Note {
+                frequency,
+                duration,
+            };
+        }
+        melody
+    }
+
+    /// Renvoie un itérateur sur les notes de la mélodie
+    pub fn iter(&self) -> impl Iterator<Item = &Note> {
+        self.notes.iter().take_while(|n| n.duration != 0)
+    }
+
+    /// Renvoie la durée totale de la mélodie en millisecondes
+    pub fn duration(&self) -> u32 {
+        self.iter().map(|n| u32::from(n.duration)).sum()
+    }
+
+    /// Renvoie la note jouée `elapsed` millisecondes après le début de la mélodie, ou `None` si
+    /// la mélodie est terminée
+    pub fn note_at(&self, elapsed: u32) -> Option<(usize, &Note)> {
+        let mut end = 0;
+        for (index, note) in self.iter().enumerate() {
+            end += u32::from(note.duration);
+            if elapsed < end {
+                return Some((index, note));
+            }
+        }
+        None
+    }
 }
 
 /// L'état du robot d'un point de vue IO
-#[derive(Debug, Copy, Clone, Deserialize, Serialize)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize, Serialize, Jsonizable)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct IO {
     /// Le son du buzzer
     pub buzzer: BuzzerState,
@@ -52,46 +152,208 @@ pub struct IO {
     pub limit_right_down: TriggerState,
     pub limit_right_middle: TriggerState,
     pub limit_right_high: TriggerState,
+
+    /// Numéro de la commande, voir [`CommandSeq`](crate::transmission::seq::CommandSeq)
+    #[serde(default)]
+    pub counter: u16,
 }
 
-#[derive(Debug, Copy, Clone, Deserialize, Serialize)]
-/// L'état des leds d'éclairages
-pub struct camera_led {
-    /// L'état des leds
-    pub camera_led_1: IOState,
-    pub camera_led_2: IOState,
+impl Default for IOState {
+    fn default() -> Self {
+        IOState::Off
+    }
 }
 
-impl Jsonizable for IO {
-    /// Désérialisation d'un JSON en `Servo`
-    fn from_json_slice(slice: &[u8]) -> Result<Self, DError> {
-        from_slice(slice)
+/// Nombre de pompes de la carte pneumatique
+pub const NB_PUMPS: usize = 2;
+
+/// Nombre de vannes de la carte pneumatique
+pub const NB_VALVES: usize = 4;
+
+/// L'état de la carte pneumatique
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Deserialize, Serialize, Jsonizable)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Pneumatic {
+    /// L'état de chaque pompe
+    pub pumps: [IOState; NB_PUMPS],
+
+    /// L'état de chaque vanne
+    pub valves: [IOState; NB_VALVES],
+
+    /// Impulsion sur chaque vanne : si la valeur n'est pas nulle, la vanne est ouverte pendant ce
+    /// nombre de millisecondes puis refermée, quel que soit son état dans `valves`
+    pub valve_pulses: [u16; NB_VALVES],
+
+    /// Pression mesurée sur le circuit de chaque pompe, en millibars (écrit par l'elec)
+    pub pressures: [u16; NB_PUMPS],
+
+    /// Numéro de la commande, voir [`CommandSeq`](crate::transmission::seq::CommandSeq)
+    #[serde(default)]
+    pub counter: u16,
+}
+
+impl Pneumatic {
+    /// Retourne vrai si au moins une pompe est allumée
+    pub fn any_pump_on(&self) -> bool {
+        self.pumps.iter().any(|&p| p == IOState::On)
     }
+}
+
+/// Nombre maximal de broches d'un banc de GPIO
+pub const MAX_GPIOS: usize = 16;
+
+/// La direction d'une broche
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum PinDirection {
+    /// Broche en entrée, son état est écrit par l'elec
+    Input,
+    /// Broche en sortie, son état est écrit par l'info
+    Output,
+}
 
-    /// Sérialisation d'un `Servo` en JSON
-    fn to_string<B>(&self) -> Result<String<B>, SError>
-    where
-        B: ArrayLength<u8>,
-    {
-        to_string(self)
+impl Default for PinDirection {
+    fn default() -> Self {
+        PinDirection::Input
     }
 }
 
-impl Jsonizable for camera_led {
-    /// Désérialisation d'un JSON en `Servo`
-    fn from_json_slice(slice: &[u8]) -> Result<Self, DError> {
-        from_slice(slice)
+/// La résistance de tirage d'une broche
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Pull {
+    /// Pas de résistance de tirage
+    Floating,
+    /// Résistance de tirage vers le haut
+    Up,
+    /// Résistance de tirage vers le bas
+    Down,
+}
+
+impl Default for Pull {
+    fn default() -> Self {
+        Pull::Floating
     }
+}
+
+/// Une broche configurable
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Gpio {
+    /// Identifiant de la broche. `pin == 0` veut dire qu'il n'y a pas de broche.
+    pub pin: u8,
+    /// Direction de la broche
+    pub direction: PinDirection,
+    /// Etat de la broche
+    pub state: IOState,
+    /// Résistance de tirage de la broche
+    pub pull: Pull,
+}
 
-    /// Sérialisation d'un `Servo` en JSON
-    fn to_string<B>(&self) -> Result<String<B>, SError>
-    where
-        B: ArrayLength<u8>,
-    {
-        to_string(self)
+/// Un banc de broches configurables : contrairement à [`IO`], ajouter un capteur ne demande pas
+/// de changer le format de la trame
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Deserialize, Serialize, Jsonizable)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct GpioBank {
+    /// Les broches du banc
+    pub pins: [Gpio; MAX_GPIOS],
+}
+
+impl GpioBank {
+    /// Renvoie la broche d'identifiant `pin`
+    pub fn get(&self, pin: u8) -> Option<&Gpio> {
+        self.pins.iter().find(|g| pin != 0 && g.pin == pin)
+    }
+
+    /// Change l'état de la broche `pin`. Renvoie `false` si la broche n'existe pas.
+    pub fn set_state(&mut self, pin: u8, state: IOState) -> bool {
+        match self.pins.iter_mut().find(|g| pin != 0 && g.pin == pin) {
+            Some(gpio) => {
+                gpio.state = state;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Ajoute ou remplace la configuration d'une broche. Renvoie `false` si le banc est plein ou
+    /// si `gpio.pin` vaut 0.
+    pub fn configure(&mut self, gpio: Gpio) -> bool {
+        if gpio.pin == 0 {
+            return false;
+        }
+        let slot = match self.pins.iter().position(|g| g.pin == gpio.pin) {
+            Some(index) => Some(index),
+            None => self.pins.iter().position(|g| g.pin == 0),
+        };
+        match slot {
+            Some(index) => {
+                self.pins[index] = gpio;
+                true
+            }
+            None => false,
+        }
     }
 }
 
+/// Nombre maximal de voies analogiques dans une trame
+pub const MAX_ANALOG_CHANNELS: usize = 8;
+
+/// La mesure d'une voie analogique
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct AnalogChannel {
+    /// Identifiant de la voie. `id == 0` veut dire qu'il n'y a pas de voie.
+    pub id: u8,
+    /// Dernière tension mesurée en millivolts
+    pub millivolts: u16,
+    /// Moyenne glissante de la tension en millivolts
+    pub average: u16,
+}
+
+/// Trame contenant les mesures des entrées analogiques (potentiomètres, capteurs de pression...)
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Deserialize, Serialize, Jsonizable)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct AnalogFrame {
+    /// Les voies mesurées
+    pub channels: [AnalogChannel; MAX_ANALOG_CHANNELS],
+}
+
+impl AnalogFrame {
+    /// Renvoie la mesure de la voie `id`
+    pub fn get(&self, id: u8) -> Option<&AnalogChannel> {
+        self.channels.iter().find(|c| id != 0 && c.id == id)
+    }
+
+    /// Ajoute ou remplace la mesure d'une voie. Renvoie `false` si la trame est pleine ou si
+    /// `channel.id` vaut 0.
+    pub fn update(&mut self, channel: AnalogChannel) -> bool {
+        if channel.id == 0 {
+            return false;
+        }
+        let slot = match self.channels.iter().position(|c| c.id == channel.id) {
+            Some(index) => Some(index),
+            None => self.channels.iter().position(|c| c.id == 0),
+        };
+        match slot {
+            Some(index) => {
+                self.channels[index] = channel;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize, Serialize, Jsonizable)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+/// L'état des leds d'éclairages
+pub struct camera_led {
+    /// L'état des leds
+    pub camera_led_1: IOState,
+    pub camera_led_2: IOState,
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -116,4 +378,110 @@ mod tests {
     }
     */
 
+    #[test]
+    fn pneumatic_ser() {
+        let a = Pneumatic {
+            pumps: [IOState::On, IOState::Off],
+            valves: [IOState::On, IOState::Off, IOState::Off, IOState::On],
+            valve_pulses: [0, 0, 250, 0],
+            pressures: [0, 0],
+            counter: 7,
+        };
+        let data = "{\"pumps\":[\"On\",\"Off\"],\"valves\":[\"On\",\"Off\",\"Off\",\"On\"],\
+                    \"valve_pulses\":[0,0,250,0],\"pressures\":[0,0],\"counter\":7}";
+        assert_eq!(a.to_string::<U2048>().unwrap(), data);
+        assert_eq!(Pneumatic::from_json_slice(data.as_bytes()).unwrap(), a);
+        assert!(a.any_pump_on());
+
+        let b = Pneumatic::from_json_slice(
+            "{\"pumps\":[\"Off\",\"Off\"],\"valves\":[\"Off\",\"On\",\"On\",\"Off\"],\
+             \"valve_pulses\":[100,0,0,0],\"pressures\":[850,0]}"
+                .as_bytes(),
+        )
+        .unwrap();
+        assert!(!b.any_pump_on());
+        assert_eq!(b.valve_pulses[0], 100);
+        assert_eq!(b.pressures[0], 850);
+        assert_eq!(b.counter, 0);
+    }
+
+    #[test]
+    fn gpio_bank() {
+        let mut bank = GpioBank::default();
+        assert!(bank.configure(Gpio {
+            pin: 3,
+            direction: PinDirection::Input,
+            state: IOState::Off,
+            pull: Pull::Up,
+        }));
+        assert!(bank.configure(Gpio {
+            pin: 7,
+            direction: PinDirection::Output,
+            ..Default::default()
+        }));
+        assert!(!bank.configure(Gpio::default()));
+        assert!(bank.set_state(7, IOState::On));
+        assert!(!bank.set_state(8, IOState::On));
+        assert!(!bank.set_state(0, IOState::On));
+
+        let strd = bank.to_string::<U2048>().unwrap();
+        let bank2 = GpioBank::from_json_slice(strd.as_bytes()).unwrap();
+        assert_eq!(bank, bank2);
+        assert_eq!(bank2.get(7).unwrap().state, IOState::On);
+        assert_eq!(bank2.get(3).unwrap().pull, Pull::Up);
+        assert!(bank2.get(0).is_none());
+
+        // Reconfigurer une broche ne prend pas de nouvel emplacement
+        assert!(bank.configure(Gpio {
+            pin: 3,
+            direction: PinDirection::Output,
+            ..Default::default()
+        }));
+        assert_eq!(bank.pins.iter().filter(|g| g.pin != 0).count(), 2);
+    }
+
+    #[test]
+    fn analog_frame() {
+        let mut frame = AnalogFrame::default();
+        assert!(frame.update(AnalogChannel {
+            id: 2,
+            millivolts: 1650,
+            average: 1600,
+        }));
+        assert!(frame.update(AnalogChannel {
+            id: 2,
+            millivolts: 1700,
+            average: 1612,
+        }));
+        assert!(!frame.update(AnalogChannel::default()));
+        let strd = frame.to_string::<U2048>().unwrap();
+        let frame2 = AnalogFrame::from_json_slice(strd.as_bytes()).unwrap();
+        assert_eq!(frame, frame2);
+        assert_eq!(frame2.get(2).unwrap().millivolts, 1700);
+        assert!(frame2.get(1).is_none());
+    }
+
+    #[test]
+    fn melody_ser() {
+        let melody = Melody::from_notes(&[(440, 100), (0, 50), (880, 200)]);
+        let strd = melody.to_string::<U2048>().unwrap();
+        assert!(strd.starts_with(
+            "{\"notes\":[{\"frequency\":440,\"duration\":100},{\"frequency\":0,\"duration\":50},"
+        ));
+        assert_eq!(Melody::from_json_slice(strd.as_bytes()).unwrap(), melody);
+    }
+
+    #[test]
+    fn melody_timing() {
+        let melody = Melody::from_notes(&[(440, 100), (0, 50), (880, 200)]);
+        assert_eq!(melody.iter().count(), 3);
+        assert_eq!(melody.duration(), 350);
+        assert_eq!(melody.note_at(0).unwrap().0, 0);
+        assert_eq!(melody.note_at(99).unwrap().1.frequency, 440);
+        assert_eq!(melody.note_at(100).unwrap().1.frequency, 0);
+        assert_eq!(melody.note_at(349).unwrap().0, 2);
+        assert!(melody.note_at(350).is_none());
+        assert!(BuzzerState::Rest.melody().is_none());
+        assert!(BuzzerState::PlayMatchEndSound.melody().unwrap().duration() > 1000);
+    }
 }
@@ -0,0 +1,315 @@
+//! Pilotage de la carte pneumatique avec des sécurités.
+//!
+//! Recopier directement les états de la trame [`Pneumatic`] sur les sorties laisse une pompe
+//! tourner indéfiniment si l'informatique oublie de l'arrêter, et garde les ventouses ouvertes
+//! quand le capteur de pression ne répond plus. Le [`PneumaticController`] applique les
+//! commandes reçues en respectant des [`Interlocks`] :
+//!
+//! * une pompe allumée depuis plus de `max_pump_on_time` ms est coupée jusqu'à ce que
+//!   l'informatique l'éteigne puis la rallume ;
+//! * les vannes sont fermées tant qu'une pompe allumée n'a pas reçu de mesure de pression depuis
+//!   `pressure_timeout` ms ;
+//! * l'arrêt d'urgence coupe les pompes et ouvre toutes les vannes pour vider le circuit.
+//!
+//! ```ignore
+//! pneumatic.set_pressure(0, sensor.read(), &clock);
+//! let outputs = pneumatic.update(&clock);
+//! pump.set_state(outputs.pumps[0]);
+//! ```
+
+use super::{IOState, Pneumatic, NB_PUMPS, NB_VALVES};
+use crate::time::{Clock, Instant};
+use crate::transmission::event::{EventFrame, MatchEvent};
+use crate::transmission::id::ID_PNEUMATIC;
+
+/// Les sécurités de la carte pneumatique. Une durée nulle désactive la sécurité correspondante.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Interlocks {
+    /// Durée maximale pendant laquelle une pompe peut rester allumée, en millisecondes
+    pub max_pump_on_time: u32,
+    /// Délai maximal entre deux mesures de pression d'une pompe allumée, en millisecondes
+    pub pressure_timeout: u32,
+}
+
+impl Default for Interlocks {
+    fn default() -> Self {
+        Interlocks {
+            max_pump_on_time: 30_000,
+            pressure_timeout: 500,
+        }
+    }
+}
+
+#[derive(Debug, Default, Copy, Clone)]
+struct Pump {
+    /// Instant d'allumage de la pompe
+    since: Option<Instant>,
+    tripped: bool,
+    /// Instant de la dernière mesure de pression
+    last_pressure: Option<Instant>,
+}
+
+/// Applique les commandes de la carte pneumatique en respectant les [`Interlocks`]
+#[derive(Debug)]
+pub struct PneumaticController {
+    interlocks: Interlocks,
+    requested: Pneumatic,
+    pumps: [Pump; NB_PUMPS],
+    /// Début et durée de l'impulsion en cours sur chaque vanne
+    pulses: [Option<(Instant, u16)>; NB_VALVES],
+    feedback_lost: bool,
+    emergency: bool,
+}
+
+impl PneumaticController {
+    /// Crée un contrôleur avec les pompes éteintes et les vannes fermées
+    pub fn new(interlocks: Interlocks) -> Self {
+        PneumaticController {
+            interlocks,
+            requested: Pneumatic::default(),
+            pumps: [Pump::default(); NB_PUMPS],
+            pulses: [None; NB_VALVES],
+            feedback_lost: false,
+            emergency: false,
+        }
+    }
+
+    /// Prend en compte la commande `frame` reçue de l'informatique. Les commandes sont ignorées
+    /// pendant un arrêt d'urgence. Les pressions de `frame` sont ignorées : ce sont celles
+    /// mesurées par la carte (voir [`PneumaticController::set_pressure`]) qui sont renvoyées.
+    pub fn command<C: Clock>(&mut self, frame: &Pneumatic, clock: &C) {
+        if self.emergency {
+            return;
+        }
+        let now = clock.now();
+        for (pump, &state) in self.pumps.iter_mut().zip(frame.pumps.iter()) {
+            match state {
+                IOState::Off => {
+                    pump.since = None;
+                    pump.tripped = false;
+                }
+                IOState::On => {
+                    pump.since.get_or_insert(now);
+                }
+            }
+        }
+        for (pulse, &duration) in self.pulses.iter_mut().zip(frame.valve_pulses.iter()) {
+            if duration != 0 {
+                *pulse = Some((now, duration));
+            }
+        }
+        self.requested.pumps = frame.pumps;
+        self.requested.valves = frame.valves;
+        self.requested.valve_pulses = frame.valve_pulses;
+        self.requested.counter = frame.counter;
+    }
+
+    /// Enregistre la pression `millibars` mesurée sur le circuit de la pompe `pump`
+    pub fn set_pressure<C: Clock>(&mut self, pump: usize, millibars: u16, clock: &C) {
+        if let Some(state) = self.pumps.get_mut(pump) {
+            self.requested.pressures[pump] = millibars;
+            state.last_pressure = Some(clock.now());
+        }
+    }
+
+    /// Déclenche l'arrêt d'urgence : les pompes sont coupées et les vannes ouvertes jusqu'à
+    /// [`PneumaticController::release`]
+    pub fn emergency_stop(&mut self) {
+        self.emergency = true;
+        self.pulses = [None; NB_VALVES];
+    }
+
+    /// Termine l'arrêt d'urgence. Les pompes restent éteintes et les vannes fermées jusqu'à la
+    /// prochaine commande.
+    pub fn release(&mut self) {
+        if self.emergency {
+            self.emergency = false;
+            self.requested.pumps = [IOState::Off; NB_PUMPS];
+            self.requested.valves = [IOState::Off; NB_VALVES];
+            for pump in self.pumps.iter_mut() {
+                pump.since = None;
+                pump.tripped = false;
+            }
+        }
+    }
+
+    /// Applique l'arrêt d'urgence global ou sa fin si `event` concerne la carte pneumatique
+    pub fn handle_event(&mut self, event: &EventFrame) {
+        if !event.is_for(ID_PNEUMATIC) {
+            return;
+        }
+        match event.event {
+            MatchEvent::EmergencyStop => self.emergency_stop(),
+            MatchEvent::EmergencyRelease => self.release(),
+            MatchEvent::MatchStart | MatchEvent::MatchEnd => {}
+        }
+    }
+
+    /// Calcule l'état des sorties à l'instant donné par `clock`. La trame renvoyée contient
+    /// l'état réellement appliqué et peut être renvoyée à l'informatique.
+    pub fn update<C: Clock>(&mut self, clock: &C) -> Pneumatic {
+        let now = clock.now();
+        let mut outputs = self.requested;
+        outputs.valve_pulses = [0; NB_VALVES];
+        if self.emergency {
+            outputs.pumps = [IOState::Off; NB_PUMPS];
+            outputs.valves = [IOState::On; NB_VALVES];
+            return outputs;
+        }
+
+        let interlocks = self.interlocks;
+        self.feedback_lost = false;
+        for (pump, output) in self.pumps.iter_mut().zip(outputs.pumps.iter_mut()) {
+            let since = match pump.since {
+                Some(since) if !pump.tripped => since,
+                _ => {
+                    *output = IOState::Off;
+                    continue;
+                }
+            };
+            let running = now.duration_since(since);
+            if interlocks.max_pump_on_time != 0 && running >= interlocks.max_pump_on_time {
+                pump.tripped = true;
+                *output = IOState::Off;
+                continue;
+            }
+            // Sans mesure depuis l'allumage, le délai part de l'allumage
+            let silence = pump
+                .last_pressure
+                .map_or(running, |last| now.duration_since(last).min(running));
+            if interlocks.pressure_timeout != 0 && silence >= interlocks.pressure_timeout {
+                self.feedback_lost = true;
+            }
+        }
+
+        for (valve, pulse) in self.pulses.iter_mut().enumerate() {
+            if let Some((start, duration)) = *pulse {
+                let elapsed = now.duration_since(start);
+                if elapsed < u32::from(duration) {
+                    outputs.valves[valve] = IOState::On;
+                    outputs.valve_pulses[valve] = duration - elapsed as u16;
+                } else {
+                    *pulse = None;
+                }
+            }
+        }
+        if self.feedback_lost {
+            outputs.valves = [IOState::Off; NB_VALVES];
+        }
+        outputs
+    }
+
+    /// Renvoie vrai si la pompe `pump` a été coupée pour être restée allumée trop longtemps
+    pub fn is_pump_tripped(&self, pump: usize) -> bool {
+        self.pumps.get(pump).map_or(false, |pump| pump.tripped)
+    }
+
+    /// Renvoie vrai si les vannes ont été fermées à la dernière mise à jour faute de mesure de
+    /// pression
+    pub fn is_feedback_lost(&self) -> bool {
+        self.feedback_lost
+    }
+
+    /// Renvoie vrai pendant un arrêt d'urgence
+    pub fn is_emergency(&self) -> bool {
+        self.emergency
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Interlocks, PneumaticController};
+    use crate::time::Instant;
+    use crate::transmission::event::{EventFrame, MatchEvent};
+    use crate::transmission::io::{IOState, Pneumatic};
+    use core::cell::Cell;
+
+    fn grab() -> Pneumatic {
+        Pneumatic {
+            pumps: [IOState::On, IOState::Off],
+            valves: [IOState::On, IOState::Off, IOState::Off, IOState::Off],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn pump_on_time_and_pressure_feedback() {
+        let now = Cell::new(Instant::from_millis(0));
+        let clock = || now.get();
+        let mut pneumatic = PneumaticController::new(Interlocks {
+            max_pump_on_time: 1000,
+            pressure_timeout: 100,
+        });
+        pneumatic.command(&grab(), &clock);
+        assert_eq!(pneumatic.update(&clock), grab());
+
+        // Pas de mesure de pression : les vannes se ferment mais la pompe continue
+        now.set(Instant::from_millis(100));
+        let outputs = pneumatic.update(&clock);
+        assert!(pneumatic.is_feedback_lost());
+        assert_eq!(outputs.pumps[0], IOState::On);
+        assert_eq!(outputs.valves[0], IOState::Off);
+        pneumatic.set_pressure(0, 850, &clock);
+        let outputs = pneumatic.update(&clock);
+        assert_eq!(outputs.valves[0], IOState::On);
+        assert_eq!(outputs.pressures[0], 850);
+
+        // La pompe est coupée au bout d'une seconde, même si la commande est renvoyée
+        now.set(Instant::from_millis(1000));
+        pneumatic.set_pressure(0, 850, &clock);
+        pneumatic.command(&grab(), &clock);
+        assert_eq!(pneumatic.update(&clock).pumps[0], IOState::Off);
+        assert!(pneumatic.is_pump_tripped(0));
+        pneumatic.command(&Pneumatic::default(), &clock);
+        pneumatic.command(&grab(), &clock);
+        assert_eq!(pneumatic.update(&clock).pumps[0], IOState::On);
+        assert!(!pneumatic.is_pump_tripped(0));
+    }
+
+    #[test]
+    fn command_keeps_measured_pressures() {
+        let clock = || Instant::from_millis(0);
+        let mut pneumatic = PneumaticController::new(Interlocks::default());
+        pneumatic.set_pressure(0, 850, &clock);
+        pneumatic.set_pressure(1, 120, &clock);
+        let command = Pneumatic {
+            pressures: [0, 9999],
+            counter: 7,
+            ..grab()
+        };
+        pneumatic.command(&command, &clock);
+        let outputs = pneumatic.update(&clock);
+        assert_eq!(outputs.pressures, [850, 120]);
+        assert_eq!(outputs.pumps, command.pumps);
+        assert_eq!(outputs.valves, command.valves);
+        assert_eq!(outputs.counter, 7);
+    }
+
+    #[test]
+    fn emergency_vent() {
+        let now = Cell::new(Instant::from_millis(0));
+        let clock = || now.get();
+        let mut pneumatic = PneumaticController::new(Interlocks::default());
+        let mut pulse = Pneumatic::default();
+        pulse.valve_pulses[2] = 250;
+        pneumatic.command(&pulse, &clock);
+        now.set(Instant::from_millis(200));
+        let outputs = pneumatic.update(&clock);
+        assert_eq!(outputs.valves[2], IOState::On);
+        assert_eq!(outputs.valve_pulses[2], 50);
+        now.set(Instant::from_millis(250));
+        assert_eq!(pneumatic.update(&clock).valves[2], IOState::Off);
+
+        pneumatic.command(&grab(), &clock);
+        pneumatic.handle_event(&EventFrame::broadcast(MatchEvent::EmergencyStop, 0));
+        pneumatic.command(&grab(), &clock);
+        let outputs = pneumatic.update(&clock);
+        assert!(pneumatic.is_emergency());
+        assert_eq!(outputs.pumps, [IOState::Off; 2]);
+        assert_eq!(outputs.valves, [IOState::On; 4]);
+
+        pneumatic.handle_event(&EventFrame::broadcast(MatchEvent::EmergencyRelease, 0));
+        assert_eq!(pneumatic.update(&clock), Pneumatic::default());
+    }
+}
@@ -0,0 +1,258 @@
+//! Gestion de la tirette de départ et du chronomètre du match.
+//!
+//! La tirette doit avoir été insérée avant d'être retirée pour lancer le match : un robot allumé
+//! sans tirette ne démarre pas tout seul. Le niveau de la broche doit rester stable pendant la
+//! durée d'anti-rebond pour être pris en compte, ce qui évite les faux départs quand la tirette
+//! vibre.
+//!
+//! ```ignore
+//! if tirette.update(&clock) {
+//!     timer.start(&clock);
+//! }
+//! io.tirette = tirette.trigger_state();
+//! ```
+
+use super::TriggerState;
+use crate::time::{Clock, Instant};
+use embedded_hal::digital::InputPin;
+
+/// La durée d'un match en millisecondes
+pub const MATCH_DURATION: u32 = 100_000;
+
+/// La durée d'anti-rebond par défaut en millisecondes
+pub const DEFAULT_DEBOUNCE: u32 = 50;
+
+/// L'état de la tirette
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum TiretteState {
+    /// Le niveau de la broche n'est pas encore stable
+    Unknown,
+    /// La tirette n'est pas insérée et ne l'a jamais été
+    Absent,
+    /// La tirette est insérée, le robot attend le départ
+    Armed,
+    /// La tirette a été retirée après avoir été insérée : le match a commencé
+    Pulled,
+}
+
+/// Une tirette de départ lue sur une broche d'entrée
+pub struct Tirette<P: InputPin> {
+    pin: P,
+    inserted_when_high: bool,
+    debounce: u32,
+    candidate: Option<(bool, Instant)>,
+    state: TiretteState,
+}
+
+impl<P: InputPin> core::fmt::Debug for Tirette<P> {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        write!(f, "Tirette {{ state: {:?} }}", self.state)
+    }
+}
+
+impl<P: InputPin> Tirette<P> {
+    /// Crée une tirette lue sur `pin`, qui est à l'état haut quand la tirette est insérée si
+    /// `inserted_when_high` est vrai
+    pub fn new(pin: P, inserted_when_high: bool) -> Self {
+        Tirette {
+            pin,
+            inserted_when_high,
+            debounce: DEFAULT_DEBOUNCE,
+            candidate: None,
+            state: TiretteState::Unknown,
+        }
+    }
+
+    /// Change la durée d'anti-rebond, en millisecondes
+    pub fn with_debounce(mut self, debounce: u32) -> Self {
+        self.debounce = debounce;
+        self
+    }
+
+    /// Lit la broche et met à jour l'état de la tirette. Renvoie vrai uniquement lors de
+    /// l'appel où la tirette passe à l'état [`TiretteState::Pulled`].
+    pub fn update<C: Clock>(&mut self, clock: &C) -> bool {
+        let now = clock.now();
+        let inserted = self.pin.is_high() == self.inserted_when_high;
+        let since = match self.candidate {
+            Some((level, since)) if level == inserted => since,
+            _ => {
+                self.candidate = Some((inserted, now));
+                now
+            }
+        };
+        if now.duration_since(since) < self.debounce {
+            return false;
+        }
+
+        let previous = self.state;
+        self.state = match (previous, inserted) {
+            (TiretteState::Pulled, _) => TiretteState::Pulled,
+            (_, true) => TiretteState::Armed,
+            (TiretteState::Armed, false) => TiretteState::Pulled,
+            (_, false) => TiretteState::Absent,
+        };
+        previous != TiretteState::Pulled && self.state == TiretteState::Pulled
+    }
+
+    /// Renvoie l'état de la tirette
+    pub fn state(&self) -> TiretteState {
+        self.state
+    }
+
+    /// Renvoie l'état à envoyer dans le champ `tirette` de la trame [`IO`](super::IO)
+    pub fn trigger_state(&self) -> TriggerState {
+        match self.state {
+            TiretteState::Pulled => TriggerState::Triggered,
+            _ => TriggerState::Waiting,
+        }
+    }
+
+    /// Réarme la tirette pour un nouveau match
+    pub fn reset(&mut self) {
+        self.candidate = None;
+        self.state = TiretteState::Unknown;
+    }
+
+    /// Libère la broche
+    pub fn release(self) -> P {
+        self.pin
+    }
+}
+
+/// Le chronomètre du match
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct MatchTimer {
+    duration: u32,
+    start: Option<Instant>,
+}
+
+impl Default for MatchTimer {
+    fn default() -> Self {
+        MatchTimer::new(MATCH_DURATION)
+    }
+}
+
+impl MatchTimer {
+    /// Crée un chronomètre pour un match de `duration` ms
+    pub fn new(duration: u32) -> Self {
+        MatchTimer {
+            duration,
+            start: None,
+        }
+    }
+
+    /// Renvoie la durée du match en ms
+    pub fn duration(&self) -> u32 {
+        self.duration
+    }
+
+    /// Démarre le match, s'il n'a pas déjà commencé
+    pub fn start<C: Clock>(&mut self, clock: &C) {
+        if self.start.is_none() {
+            self.start = Some(clock.now());
+        }
+    }
+
+    /// Renvoie vrai si le match a commencé
+    pub fn is_started(&self) -> bool {
+        self.start.is_some()
+    }
+
+    /// Renvoie le temps écoulé depuis le début du match en ms
+    pub fn elapsed<C: Clock>(&self, clock: &C) -> Option<u32> {
+        self.start.map(|start| clock.now().duration_since(start))
+    }
+
+    /// Renvoie le temps restant avant la fin du match en ms
+    pub fn remaining<C: Clock>(&self, clock: &C) -> Option<u32> {
+        self.elapsed(clock)
+            .map(|elapsed| self.duration.saturating_sub(elapsed))
+    }
+
+    /// Renvoie vrai si le match est terminé
+    pub fn is_over<C: Clock>(&self, clock: &C) -> bool {
+        self.remaining(clock) == Some(0)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{MatchTimer, Tirette, TiretteState};
+    use crate::time::Instant;
+    use crate::transmission::io::TriggerState;
+    use core::cell::Cell;
+    use embedded_hal::digital::InputPin;
+    use std::rc::Rc;
+
+    #[derive(Debug, Clone, Default)]
+    struct DummyPin {
+        high: Rc<Cell<bool>>,
+    }
+
+    impl InputPin for DummyPin {
+        fn is_high(&self) -> bool {
+            self.high.get()
+        }
+
+        fn is_low(&self) -> bool {
+            !self.high.get()
+        }
+    }
+
+    #[test]
+    fn tirette_debounce() {
+        let pin = DummyPin::default();
+        let now = Cell::new(Instant::from_millis(0));
+        let clock = || now.get();
+        let mut tirette = Tirette::new(pin.clone(), true).with_debounce(10);
+        let mut timer = MatchTimer::new(1000);
+
+        // Pas de tirette au démarrage : le robot ne part pas
+        assert!(!tirette.update(&clock));
+        assert_eq!(tirette.state(), TiretteState::Unknown);
+        now.set(Instant::from_millis(10));
+        assert!(!tirette.update(&clock));
+        assert_eq!(tirette.state(), TiretteState::Absent);
+
+        pin.high.set(true);
+        now.set(Instant::from_millis(20));
+        assert!(!tirette.update(&clock));
+        now.set(Instant::from_millis(30));
+        assert!(!tirette.update(&clock));
+        assert_eq!(tirette.state(), TiretteState::Armed);
+
+        // La tirette vibre : pas de départ
+        pin.high.set(false);
+        now.set(Instant::from_millis(35));
+        assert!(!tirette.update(&clock));
+        pin.high.set(true);
+        now.set(Instant::from_millis(38));
+        assert!(!tirette.update(&clock));
+        assert_eq!(tirette.trigger_state(), TriggerState::Waiting);
+
+        pin.high.set(false);
+        now.set(Instant::from_millis(40));
+        assert!(!tirette.update(&clock));
+        now.set(Instant::from_millis(50));
+        if tirette.update(&clock) {
+            timer.start(&clock);
+        }
+        assert_eq!(tirette.state(), TiretteState::Pulled);
+        assert_eq!(tirette.trigger_state(), TriggerState::Triggered);
+        assert!(timer.is_started());
+
+        pin.high.set(true);
+        now.set(Instant::from_millis(100));
+        assert!(!tirette.update(&clock));
+        assert_eq!(tirette.state(), TiretteState::Pulled);
+
+        assert_eq!(timer.elapsed(&clock), Some(50));
+        assert_eq!(timer.remaining(&clock), Some(950));
+        assert!(!timer.is_over(&clock));
+        now.set(Instant::from_millis(1050));
+        assert!(timer.is_over(&clock));
+    }
+}
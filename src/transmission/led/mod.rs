@@ -0,0 +1,165 @@
+//! Décrit l'API pour commander les bandeaux de LEDs de spectacle.
+//!
+//! L'informatique envoie soit la couleur de chaque LED (animation `Static`), soit une animation
+//! nommée avec sa couleur. La carte calcule ensuite la couleur de chaque LED avec
+//! [`LedFrame::color_at`] et l'envoie au bandeau avec le pilote [`ws2812::Ws2812`].
+
+pub mod ws2812;
+
+use crate::transmission::Jsonizable;
+
+/// Nombre maximal de LEDs d'un bandeau
+pub const MAX_LEDS: usize = 16;
+
+/// Une couleur RGB sur 24 bits
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Rgb {
+    /// Composante rouge
+    pub red: u8,
+    /// Composante verte
+    pub green: u8,
+    /// Composante bleue
+    pub blue: u8,
+}
+
+impl Rgb {
+    /// Crée une couleur à partir de ses trois composantes
+    pub fn new(red: u8, green: u8, blue: u8) -> Rgb {
+        Rgb { red, green, blue }
+    }
+
+    /// Renvoie la couleur dont la luminosité est multipliée par `level / 255`
+    pub fn scale(self, level: u8) -> Rgb {
+        let scale = |c: u8| (u16::from(c) * u16::from(level) / 255) as u8;
+        Rgb::new(scale(self.red), scale(self.green), scale(self.blue))
+    }
+
+    /// Renvoie une couleur saturée de la roue chromatique, `hue` allant de 0 à 255
+    pub fn wheel(hue: u8) -> Rgb {
+        let sector = hue / 85;
+        let pos = (hue % 85) * 3;
+        match sector {
+            0 => Rgb::new(255 - pos, pos, 0),
+            1 => Rgb::new(0, 255 - pos, pos),
+            _ => Rgb::new(pos, 0, 255 - pos),
+        }
+    }
+}
+
+/// Les animations que peut jouer un bandeau
+#[derive(Debug, PartialEq, Copy, Clone, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum LedAnimation {
+    /// Toutes les LEDs sont éteintes
+    Off,
+    /// Chaque LED a la couleur donnée dans `leds`
+    Static,
+    /// Toutes les LEDs clignotent avec la couleur `color`
+    Blink,
+    /// Toutes les LEDs respirent (montée et descente de luminosité) avec la couleur `color`
+    Breathe,
+    /// Arc-en-ciel défilant le long du bandeau
+    Rainbow,
+}
+
+impl Default for LedAnimation {
+    fn default() -> Self {
+        LedAnimation::Off
+    }
+}
+
+/// Trame de commande d'un bandeau de LEDs
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Deserialize, Serialize, Jsonizable)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct LedFrame {
+    /// L'animation à jouer
+    pub animation: LedAnimation,
+    /// Couleur utilisée par les animations `Blink` et `Breathe`
+    pub color: Rgb,
+    /// Période de l'animation en millisecondes
+    pub period: u16,
+    /// Couleur de chaque LED pour l'animation `Static`
+    pub leds: [Rgb; MAX_LEDS],
+}
+
+impl LedFrame {
+    /// Calcule la couleur de la LED `index` à l'instant `time` (en millisecondes)
+    pub fn color_at(&self, index: usize, time: u32) -> Rgb {
+        let period = u32::from(self.period.max(1));
+        let phase = time % period;
+        match self.animation {
+            LedAnimation::Off => Rgb::default(),
+            LedAnimation::Static => self.leds.get(index).cloned().unwrap_or_default(),
+            LedAnimation::Blink => {
+                if phase < period / 2 {
+                    self.color
+                } else {
+                    Rgb::default()
+                }
+            }
+            LedAnimation::Breathe => {
+                let half = (period / 2).max(1);
+                let level = if phase < half {
+                    phase * 255 / half
+                } else {
+                    (period - phase) * 255 / half
+                };
+                self.color.scale(level.min(255) as u8)
+            }
+            LedAnimation::Rainbow => {
+                let offset = phase * 256 / period;
+                let hue = (offset + (index as u32) * 256 / MAX_LEDS as u32) % 256;
+                Rgb::wheel(hue as u8)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{LedAnimation, LedFrame, Rgb};
+    use crate::transmission::Jsonizable;
+    use heapless::consts::U1024;
+    use heapless::String;
+    type N = U1024;
+
+    #[test]
+    fn ser_deser_led() {
+        let mut frame = LedFrame {
+            animation: LedAnimation::Static,
+            period: 1000,
+            ..Default::default()
+        };
+        frame.leds[3] = Rgb::new(255, 0, 12);
+        let strd: String<N> = frame.to_string().unwrap();
+        let frame2 = LedFrame::from_json_slice(strd.as_bytes()).unwrap();
+        assert_eq!(frame, frame2);
+        assert_eq!(frame2.color_at(3, 0), Rgb::new(255, 0, 12));
+        assert_eq!(frame2.color_at(42, 0), Rgb::default());
+    }
+
+    #[test]
+    fn led_animations() {
+        let mut frame = LedFrame {
+            animation: LedAnimation::Blink,
+            color: Rgb::new(0, 200, 0),
+            period: 1000,
+            ..Default::default()
+        };
+        assert_eq!(frame.color_at(0, 100), Rgb::new(0, 200, 0));
+        assert_eq!(frame.color_at(0, 600), Rgb::default());
+        assert_eq!(frame.color_at(0, 1100), Rgb::new(0, 200, 0));
+
+        frame.animation = LedAnimation::Breathe;
+        assert_eq!(frame.color_at(0, 0), Rgb::default());
+        assert_eq!(frame.color_at(0, 500), Rgb::new(0, 200, 0));
+        assert_eq!(frame.color_at(0, 250), Rgb::new(0, 99, 0));
+
+        frame.animation = LedAnimation::Rainbow;
+        assert_eq!(frame.color_at(0, 0), Rgb::new(255, 0, 0));
+
+        frame.animation = LedAnimation::Off;
+        assert_eq!(frame.color_at(0, 500), Rgb::default());
+    }
+}
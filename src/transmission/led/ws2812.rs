@@ -0,0 +1,109 @@
+//! Pilote pour les bandeaux de LEDs WS2812 utilisant un périphérique SPI.
+//!
+//! Chaque bit envoyé au bandeau est codé sur trois bits SPI (`100` pour un 0, `110` pour un 1),
+//! le SPI doit donc être configuré à une fréquence comprise entre 2.4 et 3 MHz. Seule la ligne
+//! MOSI est reliée au bandeau.
+
+use super::Rgb;
+use embedded_hal::spi::FullDuplex;
+
+/// Nombre d'octets nuls envoyés après les couleurs pour verrouiller le bandeau (> 50 µs)
+const RESET_BYTES: usize = 20;
+
+/// Un bandeau de LEDs WS2812 relié à un bus SPI
+#[derive(Debug)]
+pub struct Ws2812<SPI> {
+    spi: SPI,
+}
+
+impl<SPI, E> Ws2812<SPI>
+where
+    SPI: FullDuplex<u8, Error = E>,
+{
+    /// Crée un pilote de bandeau utilisant le bus `spi`
+    pub fn new(spi: SPI) -> Self {
+        Ws2812 { spi }
+    }
+
+    /// Libère le bus SPI
+    pub fn release(self) -> SPI {
+        self.spi
+    }
+
+    /// Envoie les couleurs au bandeau, la première couleur correspondant à la première LED
+    pub fn write<I>(&mut self, colors: I) -> Result<(), E>
+    where
+        I: IntoIterator<Item = Rgb>,
+    {
+        for color in colors {
+            // Les WS2812 attendent les composantes dans l'ordre vert, rouge, bleu
+            self.write_byte(color.green)?;
+            self.write_byte(color.red)?;
+            self.write_byte(color.blue)?;
+        }
+        for _ in 0..RESET_BYTES {
+            self.send(0)?;
+        }
+        Ok(())
+    }
+
+    fn write_byte(&mut self, mut data: u8) -> Result<(), E> {
+        let mut encoded: u32 = 0;
+        for _ in 0..8 {
+            encoded <<= 3;
+            encoded |= if data & 0x80 != 0 { 0b110 } else { 0b100 };
+            data <<= 1;
+        }
+        self.send((encoded >> 16) as u8)?;
+        self.send((encoded >> 8) as u8)?;
+        self.send(encoded as u8)
+    }
+
+    fn send(&mut self, byte: u8) -> Result<(), E> {
+        nb::block!(self.spi.send(byte))?;
+        nb::block!(self.spi.read())?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use super::{Ws2812, RESET_BYTES};
+    use crate::transmission::led::Rgb;
+    use embedded_hal::spi::FullDuplex;
+    use std::vec::Vec;
+
+    /// Bus SPI de test qui enregistre les octets envoyés
+    #[derive(Debug, Default)]
+    pub(crate) struct DummySpi {
+        pub(crate) sent: Vec<u8>,
+    }
+
+    impl FullDuplex<u8> for DummySpi {
+        type Error = ();
+
+        fn read(&mut self) -> nb::Result<u8, ()> {
+            Ok(0)
+        }
+
+        fn send(&mut self, word: u8) -> nb::Result<(), ()> {
+            self.sent.push(word);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn ws2812_encoding() {
+        let mut strip = Ws2812::new(DummySpi::default());
+        strip.write(vec![Rgb::new(0xFF, 0x00, 0x81)]).unwrap();
+        let sent = strip.release().sent;
+        assert_eq!(sent.len(), 9 + RESET_BYTES);
+        // Vert = 0x00 : 100 100 100 100 100 100 100 100
+        assert_eq!(&sent[0..3], &[0b1001_0010, 0b0100_1001, 0b0010_0100]);
+        // Rouge = 0xFF : 110 110 110 110 110 110 110 110
+        assert_eq!(&sent[3..6], &[0b1101_1011, 0b0110_1101, 0b1011_0110]);
+        // Bleu = 0x81 : 110 100 100 100 100 100 100 110
+        assert_eq!(&sent[6..9], &[0b1101_0010, 0b0100_1001, 0b0010_0110]);
+        assert!(sent[9..].iter().all(|&b| b == 0));
+    }
+}
@@ -0,0 +1,309 @@
+//! Remontée des logs des cartes vers l'informatique.
+//!
+//! Comme on ne peut pas brancher une sonde sur chaque carte pendant un match, les cartes envoient
+//! leurs messages de log dans des [`LogFrame`] à travers un [`Logger`]. Le logger filtre les
+//! messages selon leur niveau et limite leur débit pour ne pas saturer le transport ; les messages
+//! jetés, ou refusés par le canal, sont comptés et signalés dans la trame suivante.
+//!
+//! ```ignore
+//! let mut logger: Logger<_, U256> = Logger::new(Channel::new(ID_LOG, transport), 10, 1000);
+//! remote_log!(logger, now, LogLevel::Warn, 3, "servo {} bloqué", id);
+//! ```
+
+use crate::transmission::channel::{Channel, ChannelError, Transport};
+use crate::transmission::Jsonizable;
+use core::fmt::Write;
+use heapless::consts::U64;
+use heapless::{ArrayLength, String};
+
+/// Taille maximale du texte d'un message de log
+pub type LogMessageSize = U64;
+
+/// Niveau d'un message de log
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum LogLevel {
+    /// Détails pour le débogage fin
+    Trace,
+    /// Informations de débogage
+    Debug,
+    /// Déroulement normal
+    Info,
+    /// Situation anormale mais gérée
+    Warn,
+    /// Erreur
+    Error,
+}
+
+/// Un message de log envoyé par une carte
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize, Jsonizable)]
+pub struct LogFrame {
+    /// Niveau du message
+    pub level: LogLevel,
+    /// Code du message, libre pour chaque carte (0 si le message est uniquement textuel)
+    pub code: u16,
+    /// Heure de la carte en millisecondes
+    pub timestamp: u32,
+    /// Texte du message, tronqué s'il est trop long
+    pub message: String<LogMessageSize>,
+    /// Nombre de messages jetés depuis la trame précédente
+    pub dropped: u16,
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for LogFrame {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(
+            f,
+            "LogFrame {{ level: {}, code: {}, timestamp: {}, message: {=str}, dropped: {} }}",
+            self.level,
+            self.code,
+            self.timestamp,
+            self.message.as_str(),
+            self.dropped
+        )
+    }
+}
+
+/// Envoie des messages de log à l'informatique en limitant leur débit.
+///
+/// Au plus `max_records` messages sont envoyés par fenêtre de `window` millisecondes.
+#[derive(Debug)]
+pub struct Logger<N, B>
+where
+    N: Transport,
+    B: ArrayLength<u8>,
+{
+    channel: Channel<LogFrame, N, B>,
+    min_level: LogLevel,
+    max_records: u16,
+    window: u32,
+    window_start: u32,
+    sent_in_window: u16,
+    dropped: u16,
+}
+
+/// Écrit dans `String` autant de caractères que possible, au lieu de jeter tout un morceau du
+/// message qui ne tient pas
+struct Truncate<'a>(&'a mut String<LogMessageSize>);
+
+impl<'a> Write for Truncate<'a> {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        for c in s.chars() {
+            self.0.push(c).map_err(|_| core::fmt::Error)?;
+        }
+        Ok(())
+    }
+}
+
+impl<N, B> Logger<N, B>
+where
+    N: Transport,
+    B: ArrayLength<u8>,
+{
+    /// Crée un logger qui envoie au plus `max_records` messages toutes les `window`
+    /// millisecondes sur `channel`
+    pub fn new(channel: Channel<LogFrame, N, B>, max_records: u16, window: u32) -> Self {
+        Logger {
+            channel,
+            min_level: LogLevel::Info,
+            max_records,
+            window,
+            window_start: 0,
+            sent_in_window: 0,
+            dropped: 0,
+        }
+    }
+
+    /// Change le niveau en dessous duquel les messages sont ignorés (`Info` par défaut)
+    pub fn set_level(&mut self, level: LogLevel) {
+        self.min_level = level;
+    }
+
+    /// Renvoie le nombre de messages jetés qui n'ont pas encore été signalés
+    pub fn dropped(&self) -> u16 {
+        self.dropped
+    }
+
+    /// Libère le canal
+    pub fn release(self) -> Channel<LogFrame, N, B> {
+        self.channel
+    }
+
+    /// Envoie un message de log à l'heure `now` (en millisecondes).
+    ///
+    /// Renvoie `true` si le message a été envoyé, `false` s'il a été filtré ou jeté. Un message
+    /// refusé par le canal est compté comme jeté avant que l'erreur soit renvoyée.
+    pub fn log(
+        &mut self,
+        now: u32,
+        level: LogLevel,
+        code: u16,
+        args: core::fmt::Arguments,
+    ) -> Result<bool, ChannelError<N::Error>> {
+        if level < self.min_level {
+            return Ok(false);
+        }
+        if now.wrapping_sub(self.window_start) >= self.window {
+            self.window_start = now;
+            self.sent_in_window = 0;
+        }
+        if self.sent_in_window >= self.max_records {
+            self.dropped = self.dropped.saturating_add(1);
+            return Ok(false);
+        }
+        let mut message = String::new();
+        // Un message trop long est tronqué au dernier caractère qui tient
+        let _ = Truncate(&mut message).write_fmt(args);
+        let frame = LogFrame {
+            level,
+            code,
+            timestamp: now,
+            message,
+            dropped: self.dropped,
+        };
+        if let Err(e) = self.channel.send_at(&frame, now) {
+            self.dropped = self.dropped.saturating_add(1);
+            return Err(e);
+        }
+        self.sent_in_window += 1;
+        self.dropped = 0;
+        Ok(true)
+    }
+}
+
+/// Envoie un message de log formaté à travers un [`Logger`].
+///
+/// `remote_log!(logger, now, level, code, "format", args...)`
+#[macro_export]
+macro_rules! remote_log {
+    ($logger:expr, $now:expr, $level:expr, $code:expr, $($arg:tt)+) => {
+        $logger.log($now, $level, $code, format_args!($($arg)+))
+    };
+}
+
+#[cfg(test)]
+mod test {
+    use super::{LogFrame, LogLevel, Logger};
+    use crate::transmission::channel::test::DummyTransport;
+    use crate::transmission::channel::{Channel, ChannelError, TokenBucket, Transport};
+    use crate::transmission::Jsonizable;
+    use heapless::consts::U256;
+    use heapless::String;
+    use std::vec::Vec;
+    type N = U256;
+
+    #[test]
+    fn ser_deser_log() {
+        let frame = LogFrame {
+            level: LogLevel::Warn,
+            code: 3,
+            timestamp: 1200,
+            message: String::from("servo 5 bloqué"),
+            dropped: 0,
+        };
+        let strd: String<N> = frame.to_string().unwrap();
+        let data = "{\"level\":\"Warn\",\"code\":3,\"timestamp\":1200,\
+                    \"message\":\"servo 5 bloqué\",\"dropped\":0}";
+        assert_eq!(strd, data);
+        assert_eq!(LogFrame::from_json_slice(data.as_bytes()).unwrap(), frame);
+    }
+
+    #[test]
+    fn logger_rate_limit() {
+        let channel: Channel<LogFrame, _, N> = Channel::new(24, DummyTransport::default());
+        let mut logger = Logger::new(channel, 2, 1000);
+
+        assert!(!remote_log!(logger, 0, LogLevel::Debug, 0, "ignoré").unwrap());
+        assert!(remote_log!(logger, 10, LogLevel::Info, 1, "servo {}", 5).unwrap());
+        assert!(remote_log!(logger, 20, LogLevel::Error, 2, "qei").unwrap());
+        assert!(!remote_log!(logger, 30, LogLevel::Error, 2, "qei").unwrap());
+        assert_eq!(logger.dropped(), 1);
+        assert!(remote_log!(logger, 1010, LogLevel::Warn, 3, "reprise").unwrap());
+        assert_eq!(logger.dropped(), 0);
+
+        let transport = logger.release().release();
+        assert_eq!(transport.sent.len(), 3);
+        let first = LogFrame::from_json_slice(&transport.sent[0].1).unwrap();
+        assert_eq!(first.message, "servo 5");
+        let last = LogFrame::from_json_slice(&transport.sent[2].1).unwrap();
+        assert_eq!(last.dropped, 1);
+        assert_eq!(last.level, LogLevel::Warn);
+    }
+
+    /// Transport qui refuse les `rejects` premiers messages
+    #[derive(Debug, Default)]
+    struct Rejecting {
+        rejects: usize,
+        sent: Vec<Vec<u8>>,
+    }
+
+    impl Transport for Rejecting {
+        type Error = ();
+
+        fn send(&mut self, _id: u16, data: &[u8]) -> Result<(), ()> {
+            if self.rejects > 0 {
+                self.rejects -= 1;
+                return Err(());
+            }
+            self.sent.push(data.to_vec());
+            Ok(())
+        }
+
+        fn receive(&mut self, _id: u16, _buffer: &mut [u8]) -> Result<Option<usize>, ()> {
+            Ok(None)
+        }
+    }
+
+    #[test]
+    fn rate_limited_logger_recovers() {
+        let channel: Channel<LogFrame, _, N> =
+            Channel::new(24, DummyTransport::default()).with_rate_limit(TokenBucket::new(1, 10));
+        let mut logger = Logger::new(channel, 10, 1000);
+
+        assert!(remote_log!(logger, 0, LogLevel::Info, 1, "premier").unwrap());
+        match remote_log!(logger, 10, LogLevel::Info, 1, "trop tôt") {
+            Err(ChannelError::RateLimited) => {}
+            other => panic!("{:?} should be rate limited", other),
+        }
+        assert!(remote_log!(logger, 110, LogLevel::Info, 1, "rechargé").unwrap());
+        assert_eq!(logger.release().release().sent.len(), 2);
+    }
+
+    #[test]
+    fn rejected_records_counted() {
+        let transport = Rejecting {
+            rejects: 2,
+            ..Default::default()
+        };
+        let channel: Channel<LogFrame, _, N> = Channel::new(24, transport);
+        let mut logger = Logger::new(channel, 10, 1000);
+        match remote_log!(logger, 0, LogLevel::Warn, 1, "perdu") {
+            Err(ChannelError::Transport(())) => {}
+            other => panic!("{:?}", other),
+        }
+        assert!(remote_log!(logger, 1, LogLevel::Warn, 1, "perdu").is_err());
+        assert_eq!(logger.dropped(), 2);
+        assert!(remote_log!(logger, 2, LogLevel::Warn, 1, "reprise").unwrap());
+        assert_eq!(logger.dropped(), 0);
+
+        let transport = logger.release().release();
+        assert_eq!(transport.sent.len(), 1);
+        let frame = LogFrame::from_json_slice(&transport.sent[0]).unwrap();
+        assert_eq!(frame.dropped, 2);
+    }
+
+    #[test]
+    fn long_message_truncated() {
+        let channel: Channel<LogFrame, _, N> = Channel::new(24, DummyTransport::default());
+        let mut logger = Logger::new(channel, 10, 1000);
+        let long = "é".repeat(40);
+        assert!(remote_log!(logger, 0, LogLevel::Info, 0, "servo {}", long).unwrap());
+
+        let transport = logger.release().release();
+        let frame = LogFrame::from_json_slice(&transport.sent[0].1).unwrap();
+        // Le début du message est gardé, sans couper de caractère
+        assert_eq!(frame.message.len(), 64);
+        assert!(frame.message.starts_with("servo éé"));
+    }
+}
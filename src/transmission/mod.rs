@@ -88,17 +88,44 @@
 
 use arrayvec::ArrayVec;
 
+pub mod channel;
+mod error;
 pub mod eth;
 
+pub mod announce;
+#[cfg(feature = "arbitrary")]
+pub mod arbitrary;
+pub mod arm;
+pub mod check;
 pub mod color;
+pub mod dfu;
+pub mod diagnostic;
+pub mod event;
+pub mod funny;
+pub mod game;
+pub mod health;
+pub mod imu;
+pub mod inspect;
 pub mod io;
+pub mod led;
+pub mod log;
+pub mod motor;
 pub mod navigation;
+pub mod ping;
+pub mod ports;
+pub mod power;
+pub mod rangefinder;
+pub mod selftest;
+pub mod seq;
 pub mod servo;
+#[cfg(any(test, feature = "std"))]
+pub mod vectors;
 
-use heapless::{ArrayLength, String};
+pub use self::error::Error;
+pub use self::servo::{Servo, ServoGroup};
+pub use librobot_derive::Jsonizable;
 
-use serde_json_core::de::Error as DError;
-use serde_json_core::ser::Error as SError;
+use heapless::{ArrayLength, String};
 
 /// Taille maximale du message véhiculé par la frame
 pub const FRAME_MAX_SIZE: usize = 256 /* - 6*/;
@@ -126,6 +153,81 @@ pub mod id {
     /// L'ID des paramètres de la navigation
     pub const ID_NAVIGATION_PARAMETERS: u16 = 10;
 
+    /// L'ID des trames de vie (ping/pong)
+    pub const ID_PING: u16 = 11;
+
+    /// L'ID de la centrale inertielle
+    pub const ID_IMU: u16 = 12;
+
+    /// L'ID des capteurs de distance (VL53L1X, lidar)
+    pub const ID_RANGEFINDER: u16 = 13;
+
+    /// L'ID de la surveillance de la batterie
+    pub const ID_POWER: u16 = 14;
+
+    /// L'ID des bras robotisés
+    pub const ID_ARM: u16 = 15;
+
+    /// L'ID des bandeaux de LEDs
+    pub const ID_LED: u16 = 16;
+
+    /// L'ID du buzzer (mélodies)
+    pub const ID_BUZZER: u16 = 17;
+
+    /// L'ID des valeurs brutes du capteur de couleur
+    pub const ID_COLOR_READING: u16 = 18;
+
+    /// L'ID de la calibration du capteur de couleur
+    pub const ID_COLOR_CALIBRATION: u16 = 19;
+
+    /// L'ID des bancs de broches configurables
+    pub const ID_GPIO: u16 = 20;
+
+    /// L'ID des entrées analogiques
+    pub const ID_ANALOG: u16 = 21;
+
+    /// L'ID des trames de diagnostic
+    pub const ID_DIAGNOSTIC: u16 = 22;
+
+    /// L'ID des trames de santé
+    pub const ID_HEALTH: u16 = 23;
+
+    /// L'ID des messages de log
+    pub const ID_LOG: u16 = 24;
+
+    /// L'ID des trames de mise à jour du firmware
+    pub const ID_DFU: u16 = 25;
+
+    /// L'ID des trames des moteurs
+    pub const ID_MOTORS: u16 = 26;
+
+    /// L'ID de diffusion à toutes les cartes, utilisé par les trames d'événement
+    pub const ID_BROADCAST: u16 = 255;
+
+    /// L'ID des actions de fin de match (funny action)
+    pub const ID_FUNNY_ACTION: u16 = 27;
+
+    /// L'ID de l'état des éléments de jeu
+    pub const ID_GAME_STATUS: u16 = 28;
+
+    /// L'ID des annonces des cartes au démarrage
+    pub const ID_ANNOUNCE: u16 = 29;
+
+    /// L'ID de la calibration des servo-moteurs
+    pub const ID_SERVO_CALIBRATION: u16 = 30;
+
+    /// L'ID des réponses aux trames de paramètres de la navigation
+    pub const ID_NAVIGATION_PARAMETERS_ACK: u16 = 31;
+
+    /// La télémétrie du PID de la carte déplacement
+    pub const ID_PID_TELEMETRY: u16 = 32;
+
+    /// L'ID des fronts des interrupteurs de la carte IO
+    pub const ID_IO_EDGE: u16 = 33;
+
+    /// L'ID des résultats de l'auto-test des actionneurs
+    pub const ID_ACTUATOR_CHECK: u16 = 34;
+
     /// Le port auquel il faut ajouter l'ID pour envoyer des trames à l'informatique
     pub const INFO_LISTENING_PORT: u16 = 5000;
 
@@ -134,30 +236,230 @@ pub mod id {
 }
 
 /// Le type de message
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum MessageKind {
     /// Commande de servomoteur
     Servo,
     /// Commande de déplacement
     Navigation,
+    /// Etat de la carte IO (tirette, buzzer, fins de course)
+    IO,
+    /// Commande de la carte pneumatique
+    Pneumatic,
+    /// Couleur vue par le robot
+    Color,
+    /// Paramètres de la navigation
+    NavigationParameters,
+    /// Trame de vie (ping/pong)
+    Ping,
+    /// Mesure de la centrale inertielle
+    Imu,
+    /// Mesures des capteurs de distance
+    Range,
+    /// Etat de la batterie
+    Power,
+    /// Commande d'un bras à plusieurs articulations
+    Arm,
+    /// Commande des bandeaux de LEDs
+    Led,
+    /// Mélodie à jouer sur le buzzer
+    Buzzer,
+    /// Valeurs brutes du capteur de couleur
+    ColorReading,
+    /// Calibration du capteur de couleur
+    ColorCalibration,
+    /// Banc de broches configurables
+    Gpio,
+    /// Mesures des entrées analogiques
+    Analog,
+    /// Signalement d'une erreur
+    Diagnostic,
+    /// Etat de santé d'une carte
+    Health,
+    /// Message de log d'une carte
+    Log,
+    /// Mise à jour du firmware d'une carte
+    Dfu,
+    /// Moteurs d'une carte d'actionneurs
+    Motors,
+    /// Événement de match diffusé aux cartes
+    Event,
+    /// Actions de fin de match (funny action)
+    FunnyAction,
+    /// État des éléments de jeu et des mécanismes
+    GameStatus,
+    /// Annonce des modules d'une carte à son démarrage
+    Announce,
+    /// Calibration d'un servo-moteur
+    ServoCalibration,
+    /// Réponse à une trame de paramètres de la navigation
+    NavigationParametersAck,
+    /// Télémétrie des grandeurs internes du PID
+    PidTelemetry,
+    /// Fronts des interrupteurs de la carte IO
+    IoEdge,
+    /// Résultat de l'auto-test des actionneurs au démarrage
+    ActuatorCheck,
 }
 
 impl Into<u8> for MessageKind {
     fn into(self) -> u8 {
         match self {
             MessageKind::Servo => 4,
-            MessageKind::Navigation => 5, // TODO : agree into
+            MessageKind::Navigation => 5,
+            MessageKind::IO => 6,
+            MessageKind::Pneumatic => 7,
+            MessageKind::Color => 8,
+            MessageKind::NavigationParameters => 9,
+            MessageKind::Ping => 10,
+            MessageKind::Imu => 11,
+            MessageKind::Range => 12,
+            MessageKind::Power => 13,
+            MessageKind::Arm => 14,
+            MessageKind::Led => 15,
+            MessageKind::Buzzer => 16,
+            MessageKind::ColorReading => 17,
+            MessageKind::ColorCalibration => 18,
+            MessageKind::Gpio => 19,
+            MessageKind::Analog => 20,
+            MessageKind::Diagnostic => 21,
+            MessageKind::Health => 22,
+            MessageKind::Log => 23,
+            MessageKind::Dfu => 24,
+            MessageKind::Motors => 25,
+            MessageKind::Event => 26,
+            MessageKind::FunnyAction => 27,
+            MessageKind::GameStatus => 28,
+            MessageKind::Announce => 29,
+            MessageKind::ServoCalibration => 30,
+            MessageKind::NavigationParametersAck => 31,
+            MessageKind::PidTelemetry => 32,
+            MessageKind::IoEdge => 33,
+            MessageKind::ActuatorCheck => 34,
         }
     }
 }
 
 impl MessageKind {
+    /// Nombre de types de message différents
+    pub const COUNT: usize = 31;
+
+    /// Renvoie la position de ce type de message, entre 0 et [`MessageKind::COUNT`]
+    pub fn index(self) -> usize {
+        usize::from(Into::<u8>::into(self) - 4)
+    }
+
     /// Crée un `MessageKind` depuis un entier
-    pub fn from_u8(data: u8) -> Result<MessageKind, ()> {
+    pub fn from_u8(data: u8) -> Result<MessageKind, Error> {
         match data {
             4 => Ok(MessageKind::Servo),
             5 => Ok(MessageKind::Navigation),
-            _ => Err(()),
+            6 => Ok(MessageKind::IO),
+            7 => Ok(MessageKind::Pneumatic),
+            8 => Ok(MessageKind::Color),
+            9 => Ok(MessageKind::NavigationParameters),
+            10 => Ok(MessageKind::Ping),
+            11 => Ok(MessageKind::Imu),
+            12 => Ok(MessageKind::Range),
+            13 => Ok(MessageKind::Power),
+            14 => Ok(MessageKind::Arm),
+            15 => Ok(MessageKind::Led),
+            16 => Ok(MessageKind::Buzzer),
+            17 => Ok(MessageKind::ColorReading),
+            18 => Ok(MessageKind::ColorCalibration),
+            19 => Ok(MessageKind::Gpio),
+            20 => Ok(MessageKind::Analog),
+            21 => Ok(MessageKind::Diagnostic),
+            22 => Ok(MessageKind::Health),
+            23 => Ok(MessageKind::Log),
+            24 => Ok(MessageKind::Dfu),
+            25 => Ok(MessageKind::Motors),
+            26 => Ok(MessageKind::Event),
+            27 => Ok(MessageKind::FunnyAction),
+            28 => Ok(MessageKind::GameStatus),
+            29 => Ok(MessageKind::Announce),
+            30 => Ok(MessageKind::ServoCalibration),
+            31 => Ok(MessageKind::NavigationParametersAck),
+            32 => Ok(MessageKind::PidTelemetry),
+            33 => Ok(MessageKind::IoEdge),
+            34 => Ok(MessageKind::ActuatorCheck),
+            _ => Err(Error::UnknownMessageKind(data)),
+        }
+    }
+
+    /// Renvoie l'identifiant de la carte (voir [`id`]) associé à ce type de message
+    pub fn id(self) -> u16 {
+        match self {
+            MessageKind::Servo => id::ID_SERVO,
+            MessageKind::Navigation => id::ID_NAVIGATION,
+            MessageKind::IO => id::ID_IO,
+            MessageKind::Pneumatic => id::ID_PNEUMATIC,
+            MessageKind::Color => id::ID_COLOR,
+            MessageKind::NavigationParameters => id::ID_NAVIGATION_PARAMETERS,
+            MessageKind::Ping => id::ID_PING,
+            MessageKind::Imu => id::ID_IMU,
+            MessageKind::Range => id::ID_RANGEFINDER,
+            MessageKind::Power => id::ID_POWER,
+            MessageKind::Arm => id::ID_ARM,
+            MessageKind::Led => id::ID_LED,
+            MessageKind::Buzzer => id::ID_BUZZER,
+            MessageKind::ColorReading => id::ID_COLOR_READING,
+            MessageKind::ColorCalibration => id::ID_COLOR_CALIBRATION,
+            MessageKind::Gpio => id::ID_GPIO,
+            MessageKind::Analog => id::ID_ANALOG,
+            MessageKind::Diagnostic => id::ID_DIAGNOSTIC,
+            MessageKind::Health => id::ID_HEALTH,
+            MessageKind::Log => id::ID_LOG,
+            MessageKind::Dfu => id::ID_DFU,
+            MessageKind::Motors => id::ID_MOTORS,
+            MessageKind::Event => id::ID_BROADCAST,
+            MessageKind::FunnyAction => id::ID_FUNNY_ACTION,
+            MessageKind::GameStatus => id::ID_GAME_STATUS,
+            MessageKind::Announce => id::ID_ANNOUNCE,
+            MessageKind::ServoCalibration => id::ID_SERVO_CALIBRATION,
+            MessageKind::NavigationParametersAck => id::ID_NAVIGATION_PARAMETERS_ACK,
+            MessageKind::PidTelemetry => id::ID_PID_TELEMETRY,
+            MessageKind::IoEdge => id::ID_IO_EDGE,
+            MessageKind::ActuatorCheck => id::ID_ACTUATOR_CHECK,
+        }
+    }
+
+    /// Crée un `MessageKind` depuis l'identifiant d'une carte (voir [`id`])
+    pub fn from_id(module_id: u16) -> Result<MessageKind, Error> {
+        match module_id {
+            id::ID_SERVO => Ok(MessageKind::Servo),
+            id::ID_NAVIGATION => Ok(MessageKind::Navigation),
+            id::ID_IO => Ok(MessageKind::IO),
+            id::ID_PNEUMATIC => Ok(MessageKind::Pneumatic),
+            id::ID_COLOR => Ok(MessageKind::Color),
+            id::ID_NAVIGATION_PARAMETERS => Ok(MessageKind::NavigationParameters),
+            id::ID_PING => Ok(MessageKind::Ping),
+            id::ID_IMU => Ok(MessageKind::Imu),
+            id::ID_RANGEFINDER => Ok(MessageKind::Range),
+            id::ID_POWER => Ok(MessageKind::Power),
+            id::ID_ARM => Ok(MessageKind::Arm),
+            id::ID_LED => Ok(MessageKind::Led),
+            id::ID_BUZZER => Ok(MessageKind::Buzzer),
+            id::ID_COLOR_READING => Ok(MessageKind::ColorReading),
+            id::ID_COLOR_CALIBRATION => Ok(MessageKind::ColorCalibration),
+            id::ID_GPIO => Ok(MessageKind::Gpio),
+            id::ID_ANALOG => Ok(MessageKind::Analog),
+            id::ID_DIAGNOSTIC => Ok(MessageKind::Diagnostic),
+            id::ID_HEALTH => Ok(MessageKind::Health),
+            id::ID_LOG => Ok(MessageKind::Log),
+            id::ID_DFU => Ok(MessageKind::Dfu),
+            id::ID_MOTORS => Ok(MessageKind::Motors),
+            id::ID_BROADCAST => Ok(MessageKind::Event),
+            id::ID_FUNNY_ACTION => Ok(MessageKind::FunnyAction),
+            id::ID_GAME_STATUS => Ok(MessageKind::GameStatus),
+            id::ID_ANNOUNCE => Ok(MessageKind::Announce),
+            id::ID_SERVO_CALIBRATION => Ok(MessageKind::ServoCalibration),
+            id::ID_NAVIGATION_PARAMETERS_ACK => Ok(MessageKind::NavigationParametersAck),
+            id::ID_PID_TELEMETRY => Ok(MessageKind::PidTelemetry),
+            id::ID_IO_EDGE => Ok(MessageKind::IoEdge),
+            id::ID_ACTUATOR_CHECK => Ok(MessageKind::ActuatorCheck),
+            _ => Err(Error::UnknownModuleId(module_id)),
         }
     }
 }
@@ -168,10 +470,80 @@ where
     Self: core::marker::Sized,
 {
     /// Désérialisation d'un JSON en `Servo`
-    fn from_json_slice(slice: &[u8]) -> Result<Self, DError>;
+    fn from_json_slice(slice: &[u8]) -> Result<Self, Error>;
 
     /// Sérialisation d'un `Servo` en JSON
-    fn to_string<B>(&self) -> Result<String<B>, SError>
+    fn to_string<B>(&self) -> Result<String<B>, Error>
     where
         B: ArrayLength<u8>;
 }
+
+#[cfg(test)]
+mod test {
+    use super::{Error, MessageKind};
+
+    #[test]
+    fn message_kind_u8_round_trip() {
+        for value in 0..=255u8 {
+            if let Ok(kind) = MessageKind::from_u8(value) {
+                let back: u8 = kind.into();
+                assert_eq!(back, value);
+            }
+        }
+        assert_eq!(MessageKind::from_u8(4).unwrap(), MessageKind::Servo);
+        assert_eq!(
+            MessageKind::from_u8(9).unwrap(),
+            MessageKind::NavigationParameters
+        );
+        match MessageKind::from_u8(0) {
+            Err(Error::UnknownMessageKind(0)) => {}
+            other => panic!("{:?} should be an unknown message kind", other),
+        }
+        match MessageKind::from_id(0) {
+            Err(Error::UnknownModuleId(0)) => {}
+            other => panic!("{:?} should be an unknown module id", other),
+        }
+    }
+
+    #[test]
+    fn message_kind_id_round_trip() {
+        let kinds = [
+            MessageKind::Servo,
+            MessageKind::Navigation,
+            MessageKind::IO,
+            MessageKind::Pneumatic,
+            MessageKind::Color,
+            MessageKind::NavigationParameters,
+            MessageKind::Ping,
+            MessageKind::Imu,
+            MessageKind::Range,
+            MessageKind::Power,
+            MessageKind::Arm,
+            MessageKind::Led,
+            MessageKind::Buzzer,
+            MessageKind::ColorReading,
+            MessageKind::ColorCalibration,
+            MessageKind::Gpio,
+            MessageKind::Analog,
+            MessageKind::Diagnostic,
+            MessageKind::Health,
+            MessageKind::Log,
+            MessageKind::Dfu,
+            MessageKind::Motors,
+            MessageKind::Event,
+            MessageKind::FunnyAction,
+            MessageKind::GameStatus,
+            MessageKind::Announce,
+            MessageKind::ServoCalibration,
+            MessageKind::NavigationParametersAck,
+            MessageKind::PidTelemetry,
+            MessageKind::IoEdge,
+            MessageKind::ActuatorCheck,
+        ];
+        assert_eq!(kinds.len(), MessageKind::COUNT);
+        for (index, kind) in kinds.iter().enumerate() {
+            assert_eq!(MessageKind::from_id(kind.id()).unwrap(), *kind);
+            assert_eq!(kind.index(), index);
+        }
+    }
+}
@@ -0,0 +1,365 @@
+//! Pilotage des moteurs brushless par leur contrôleur (ESC).
+//!
+//! La trame [`MotorsGroup`] ne fait qu'allumer ou éteindre un brushless. Donner directement la
+//! pleine puissance au brushless du lanceur de balles fait chuter le 5 V de la carte. Le
+//! [`BrushlessController`] transforme cette commande tout ou rien en une séquence propre :
+//!
+//! * l'ESC est armé en recevant une consigne nulle pendant `arming_time` ms après le démarrage ;
+//! * à l'allumage, la consigne monte progressivement jusqu'à `throttle` en `ramp_time` ms ;
+//! * à l'extinction, la consigne est coupée et le moteur ralentit en roue libre.
+//!
+//! La consigne est envoyée à une [`EscOutput`], une PWM de servo ([`PwmEsc`]) ou une liaison
+//! DShot ([`DshotEsc`]) :
+//!
+//! ```ignore
+//! launcher.command(&group);
+//! launcher.update(&clock, &mut esc);
+//! ```
+
+use super::{Brushless, MotorsGroup};
+use crate::time::{Clock, Instant};
+use crate::units::DutyCycle;
+use embedded_hal::PwmPin;
+
+/// La consigne maximale d'un ESC, en pour mille
+pub const MAX_THROTTLE: u16 = 1000;
+
+/// Une sortie vers un contrôleur de brushless
+pub trait EscOutput {
+    /// Envoie la consigne `throttle`, en pour mille (0 : moteur arrêté)
+    fn set_throttle(&mut self, throttle: u16);
+}
+
+/// Un ESC commandé par une PWM de servo-moteur : une impulsion de 1 ms correspond à l'arrêt et
+/// une impulsion de 2 ms à la pleine puissance.
+pub struct PwmEsc<P>
+where
+    P: PwmPin<Duty = u16>,
+{
+    pwm: P,
+    min: DutyCycle,
+    max: DutyCycle,
+}
+
+impl<P> core::fmt::Debug for PwmEsc<P>
+where
+    P: PwmPin<Duty = u16>,
+{
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        write!(f, "PwmEsc {{ duty: {} }}", self.pwm.get_duty())
+    }
+}
+
+impl<P> PwmEsc<P>
+where
+    P: PwmPin<Duty = u16>,
+{
+    /// Crée un ESC sur `pwm`, dont le timer doit être réglé à 50 Hz : les impulsions de 1 ms et
+    /// 2 ms correspondent à des rapports cycliques de 5% et 10%
+    pub fn new(pwm: P) -> Self {
+        PwmEsc::with_range(pwm, DutyCycle::from_percent(5), DutyCycle::from_percent(10))
+    }
+
+    /// Crée un ESC dont l'arrêt et la pleine puissance correspondent aux rapports cycliques
+    /// `min` et `max`
+    pub fn with_range(mut pwm: P, min: DutyCycle, max: DutyCycle) -> Self {
+        pwm.set_duty(min.of(pwm.get_max_duty()));
+        pwm.enable();
+        PwmEsc { pwm, min, max }
+    }
+
+    /// Libère la broche
+    pub fn release(self) -> P {
+        self.pwm
+    }
+}
+
+impl<P> EscOutput for PwmEsc<P>
+where
+    P: PwmPin<Duty = u16>,
+{
+    fn set_throttle(&mut self, throttle: u16) {
+        let max_duty = self.pwm.get_max_duty();
+        let (min, max) = (self.min.of(max_duty), self.max.of(max_duty));
+        let throttle = u32::from(throttle.min(MAX_THROTTLE));
+        let range = u32::from(max.saturating_sub(min));
+        let duty = u32::from(min) + range * throttle / u32::from(MAX_THROTTLE);
+        self.pwm.set_duty(duty as u16);
+    }
+}
+
+/// Construit une trame DShot de 16 bits : 11 bits de consigne, le bit de demande de télémétrie
+/// puis 4 bits de CRC. Une consigne en pour mille non nulle est ramenée entre 48 et 2047, les
+/// valeurs inférieures étant réservées aux commandes spéciales de l'ESC.
+pub fn dshot_frame(throttle: u16, telemetry: bool) -> u16 {
+    let value = match throttle.min(MAX_THROTTLE) {
+        0 => 0,
+        throttle => 48 + (u32::from(throttle) * 1999 / u32::from(MAX_THROTTLE)) as u16,
+    };
+    let data = (value << 1) | u16::from(telemetry);
+    let crc = (data ^ (data >> 4) ^ (data >> 8)) & 0x0F;
+    (data << 4) | crc
+}
+
+/// Une liaison capable d'émettre des trames DShot, généralement par DMA sur un timer
+pub trait DshotWrite {
+    /// Émet la trame `frame`, bit de poids fort en premier
+    fn write(&mut self, frame: u16);
+}
+
+/// Un ESC commandé en DShot
+#[derive(Debug)]
+pub struct DshotEsc<W: DshotWrite> {
+    link: W,
+}
+
+impl<W: DshotWrite> DshotEsc<W> {
+    /// Crée un ESC commandé par la liaison `link`
+    pub fn new(link: W) -> Self {
+        DshotEsc { link }
+    }
+
+    /// Libère la liaison
+    pub fn release(self) -> W {
+        self.link
+    }
+}
+
+impl<W: DshotWrite> EscOutput for DshotEsc<W> {
+    fn set_throttle(&mut self, throttle: u16) {
+        self.link.write(dshot_frame(throttle, false));
+    }
+}
+
+/// Les paramètres de la séquence de démarrage d'un brushless
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct EscConfig {
+    /// Durée pendant laquelle l'ESC reçoit une consigne nulle pour s'armer, en millisecondes
+    pub arming_time: u32,
+    /// Durée pour passer de l'arrêt à la pleine puissance, en millisecondes
+    pub ramp_time: u32,
+    /// La consigne quand le moteur est allumé, en pour mille
+    pub throttle: u16,
+}
+
+impl Default for EscConfig {
+    fn default() -> Self {
+        EscConfig {
+            arming_time: 3000,
+            ramp_time: 1500,
+            throttle: MAX_THROTTLE,
+        }
+    }
+}
+
+/// L'état de l'armement d'un ESC
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum EscState {
+    /// L'ESC n'a encore reçu aucune consigne
+    Disarmed,
+    /// L'ESC reçoit une consigne nulle pour s'armer
+    Arming,
+    /// L'ESC accepte les consignes
+    Armed,
+}
+
+/// Transforme la commande tout ou rien d'un brushless en séquence d'armement et rampe de
+/// consigne
+#[derive(Debug, Copy, Clone)]
+pub struct BrushlessController {
+    id: u8,
+    config: EscConfig,
+    state: EscState,
+    arming_since: Instant,
+    on: bool,
+    throttle: f32,
+    last_update: Option<Instant>,
+}
+
+impl BrushlessController {
+    /// Crée le contrôleur du brushless `id` de la trame [`MotorsGroup`]
+    pub fn new(id: u8, config: EscConfig) -> Self {
+        BrushlessController {
+            id,
+            config,
+            state: EscState::Disarmed,
+            arming_since: Instant::default(),
+            on: false,
+            throttle: 0.0,
+            last_update: None,
+        }
+    }
+
+    /// Prend en compte la commande du brushless dans `group`, s'il y est
+    pub fn command(&mut self, group: &MotorsGroup) {
+        let motor = group
+            .brushless
+            .iter()
+            .find(|motor| self.id != 0 && motor.id == self.id);
+        if let Some(&Brushless { on, .. }) = motor {
+            self.set_on(on);
+        }
+    }
+
+    /// Allume ou éteint le moteur. Un allumage avant la fin de l'armement est pris en compte à
+    /// la fin de celui-ci.
+    pub fn set_on(&mut self, on: bool) {
+        self.on = on;
+        if !on {
+            self.throttle = 0.0;
+        }
+    }
+
+    /// Désarme l'ESC, par exemple après une coupure de la puissance : la séquence d'armement
+    /// est refaite à la prochaine mise à jour
+    pub fn disarm(&mut self) {
+        self.state = EscState::Disarmed;
+        self.throttle = 0.0;
+        self.last_update = None;
+    }
+
+    /// Calcule la consigne à l'instant donné par `clock` et l'envoie à `output`. Renvoie la
+    /// consigne envoyée, en pour mille.
+    pub fn update<C: Clock, E: EscOutput>(&mut self, clock: &C, output: &mut E) -> u16 {
+        let now = clock.now();
+        let dt = self.last_update.map_or(0, |last| now.duration_since(last));
+        self.last_update = Some(now);
+        match self.state {
+            EscState::Disarmed => {
+                self.state = EscState::Arming;
+                self.arming_since = now;
+            }
+            EscState::Arming => {
+                if now.duration_since(self.arming_since) >= self.config.arming_time {
+                    self.state = EscState::Armed;
+                }
+            }
+            EscState::Armed => {
+                let target = if self.on {
+                    f32::from(self.config.throttle.min(MAX_THROTTLE))
+                } else {
+                    0.0
+                };
+                self.throttle = if self.config.ramp_time == 0 {
+                    target
+                } else {
+                    let step = dt as f32 * f32::from(MAX_THROTTLE) / self.config.ramp_time as f32;
+                    (self.throttle + step).min(target)
+                };
+            }
+        }
+        let throttle = self.throttle as u16;
+        output.set_throttle(throttle);
+        throttle
+    }
+
+    /// Renvoie l'état de l'armement de l'ESC
+    pub fn state(&self) -> EscState {
+        self.state
+    }
+
+    /// Renvoie vrai si le moteur est allumé et a atteint sa consigne
+    pub fn is_up_to_speed(&self) -> bool {
+        self.on
+            && self.state == EscState::Armed
+            && self.throttle >= f32::from(self.config.throttle.min(MAX_THROTTLE))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{dshot_frame, BrushlessController, EscConfig, EscOutput, EscState, PwmEsc};
+    use crate::time::Instant;
+    use crate::transmission::motor::{Brushless, MotorsGroup};
+    use core::cell::Cell;
+    use embedded_hal::PwmPin;
+    use std::rc::Rc;
+    use std::vec::Vec;
+
+    #[derive(Debug, Clone, Default)]
+    struct DummyPwm {
+        duty: Rc<Cell<u16>>,
+    }
+
+    impl PwmPin for DummyPwm {
+        type Channel = ();
+        type Time = ();
+        type Duty = u16;
+
+        fn disable(&mut self) {}
+
+        fn enable(&mut self) {}
+
+        fn get_period(&self) {}
+
+        fn get_duty(&self) -> u16 {
+            self.duty.get()
+        }
+
+        fn get_max_duty(&self) -> u16 {
+            20_000
+        }
+
+        fn set_duty(&mut self, duty: u16) {
+            self.duty.set(duty);
+        }
+    }
+
+    #[test]
+    fn esc_outputs() {
+        let pwm = DummyPwm::default();
+        let mut esc = PwmEsc::new(pwm.clone());
+        assert_eq!(pwm.duty.get(), 1000);
+        esc.set_throttle(500);
+        assert_eq!(pwm.duty.get(), 1500);
+        esc.set_throttle(2000);
+        assert_eq!(pwm.duty.get(), 2000);
+
+        assert_eq!(dshot_frame(0, false), 0);
+        // Consigne DShot 1047, sans télémétrie, CRC 0b0100
+        assert_eq!(dshot_frame(500, false), 0x82E4);
+        assert_eq!(dshot_frame(1000, true) >> 4, (2047 << 1) | 1);
+    }
+
+    #[test]
+    fn arming_then_ramp() {
+        let now = Cell::new(Instant::from_millis(0));
+        let clock = || now.get();
+        let pwm = DummyPwm::default();
+        let mut esc = PwmEsc::new(pwm.clone());
+        let mut launcher = BrushlessController::new(
+            3,
+            EscConfig {
+                arming_time: 1000,
+                ramp_time: 500,
+                throttle: 800,
+            },
+        );
+        let mut group = MotorsGroup::default();
+        group.brushless[0] = Brushless { id: 3, on: true };
+        launcher.command(&group);
+
+        assert_eq!(launcher.update(&clock, &mut esc), 0);
+        assert_eq!(launcher.state(), EscState::Arming);
+        now.set(Instant::from_millis(1000));
+        assert_eq!(launcher.update(&clock, &mut esc), 0);
+        assert_eq!(launcher.state(), EscState::Armed);
+
+        let mut throttles = Vec::new();
+        for millis in (1100..=1500).step_by(100) {
+            now.set(Instant::from_millis(millis));
+            throttles.push(launcher.update(&clock, &mut esc));
+        }
+        assert_eq!(throttles, vec![200, 400, 600, 800, 800]);
+        assert!(launcher.is_up_to_speed());
+        assert_eq!(pwm.duty.get(), 1800);
+
+        group.brushless[0].on = false;
+        launcher.command(&group);
+        assert_eq!(launcher.update(&clock, &mut esc), 0);
+        assert_eq!(pwm.duty.get(), 1000);
+        assert!(!launcher.is_up_to_speed());
+    }
+}
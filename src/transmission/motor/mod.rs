@@ -0,0 +1,294 @@
+//! Représentation haut-niveau des moteurs pilotés par les cartes d'actionneurs.
+//!
+//! Le format binaire est celui de `motor_read_frame` et `motor_write_frame` dans
+//! `c_src/SharedWithRust.c` :
+//! ```txt
+//! <nb_controlled: u8> <nb_uncontrolled: u8> <nb_brushless: u8>
+//! <[<id: u8> <wanted_angle: u8> <wanted_nb_turns: u8> <finished, new_command: u8>] ...>
+//! <[<id: u8> <on_off, rotation: u8>] ...>
+//! <[<id: u8> <on_off: u8>] ...>
+//! ```
+
+pub mod brushless;
+
+use crate::transmission::{Jsonizable, Message};
+
+/// Nombre maximal de moteurs de chaque type
+pub const MAX_MOTORS: usize = 8;
+
+/// Un moteur asservi en angle et en nombre de tours
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ControlledMotor {
+    /// Identifiant du moteur (0 : pas de moteur)
+    pub id: u8,
+    /// Angle voulu
+    pub wanted_angle_position: u8,
+    /// Nombre de tours voulu
+    pub wanted_nb_turns: u8,
+    /// Vrai quand le moteur a atteint sa consigne
+    pub finished: bool,
+    /// Vrai si la trame contient une nouvelle consigne
+    pub new_command: bool,
+}
+
+/// Sens de rotation d'un moteur non asservi
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Rotation {
+    /// Rotation trigonométrique, qui est le sens par défaut
+    CounterClockwise = 0,
+    /// Rotation horaire
+    Clockwise = 1,
+}
+
+impl Default for Rotation {
+    fn default() -> Self {
+        Rotation::CounterClockwise
+    }
+}
+
+/// Un moteur non asservi, seulement allumé ou éteint
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct UncontrolledMotor {
+    /// Identifiant du moteur (0 : pas de moteur)
+    pub id: u8,
+    /// Vrai si le moteur tourne
+    pub on: bool,
+    /// Sens de rotation
+    pub rotation: Rotation,
+}
+
+/// Un moteur brushless
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Brushless {
+    /// Identifiant du moteur (0 : pas de moteur)
+    pub id: u8,
+    /// Vrai si le moteur tourne
+    pub on: bool,
+}
+
+/// L'ensemble des moteurs d'une carte
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize, Jsonizable)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct MotorsGroup {
+    /// Les moteurs asservis
+    pub controlled: [ControlledMotor; MAX_MOTORS],
+    /// Les moteurs non asservis
+    pub uncontrolled: [UncontrolledMotor; MAX_MOTORS],
+    /// Les moteurs brushless
+    pub brushless: [Brushless; MAX_MOTORS],
+}
+
+/// Les erreurs de lecture et d'écriture de la trame binaire des moteurs
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum MotorFrameError {
+    /// La trame est trop courte pour contenir l'en-tête
+    Empty,
+    /// La trame annonce plus de `MAX_MOTORS` moteurs d'un type
+    TooManyMotors(u8),
+    /// La taille de la trame ne correspond pas au nombre de moteurs annoncé
+    SizeMismatch {
+        /// Taille attendue
+        expected: usize,
+        /// Taille reçue
+        found: usize,
+    },
+    /// Un moteur a un ID nul
+    NullId,
+    /// Deux moteurs du même type ont le même ID
+    DuplicateId(u8),
+    /// L'octet d'informations d'un moteur a des bits réservés non nuls
+    InvalidInfos(u8),
+}
+
+/// Renvoie la taille de la trame binaire pour le nombre de moteurs de chaque type donné
+pub fn frame_size(nb_controlled: usize, nb_uncontrolled: usize, nb_brushless: usize) -> usize {
+    3 + nb_controlled * 4 + nb_uncontrolled * 2 + nb_brushless * 2
+}
+
+fn check_id(id: u8, mut ids: impl Iterator<Item = u8>) -> Result<u8, MotorFrameError> {
+    if id == 0 {
+        Err(MotorFrameError::NullId)
+    } else if ids.any(|other| other == id) {
+        Err(MotorFrameError::DuplicateId(id))
+    } else {
+        Ok(id)
+    }
+}
+
+fn check_infos(infos: u8, mask: u8) -> Result<u8, MotorFrameError> {
+    if infos & !mask != 0 {
+        Err(MotorFrameError::InvalidInfos(infos))
+    } else {
+        Ok(infos)
+    }
+}
+
+impl MotorsGroup {
+    /// Lit un groupe de moteurs depuis une trame binaire
+    pub fn new(message: Message) -> Result<Self, MotorFrameError> {
+        if message.len() < 3 {
+            return Err(MotorFrameError::Empty);
+        }
+        let counts = [message[0], message[1], message[2]];
+        if let Some(&count) = counts.iter().find(|&&count| count as usize > MAX_MOTORS) {
+            return Err(MotorFrameError::TooManyMotors(count));
+        }
+        let (nb_controlled, nb_uncontrolled, nb_brushless) =
+            (counts[0] as usize, counts[1] as usize, counts[2] as usize);
+        let expected = frame_size(nb_controlled, nb_uncontrolled, nb_brushless);
+        if message.len() != expected {
+            return Err(MotorFrameError::SizeMismatch {
+                expected,
+                found: message.len(),
+            });
+        }
+
+        let mut group = MotorsGroup::default();
+        let mut data = &message[3..];
+        for index in 0..nb_controlled {
+            let (motor, rest) = data.split_at(4);
+            data = rest;
+            let infos = check_infos(motor[3], 0b11)?;
+            group.controlled[index] = ControlledMotor {
+                id: check_id(motor[0], group.controlled.iter().map(|m| m.id))?,
+                wanted_angle_position: motor[1],
+                wanted_nb_turns: motor[2],
+                finished: infos & 0b10 != 0,
+                new_command: infos & 0b01 != 0,
+            };
+        }
+        for index in 0..nb_uncontrolled {
+            let (motor, rest) = data.split_at(2);
+            data = rest;
+            let infos = check_infos(motor[1], 0b11)?;
+            group.uncontrolled[index] = UncontrolledMotor {
+                id: check_id(motor[0], group.uncontrolled.iter().map(|m| m.id))?,
+                on: infos & 0b10 != 0,
+                rotation: if infos & 0b01 != 0 {
+                    Rotation::Clockwise
+                } else {
+                    Rotation::CounterClockwise
+                },
+            };
+        }
+        for index in 0..nb_brushless {
+            let (motor, rest) = data.split_at(2);
+            data = rest;
+            let infos = check_infos(motor[1], 0b1)?;
+            group.brushless[index] = Brushless {
+                id: check_id(motor[0], group.brushless.iter().map(|m| m.id))?,
+                on: infos != 0,
+            };
+        }
+        Ok(group)
+    }
+
+    /// Écrit le groupe de moteurs dans une trame binaire
+    pub fn into_bytes(self) -> Result<Message, MotorFrameError> {
+        let controlled = self.controlled.iter().filter(|m| m.id != 0);
+        let uncontrolled = self.uncontrolled.iter().filter(|m| m.id != 0);
+        let brushless = self.brushless.iter().filter(|m| m.id != 0);
+
+        let mut message = Message::new();
+        message.push(controlled.clone().count() as u8);
+        message.push(uncontrolled.clone().count() as u8);
+        message.push(brushless.clone().count() as u8);
+        for motor in controlled {
+            message.push(motor.id);
+            message.push(motor.wanted_angle_position);
+            message.push(motor.wanted_nb_turns);
+            message.push(((motor.finished as u8) << 1) | motor.new_command as u8);
+        }
+        for motor in uncontrolled {
+            message.push(motor.id);
+            message.push(((motor.on as u8) << 1) | motor.rotation as u8);
+        }
+        for motor in brushless {
+            message.push(motor.id);
+            message.push(motor.on as u8);
+        }
+        Ok(message)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Brushless, MotorFrameError, MotorsGroup, Rotation, UncontrolledMotor};
+    use crate::transmission::{Jsonizable, Message};
+    use heapless::consts::U2048;
+    use heapless::String;
+
+    fn message(bytes: &[u8]) -> Message {
+        bytes.iter().cloned().collect()
+    }
+
+    #[test]
+    fn motors_binary_round_trip() {
+        #[rustfmt::skip]
+        let bytes = [
+            1, 2, 1,
+            3, 90, 2, 0b01,
+            1, 0b11,
+            2, 0b00,
+            9, 1,
+        ];
+        let group = MotorsGroup::new(message(&bytes)).unwrap();
+        assert_eq!(group.controlled[0].id, 3);
+        assert_eq!(group.controlled[0].wanted_angle_position, 90);
+        assert!(group.controlled[0].new_command);
+        assert!(!group.controlled[0].finished);
+        assert_eq!(
+            group.uncontrolled[0],
+            UncontrolledMotor {
+                id: 1,
+                on: true,
+                rotation: Rotation::Clockwise
+            }
+        );
+        assert!(!group.uncontrolled[1].on);
+        assert_eq!(group.brushless[0], Brushless { id: 9, on: true });
+        assert_eq!(group.clone().into_bytes().unwrap().as_slice(), &bytes[..]);
+
+        let json: String<U2048> = group.to_string().unwrap();
+        assert_eq!(
+            MotorsGroup::from_json_slice(json.as_bytes()).unwrap(),
+            group
+        );
+    }
+
+    #[test]
+    fn reject_invalid_motor_frames() {
+        assert_eq!(
+            MotorsGroup::new(message(&[0, 0])),
+            Err(MotorFrameError::Empty)
+        );
+        assert_eq!(
+            MotorsGroup::new(message(&[0, 9, 0])),
+            Err(MotorFrameError::TooManyMotors(9))
+        );
+        assert_eq!(
+            MotorsGroup::new(message(&[0, 1, 0])),
+            Err(MotorFrameError::SizeMismatch {
+                expected: 5,
+                found: 3
+            })
+        );
+        assert_eq!(
+            MotorsGroup::new(message(&[0, 0, 1, 0, 1])),
+            Err(MotorFrameError::NullId)
+        );
+        assert_eq!(
+            MotorsGroup::new(message(&[0, 0, 2, 4, 1, 4, 0])),
+            Err(MotorFrameError::DuplicateId(4))
+        );
+        assert_eq!(
+            MotorsGroup::new(message(&[0, 0, 1, 4, 2])),
+            Err(MotorFrameError::InvalidInfos(2))
+        );
+    }
+}
@@ -1,16 +1,16 @@
 //! Décrit l'API pour interagir avec la carte déplacement
 
 mod params;
+mod telemetry;
 
 use crate::transmission::Jsonizable;
-use heapless::{ArrayLength, String};
-use serde_json_core::de::{from_slice, Error as DError};
-use serde_json_core::ser::{to_string, Error as SError};
 
-pub use self::params::NavigationParametersFrame;
+pub use self::params::{NavigationParametersAck, NavigationParametersFrame, ParamsStatus};
+pub use self::telemetry::{PidTelemetryFrame, PidTerms};
 
 /// Trame contenant les informations echangees entre l'info et l'elec.
-#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, Deserialize, Serialize, Jsonizable)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct NavigationFrame {
     // Variables d'état écrites par l'elec
     /// position x du robot en dixieme de millimetres
@@ -59,12 +59,14 @@ pub struct NavigationFrame {
     /// argument 2 de la commande
     pub args_cmd2: u16,
     /// numéro de la commande en cours. Si on reçoit une commande
-    /// avec un numéro plus grand, on l'execute en priorité
+    /// avec un numéro plus grand, on l'execute en priorité (voir
+    /// [`CommandSeq`](crate::transmission::seq::CommandSeq))
     pub counter: u16,
 }
 
 /// Les differentes commandes que le déplacement peut effectuer
 #[derive(Debug, PartialEq, Copy, Clone, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum NavigationCommand {
     /// avancer.
     /// Arguments : distance, _
@@ -92,21 +94,6 @@ impl Default for NavigationCommand {
     }
 }
 
-impl Jsonizable for NavigationFrame {
-    /// Construit une trame a partir d'un flux de donnees json.
-    fn from_json_slice(slice: &[u8]) -> Result<Self, DError> {
-        from_slice(slice)
-    }
-
-    /// Construit une chaine de caractère en json à partir de cette trame
-    fn to_string<B>(&self) -> Result<String<B>, SError>
-    where
-        B: ArrayLength<u8>,
-    {
-        to_string(self)
-    }
-}
-
 #[cfg(test)]
 mod test {
     use super::{NavigationCommand, NavigationFrame};
@@ -1,40 +1,81 @@
 use crate::transmission::Jsonizable;
-use heapless::{ArrayLength, String};
-use serde_json_core::de::{from_slice, Error as DError};
-use serde_json_core::ser::{to_string, Error as SError};
+use crate::units::Fixed16;
 
 /// Trame contenant les paramètres de la navigation, pour permettre un
 /// changement en direct des paramètres du robot (concernant l'odométrie,
 /// les coefficients du PID, etc)
-#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize, Serialize, Jsonizable)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct NavigationParametersFrame {
     /// Le rayon d'une roue codeuse en dixièmes de mm
     pub coder_radius: u16,
-    /// Coefficient de correction de la roue codeuse droite en fixé 16 bits
-    pub right_wheel_coef: u32,
+    /// Coefficient de correction de la roue codeuse droite, négatif si la roue compte à l'envers
+    pub right_wheel_coef: Fixed16,
     /// La distance entre les roues codeuses en dixièmes de mm
     pub inter_axial_length: u16,
-    /// Le coefficient proportionnel sur la position en fixé 16 bits
-    pub pos_kp: u32,
-    /// Le coefficient dérivé sur la position en fixé 16 bits
-    pub pos_kd: u32,
-    /// Le coefficient proportionnel sur l'orientation en fixé 16 bits
-    pub orient_kp: u32,
-    /// Le coefficient dérivée sur l'orientation en fixé 16 bits
-    pub orient_kd: u32,
+    /// Le coefficient proportionnel sur la position
+    pub pos_kp: Fixed16,
+    /// Le coefficient dérivé sur la position
+    pub pos_kd: Fixed16,
+    /// Le coefficient proportionnel sur l'orientation
+    pub orient_kp: Fixed16,
+    /// Le coefficient dérivée sur l'orientation
+    pub orient_kd: Fixed16,
+    /// La commande maximale de l'asservissement en orientation, pour limiter les rotations
+    /// indépendamment de la commande maximale des moteurs. 0 pour garder la valeur actuelle.
+    #[serde(default)]
+    pub max_angle_output: u16,
+}
+
+/// Le résultat de l'application d'une [`NavigationParametersFrame`]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ParamsStatus {
+    /// Les paramètres ont été appliqués
+    Applied,
+    /// Refusés : le rayon des roues codeuses est nul
+    InvalidCoderRadius,
+    /// Refusés : la distance entre les roues codeuses est nulle
+    InvalidInterAxialLength,
+    /// Refusés : le coefficient de la roue codeuse droite est nul
+    InvalidWheelCoef,
+    /// Refusés : un coefficient du PID est négatif
+    NegativeGain,
 }
 
-impl Jsonizable for NavigationParametersFrame {
-    /// Construit une trame a partir d'un flux de donnees json.
-    fn from_json_slice(slice: &[u8]) -> Result<Self, DError> {
-        from_slice(slice)
+impl ParamsStatus {
+    /// Renvoie vrai si les paramètres ont été appliqués
+    pub fn is_applied(self) -> bool {
+        self == ParamsStatus::Applied
     }
+}
 
-    /// Construit une chaine de caractère en json à partir de cette trame
-    fn to_string<B>(&self) -> Result<String<B>, SError>
-    where
-        B: ArrayLength<u8>,
-    {
-        to_string(self)
+impl NavigationParametersFrame {
+    /// Vérifie que les paramètres peuvent être appliqués, sinon renvoie la raison du refus
+    pub fn validate(&self) -> Result<(), ParamsStatus> {
+        let gains = [self.pos_kp, self.pos_kd, self.orient_kp, self.orient_kd];
+        if self.coder_radius == 0 {
+            Err(ParamsStatus::InvalidCoderRadius)
+        } else if self.inter_axial_length == 0 {
+            Err(ParamsStatus::InvalidInterAxialLength)
+        } else if self.right_wheel_coef == Fixed16(0) {
+            Err(ParamsStatus::InvalidWheelCoef)
+        } else if gains.iter().any(|&gain| gain < Fixed16(0)) {
+            Err(ParamsStatus::NegativeGain)
+        } else {
+            Ok(())
+        }
     }
 }
+
+/// Réponse de la carte déplacement à une [`NavigationParametersFrame`] : les paramètres
+/// effectivement utilisés après la trame, et si elle a été appliquée ou pourquoi elle a été
+/// refusée.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize, Serialize, Jsonizable)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct NavigationParametersAck {
+    /// Les paramètres utilisés par la carte
+    pub params: NavigationParametersFrame,
+    /// Le résultat de l'application de la trame
+    pub status: ParamsStatus,
+}
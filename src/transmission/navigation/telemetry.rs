@@ -0,0 +1,112 @@
+use crate::transmission::Jsonizable;
+
+/// Les grandeurs internes d'un asservissement lors d'une mise à jour du PID. Les positions sont
+/// en dixièmes de mm pour l'asservissement longitudinal, et en dixièmes de mm d'écart entre les
+/// roues pour l'asservissement angulaire. Les commandes sont en dixièmes d'unité de commande
+/// moteur. Toutes les valeurs sont saturées à ±3276,7.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PidTerms {
+    /// La consigne de position
+    pub goal: i16,
+    /// La consigne de vitesse, par période d'échantillonnage
+    pub speed: i16,
+    /// L'écart entre la position mesurée et la consigne
+    pub error: i16,
+    /// La contribution du terme proportionnel à la commande
+    pub p: i16,
+    /// La contribution du terme intégral à la commande
+    pub i: i16,
+    /// La contribution du terme dérivé à la commande
+    pub d: i16,
+    /// La commande après saturation, 0 si l'asservissement est désactivé
+    pub out: i16,
+}
+
+/// Trame de télémétrie envoyée par la carte déplacement après une mise à jour du PID, pour
+/// analyser hors de la carte la réponse à un échelon. Elle n'est produite qu'une mise à jour sur
+/// `n` (voir `Controller::set_telemetry_rate`).
+///
+/// Les champs sont courts et les valeurs saturées pour que le JSON tienne toujours dans un
+/// [`Message`](crate::transmission::Message).
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, Deserialize, Serialize, Jsonizable)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PidTelemetryFrame {
+    /// Le numéro de la mise à jour du PID modulo 65536, pour repérer les trames perdues
+    pub sample: u16,
+    /// Le temps écoulé depuis la mise à jour précédente, en centièmes de période
+    /// d'échantillonnage
+    pub dt: u16,
+    /// L'asservissement longitudinal
+    pub lin: PidTerms,
+    /// L'asservissement angulaire
+    pub ang: PidTerms,
+    /// La commande envoyée au moteur gauche, négative en marche arrière
+    pub left: i32,
+    /// La commande envoyée au moteur droit, négative en marche arrière
+    pub right: i32,
+}
+
+#[cfg(test)]
+mod test {
+    use super::{PidTelemetryFrame, PidTerms};
+    use crate::transmission::{Jsonizable, Message, FRAME_MAX_SIZE};
+    use heapless::consts::{U256, U512};
+    use heapless::String;
+
+    #[test]
+    fn ser_deser_pid_telemetry() {
+        let terms = PidTerms {
+            goal: 1000,
+            speed: 5,
+            error: -12,
+            p: -12,
+            i: -3,
+            d: 0,
+            out: -15,
+        };
+        let frame = PidTelemetryFrame {
+            sample: 40,
+            dt: 100,
+            lin: terms,
+            ang: PidTerms::default(),
+            left: 2,
+            right: 1,
+        };
+        let strd: String<U256> = frame.to_string().unwrap();
+        let data = "{\"sample\":40,\"dt\":100,\"lin\":{\"goal\":1000,\"speed\":5,\
+                    \"error\":-12,\"p\":-12,\"i\":-3,\"d\":0,\"out\":-15},\
+                    \"ang\":{\"goal\":0,\"speed\":0,\"error\":0,\"p\":0,\"i\":0,\
+                    \"d\":0,\"out\":0},\"left\":2,\"right\":1}";
+        assert_eq!(strd, data);
+        assert_eq!(
+            PidTelemetryFrame::from_json_slice(data.as_bytes()).unwrap(),
+            frame
+        );
+
+        // Les valeurs les plus longues tiennent dans un message
+        let min = i16::min_value();
+        let extreme = PidTerms {
+            goal: min,
+            speed: min,
+            error: min,
+            p: min,
+            i: min,
+            d: min,
+            out: min,
+        };
+        let frame = PidTelemetryFrame {
+            sample: u16::max_value(),
+            dt: u16::max_value(),
+            lin: extreme,
+            ang: extreme,
+            left: -i32::from(u16::max_value()),
+            right: -i32::from(u16::max_value()),
+        };
+        let strd: String<U512> = frame.to_string().unwrap();
+        assert!(strd.len() <= FRAME_MAX_SIZE);
+        let mut message = Message::new();
+        message.extend(strd.bytes());
+        assert_eq!(PidTelemetryFrame::from_json_slice(&message).unwrap(), frame);
+    }
+}
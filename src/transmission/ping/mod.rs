@@ -0,0 +1,265 @@
+//! Trames de vie (ping/pong) permettant à l'informatique de savoir si une carte est en marche.
+//!
+//! L'informatique envoie régulièrement un [`PingFrame`] de type `Ping` ; la carte doit répondre
+//! le plus vite possible avec un `Pong` portant le même numéro de séquence et le même horodatage,
+//! ainsi que sa propre heure, ce que fait [`answer_ping`]. Le [`RttMeter`] permet à l'émetteur de
+//! mesurer le temps d'aller-retour et d'estimer le décalage entre les horloges des deux côtés.
+//!
+//! N'importe quelle trame peut être horodatée avec [`Timestamped`] pour corréler les mesures de
+//! l'électronique avec les logs de l'informatique.
+
+use crate::transmission::channel::{Channel, ChannelError, Transport};
+use crate::transmission::{Error, Jsonizable};
+use heapless::{ArrayLength, String};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json_core::de::from_slice;
+use serde_json_core::ser::to_string;
+
+/// Le type d'une trame de vie
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum PingKind {
+    /// Demande de réponse
+    Ping,
+    /// Réponse à un `Ping`
+    Pong,
+}
+
+/// Trame de vie échangée entre l'informatique et une carte
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize, Serialize, Jsonizable)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PingFrame {
+    /// `Ping` ou `Pong`
+    pub kind: PingKind,
+    /// Numéro de séquence, recopié dans la réponse
+    pub seq: u16,
+    /// Horodatage de l'émetteur du `Ping` en millisecondes, recopié dans la réponse
+    pub timestamp: u32,
+    /// Heure du répondeur au moment de la réponse en millisecondes (0 dans un `Ping`)
+    #[serde(default)]
+    pub remote_timestamp: u32,
+}
+
+impl PingFrame {
+    /// Crée une demande de réponse
+    pub fn ping(seq: u16, timestamp: u32) -> PingFrame {
+        PingFrame {
+            kind: PingKind::Ping,
+            seq,
+            timestamp,
+            remote_timestamp: 0,
+        }
+    }
+
+    /// Construit la réponse à cette trame émise à l'heure locale `now` (en millisecondes), ou
+    /// `None` si la trame est déjà une réponse
+    pub fn pong(&self, now: u32) -> Option<PingFrame> {
+        match self.kind {
+            PingKind::Ping => Some(PingFrame {
+                kind: PingKind::Pong,
+                remote_timestamp: now,
+                ..*self
+            }),
+            PingKind::Pong => None,
+        }
+    }
+}
+
+/// Une trame accompagnée de l'heure de l'émetteur au moment de la mesure.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Timestamped<T> {
+    /// Heure de l'émetteur en millisecondes
+    pub timestamp: u32,
+    /// La trame horodatée
+    pub frame: T,
+}
+
+impl<T> Timestamped<T> {
+    /// Horodate `frame` avec l'heure `timestamp` (en millisecondes)
+    pub fn new(timestamp: u32, frame: T) -> Self {
+        Timestamped { timestamp, frame }
+    }
+}
+
+impl<T> Jsonizable for Timestamped<T>
+where
+    T: Serialize + DeserializeOwned,
+{
+    /// Construit une trame a partir d'un flux de donnees json.
+    fn from_json_slice(slice: &[u8]) -> Result<Self, Error> {
+        Ok(from_slice(slice)?)
+    }
+
+    /// Construit une chaine de caractère en json à partir de cette trame
+    fn to_string<B>(&self) -> Result<String<B>, Error>
+    where
+        B: ArrayLength<u8>,
+    {
+        Ok(to_string(self)?)
+    }
+}
+
+/// Lit la prochaine trame de vie reçue sur `channel` et y répond si c'est un `Ping`. `now` est
+/// l'heure locale en millisecondes.
+///
+/// Renvoie `true` si une réponse a été envoyée.
+pub fn answer_ping<N, B>(
+    channel: &mut Channel<PingFrame, N, B>,
+    now: u32,
+) -> Result<bool, ChannelError<N::Error>>
+where
+    N: Transport,
+    B: ArrayLength<u8>,
+{
+    match channel.try_recv().and_then(|frame| frame.pong(now)) {
+        Some(pong) => channel.send_at(&pong, now).map(|_| true),
+        None => Ok(false),
+    }
+}
+
+/// Mesure du temps d'aller-retour (RTT) et du décalage d'horloge à l'aide des trames de vie.
+///
+/// Le décalage est estimé en supposant que le répondeur a répondu au milieu de l'aller-retour :
+/// `décalage = heure distante - (heure d'envoi + heure de réception) / 2`.
+#[derive(Debug, Default)]
+pub struct RttMeter {
+    seq: u16,
+    last_rtt: Option<u32>,
+    offset: Option<i32>,
+}
+
+impl RttMeter {
+    /// Crée un nouveau compteur sans mesure
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Construit le prochain `Ping` à envoyer. `now` est l'heure actuelle en millisecondes.
+    pub fn next_ping(&mut self, now: u32) -> PingFrame {
+        self.seq = self.seq.wrapping_add(1);
+        PingFrame::ping(self.seq, now)
+    }
+
+    /// Prend en compte une réponse reçue à l'heure `now` (en millisecondes) et renvoie le temps
+    /// d'aller-retour mesuré. Les réponses à un `Ping` autre que le dernier envoyé sont ignorées.
+    pub fn on_pong(&mut self, frame: &PingFrame, now: u32) -> Option<u32> {
+        if frame.kind != PingKind::Pong || frame.seq != self.seq {
+            return None;
+        }
+        let rtt = now.wrapping_sub(frame.timestamp);
+        let midpoint = frame.timestamp.wrapping_add(rtt / 2);
+        self.last_rtt = Some(rtt);
+        self.offset = Some(frame.remote_timestamp.wrapping_sub(midpoint) as i32);
+        Some(rtt)
+    }
+
+    /// Renvoie le dernier temps d'aller-retour mesuré, en millisecondes
+    pub fn last_rtt(&self) -> Option<u32> {
+        self.last_rtt
+    }
+
+    /// Renvoie le décalage estimé (heure distante - heure locale) en millisecondes
+    pub fn offset(&self) -> Option<i32> {
+        self.offset
+    }
+
+    /// Convertit une heure locale en heure du répondeur, si un décalage a été estimé
+    pub fn to_remote_time(&self, local: u32) -> Option<u32> {
+        self.offset.map(|offset| local.wrapping_add(offset as u32))
+    }
+
+    /// Convertit une heure du répondeur en heure locale, si un décalage a été estimé
+    pub fn to_local_time(&self, remote: u32) -> Option<u32> {
+        self.offset.map(|offset| remote.wrapping_sub(offset as u32))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{answer_ping, PingFrame, PingKind, RttMeter, Timestamped};
+    use crate::transmission::channel::test::DummyTransport;
+    use crate::transmission::channel::Channel;
+    use crate::transmission::color::Color;
+    use crate::transmission::Jsonizable;
+    use heapless::consts::U64;
+
+    #[test]
+    fn ser_deser_ping() {
+        let ping = PingFrame::ping(12, 3400);
+        let data = "{\"kind\":\"Ping\",\"seq\":12,\"timestamp\":3400,\"remote_timestamp\":0}";
+        assert_eq!(ping.to_string::<U64>().unwrap(), data);
+        assert_eq!(PingFrame::from_json_slice(data.as_bytes()).unwrap(), ping);
+        let data = "{\"kind\":\"Ping\",\"seq\":12,\"timestamp\":3400}";
+        assert_eq!(PingFrame::from_json_slice(data.as_bytes()).unwrap(), ping);
+    }
+
+    #[test]
+    fn ping_answered_with_pong() {
+        let mut transport = DummyTransport::default();
+        transport
+            .incoming
+            .push(b"{\"kind\":\"Ping\",\"seq\":3,\"timestamp\":100}".to_vec());
+        transport
+            .incoming
+            .push(b"{\"kind\":\"Pong\",\"seq\":3,\"timestamp\":100}".to_vec());
+        let mut channel: Channel<PingFrame, _, U64> = Channel::new(11, transport);
+
+        assert!(answer_ping(&mut channel, 42).unwrap());
+        assert!(!answer_ping(&mut channel, 43).unwrap());
+        assert!(!answer_ping(&mut channel, 44).unwrap());
+
+        let transport = channel.release();
+        assert_eq!(
+            transport.sent,
+            vec![(
+                11,
+                b"{\"kind\":\"Pong\",\"seq\":3,\"timestamp\":100,\"remote_timestamp\":42}".to_vec()
+            )]
+        );
+    }
+
+    #[test]
+    fn rtt_measurement() {
+        let mut meter = RttMeter::new();
+        let first = meter.next_ping(1000);
+        let second = meter.next_ping(1010);
+        assert_eq!(meter.on_pong(&first.pong(0).unwrap(), 1020), None);
+        assert_eq!(meter.offset(), None);
+        assert_eq!(meter.on_pong(&second.pong(0).unwrap(), 1025), Some(15));
+        assert_eq!(meter.last_rtt(), Some(15));
+        assert_eq!(second.pong(0).unwrap().kind, PingKind::Pong);
+        assert_eq!(second.pong(0).unwrap().pong(0), None);
+    }
+
+    #[test]
+    fn clock_offset_estimation() {
+        let mut meter = RttMeter::new();
+        let ping = meter.next_ping(1000);
+        // Le répondeur a 500 ms d'avance et répond 10 ms après l'envoi
+        meter.on_pong(&ping.pong(1510).unwrap(), 1020);
+        assert_eq!(meter.offset(), Some(500));
+        assert_eq!(meter.to_remote_time(2000), Some(2500));
+        assert_eq!(meter.to_local_time(2500), Some(2000));
+
+        // Le répondeur est en retard
+        let ping = meter.next_ping(3000);
+        meter.on_pong(&ping.pong(10).unwrap(), 3020);
+        assert_eq!(meter.offset(), Some(-3000));
+        assert_eq!(meter.to_local_time(10), Some(3010));
+    }
+
+    #[test]
+    fn ser_deser_timestamped() {
+        let frame = Timestamped::new(1234, Color::Red);
+        let data = "{\"timestamp\":1234,\"frame\":\"Red\"}";
+        assert_eq!(frame.to_string::<U64>().unwrap(), data);
+        let frame2: Timestamped<Color> = Timestamped::from_json_slice(data.as_bytes()).unwrap();
+        assert_eq!(frame2.timestamp, 1234);
+        match frame2.frame {
+            Color::Red => {}
+            other => panic!("{:?} should be Red", other),
+        }
+    }
+}
@@ -0,0 +1,140 @@
+//! Calcul des ports UDP utilisés par chaque carte.
+//!
+//! Chaque carte écoute sur le port `ELEC_LISTENING_PORT + id` et envoie ses trames sur le port
+//! `INFO_LISTENING_PORT + id` de l'ordinateur embarqué. Plutôt que de faire l'addition à la
+//! main, on demande les ports à une [`PortMap`], qui vérifie aussi que deux cartes ne se
+//! retrouvent pas sur le même port :
+//!
+//! ```ignore
+//! let ports = PortMap::default();
+//! ports.validate(&[ID_SERVO, ID_IO, ID_BROADCAST])?;
+//! eth::try_listen_on(&mut w5500, &mut spi, ports.elec_port(ID_SERVO), Socket::Socket1)?;
+//! ```
+
+use crate::transmission::id::{ELEC_LISTENING_PORT, INFO_LISTENING_PORT};
+use crate::transmission::{Error, MessageKind};
+
+/// Associe l'identifiant de chaque carte à ses ports d'écoute et d'envoi
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PortMap {
+    elec_base: u16,
+    info_base: u16,
+}
+
+impl Default for PortMap {
+    /// Les ports utilisés par l'informatique, à partir de [`ELEC_LISTENING_PORT`] et
+    /// [`INFO_LISTENING_PORT`]
+    fn default() -> Self {
+        PortMap::new(ELEC_LISTENING_PORT, INFO_LISTENING_PORT)
+    }
+}
+
+impl PortMap {
+    /// Crée une table où les cartes écoutent à partir du port `elec_base` et envoient à partir
+    /// du port `info_base`
+    pub const fn new(elec_base: u16, info_base: u16) -> Self {
+        PortMap {
+            elec_base,
+            info_base,
+        }
+    }
+
+    /// Renvoie le port sur lequel la carte `id` écoute les trames de l'informatique.
+    ///
+    /// Le calcul est fait modulo 2^16 : [`PortMap::validate`] vérifie qu'il ne déborde pas.
+    pub fn elec_port(&self, id: u16) -> u16 {
+        self.elec_base.wrapping_add(id)
+    }
+
+    /// Renvoie le port de l'ordinateur embarqué auquel la carte `id` envoie ses trames
+    pub fn info_port(&self, id: u16) -> u16 {
+        self.info_base.wrapping_add(id)
+    }
+
+    /// Renvoie l'identifiant de la carte qui écoute sur le port `port`, s'il y en a une. Un port
+    /// qui ne correspond à aucun type de message (voir [`MessageKind::from_id`]), par exemple un
+    /// port d'envoi, n'appartient à aucune carte.
+    pub fn module_id(&self, port: u16) -> Option<u16> {
+        port.checked_sub(self.elec_base)
+            .filter(|&id| MessageKind::from_id(id).is_ok())
+    }
+
+    /// Vérifie que les ports des cartes `ids` ne débordent pas et sont tous différents, y
+    /// compris entre les ports d'écoute et d'envoi
+    pub fn validate(&self, ids: &[u16]) -> Result<(), Error> {
+        for (index, &id) in ids.iter().enumerate() {
+            if self.elec_base.checked_add(id).is_none() || self.info_base.checked_add(id).is_none()
+            {
+                return Err(Error::PortOverflow(id));
+            }
+            for &other in ids[..index].iter() {
+                let collides = self.elec_port(id) == self.elec_port(other)
+                    || self.elec_port(id) == self.info_port(other)
+                    || self.info_port(id) == self.elec_port(other);
+                if collides {
+                    return Err(Error::PortCollision(other, id));
+                }
+            }
+            if self.elec_port(id) == self.info_port(id) {
+                return Err(Error::PortCollision(id, id));
+            }
+        }
+        Ok(())
+    }
+
+    /// Vérifie les ports des cartes de tous les types de message, voir [`PortMap::validate`]
+    pub fn validate_all(&self) -> Result<(), Error> {
+        let mut ids = [0; MessageKind::COUNT];
+        let kinds = (0..=u8::max_value()).filter_map(|kind| MessageKind::from_u8(kind).ok());
+        for (id, kind) in ids.iter_mut().zip(kinds) {
+            *id = kind.id();
+        }
+        self.validate(&ids)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::PortMap;
+    use crate::transmission::id::{ID_BROADCAST, ID_IO, ID_NAVIGATION, ID_SERVO};
+    use crate::transmission::Error;
+
+    #[test]
+    fn default_ports() {
+        let ports = PortMap::default();
+        assert_eq!(ports.elec_port(ID_SERVO), 52);
+        assert_eq!(ports.info_port(ID_SERVO), 5002);
+        assert_eq!(ports.module_id(52), Some(ID_SERVO));
+        assert_eq!(ports.module_id(10), None);
+        // Le port d'envoi de la carte des servos n'est le port d'écoute d'aucune carte
+        assert_eq!(ports.module_id(5002), None);
+        assert_eq!(
+            ports.module_id(ports.elec_port(ID_BROADCAST)),
+            Some(ID_BROADCAST)
+        );
+        assert!(ports.validate_all().is_ok());
+    }
+
+    #[test]
+    fn port_collisions() {
+        let ports = PortMap::default();
+        match ports.validate(&[ID_NAVIGATION, ID_SERVO, ID_NAVIGATION]) {
+            Err(Error::PortCollision(ID_NAVIGATION, ID_NAVIGATION)) => {}
+            other => panic!("{:?}", other),
+        }
+
+        // Les ports d'écoute de l'une rejoignent les ports d'envoi de l'autre
+        let close = PortMap::new(50, 52);
+        match close.validate(&[ID_SERVO, ID_IO]) {
+            Err(Error::PortCollision(ID_SERVO, ID_IO)) => {}
+            other => panic!("{:?}", other),
+        }
+
+        let high = PortMap::new(65400, 5000);
+        match high.validate(&[ID_SERVO, ID_BROADCAST]) {
+            Err(Error::PortOverflow(ID_BROADCAST)) => {}
+            other => panic!("{:?}", other),
+        }
+    }
+}
@@ -0,0 +1,147 @@
+//! Décrit l'état de la batterie mesuré par la carte IO
+
+use crate::transmission::Jsonizable;
+use crate::units::{MilliAmpere, MilliVolt};
+
+/// Nombre maximal de cellules surveillées
+pub const MAX_CELLS: usize = 6;
+
+/// Les seuils utilisés pour estimer l'état de la batterie
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct PowerThresholds {
+    /// Tension d'une cellule vide (0% de charge)
+    pub empty_cell_voltage: MilliVolt,
+    /// Tension d'une cellule pleine (100% de charge)
+    pub full_cell_voltage: MilliVolt,
+    /// En dessous de cette tension, une cellule est considérée déchargée
+    pub low_cell_voltage: MilliVolt,
+    /// Au delà de ce courant, on signale une surintensité
+    pub over_current: MilliAmpere,
+}
+
+impl Default for PowerThresholds {
+    /// Seuils d'une batterie LiPo
+    fn default() -> Self {
+        PowerThresholds {
+            empty_cell_voltage: MilliVolt(3300),
+            full_cell_voltage: MilliVolt(4200),
+            low_cell_voltage: MilliVolt(3500),
+            over_current: MilliAmpere(20000),
+        }
+    }
+}
+
+/// Trame contenant l'état de la batterie
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, Deserialize, Serialize, Jsonizable)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PowerFrame {
+    /// Tension de chaque cellule. `0` veut dire qu'il n'y a pas de cellule.
+    pub cells: [MilliVolt; MAX_CELLS],
+    /// Courant total consommé
+    pub current: MilliAmpere,
+    /// Etat de charge estimé en pourcents
+    pub state_of_charge: u8,
+    /// Vrai si au moins une cellule est déchargée
+    pub low_battery: bool,
+    /// Vrai si le courant consommé dépasse le seuil de surintensité
+    pub over_current: bool,
+}
+
+impl PowerFrame {
+    /// Construit la trame à partir des mesures en calculant l'état de charge et les indicateurs.
+    /// L'état de charge est estimé linéairement à partir de la cellule la plus faible.
+    pub fn from_measurements(
+        cells: [MilliVolt; MAX_CELLS],
+        current: MilliAmpere,
+        thresholds: &PowerThresholds,
+    ) -> PowerFrame {
+        let weakest = cells.iter().filter(|&&c| c != MilliVolt(0)).min().cloned();
+        let state_of_charge = match weakest {
+            Some(voltage) if voltage >= thresholds.full_cell_voltage => 100,
+            Some(voltage) if voltage > thresholds.empty_cell_voltage => {
+                let range = thresholds.full_cell_voltage - thresholds.empty_cell_voltage;
+                ((voltage - thresholds.empty_cell_voltage).0 * 100 / range.0) as u8
+            }
+            _ => 0,
+        };
+        PowerFrame {
+            cells,
+            current,
+            state_of_charge,
+            low_battery: weakest.map_or(false, |v| v < thresholds.low_cell_voltage),
+            over_current: current > thresholds.over_current,
+        }
+    }
+
+    /// Renvoie la tension totale de la batterie
+    pub fn total_voltage(&self) -> MilliVolt {
+        self.cells
+            .iter()
+            .fold(MilliVolt(0), |total, &cell| total + cell)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{PowerFrame, PowerThresholds, MAX_CELLS};
+    use crate::transmission::Jsonizable;
+    use crate::units::{MilliAmpere, MilliVolt};
+    use heapless::consts::U256;
+    use heapless::String;
+    type N = U256;
+
+    fn cells(voltages: [i32; MAX_CELLS]) -> [MilliVolt; MAX_CELLS] {
+        let mut cells = [MilliVolt(0); MAX_CELLS];
+        for (cell, &voltage) in cells.iter_mut().zip(voltages.iter()) {
+            *cell = MilliVolt(voltage);
+        }
+        cells
+    }
+
+    #[test]
+    fn power_from_measurements() {
+        let thresholds = PowerThresholds::default();
+        let frame = PowerFrame::from_measurements(
+            cells([3750, 3800, 3900, 0, 0, 0]),
+            MilliAmpere(1500),
+            &thresholds,
+        );
+        assert_eq!(frame.state_of_charge, 50);
+        assert!(!frame.low_battery);
+        assert!(!frame.over_current);
+        assert_eq!(frame.total_voltage(), MilliVolt(11450));
+
+        let frame = PowerFrame::from_measurements(
+            cells([3400, 4200, 4200, 0, 0, 0]),
+            MilliAmpere(25000),
+            &thresholds,
+        );
+        assert_eq!(frame.state_of_charge, 11);
+        assert!(frame.low_battery);
+        assert!(frame.over_current);
+
+        let frame = PowerFrame::from_measurements(
+            cells([4250, 4250, 0, 0, 0, 0]),
+            MilliAmpere(0),
+            &thresholds,
+        );
+        assert_eq!(frame.state_of_charge, 100);
+        let frame = PowerFrame::from_measurements(cells([0; 6]), MilliAmpere(0), &thresholds);
+        assert_eq!(frame.state_of_charge, 0);
+        assert!(!frame.low_battery);
+    }
+
+    #[test]
+    fn ser_deser_power() {
+        let frame = PowerFrame::from_measurements(
+            cells([3750, 3800, 3900, 0, 0, 0]),
+            MilliAmpere(-200),
+            &PowerThresholds::default(),
+        );
+        let strd: String<N> = frame.to_string().unwrap();
+        let data = "{\"cells\":[3750,3800,3900,0,0,0],\"current\":-200,\"state_of_charge\":50,\
+                    \"low_battery\":false,\"over_current\":false}";
+        assert_eq!(strd, data);
+        assert_eq!(PowerFrame::from_json_slice(data.as_bytes()).unwrap(), frame);
+    }
+}
@@ -0,0 +1,109 @@
+//! Décrit les mesures de distance remontées par les capteurs de distance (couronne de VL53L1X,
+//! petit lidar) pour l'évitement d'obstacles.
+
+use crate::transmission::Jsonizable;
+
+/// Nombre maximal de mesures dans une trame
+pub const MAX_RANGE_MEASURES: usize = 16;
+
+/// Une mesure de distance
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct RangeMeasure {
+    /// Identifiant du capteur (ou de l'angle du lidar). `sensor == 0` veut dire qu'il n'y a pas
+    /// de mesure.
+    pub sensor: u8,
+    /// Distance mesurée en millimètres
+    pub distance: u16,
+    /// Qualité de la mesure, de 0 (inutilisable) à 255 (parfaite)
+    pub quality: u8,
+}
+
+/// Trame regroupant au plus `MAX_RANGE_MEASURES` mesures de distance
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, Deserialize, Serialize, Jsonizable)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct RangeFrame {
+    /// Les mesures
+    pub measures: [RangeMeasure; MAX_RANGE_MEASURES],
+}
+
+impl RangeFrame {
+    /// Ajoute une mesure dans le premier emplacement libre. Renvoie la mesure si la trame est
+    /// pleine ou si `measure.sensor` vaut 0.
+    pub fn push(&mut self, measure: RangeMeasure) -> Result<(), RangeMeasure> {
+        if measure.sensor == 0 {
+            return Err(measure);
+        }
+        match self.measures.iter_mut().find(|m| m.sensor == 0) {
+            Some(slot) => {
+                *slot = measure;
+                Ok(())
+            }
+            None => Err(measure),
+        }
+    }
+
+    /// Renvoie un itérateur sur les mesures présentes
+    pub fn iter(&self) -> impl Iterator<Item = &RangeMeasure> {
+        self.measures.iter().filter(|m| m.sensor != 0)
+    }
+
+    /// Renvoie la mesure de plus petite distance dont la qualité est au moins `min_quality`
+    pub fn closest(&self, min_quality: u8) -> Option<&RangeMeasure> {
+        self.iter()
+            .filter(|m| m.quality >= min_quality)
+            .min_by_key(|m| m.distance)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{RangeFrame, RangeMeasure, MAX_RANGE_MEASURES};
+    use crate::transmission::Jsonizable;
+    use heapless::consts::U1024;
+    use heapless::String;
+    type N = U1024;
+
+    #[test]
+    fn ser_deser_range() {
+        let mut frame = RangeFrame::default();
+        frame
+            .push(RangeMeasure {
+                sensor: 1,
+                distance: 350,
+                quality: 200,
+            })
+            .unwrap();
+        frame
+            .push(RangeMeasure {
+                sensor: 2,
+                distance: 120,
+                quality: 10,
+            })
+            .unwrap();
+        let strd: String<N> = frame.to_string().unwrap();
+        let frame2 = RangeFrame::from_json_slice(strd.as_bytes()).unwrap();
+        assert_eq!(frame, frame2);
+        assert_eq!(frame2.iter().count(), 2);
+        assert_eq!(frame2.closest(0).unwrap().sensor, 2);
+        assert_eq!(frame2.closest(100).unwrap().sensor, 1);
+    }
+
+    #[test]
+    fn range_frame_full() {
+        let mut frame = RangeFrame::default();
+        let measure = RangeMeasure {
+            sensor: 3,
+            distance: 10,
+            quality: 255,
+        };
+        for _ in 0..MAX_RANGE_MEASURES {
+            frame.push(measure).unwrap();
+        }
+        assert_eq!(frame.push(measure), Err(measure));
+        assert_eq!(
+            RangeFrame::default().push(RangeMeasure::default()),
+            Err(RangeMeasure::default())
+        );
+    }
+}
@@ -0,0 +1,598 @@
+//! Auto-test de la pile de transmission.
+//!
+//! Au démarrage, une carte peut appeler [`self_test`] pour vérifier que chaque type de trame
+//! survit à un aller-retour complet : sérialisation, envoi sur un [`LoopbackTransport`], réception,
+//! désérialisation puis comparaison avec la trame d'origine. Chaque échec est signalé par une
+//! [`DiagnosticFrame`] avec le code [`ErrorCode::SelfTestFailed`], dont le module est
+//! l'identifiant du type de trame et le contexte l'étape qui a échoué (voir [`Stage`]).
+//!
+//! ```ignore
+//! let failures = self_test::<U4096, _>(|frame| diagnostics.send(&frame).unwrap_or(()));
+//! ```
+
+use crate::transmission::announce::AnnounceFrame;
+use crate::transmission::arm::{ArmFrame, Joint, TorqueStatus};
+use crate::transmission::channel::{Channel, LoopbackTransport};
+use crate::transmission::check::{Actuator, ActuatorCheck, ActuatorCheckFrame, Verdict};
+use crate::transmission::color::{Color, ColorCalibration, ColorReading};
+use crate::transmission::dfu::DfuFrame;
+use crate::transmission::diagnostic::{DiagnosticFrame, DiagnosticReporter, ErrorCode, Severity};
+use crate::transmission::event::{EventFrame, MatchEvent};
+use crate::transmission::funny::{FunnyAction, FunnyActionFrame, FunnyActionKind};
+use crate::transmission::game::{GameElement, GameStatusFrame, MechanismState};
+use crate::transmission::health::{HealthFrame, TransmissionCounters};
+use crate::transmission::imu::{ImuFrame, Vector3};
+use crate::transmission::io::edge::{pin, Edge, EdgeFrame};
+use crate::transmission::io::{
+    AnalogChannel, AnalogFrame, BuzzerState, Gpio, GpioBank, IOState, Melody, Note, PinDirection,
+    Pneumatic, Pull, TriggerState, IO,
+};
+use crate::transmission::led::{LedAnimation, LedFrame, Rgb};
+use crate::transmission::log::{LogFrame, LogLevel};
+use crate::transmission::motor::{
+    Brushless, ControlledMotor, MotorsGroup, Rotation as MotorRotation, UncontrolledMotor,
+};
+use crate::transmission::navigation::{
+    NavigationCommand, NavigationFrame, NavigationParametersAck, NavigationParametersFrame,
+    ParamsStatus, PidTelemetryFrame, PidTerms,
+};
+use crate::transmission::ping::PingFrame;
+use crate::transmission::power::PowerFrame;
+use crate::transmission::rangefinder::{RangeFrame, RangeMeasure};
+use crate::transmission::servo::{
+    self, BlockingMode, Control, Rotation as ServoRotation, ServoCalibration,
+};
+use crate::transmission::{Jsonizable, MessageKind, Servo, ServoGroup};
+use crate::units::{Celsius, Fixed16, MilliAmpere, MilliVolt};
+use core::marker::PhantomData;
+use heapless::{ArrayLength, String};
+
+/// L'étape de l'aller-retour qui a échoué, envoyée dans le contexte de la trame de diagnostic
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Stage {
+    /// La trame n'a pas pu être sérialisée ou envoyée
+    Send = 1,
+    /// Aucune trame valide n'a été reçue
+    Receive = 2,
+    /// La trame reçue est différente de la trame envoyée
+    Compare = 3,
+}
+
+/// Fait faire un aller-retour à `sample` sur un transport en boucle locale
+pub fn round_trip<T, B>(kind: MessageKind, sample: &T) -> Result<(), Stage>
+where
+    T: Jsonizable + PartialEq,
+    B: ArrayLength<u8>,
+{
+    let mut channel: Channel<T, LoopbackTransport<B>, B> =
+        Channel::new(kind.id(), LoopbackTransport::new());
+    channel.send(sample).map_err(|_| Stage::Send)?;
+    let received = channel.try_recv().ok_or(Stage::Receive)?;
+    if received == *sample {
+        Ok(())
+    } else {
+        Err(Stage::Compare)
+    }
+}
+
+/// Reçoit un exemple de chaque type de trame (voir [`visit_samples`])
+pub trait SampleVisitor {
+    /// Appelée avec le type `kind` et un exemple `sample` de trame de ce type
+    fn visit<T: Jsonizable + PartialEq>(&mut self, kind: MessageKind, sample: &T);
+}
+
+/// Renvoie un groupe de deux servos dont tous les champs sont renseignés
+pub fn servo_sample() -> ServoGroup {
+    let mut group = ServoGroup {
+        prepare: true,
+        counter: 12,
+        bus: 1,
+        ..Default::default()
+    };
+    group.servos[0] = Servo {
+        id: 4,
+        known_position: 511,
+        control: Control::Speed,
+        rotation: ServoRotation::Clockwise,
+        data: 512,
+        blocked: true,
+        mode: BlockingMode::HoldOnBlock,
+        color: servo::Color::Blue,
+        max_speed: 300,
+        max_acceleration: 40,
+        torque_limit: 250,
+        compliance_margin: 3,
+        load: -120,
+        temperature: Celsius(42),
+        voltage: MilliVolt(11_800),
+        error: servo::error::OVERHEATING,
+    };
+    group.servos[1] = Servo {
+        id: 7,
+        known_position: 16,
+        control: Control::Position,
+        rotation: ServoRotation::CounterClockwise,
+        data: 1023,
+        blocked: false,
+        mode: BlockingMode::Unblocking,
+        color: servo::Color::Magenta,
+        max_speed: 0,
+        max_acceleration: 0,
+        torque_limit: 1023,
+        compliance_margin: 1,
+        load: 75,
+        temperature: Celsius(-5),
+        voltage: MilliVolt(12_100),
+        error: 0,
+    };
+    group
+}
+
+/// Renvoie un groupe de moteurs contenant chaque type de moteur
+pub fn motors_sample() -> MotorsGroup {
+    let mut group = MotorsGroup::default();
+    group.controlled[0] = ControlledMotor {
+        id: 1,
+        wanted_angle_position: 90,
+        wanted_nb_turns: 2,
+        finished: true,
+        new_command: false,
+    };
+    group.controlled[1] = ControlledMotor {
+        id: 2,
+        wanted_angle_position: 255,
+        wanted_nb_turns: 0,
+        finished: false,
+        new_command: true,
+    };
+    group.uncontrolled[0] = UncontrolledMotor {
+        id: 3,
+        on: true,
+        rotation: MotorRotation::Clockwise,
+    };
+    group.brushless[0] = Brushless { id: 5, on: true };
+    group
+}
+
+/// Renvoie une trame de centrale inertielle avec magnétomètre
+pub fn imu_sample() -> ImuFrame {
+    ImuFrame {
+        accel: Vector3 {
+            x: -981,
+            y: 12,
+            z: 9810,
+        },
+        gyro: Vector3 {
+            x: 3,
+            y: -250,
+            z: 70_000,
+        },
+        mag: Some(Vector3 {
+            x: 200,
+            y: -10,
+            z: 430,
+        }),
+        temperature: 254,
+    }
+}
+
+/// Appelle `visitor` avec un exemple de chaque type de trame de [`MessageKind`]
+pub fn visit_samples<V: SampleVisitor>(visitor: &mut V) {
+    let kind = MessageKind::Servo;
+    visitor.visit(kind, &servo_sample());
+    let kind = MessageKind::Navigation;
+    let navigation = NavigationFrame {
+        x: 1500,
+        y: -320,
+        angle: -7854,
+        left_dist: 12_000,
+        right_dist: 11_950,
+        blocked: false,
+        moving_done: true,
+        asserv_lin: true,
+        asserv_ang: false,
+        led: true,
+        reset: false,
+        max_lin_speed: 800,
+        max_ang_speed: 3000,
+        lin_accuracy: 50,
+        ang_accuracy: 100,
+        command: NavigationCommand::TurnRelative,
+        args_cmd1: 15_708,
+        args_cmd2: 1,
+        counter: 42,
+    };
+    visitor.visit(kind, &navigation);
+    let kind = MessageKind::IO;
+    let io = IO {
+        buzzer: BuzzerState::Rest,
+        tirette: TriggerState::Waiting,
+        limit_left_down: TriggerState::Waiting,
+        limit_left_middle: TriggerState::Waiting,
+        limit_left_high: TriggerState::Waiting,
+        limit_right_down: TriggerState::Triggered,
+        limit_right_middle: TriggerState::Triggered,
+        limit_right_high: TriggerState::Triggered,
+        counter: 1,
+    };
+    visitor.visit(kind, &io);
+    let kind = MessageKind::Pneumatic;
+    let pneumatic = Pneumatic {
+        pumps: [IOState::On, IOState::Off],
+        valves: [IOState::Off, IOState::On, IOState::On, IOState::Off],
+        valve_pulses: [0, 250, 0, 1000],
+        pressures: [870, 12],
+        counter: 3,
+    };
+    visitor.visit(kind, &pneumatic);
+    let kind = MessageKind::Color;
+    visitor.visit(kind, &Color::Green);
+    let kind = MessageKind::NavigationParameters;
+    let params = NavigationParametersFrame {
+        coder_radius: 315,
+        right_wheel_coef: Fixed16::from_f32(-1.0),
+        inter_axial_length: 2800,
+        pos_kp: Fixed16::ONE,
+        pos_kd: Fixed16(0),
+        orient_kp: Fixed16::ONE,
+        orient_kd: Fixed16(0),
+        max_angle_output: 100,
+    };
+    visitor.visit(kind, &params);
+    let kind = MessageKind::Ping;
+    visitor.visit(kind, &PingFrame::ping(1, 1000));
+    let kind = MessageKind::Imu;
+    visitor.visit(kind, &imu_sample());
+    let kind = MessageKind::Range;
+    let mut range = RangeFrame::default();
+    range.measures[0] = RangeMeasure {
+        sensor: 1,
+        distance: 350,
+        quality: 200,
+    };
+    range.measures[1] = RangeMeasure {
+        sensor: 4,
+        distance: 2000,
+        quality: 15,
+    };
+    visitor.visit(kind, &range);
+    let kind = MessageKind::Power;
+    let power = PowerFrame {
+        cells: [
+            MilliVolt(4150),
+            MilliVolt(4120),
+            MilliVolt(3480),
+            MilliVolt(0),
+            MilliVolt(0),
+            MilliVolt(0),
+        ],
+        current: MilliAmpere(-2350),
+        state_of_charge: 64,
+        low_battery: true,
+        over_current: false,
+    };
+    visitor.visit(kind, &power);
+    let kind = MessageKind::Arm;
+    let mut arm = ArmFrame {
+        arm: 1,
+        ..Default::default()
+    };
+    arm.joints[0] = Joint {
+        id: 11,
+        target_angle: -900,
+        speed: 120,
+        present_angle: -875,
+        torque: TorqueStatus::On,
+    };
+    arm.joints[1] = Joint {
+        id: 12,
+        target_angle: 450,
+        speed: 0,
+        present_angle: 460,
+        torque: TorqueStatus::Overload,
+    };
+    visitor.visit(kind, &arm);
+    let kind = MessageKind::Led;
+    let mut led = LedFrame {
+        animation: LedAnimation::Breathe,
+        color: Rgb::new(255, 128, 0),
+        period: 1500,
+        ..Default::default()
+    };
+    led.leds[0] = Rgb::new(1, 2, 3);
+    led.leds[15] = Rgb::new(0, 0, 255);
+    visitor.visit(kind, &led);
+    let kind = MessageKind::Buzzer;
+    let mut melody = Melody::default();
+    melody.notes[0] = Note {
+        frequency: 440,
+        duration: 250,
+    };
+    melody.notes[1] = Note {
+        frequency: 0,
+        duration: 100,
+    };
+    melody.notes[2] = Note {
+        frequency: 880,
+        duration: 500,
+    };
+    visitor.visit(kind, &melody);
+    let kind = MessageKind::ColorReading;
+    let reading = ColorReading {
+        red: 1200,
+        green: 340,
+        blue: 90,
+        clear: 1700,
+    };
+    visitor.visit(kind, &reading);
+    let kind = MessageKind::ColorCalibration;
+    let calibration = ColorCalibration {
+        red: reading,
+        green: ColorReading {
+            red: 200,
+            green: 1100,
+            blue: 150,
+            clear: 1500,
+        },
+        blue: ColorReading {
+            red: 80,
+            green: 300,
+            blue: 1250,
+            clear: 1650,
+        },
+    };
+    visitor.visit(kind, &calibration);
+    let kind = MessageKind::Gpio;
+    let mut gpios = GpioBank::default();
+    gpios.pins[0] = Gpio {
+        pin: 3,
+        direction: PinDirection::Output,
+        state: IOState::On,
+        pull: Pull::Floating,
+    };
+    gpios.pins[1] = Gpio {
+        pin: 8,
+        direction: PinDirection::Input,
+        state: IOState::Off,
+        pull: Pull::Down,
+    };
+    visitor.visit(kind, &gpios);
+    let kind = MessageKind::Analog;
+    let mut analog = AnalogFrame::default();
+    analog.channels[0] = AnalogChannel {
+        id: 1,
+        millivolts: 3300,
+        average: 3280,
+    };
+    analog.channels[1] = AnalogChannel {
+        id: 6,
+        millivolts: 12,
+        average: 0,
+    };
+    visitor.visit(kind, &analog);
+    let kind = MessageKind::Diagnostic;
+    let diagnostic = DiagnosticReporter::new(kind.id()).report(
+        ErrorCode::SelfTestFailed,
+        Severity::Warning,
+        Some(-1),
+    );
+    visitor.visit(kind, &diagnostic);
+    let kind = MessageKind::Health;
+    let health = HealthFrame {
+        loop_period: 1000,
+        max_jitter: 37,
+        cpu_headroom: 58,
+        free_stack: 2048,
+        transmission: TransmissionCounters {
+            sent: 123_456,
+            received: 654_321,
+            invalid: 7,
+            send_errors: 2,
+            unauthenticated: 1,
+            rate_limited: 9,
+        },
+    };
+    visitor.visit(kind, &health);
+    let kind = MessageKind::Log;
+    let log = LogFrame {
+        level: LogLevel::Info,
+        code: 0,
+        timestamp: 0,
+        message: String::from("self-test"),
+        dropped: 0,
+    };
+    visitor.visit(kind, &log);
+    let kind = MessageKind::Dfu;
+    let dfu = DfuFrame::enter(b"librobot");
+    visitor.visit(kind, &dfu);
+    let kind = MessageKind::Motors;
+    visitor.visit(kind, &motors_sample());
+    let kind = MessageKind::Event;
+    let event = EventFrame::broadcast(MatchEvent::MatchStart, 0);
+    visitor.visit(kind, &event);
+    let kind = MessageKind::FunnyAction;
+    let mut funny = FunnyActionFrame::default();
+    funny.actions[0] = FunnyAction {
+        kind: FunnyActionKind::Servo,
+        target: 4,
+        value: 700,
+        offset: 0,
+    };
+    funny.actions[1] = FunnyAction {
+        kind: FunnyActionKind::Valve,
+        target: 2,
+        value: 1,
+        offset: -500,
+    };
+    visitor.visit(kind, &funny);
+    let kind = MessageKind::GameStatus;
+    let game = GameStatusFrame {
+        held: [
+            GameElement::Red,
+            GameElement::Unknown,
+            GameElement::Empty,
+            GameElement::Blue,
+        ],
+        storage: [3, 0, 1, 5],
+        mechanisms: [
+            MechanismState::Deployed,
+            MechanismState::Retracted,
+            MechanismState::Moving,
+            MechanismState::Fault,
+            MechanismState::Unknown,
+            MechanismState::Retracted,
+        ],
+    };
+    visitor.visit(kind, &game);
+    let kind = MessageKind::Announce;
+    let modules = [
+        MessageKind::Navigation.id(),
+        MessageKind::NavigationParameters.id(),
+    ];
+    let announce = AnnounceFrame::new(1, &modules, 0x1a2b_3c4d);
+    visitor.visit(kind, &announce);
+    let kind = MessageKind::ServoCalibration;
+    let calibration = ServoCalibration {
+        id: 4,
+        offset: -12,
+        min_position: 100,
+        max_position: 900,
+        inverted: true,
+    };
+    visitor.visit(kind, &calibration);
+    let kind = MessageKind::NavigationParametersAck;
+    let status = ParamsStatus::Applied;
+    visitor.visit(kind, &NavigationParametersAck { params, status });
+    let kind = MessageKind::PidTelemetry;
+    let telemetry = PidTelemetryFrame {
+        sample: 17,
+        dt: 10,
+        lin: PidTerms {
+            goal: 500,
+            speed: 480,
+            error: 20,
+            p: 40,
+            i: -3,
+            d: 1,
+            out: 38,
+        },
+        ang: PidTerms {
+            goal: -100,
+            speed: -90,
+            error: -10,
+            p: -25,
+            i: 0,
+            d: -2,
+            out: -27,
+        },
+        left: 1200,
+        right: -1180,
+    };
+    visitor.visit(kind, &telemetry);
+    let kind = MessageKind::IoEdge;
+    let edge = EdgeFrame {
+        pin: pin::TIRETTE,
+        edge: Edge::Triggered,
+        timestamp: 0,
+    };
+    visitor.visit(kind, &edge);
+    let kind = MessageKind::ActuatorCheck;
+    let mut checks = ActuatorCheckFrame {
+        page: 1,
+        pages: 2,
+        ..Default::default()
+    };
+    checks.checks[0] = ActuatorCheck {
+        actuator: Actuator::Servo,
+        id: 4,
+        verdict: Verdict::Passed,
+    };
+    checks.checks[1] = ActuatorCheck {
+        actuator: Actuator::Valve,
+        id: 2,
+        verdict: Verdict::NoEffect,
+    };
+    checks.checks[2] = ActuatorCheck {
+        actuator: Actuator::Motor,
+        id: 3,
+        verdict: Verdict::NoResponse,
+    };
+    visitor.visit(kind, &checks);
+}
+
+struct SelfTest<B, F> {
+    report: F,
+    failures: u16,
+    _buffer: PhantomData<B>,
+}
+
+impl<B, F> SampleVisitor for SelfTest<B, F>
+where
+    B: ArrayLength<u8>,
+    F: FnMut(DiagnosticFrame),
+{
+    fn visit<T: Jsonizable + PartialEq>(&mut self, kind: MessageKind, sample: &T) {
+        if let Err(stage) = round_trip::<T, B>(kind, sample) {
+            self.failures += 1;
+            (self.report)(DiagnosticReporter::new(kind.id()).report(
+                ErrorCode::SelfTestFailed,
+                Severity::Error,
+                Some(stage as i32),
+            ));
+        }
+    }
+}
+
+/// Teste l'aller-retour de chaque type de trame et appelle `report` pour chaque échec.
+///
+/// `B` est la taille des buffers utilisés : elle doit pouvoir contenir la plus grande trame
+/// (un [`ServoGroup`] complet). Renvoie le nombre de types de trame en échec.
+pub fn self_test<B, F>(report: F) -> u16
+where
+    B: ArrayLength<u8>,
+    F: FnMut(DiagnosticFrame),
+{
+    let mut test = SelfTest {
+        report,
+        failures: 0,
+        _buffer: PhantomData::<B>,
+    };
+    visit_samples(&mut test);
+    test.failures
+}
+
+#[cfg(test)]
+mod test {
+    use super::{round_trip, self_test, Stage};
+    use crate::transmission::color::Color;
+    use crate::transmission::MessageKind;
+    use heapless::consts::{U4, U4096};
+    use std::vec::Vec;
+
+    #[test]
+    fn self_test_passes() {
+        let mut reports = Vec::new();
+        let failures = self_test::<U4096, _>(|frame| reports.push(frame));
+        assert_eq!(failures, 0, "{:?}", reports);
+        assert!(reports.is_empty());
+    }
+
+    #[test]
+    fn self_test_reports_failures() {
+        assert_eq!(
+            round_trip::<_, U4>(MessageKind::Color, &Color::Green),
+            Err(Stage::Send)
+        );
+
+        let mut reports = Vec::new();
+        let failures = self_test::<U4, _>(|frame| reports.push(frame));
+        assert!(failures > 0);
+        assert_eq!(usize::from(failures), reports.len());
+        let servo = reports
+            .iter()
+            .find(|frame| frame.module == MessageKind::Servo.id())
+            .unwrap();
+        assert_eq!(servo.context, Some(Stage::Send as i32));
+    }
+}
@@ -0,0 +1,146 @@
+//! Numérotation des commandes envoyées par l'informatique.
+//!
+//! Les trames UDP peuvent arriver dans le désordre : une commande ancienne reçue après une plus
+//! récente ne doit pas ramener un actionneur dans un état périmé. L'informatique incrémente le
+//! champ `counter` de chaque nouvelle commande d'un module et la carte ne garde que les commandes
+//! plus récentes que la dernière exécutée :
+//!
+//! ```ignore
+//! let group = ServoGroup::from_json_slice(&buffer)?;
+//! if servo_seq.accept_frame(&group) {
+//!     servos.merge(&group)?;
+//! }
+//! ```
+//!
+//! Les numéros sont comparés modulo 2^16, comme les numéros de séquence TCP : une commande est
+//! plus récente si elle est au plus 32767 numéros après la dernière. Le numéro 0 veut dire que
+//! l'émetteur ne numérote pas ses commandes, elles sont alors toujours acceptées.
+
+use crate::transmission::io::{Pneumatic, IO};
+use crate::transmission::navigation::NavigationFrame;
+use crate::transmission::ServoGroup;
+
+/// Renvoie vrai si la commande `counter` est plus récente que la commande `last`
+pub fn is_newer(counter: u16, last: u16) -> bool {
+    let delta = counter.wrapping_sub(last);
+    delta != 0 && delta < 0x8000
+}
+
+/// Une trame de commande numérotée
+pub trait Sequenced {
+    /// Le numéro de la commande, 0 si elle n'est pas numérotée
+    fn counter(&self) -> u16;
+}
+
+impl Sequenced for NavigationFrame {
+    fn counter(&self) -> u16 {
+        self.counter
+    }
+}
+
+impl Sequenced for ServoGroup {
+    fn counter(&self) -> u16 {
+        self.counter
+    }
+}
+
+impl Sequenced for IO {
+    fn counter(&self) -> u16 {
+        self.counter
+    }
+}
+
+impl Sequenced for Pneumatic {
+    fn counter(&self) -> u16 {
+        self.counter
+    }
+}
+
+/// Le numéro de la dernière commande exécutée par un module
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct CommandSeq {
+    last: Option<u16>,
+}
+
+impl CommandSeq {
+    /// Crée un suivi qui accepte la première commande reçue, quel que soit son numéro
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Renvoie vrai si la commande `counter` doit être exécutée, sans la retenir
+    pub fn is_newer(&self, counter: u16) -> bool {
+        match self.last {
+            _ if counter == 0 => true,
+            Some(last) => is_newer(counter, last),
+            None => true,
+        }
+    }
+
+    /// Renvoie vrai si la commande `counter` doit être exécutée, et la retient comme dernière
+    /// commande si c'est le cas
+    pub fn accept(&mut self, counter: u16) -> bool {
+        if !self.is_newer(counter) {
+            return false;
+        }
+        if counter != 0 {
+            self.last = Some(counter);
+        }
+        true
+    }
+
+    /// Comme [`CommandSeq::accept`], avec le numéro de la trame `frame`
+    pub fn accept_frame<T: Sequenced>(&mut self, frame: &T) -> bool {
+        self.accept(frame.counter())
+    }
+
+    /// Renvoie le numéro de la dernière commande exécutée
+    pub fn last(&self) -> Option<u16> {
+        self.last
+    }
+
+    /// Oublie la dernière commande, par exemple quand l'informatique a redémarré et recompte
+    /// depuis 1
+    pub fn reset(&mut self) {
+        self.last = None;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{is_newer, CommandSeq};
+    use crate::transmission::ServoGroup;
+
+    #[test]
+    fn newer_wraps() {
+        assert!(is_newer(2, 1));
+        assert!(!is_newer(1, 1));
+        assert!(!is_newer(1, 2));
+        assert!(is_newer(3, u16::max_value() - 2));
+        assert!(!is_newer(u16::max_value(), 3));
+    }
+
+    #[test]
+    fn command_seq_rejects_stale() {
+        let mut seq = CommandSeq::new();
+        assert!(seq.accept(40));
+        assert!(seq.accept(42));
+        // La commande 41 arrive après la 42
+        assert!(!seq.accept(41));
+        assert!(!seq.accept(42));
+        assert_eq!(seq.last(), Some(42));
+        assert!(seq.accept(0));
+        assert_eq!(seq.last(), Some(42));
+
+        let group = ServoGroup {
+            counter: 43,
+            ..Default::default()
+        };
+        assert!(seq.accept_frame(&group));
+        assert!(!seq.accept_frame(&group));
+
+        seq.reset();
+        assert!(seq.accept(1));
+    }
+}
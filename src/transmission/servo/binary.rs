@@ -0,0 +1,234 @@
+//! Format binaire de la trame des servo-moteurs, partagé avec l'informatique.
+//!
+//! C'est le même format que `servo_read_frame` et `servo_write_frame` dans
+//! `c_src/SharedWithRust.c`, mais lu et écrit en Rust sans passer par le C :
+//! ```txt
+//! <nb_servos: u8>
+//! <[<id: u8> <position: u16> <command: u16> <command_type, blocking data, color: u8>
+//!   <torque_limit: u16> <compliance_margin: u8>] ...>
+//! ```
+//! Les entiers sont en big endian. L'octet d'informations est de la forme `0b00TBMCCC` avec `T` le
+//! type de commande (0 en position, 1 en vitesse), `B` le blocage, `M` le mode de blocage et `CCC`
+//! la couleur.
+
+use super::{BlockingMode, Color, Control, Servo, ServoGroup};
+use crate::transmission::Message;
+
+/// Nombre maximal de servos dans une trame binaire
+pub const MAX_SERVOS: usize = 8;
+
+/// Taille d'un servo dans la trame binaire
+const SERVO_SIZE: usize = 9;
+
+/// Les erreurs de lecture et d'écriture de la trame binaire
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ServoFrameError {
+    /// La trame est vide
+    Empty,
+    /// La trame annonce plus de `MAX_SERVOS` servos
+    TooManyServos(u8),
+    /// La taille de la trame ne correspond pas au nombre de servos annoncé
+    SizeMismatch {
+        /// Taille attendue
+        expected: usize,
+        /// Taille reçue
+        found: usize,
+    },
+    /// Un servo a un ID nul
+    NullId,
+    /// Deux servos ont le même ID
+    DuplicateId(u8),
+    /// L'octet d'informations d'un servo a des bits réservés non nuls
+    InvalidInfos(u8),
+}
+
+/// Renvoie la taille de la trame binaire pour `nb_servos` servos
+pub fn frame_size(nb_servos: usize) -> usize {
+    1 + nb_servos * SERVO_SIZE
+}
+
+fn color_from_u8(color: u8) -> Color {
+    match color & 0b111 {
+        0x00 => Color::Black,
+        0x01 => Color::Red,
+        0x02 => Color::Green,
+        0x03 => Color::Yellow,
+        0x04 => Color::Blue,
+        0x05 => Color::Magenta,
+        0x06 => Color::Cyan,
+        _ => Color::White,
+    }
+}
+
+impl ServoGroup {
+    /// Lit un groupe de servos depuis une trame binaire
+    pub fn new(message: Message) -> Result<Self, ServoFrameError> {
+        let (&nb_servos, mut data) = message.split_first().ok_or(ServoFrameError::Empty)?;
+        if nb_servos as usize > MAX_SERVOS {
+            return Err(ServoFrameError::TooManyServos(nb_servos));
+        }
+        let nb_servos = nb_servos as usize;
+        if message.len() != frame_size(nb_servos) {
+            return Err(ServoFrameError::SizeMismatch {
+                expected: frame_size(nb_servos),
+                found: message.len(),
+            });
+        }
+
+        let mut group = ServoGroup::default();
+        for index in 0..nb_servos {
+            let (servo, rest) = data.split_at(SERVO_SIZE);
+            data = rest;
+            let id = servo[0];
+            // L'ID doit être non nul et unique
+            if id == 0 {
+                return Err(ServoFrameError::NullId);
+            }
+            if group.servos.iter().any(|s| s.id == id) {
+                return Err(ServoFrameError::DuplicateId(id));
+            }
+            let infos = servo[5];
+            if infos & 0b1100_0000 != 0 {
+                return Err(ServoFrameError::InvalidInfos(infos));
+            }
+            group.servos[index] = Servo {
+                id,
+                known_position: u16::from_be_bytes([servo[1], servo[2]]),
+                data: u16::from_be_bytes([servo[3], servo[4]]),
+                control: if infos & 0b0010_0000 != 0 {
+                    Control::Speed
+                } else {
+                    Control::Position
+                },
+                blocked: infos & 0b0001_0000 != 0,
+                mode: if infos & 0b0000_1000 != 0 {
+                    BlockingMode::HoldOnBlock
+                } else {
+                    BlockingMode::Unblocking
+                },
+                color: color_from_u8(infos),
+                torque_limit: u16::from_be_bytes([servo[6], servo[7]]),
+                compliance_margin: servo[8],
+                ..Default::default()
+            };
+        }
+        Ok(group)
+    }
+
+    /// Écrit le groupe de servos dans une trame binaire. Échoue s'il contient plus de
+    /// `MAX_SERVOS` servos.
+    pub fn into_bytes(self) -> Result<Message, ServoFrameError> {
+        let mut message = Message::new();
+        let servos = self.servos.iter().filter(|servo| servo.id != 0);
+        let nb_servos = servos.clone().count();
+        if nb_servos > MAX_SERVOS {
+            return Err(ServoFrameError::TooManyServos(nb_servos as u8));
+        }
+        message.push(nb_servos as u8);
+        for servo in servos {
+            let infos = (((servo.control == Control::Speed) as u8) << 5)
+                | ((servo.blocked as u8) << 4)
+                | ((servo.mode as u8) << 3)
+                | servo.color as u8;
+            message.push(servo.id);
+            message.extend(servo.known_position.to_be_bytes().iter().cloned());
+            message.extend(servo.data.to_be_bytes().iter().cloned());
+            message.push(infos);
+            message.extend(servo.torque_limit.to_be_bytes().iter().cloned());
+            message.push(servo.compliance_margin);
+        }
+        Ok(message)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{frame_size, ServoFrameError};
+    use crate::transmission::servo::{BlockingMode, Color, Control, Servo, ServoGroup};
+    use crate::transmission::Message;
+
+    fn message(bytes: &[u8]) -> Message {
+        bytes.iter().cloned().collect()
+    }
+
+    #[test]
+    fn read_servo_frame() {
+        #[rustfmt::skip]
+        let bytes = [
+            2,
+            4, 0x01, 0xFF, 0x02, 0x00, 0b0011_1100, 0x00, 0xFA, 3,
+            7, 0x00, 0x10, 0x00, 0x20, 0b0000_0010, 0x00, 0x00, 0,
+        ];
+        let group = ServoGroup::new(message(&bytes)).unwrap();
+        let first = group.servos[0];
+        assert_eq!(first.id, 4);
+        assert_eq!(first.known_position, 511);
+        assert_eq!(first.data, 512);
+        assert_eq!(first.control, Control::Speed);
+        assert!(first.blocked);
+        assert_eq!(first.mode, BlockingMode::HoldOnBlock);
+        assert_eq!(first.color, Color::Blue);
+        assert_eq!(first.torque_limit, 250);
+        assert_eq!(first.compliance_margin, 3);
+        assert_eq!(group.servos[1].control, Control::Position);
+        assert_eq!(group.servos[1].color, Color::Green);
+        assert_eq!(group.servos[2].id, 0);
+
+        assert_eq!(group.into_bytes().unwrap().as_slice(), &bytes[..]);
+    }
+
+    #[test]
+    fn reject_invalid_servo_frames() {
+        assert_eq!(ServoGroup::new(Message::new()), Err(ServoFrameError::Empty));
+        assert_eq!(
+            ServoGroup::new(message(&[1, 4, 0, 0])),
+            Err(ServoFrameError::SizeMismatch {
+                expected: 10,
+                found: 4
+            })
+        );
+        let mut too_many = [0; 1 + 9 * 9];
+        too_many[0] = 9;
+        assert_eq!(
+            ServoGroup::new(message(&too_many)),
+            Err(ServoFrameError::TooManyServos(9))
+        );
+        too_many[0] = 255;
+        assert_eq!(
+            ServoGroup::new(message(&too_many)),
+            Err(ServoFrameError::TooManyServos(255))
+        );
+
+        let mut bytes = [0; 19];
+        bytes[0] = 2;
+        assert_eq!(
+            ServoGroup::new(message(&bytes)),
+            Err(ServoFrameError::NullId)
+        );
+        bytes[1] = 5;
+        bytes[10] = 5;
+        assert_eq!(
+            ServoGroup::new(message(&bytes)),
+            Err(ServoFrameError::DuplicateId(5))
+        );
+        bytes[10] = 6;
+        bytes[15] = 0b1000_0001;
+        assert_eq!(
+            ServoGroup::new(message(&bytes)),
+            Err(ServoFrameError::InvalidInfos(0b1000_0001))
+        );
+        bytes[15] = 0b0000_0001;
+        assert!(ServoGroup::new(message(&bytes)).is_ok());
+        assert_eq!(frame_size(2), bytes.len());
+
+        let group = ServoGroup {
+            servos: [Servo {
+                id: 1,
+                ..Default::default()
+            }; 9],
+            ..Default::default()
+        };
+        assert_eq!(group.into_bytes(), Err(ServoFrameError::TooManyServos(9)));
+    }
+}
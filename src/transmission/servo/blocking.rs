@@ -0,0 +1,304 @@
+//! Suivi du blocage des servo-moteurs côté électronique.
+//!
+//! Le bit de blocage lu dans un servo change à chaque lecture quand le servo force à la limite
+//! de son couple. Le [`ServoBlockMonitor`] filtre ce bit : un servo n'est déclaré bloqué que si
+//! le bit reste levé pendant `debounce` ms, et débloqué que s'il reste baissé aussi longtemps.
+//! Un servo en [`BlockingMode::Unblocking`] relâche son couple quand il est bloqué : il est
+//! considéré débloqué au bout de `release` ms, même si le bit ne s'est pas encore baissé.
+//!
+//! Les servos sont identifiés par leur bus et leur identifiant : deux servos de même identifiant
+//! sur des bus différents sont suivis séparément. Chaque changement d'état est signalé par une
+//! [`DiagnosticFrame`] dont le contexte vaut `bus << 8 | id` :
+//!
+//! ```ignore
+//! let mut monitor = ServoBlockMonitor::new(50, 500);
+//! // A chaque lecture des servos
+//! monitor.observe_group(&mut group, &clock, |frame| send(frame));
+//! ```
+
+use super::{BlockingMode, Servo, ServoGroup};
+use crate::time::{Clock, Instant};
+use crate::transmission::diagnostic::{DiagnosticFrame, DiagnosticReporter, ErrorCode, Severity};
+use crate::transmission::id::ID_SERVO;
+
+/// Nombre de servos suivis par le `ServoBlockMonitor`
+const MAX_MONITORED: usize = 9;
+
+#[derive(Debug, Default, Copy, Clone)]
+struct Slot {
+    bus: u8,
+    id: u8,
+    blocked: bool,
+    /// Instant depuis lequel le bit lu diffère de l'état `blocked`
+    changing_since: Option<Instant>,
+    /// Instant auquel le servo a été déclaré bloqué
+    blocked_at: Instant,
+    /// Faux après un déblocage automatique, jusqu'à ce que le bit lu se baisse
+    armed: bool,
+}
+
+impl Slot {
+    fn new(bus: u8, id: u8) -> Self {
+        Slot {
+            bus,
+            id,
+            armed: true,
+            ..Default::default()
+        }
+    }
+}
+
+/// Transition du blocage d'un servo
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Transition {
+    Blocked,
+    Unblocked,
+}
+
+/// Agrège dans le temps le blocage des servo-moteurs et signale ses changements.
+#[derive(Debug)]
+pub struct ServoBlockMonitor {
+    debounce: u32,
+    release: u32,
+    reporter: DiagnosticReporter,
+    slots: [Slot; MAX_MONITORED],
+}
+
+impl ServoBlockMonitor {
+    /// Crée un suivi qui attend `debounce` ms avant de changer l'état d'un servo, et qui
+    /// débloque automatiquement un servo en [`BlockingMode::Unblocking`] au bout de `release` ms
+    pub fn new(debounce: u32, release: u32) -> Self {
+        ServoBlockMonitor {
+            debounce,
+            release,
+            reporter: DiagnosticReporter::new(ID_SERVO),
+            slots: Default::default(),
+        }
+    }
+
+    fn find(&self, bus: u8, id: u8) -> Option<usize> {
+        self.slots
+            .iter()
+            .position(|slot| slot.id == id && slot.bus == bus)
+    }
+
+    /// Prend en compte le bit de blocage lu dans `servo`, branché sur le bus `bus`, et le
+    /// remplace par l'état filtré. Renvoie la trame à envoyer si l'état du servo change. Un servo
+    /// vu pour la première fois part non bloqué ; au delà de 9 servos, le bit lu est laissé tel
+    /// quel.
+    pub fn observe<C: Clock>(
+        &mut self,
+        bus: u8,
+        servo: &mut Servo,
+        clock: &C,
+    ) -> Option<DiagnosticFrame> {
+        if servo.id == 0 {
+            return None;
+        }
+        let index = match self.find(bus, servo.id) {
+            Some(index) => index,
+            None => {
+                let free = self.slots.iter().position(|slot| slot.id == 0)?;
+                self.slots[free] = Slot::new(bus, servo.id);
+                free
+            }
+        };
+        let transition = self.step(index, servo.blocked, servo.mode, clock.now());
+        servo.blocked = self.slots[index].blocked;
+        let context = i32::from(bus) << 8 | i32::from(servo.id);
+        let (code, context) = (ErrorCode::Blocked, Some(context));
+        Some(match transition? {
+            Transition::Blocked => self.reporter.report(code, Severity::Warning, context),
+            Transition::Unblocked => self.reporter.notice(code, Severity::Info, context),
+        })
+    }
+
+    /// Applique [`ServoBlockMonitor::observe`] à chaque servo de `group`, sur le bus
+    /// `group.bus`, et appelle `report` avec chaque trame à envoyer
+    pub fn observe_group<C: Clock, F: FnMut(DiagnosticFrame)>(
+        &mut self,
+        group: &mut ServoGroup,
+        clock: &C,
+        mut report: F,
+    ) {
+        let bus = group.bus;
+        for servo in group.servos.iter_mut() {
+            if let Some(frame) = self.observe(bus, servo, clock) {
+                report(frame);
+            }
+        }
+    }
+
+    fn step(
+        &mut self,
+        index: usize,
+        raw: bool,
+        mode: BlockingMode,
+        now: Instant,
+    ) -> Option<Transition> {
+        let debounce = self.debounce;
+        let release = self.release;
+        let slot = &mut self.slots[index];
+        if !raw {
+            slot.armed = true;
+        }
+        if slot.blocked
+            && mode == BlockingMode::Unblocking
+            && now.duration_since(slot.blocked_at) >= release
+        {
+            slot.blocked = false;
+            slot.changing_since = None;
+            slot.armed = !raw;
+            return Some(Transition::Unblocked);
+        }
+        if raw == slot.blocked || (raw && !slot.armed) {
+            slot.changing_since = None;
+            return None;
+        }
+        let since = *slot.changing_since.get_or_insert(now);
+        if now.duration_since(since) < debounce {
+            return None;
+        }
+        slot.blocked = raw;
+        slot.changing_since = None;
+        if raw {
+            slot.blocked_at = now;
+            Some(Transition::Blocked)
+        } else {
+            Some(Transition::Unblocked)
+        }
+    }
+
+    /// Renvoie vrai si le servo `id` du bus `bus` est considéré bloqué
+    pub fn is_blocked(&self, bus: u8, id: u8) -> bool {
+        self.slots
+            .iter()
+            .any(|slot| slot.id == id && slot.bus == bus && id != 0 && slot.blocked)
+    }
+
+    /// Oublie le servo `id` du bus `bus`, qui repartira non bloqué à sa prochaine lecture
+    pub fn forget(&mut self, bus: u8, id: u8) {
+        if let Some(index) = self.find(bus, id) {
+            self.slots[index] = Slot::default();
+        }
+    }
+
+    /// Renvoie le compteur des blocages signalés
+    pub fn reporter(&self) -> &DiagnosticReporter {
+        &self.reporter
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::ServoBlockMonitor;
+    use crate::time::Instant;
+    use crate::transmission::diagnostic::{ErrorCode, Severity};
+    use crate::transmission::servo::{BlockingMode, Servo, ServoGroup};
+
+    fn read(
+        monitor: &mut ServoBlockMonitor,
+        mode: BlockingMode,
+        blocked: bool,
+        millis: u32,
+    ) -> (bool, Option<Severity>) {
+        let mut servo = Servo {
+            id: 4,
+            blocked,
+            mode,
+            ..Default::default()
+        };
+        let clock = || Instant::from_millis(millis);
+        let frame = monitor.observe(0, &mut servo, &clock);
+        if let Some(frame) = frame {
+            assert_eq!(frame.code, ErrorCode::Blocked);
+            assert_eq!(frame.context, Some(4));
+        }
+        (servo.blocked, frame.map(|frame| frame.severity))
+    }
+
+    #[test]
+    fn debounced_hold_on_block() {
+        let mode = BlockingMode::HoldOnBlock;
+        let mut monitor = ServoBlockMonitor::new(50, 200);
+        assert_eq!(read(&mut monitor, mode, true, 0), (false, None));
+        assert_eq!(read(&mut monitor, mode, false, 20), (false, None));
+        assert_eq!(read(&mut monitor, mode, true, 30), (false, None));
+        assert_eq!(read(&mut monitor, mode, true, 79), (false, None));
+        assert_eq!(
+            read(&mut monitor, mode, true, 80),
+            (true, Some(Severity::Warning))
+        );
+        assert!(monitor.is_blocked(0, 4));
+        // Pas de déblocage automatique en maintien du couple
+        assert_eq!(read(&mut monitor, mode, true, 1000), (true, None));
+        assert_eq!(read(&mut monitor, mode, false, 1010), (true, None));
+        assert_eq!(
+            read(&mut monitor, mode, false, 1060),
+            (false, Some(Severity::Info))
+        );
+        assert_eq!(monitor.reporter().occurrences(ErrorCode::Blocked), 1);
+    }
+
+    #[test]
+    fn automatic_unblocking() {
+        let mode = BlockingMode::Unblocking;
+        let mut monitor = ServoBlockMonitor::new(50, 200);
+        read(&mut monitor, mode, true, 0);
+        assert_eq!(
+            read(&mut monitor, mode, true, 50),
+            (true, Some(Severity::Warning))
+        );
+        assert_eq!(
+            read(&mut monitor, mode, true, 250),
+            (false, Some(Severity::Info))
+        );
+        // Le bit doit se baisser avant un nouveau blocage
+        assert_eq!(read(&mut monitor, mode, true, 400), (false, None));
+        read(&mut monitor, mode, false, 410);
+        read(&mut monitor, mode, true, 420);
+        assert_eq!(
+            read(&mut monitor, mode, true, 470),
+            (true, Some(Severity::Warning))
+        );
+        assert_eq!(monitor.reporter().occurrences(ErrorCode::Blocked), 2);
+        monitor.forget(0, 4);
+        assert!(!monitor.is_blocked(0, 4));
+    }
+
+    #[test]
+    fn same_id_on_two_buses() {
+        let mut monitor = ServoBlockMonitor::new(50, 200);
+        let servo = Servo {
+            id: 4,
+            blocked: true,
+            mode: BlockingMode::HoldOnBlock,
+            ..Default::default()
+        };
+        let mut group = ServoGroup {
+            bus: 1,
+            ..Default::default()
+        };
+        group.servos[0] = servo;
+        let mut frames = std::vec::Vec::new();
+        for &millis in [0, 60].iter() {
+            let clock = || Instant::from_millis(millis);
+            let mut blocked = group.clone();
+            monitor.observe_group(&mut blocked, &clock, |frame| frames.push(frame));
+            // Le servo 4 du bus 0 n'est jamais bloqué
+            let mut free = Servo {
+                blocked: false,
+                ..servo
+            };
+            assert_eq!(monitor.observe(0, &mut free, &clock), None);
+            assert!(!free.blocked);
+        }
+        assert!(monitor.is_blocked(1, 4));
+        assert!(!monitor.is_blocked(0, 4));
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].context, Some(1 << 8 | 4));
+
+        monitor.forget(1, 4);
+        assert!(!monitor.is_blocked(1, 4));
+    }
+}
@@ -0,0 +1,129 @@
+//! Aiguillage des trames de servos entre les bus d'une même carte.
+//!
+//! Une carte peut piloter plusieurs bus de servos (un bus Dynamixel par pince par exemple), tous
+//! commandés par le même flux de [`ServoGroup`]. Le champ `bus` de chaque trame indique le bus
+//! visé : les identifiants des servos ne sont uniques qu'au sein d'un bus. [`ServoBuses`] garde
+//! l'état de chaque bus et lui applique les trames qui le concernent :
+//!
+//! ```ignore
+//! if let Some(frame) = channel.try_recv() {
+//!     let bus = buses.route(&frame)?;
+//!     schedulers[usize::from(bus)].update(&frame);
+//! }
+//! for group in buses.iter() {
+//!     channel.send(group)?;
+//! }
+//! ```
+
+use super::{Servo, ServoGroup};
+
+/// Nombre maximal de bus de servos sur une carte
+pub const MAX_SERVO_BUSES: usize = 4;
+
+/// Les erreurs qui peuvent survenir lors de l'aiguillage d'une trame
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ServoBusError {
+    /// La carte n'a pas de bus avec ce numéro
+    UnknownBus(u8),
+    /// Le bus suit déjà le nombre maximal de servos, celui-ci n'a pas pu être ajouté
+    Full(Servo),
+}
+
+/// L'état des servos de chaque bus d'une carte
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ServoBuses {
+    groups: [ServoGroup; MAX_SERVO_BUSES],
+    count: u8,
+}
+
+impl ServoBuses {
+    /// Crée une carte avec `count` bus sans servos, numérotés à partir de 0. Le nombre de bus
+    /// est limité à [`MAX_SERVO_BUSES`].
+    pub fn new(count: u8) -> Self {
+        let mut groups: [ServoGroup; MAX_SERVO_BUSES] = Default::default();
+        for (bus, group) in groups.iter_mut().enumerate() {
+            group.bus = bus as u8;
+        }
+        ServoBuses {
+            groups,
+            count: count.min(MAX_SERVO_BUSES as u8),
+        }
+    }
+
+    /// Applique `frame` à l'état du bus qu'elle vise et renvoie le numéro de ce bus
+    pub fn route(&mut self, frame: &ServoGroup) -> Result<u8, ServoBusError> {
+        let group = self
+            .get_mut(frame.bus)
+            .ok_or(ServoBusError::UnknownBus(frame.bus))?;
+        group.merge(frame).map_err(ServoBusError::Full)?;
+        Ok(frame.bus)
+    }
+
+    /// Renvoie l'état du bus `bus`
+    pub fn get(&self, bus: u8) -> Option<&ServoGroup> {
+        self.groups[..usize::from(self.count)].get(usize::from(bus))
+    }
+
+    /// Renvoie l'état du bus `bus`, par exemple pour y recopier les mesures des servos
+    pub fn get_mut(&mut self, bus: u8) -> Option<&mut ServoGroup> {
+        self.groups[..usize::from(self.count)].get_mut(usize::from(bus))
+    }
+
+    /// Renvoie l'état de chaque bus, prêt à être renvoyé à l'informatique
+    pub fn iter(&self) -> impl Iterator<Item = &ServoGroup> {
+        self.groups.iter().take(usize::from(self.count))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ServoBusError, ServoBuses};
+    use crate::transmission::servo::{Servo, ServoGroup};
+    use crate::transmission::Jsonizable;
+    use heapless::consts::U4096;
+    use heapless::String;
+    use std::vec::Vec;
+
+    fn servo(id: u8, data: u16) -> Servo {
+        Servo {
+            id,
+            data,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn same_id_on_two_buses() {
+        let mut buses = ServoBuses::new(2);
+        let left = ServoGroup::partial(&[servo(1, 100)]).unwrap();
+        let right = ServoGroup {
+            bus: 1,
+            ..ServoGroup::partial(&[servo(1, 900)]).unwrap()
+        };
+        let json: String<U4096> = right.to_string().unwrap();
+        let right = ServoGroup::from_json_slice(json.as_bytes()).unwrap();
+
+        assert_eq!(buses.route(&left), Ok(0));
+        assert_eq!(buses.route(&right), Ok(1));
+        assert_eq!(buses.get(0).unwrap().servos[0], servo(1, 100));
+        assert_eq!(buses.get(1).unwrap().servos[0], servo(1, 900));
+        let tags: Vec<u8> = buses.iter().map(|group| group.bus).collect();
+        assert_eq!(tags, vec![0, 1]);
+
+        let unknown = ServoGroup {
+            bus: 2,
+            ..left.clone()
+        };
+        assert_eq!(buses.route(&unknown), Err(ServoBusError::UnknownBus(2)));
+        assert!(buses.get(2).is_none());
+
+        // Une trame sans champ `bus` concerne le premier bus
+        let data = ServoGroup::default().to_string::<U4096>().unwrap();
+        let legacy = data.replace(",\"bus\":0", "");
+        assert_ne!(legacy, data.as_str());
+        let legacy = ServoGroup::from_json_slice(legacy.as_bytes()).unwrap();
+        assert_eq!(legacy.bus, 0);
+    }
+}
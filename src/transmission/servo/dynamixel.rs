@@ -0,0 +1,603 @@
+//! Pilote des servo-moteurs Dynamixel (protocole 2.0, série X).
+//!
+//! Le pilote utilise un UART half-duplex fourni par `embedded-hal`. Les octets émis qui sont
+//! renvoyés en écho par l'UART sont ignorés : seuls les paquets de statut sont pris en compte.
+//!
+//! [`Dynamixel::apply`] traduit la commande d'un [`Servo`] en écritures de registres et
+//! [`Dynamixel::update_feedback`] remplit ses champs de retour (position, charge, température...).
+
+use super::{Control, Rotation, Servo, ServoCalibrations, ServoGroup};
+use crate::units::{Celsius, MilliVolt};
+use embedded_hal::serial::{Read, Write};
+use nb::block;
+
+/// Les adresses des registres utilisés (table de contrôle de la série X)
+pub mod register {
+    /// Activation du couple (1 octet)
+    pub const TORQUE_ENABLE: u16 = 64;
+    /// LED (1 octet)
+    pub const LED: u16 = 65;
+    /// Erreurs matérielles (1 octet)
+    pub const HARDWARE_ERROR_STATUS: u16 = 70;
+    /// PWM maximal (2 octets)
+    pub const GOAL_PWM: u16 = 100;
+    /// Vitesse commandée (4 octets)
+    pub const GOAL_VELOCITY: u16 = 104;
+    /// Vitesse maximale du profil de position (4 octets)
+    pub const PROFILE_VELOCITY: u16 = 112;
+    /// Position commandée (4 octets)
+    pub const GOAL_POSITION: u16 = 116;
+    /// Charge actuelle, premier registre de retour (2 octets)
+    pub const PRESENT_LOAD: u16 = 126;
+    /// Position actuelle (4 octets)
+    pub const PRESENT_POSITION: u16 = 132;
+    /// Tension d'alimentation en dixièmes de volt (2 octets)
+    pub const PRESENT_INPUT_VOLTAGE: u16 = 144;
+    /// Température en degrés Celsius (1 octet)
+    pub const PRESENT_TEMPERATURE: u16 = 146;
+}
+
+/// Valeur maximale du registre `GOAL_PWM`
+const PWM_LIMIT: u32 = 885;
+
+/// Taille maximale d'un paquet envoyé ou reçu
+const MAX_PACKET_SIZE: usize = 64;
+
+const HEADER: [u8; 4] = [0xFF, 0xFF, 0xFD, 0x00];
+
+/// L'identifiant auquel répondent tous les servos du bus, qui ne renvoient alors pas de statut
+pub const BROADCAST_ID: u8 = 0xFE;
+
+/// Les instructions du protocole
+mod instruction {
+    pub const PING: u8 = 0x01;
+    pub const READ: u8 = 0x02;
+    pub const WRITE: u8 = 0x03;
+    pub const REG_WRITE: u8 = 0x04;
+    pub const ACTION: u8 = 0x05;
+    pub const STATUS: u8 = 0x55;
+}
+
+/// Les erreurs du pilote Dynamixel
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DynamixelError<E> {
+    /// Erreur de l'UART
+    Serial(E),
+    /// Le servo n'a pas répondu
+    Timeout,
+    /// Le CRC du paquet reçu est faux
+    Checksum,
+    /// Le paquet reçu est mal formé ou trop long
+    InvalidPacket,
+    /// Le servo a signalé une erreur de traitement de l'instruction
+    Status(u8),
+}
+
+/// Calcule le CRC-16 d'un paquet Dynamixel (polynôme 0x8005)
+pub(crate) fn crc16(data: &[u8]) -> u16 {
+    crc16_update(0, data)
+}
+
+/// Continue le calcul du CRC-16 `crc` avec les octets de `data`
+pub(crate) fn crc16_update(mut crc: u16, data: &[u8]) -> u16 {
+    for byte in data {
+        crc ^= u16::from(*byte) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x8005
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+/// Pilote d'un bus de servo-moteurs Dynamixel
+#[derive(Debug)]
+pub struct Dynamixel<S> {
+    serial: S,
+    max_polls: u32,
+}
+
+impl<S, E> Dynamixel<S>
+where
+    S: Read<u8, Error = E> + Write<u8, Error = E>,
+{
+    /// Crée le pilote. `max_polls` est le nombre de lectures sans donnée sur l'UART au bout
+    /// duquel on considère que le servo ne répondra pas.
+    pub fn new(serial: S, max_polls: u32) -> Self {
+        Dynamixel { serial, max_polls }
+    }
+
+    /// Libère l'UART
+    pub fn release(self) -> S {
+        self.serial
+    }
+
+    /// Vérifie que le servo `id` répond et renvoie son numéro de modèle
+    pub fn ping(&mut self, id: u8) -> Result<u16, DynamixelError<E>> {
+        let mut info = [0; 3];
+        self.send(id, instruction::PING, &[])?;
+        self.receive(id, &mut info)?;
+        Ok(u16::from_le_bytes([info[0], info[1]]))
+    }
+
+    /// Écrit `data` dans les registres du servo `id` à partir de `address`
+    pub fn write(&mut self, id: u8, address: u16, data: &[u8]) -> Result<(), DynamixelError<E>> {
+        self.write_with(instruction::WRITE, id, address, data)
+    }
+
+    /// Charge `data` dans le servo `id` sans l'écrire dans ses registres : l'écriture n'a lieu
+    /// qu'au prochain [`Dynamixel::action`]
+    pub fn reg_write(
+        &mut self,
+        id: u8,
+        address: u16,
+        data: &[u8],
+    ) -> Result<(), DynamixelError<E>> {
+        self.write_with(instruction::REG_WRITE, id, address, data)
+    }
+
+    /// Exécute en même temps les écritures chargées dans tous les servos du bus
+    pub fn action(&mut self) -> Result<(), DynamixelError<E>> {
+        self.send(BROADCAST_ID, instruction::ACTION, &[])
+    }
+
+    fn write_with(
+        &mut self,
+        instruction: u8,
+        id: u8,
+        address: u16,
+        data: &[u8],
+    ) -> Result<(), DynamixelError<E>> {
+        let mut params = [0; MAX_PACKET_SIZE / 2];
+        if data.len() + 2 > params.len() {
+            return Err(DynamixelError::InvalidPacket);
+        }
+        params[..2].copy_from_slice(&address.to_le_bytes());
+        params[2..2 + data.len()].copy_from_slice(data);
+        self.send(id, instruction, &params[..2 + data.len()])?;
+        self.receive(id, &mut []).map(|_| ())
+    }
+
+    /// Lit `buffer.len()` octets des registres du servo `id` à partir de `address`
+    pub fn read(
+        &mut self,
+        id: u8,
+        address: u16,
+        buffer: &mut [u8],
+    ) -> Result<(), DynamixelError<E>> {
+        let mut params = [0; 4];
+        params[..2].copy_from_slice(&address.to_le_bytes());
+        params[2..].copy_from_slice(&(buffer.len() as u16).to_le_bytes());
+        self.send(id, instruction::READ, &params)?;
+        let size = self.receive(id, buffer)?;
+        if size == buffer.len() {
+            Ok(())
+        } else {
+            Err(DynamixelError::InvalidPacket)
+        }
+    }
+
+    /// Envoie la commande de `servo` au servo correspondant
+    pub fn apply(&mut self, servo: &Servo) -> Result<(), DynamixelError<E>> {
+        self.apply_with(servo, instruction::WRITE)
+    }
+
+    /// Charge la commande de `servo` dans le servo correspondant, qui ne bouge qu'au prochain
+    /// [`Dynamixel::action`]. Seule la consigne de position ou de vitesse est différée.
+    pub fn prepare(&mut self, servo: &Servo) -> Result<(), DynamixelError<E>> {
+        self.apply_with(servo, instruction::REG_WRITE)
+    }
+
+    fn apply_with(&mut self, servo: &Servo, goal: u8) -> Result<(), DynamixelError<E>> {
+        let pwm = if servo.torque_limit == 0 {
+            PWM_LIMIT
+        } else {
+            u32::from(servo.torque_limit.min(1000)) * PWM_LIMIT / 1000
+        };
+        self.write(servo.id, register::GOAL_PWM, &(pwm as u16).to_le_bytes())?;
+        self.write(
+            servo.id,
+            register::LED,
+            &[(servo.color != super::Color::Black) as u8],
+        )?;
+        match servo.control {
+            Control::Position => {
+                // 1 unité de vitesse = 0.229 tour/min, 1 tour = 4096 unités de position
+                let velocity = match u32::from(servo.max_speed) * 60_000 / (4096 * 229) {
+                    0 if servo.max_speed != 0 => 1,
+                    velocity => velocity,
+                };
+                self.write(
+                    servo.id,
+                    register::PROFILE_VELOCITY,
+                    &velocity.to_le_bytes(),
+                )?;
+                self.write_with(
+                    goal,
+                    servo.id,
+                    register::GOAL_POSITION,
+                    &u32::from(servo.data).to_le_bytes(),
+                )
+            }
+            Control::Speed => {
+                let velocity = match servo.rotation {
+                    Rotation::CounterClockwise => i32::from(servo.data),
+                    Rotation::Clockwise => -i32::from(servo.data),
+                };
+                self.write_with(
+                    goal,
+                    servo.id,
+                    register::GOAL_VELOCITY,
+                    &velocity.to_le_bytes(),
+                )
+            }
+        }
+    }
+
+    /// Active ou désactive le couple du servo `id`
+    pub fn set_torque(&mut self, id: u8, enabled: bool) -> Result<(), DynamixelError<E>> {
+        self.write(id, register::TORQUE_ENABLE, &[enabled as u8])
+    }
+
+    /// Lit l'état du servo et met à jour ses champs de retour
+    pub fn update_feedback(&mut self, servo: &mut Servo) -> Result<(), DynamixelError<E>> {
+        let mut feedback =
+            [0; (register::PRESENT_TEMPERATURE - register::PRESENT_LOAD + 1) as usize];
+        self.read(servo.id, register::PRESENT_LOAD, &mut feedback)?;
+        let field = |address: u16| (address - register::PRESENT_LOAD) as usize;
+
+        let load = field(register::PRESENT_LOAD);
+        servo.load = i16::from_le_bytes([feedback[load], feedback[load + 1]]);
+        let position = field(register::PRESENT_POSITION);
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(&feedback[position..position + 4]);
+        servo.known_position = i32::from_le_bytes(bytes)
+            .max(0)
+            .min(i32::from(u16::max_value())) as u16;
+        let voltage = field(register::PRESENT_INPUT_VOLTAGE);
+        // La tension est donnée en dixièmes de volt
+        let decivolts = u16::from_le_bytes([feedback[voltage], feedback[voltage + 1]]);
+        servo.voltage = MilliVolt(i32::from(decivolts) * 100);
+        servo.temperature = Celsius(i16::from(feedback[field(register::PRESENT_TEMPERATURE)]));
+
+        let mut error = [0];
+        self.read(servo.id, register::HARDWARE_ERROR_STATUS, &mut error)?;
+        servo.error = error[0];
+        Ok(())
+    }
+
+    /// Envoie la commande de chaque servo du groupe. Si le groupe est à préparer, les commandes
+    /// sont chargées sans être exécutées ; s'il est à déclencher, toutes les commandes chargées
+    /// démarrent ensuite en même temps.
+    pub fn apply_group(&mut self, group: &ServoGroup) -> Result<(), DynamixelError<E>> {
+        self.apply_commands(group, group.updated().cloned())
+    }
+
+    /// Met à jour les champs de retour de chaque servo du groupe
+    pub fn update_group(&mut self, group: &mut ServoGroup) -> Result<(), DynamixelError<E>> {
+        for servo in group.servos.iter_mut().filter(|servo| servo.id != 0) {
+            self.update_feedback(servo)?;
+        }
+        Ok(())
+    }
+
+    /// Envoie la commande de chaque servo du groupe, corrigée par sa calibration
+    pub fn apply_group_calibrated(
+        &mut self,
+        group: &ServoGroup,
+        calibrations: &ServoCalibrations,
+    ) -> Result<(), DynamixelError<E>> {
+        let commands = group.updated().map(|servo| calibrations.command(servo));
+        self.apply_commands(group, commands)
+    }
+
+    fn apply_commands<I: Iterator<Item = Servo>>(
+        &mut self,
+        group: &ServoGroup,
+        commands: I,
+    ) -> Result<(), DynamixelError<E>> {
+        for servo in commands {
+            if group.prepare {
+                self.prepare(&servo)?;
+            } else {
+                self.apply(&servo)?;
+            }
+        }
+        if group.trigger {
+            self.action()?;
+        }
+        Ok(())
+    }
+
+    /// Met à jour les champs de retour de chaque servo du groupe, en positions logiques
+    pub fn update_group_calibrated(
+        &mut self,
+        group: &mut ServoGroup,
+        calibrations: &ServoCalibrations,
+    ) -> Result<(), DynamixelError<E>> {
+        for servo in group.servos.iter_mut().filter(|servo| servo.id != 0) {
+            self.update_feedback(servo)?;
+            calibrations.feedback(servo);
+        }
+        Ok(())
+    }
+
+    fn write_byte(&mut self, byte: u8) -> Result<(), DynamixelError<E>> {
+        block!(self.serial.write(byte)).map_err(DynamixelError::Serial)
+    }
+
+    fn read_byte(&mut self) -> Result<u8, DynamixelError<E>> {
+        for _ in 0..self.max_polls {
+            match self.serial.read() {
+                Ok(byte) => return Ok(byte),
+                Err(nb::Error::WouldBlock) => {}
+                Err(nb::Error::Other(e)) => return Err(DynamixelError::Serial(e)),
+            }
+        }
+        Err(DynamixelError::Timeout)
+    }
+
+    /// Construit et envoie un paquet d'instruction
+    fn send(&mut self, id: u8, instruction: u8, params: &[u8]) -> Result<(), DynamixelError<E>> {
+        let mut packet = [0; MAX_PACKET_SIZE];
+        packet[..4].copy_from_slice(&HEADER);
+        packet[4] = id;
+        packet[7] = instruction;
+        let mut size = 8;
+        for byte in params {
+            if size + 3 > MAX_PACKET_SIZE {
+                return Err(DynamixelError::InvalidPacket);
+            }
+            packet[size] = *byte;
+            size += 1;
+            // Bourrage : une séquence FF FF FD dans les données est suivie d'un FD
+            if packet[size - 3..size] == HEADER[..3] {
+                packet[size] = 0xFD;
+                size += 1;
+            }
+        }
+        let length = (size - 7 + 2) as u16;
+        packet[5..7].copy_from_slice(&length.to_le_bytes());
+        let crc = crc16(&packet[..size]);
+        packet[size..size + 2].copy_from_slice(&crc.to_le_bytes());
+        for byte in packet[..size + 2].iter() {
+            self.write_byte(*byte)?;
+        }
+        block!(self.serial.flush()).map_err(DynamixelError::Serial)
+    }
+
+    /// Attend le paquet de statut du servo `id` et copie ses paramètres dans `params`.
+    /// Renvoie le nombre de paramètres reçus.
+    fn receive(&mut self, id: u8, params: &mut [u8]) -> Result<usize, DynamixelError<E>> {
+        loop {
+            let mut packet = [0; MAX_PACKET_SIZE];
+            // Recherche de l'en-tête
+            let mut matched = 0;
+            while matched < HEADER.len() {
+                let byte = self.read_byte()?;
+                matched = if byte == HEADER[matched] {
+                    matched + 1
+                } else if byte == HEADER[0] && matched == 2 {
+                    // Un 0xFF en trop avant le 0xFD : les deux derniers octets forment encore le
+                    // début de l'en-tête
+                    2
+                } else if byte == HEADER[0] {
+                    1
+                } else {
+                    0
+                };
+            }
+            packet[..4].copy_from_slice(&HEADER);
+            for byte in packet[4..7].iter_mut() {
+                *byte = self.read_byte()?;
+            }
+            let length = u16::from_le_bytes([packet[5], packet[6]]) as usize;
+            if length < 3 || 7 + length > MAX_PACKET_SIZE {
+                return Err(DynamixelError::InvalidPacket);
+            }
+            for byte in packet[7..7 + length].iter_mut() {
+                *byte = self.read_byte()?;
+            }
+            let end = 7 + length - 2;
+            if crc16(&packet[..end]) != u16::from_le_bytes([packet[end], packet[end + 1]]) {
+                return Err(DynamixelError::Checksum);
+            }
+            // On ignore l'écho de nos propres instructions
+            if packet[7] != instruction::STATUS {
+                continue;
+            }
+            if packet[4] != id || length < 4 {
+                return Err(DynamixelError::InvalidPacket);
+            }
+            if packet[8] & 0x7F != 0 {
+                return Err(DynamixelError::Status(packet[8]));
+            }
+            // Retrait du bourrage
+            let mut count = 0;
+            let mut index = 9;
+            while index < end {
+                if count == params.len() {
+                    return Err(DynamixelError::InvalidPacket);
+                }
+                params[count] = packet[index];
+                count += 1;
+                if index >= 11 && packet[index - 2..=index] == [0xFF, 0xFF, 0xFD] {
+                    index += 1;
+                }
+                index += 1;
+            }
+            return Ok(count);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{crc16, register, Dynamixel, DynamixelError, BROADCAST_ID};
+    use crate::transmission::servo::{Control, Servo, ServoGroup};
+    use crate::units::{Celsius, MilliVolt};
+    use embedded_hal::serial::{Read, Write};
+    use std::collections::VecDeque;
+    use std::vec::Vec;
+
+    #[derive(Debug, Default)]
+    struct DummySerial {
+        sent: Vec<u8>,
+        incoming: VecDeque<u8>,
+    }
+
+    impl Read<u8> for DummySerial {
+        type Error = ();
+
+        fn read(&mut self) -> nb::Result<u8, ()> {
+            self.incoming.pop_front().ok_or(nb::Error::WouldBlock)
+        }
+    }
+
+    impl Write<u8> for DummySerial {
+        type Error = ();
+
+        fn write(&mut self, byte: u8) -> nb::Result<(), ()> {
+            self.sent.push(byte);
+            Ok(())
+        }
+
+        fn flush(&mut self) -> nb::Result<(), ()> {
+            Ok(())
+        }
+    }
+
+    fn status(id: u8, params: &[u8]) -> Vec<u8> {
+        let mut packet = vec![0xFF, 0xFF, 0xFD, 0x00, id];
+        packet.extend_from_slice(&(params.len() as u16 + 4).to_le_bytes());
+        packet.push(0x55);
+        packet.push(0);
+        packet.extend_from_slice(params);
+        let crc = crc16(&packet);
+        packet.extend_from_slice(&crc.to_le_bytes());
+        packet
+    }
+
+    #[test]
+    fn ping_and_write_packets() {
+        let mut serial = DummySerial::default();
+        serial.incoming.extend(status(1, &[0x06, 0x04, 0x26]));
+        serial.incoming.extend(&[
+            0xFF, 0xFF, 0xFD, 0x00, 0x01, 0x04, 0x00, 0x55, 0x00, 0xA1, 0x0C,
+        ]);
+        let mut dxl = Dynamixel::new(serial, 10);
+
+        assert_eq!(dxl.ping(1), Ok(0x0406));
+        dxl.write(1, register::GOAL_POSITION, &512u32.to_le_bytes())
+            .unwrap();
+        assert_eq!(dxl.ping(1), Err(DynamixelError::Timeout));
+
+        let serial = dxl.release();
+        assert_eq!(
+            &serial.sent[..10],
+            &[0xFF, 0xFF, 0xFD, 0x00, 0x01, 0x03, 0x00, 0x01, 0x19, 0x4E]
+        );
+        assert_eq!(
+            &serial.sent[10..26],
+            &[
+                0xFF, 0xFF, 0xFD, 0x00, 0x01, 0x09, 0x00, 0x03, 0x74, 0x00, 0x00, 0x02, 0x00, 0x00,
+                0xCA, 0x89
+            ]
+        );
+    }
+
+    #[test]
+    fn stray_header_byte() {
+        let mut serial = DummySerial::default();
+        serial.incoming.push_back(0xFF);
+        serial.incoming.extend(status(1, &[0x06, 0x04, 0x26]));
+        serial.incoming.extend(&[0xFF, 0xFF, 0xFD, 0xFF]);
+        serial.incoming.extend(status(1, &[0x06, 0x04, 0x26]));
+        let mut dxl = Dynamixel::new(serial, 10);
+        assert_eq!(dxl.ping(1), Ok(0x0406));
+        assert_eq!(dxl.ping(1), Ok(0x0406));
+    }
+
+    #[test]
+    fn echo_ignored_and_errors_reported() {
+        let mut serial = DummySerial::default();
+        // Écho de l'instruction, puis statut signalant une erreur de données
+        serial
+            .incoming
+            .extend(&[0xFF, 0xFF, 0xFD, 0x00, 0x01, 0x03, 0x00, 0x01, 0x19, 0x4E]);
+        let mut error = status(1, &[]);
+        error[8] = 0x07;
+        let len = error.len();
+        let crc = crc16(&error[..len - 2]);
+        error[len - 2..].copy_from_slice(&crc.to_le_bytes());
+        serial.incoming.extend(error);
+        let mut dxl = Dynamixel::new(serial, 10);
+        assert_eq!(dxl.ping(1), Err(DynamixelError::Status(0x07)));
+    }
+
+    #[test]
+    fn feedback() {
+        let mut feedback = [0u8; 21];
+        feedback[0..2].copy_from_slice(&(-250i16).to_le_bytes());
+        feedback[6..10].copy_from_slice(&2048i32.to_le_bytes());
+        feedback[18..20].copy_from_slice(&118u16.to_le_bytes());
+        feedback[20] = 52;
+        let mut serial = DummySerial::default();
+        serial.incoming.extend(status(3, &feedback));
+        serial.incoming.extend(status(3, &[0x20]));
+        let mut dxl = Dynamixel::new(serial, 10);
+
+        let mut servo = Servo {
+            id: 3,
+            control: Control::Position,
+            ..Default::default()
+        };
+        dxl.update_feedback(&mut servo).unwrap();
+        assert_eq!(servo.load, -250);
+        assert_eq!(servo.known_position, 2048);
+        assert_eq!(servo.voltage, MilliVolt(11800));
+        assert_eq!(servo.temperature, Celsius(52));
+        assert_eq!(servo.error, 0x20);
+    }
+
+    #[test]
+    fn synchronized_group() {
+        let finger = |id| Servo {
+            id,
+            control: Control::Position,
+            data: 300,
+            ..Default::default()
+        };
+        let mut group = ServoGroup::partial(&[finger(1), finger(2)]).unwrap();
+        group.prepare = true;
+        group.trigger = true;
+        let mut serial = DummySerial::default();
+        for &id in [1, 2].iter() {
+            for _ in 0..4 {
+                serial.incoming.extend(status(id, &[]));
+            }
+        }
+        let mut dxl = Dynamixel::new(serial, 10);
+        dxl.apply_group(&group).unwrap();
+
+        // (id, instruction) de chaque paquet envoyé
+        let sent = dxl.release().sent;
+        let mut packets = Vec::new();
+        let mut index = 0;
+        while index < sent.len() {
+            let length = usize::from(u16::from_le_bytes([sent[index + 5], sent[index + 6]]));
+            packets.push((sent[index + 4], sent[index + 7]));
+            index += 7 + length;
+        }
+        let goal = |id| (id, 0x04);
+        assert_eq!(packets.len(), 9);
+        assert_eq!(packets[3], goal(1));
+        assert_eq!(packets[7], goal(2));
+        assert_eq!(packets[8], (BROADCAST_ID, 0x05));
+        assert!(packets[..3].iter().all(|&packet| packet == (1, 0x03)));
+    }
+}
@@ -1,12 +1,21 @@
 //! Représentation haut-niveau d'un servo-moteur.
 
+pub mod binary;
+mod blocking;
+mod bus;
+pub mod dynamixel;
+mod ramp;
+
+pub use self::blocking::ServoBlockMonitor;
+pub use self::bus::{ServoBusError, ServoBuses, MAX_SERVO_BUSES};
+pub use self::ramp::ServoScheduler;
+
 use crate::transmission::Jsonizable;
-use heapless::{ArrayLength, String};
-use serde_json_core::de::{from_slice, Error as DError};
-use serde_json_core::ser::{to_string, Error as SError};
+use crate::units::{Celsius, MilliVolt};
 
 /// Représentation d'un unique servo-moteur
-#[derive(Debug, Default, Copy, Clone, Eq, Deserialize, Serialize)]
+#[derive(Debug, Default, Copy, Clone, Eq, Deserialize, Serialize, Jsonizable)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct Servo {
     // TODO : spécifier les histoires d'ID = 0
     /// Identifiant du servo-moteur.
@@ -31,34 +40,96 @@ pub struct Servo {
     pub rotation: Rotation,
     /// Représente les informations de contrôle associées à la commande `Speed` ou `Position`.
     pub data: u16,
-    /// Retourne vrai si le servo-moteur est bloqué
+    /// Retourne vrai si le servo-moteur est bloqué. Côté électronique, le
+    /// [`ServoBlockMonitor`] remplace le bit lu dans le servo par un état filtré dans le temps.
     pub blocked: bool,
     /// Comportement du servo-moteur face à un blocage extérieur.
     pub mode: BlockingMode,
     /// Couleur émise par le servo-moteur.
     pub color: Color,
+    /// Vitesse maximale pour atteindre la position commandée, en unités de position par
+    /// seconde (0 : pas de limite).
+    #[serde(default)]
+    pub max_speed: u16,
+    /// Accélération maximale pour atteindre la position commandée, en unités de position par
+    /// seconde au carré (0 : pas de limite).
+    #[serde(default)]
+    pub max_acceleration: u16,
+    /// Couple maximal que le servo peut exercer, en pour mille de son couple nominal
+    /// (0 : couple par défaut du servo). Permet de rendre un servo "souple" pour saisir des
+    /// éléments fragiles.
+    #[serde(default)]
+    pub torque_limit: u16,
+    /// Écart de position autour de la consigne dans lequel le servo n'exerce aucun couple,
+    /// en unités de position (0 : asservissement rigide).
+    #[serde(default)]
+    pub compliance_margin: u8,
+    /// Charge actuelle du servo en pour mille du couple nominal, négative dans le sens horaire.
+    #[serde(default)]
+    pub load: i16,
+    /// Température interne du servo.
+    #[serde(default)]
+    pub temperature: Celsius,
+    /// Tension d'alimentation du servo.
+    #[serde(default)]
+    pub voltage: MilliVolt,
+    /// Erreurs matérielles remontées par le servo, voir le module [`error`].
+    #[serde(default)]
+    pub error: u8,
+}
+
+/// Les bits d'erreur du champ `error` d'un [`Servo`].
+pub mod error {
+    /// La tension d'alimentation est hors de la plage de fonctionnement
+    pub const INPUT_VOLTAGE: u8 = 0x01;
+    /// La température dépasse la limite du servo
+    pub const OVERHEATING: u8 = 0x04;
+    /// Le capteur de position est défaillant
+    pub const ENCODER: u8 = 0x08;
+    /// Un choc électrique a été détecté
+    pub const ELECTRICAL_SHOCK: u8 = 0x10;
+    /// La charge dépasse le couple maximal
+    pub const OVERLOAD: u8 = 0x20;
 }
 
-impl Jsonizable for Servo {
-    /// Désérialisation d'un JSON en `Servo`
-    fn from_json_slice(slice: &[u8]) -> Result<Self, DError> {
-        from_slice(slice)
+impl Servo {
+    /// Renvoie vrai si le servo signale l'erreur `bit` (voir le module [`error`])
+    pub fn has_error(&self, bit: u8) -> bool {
+        self.error & bit != 0
     }
 
-    /// Sérialisation d'un `Servo` en JSON
-    fn to_string<B>(&self) -> Result<String<B>, SError>
-    where
-        B: ArrayLength<u8>,
-    {
-        to_string(self)
+    /// Renvoie vrai si le servo signale une surchauffe ou dépasse la température `limit`
+    pub fn is_overheating(&self, limit: Celsius) -> bool {
+        self.has_error(error::OVERHEATING) || self.temperature >= limit
     }
 }
 
 /// Un ensemble de au plus 8 servos-moteurs
-#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
+#[derive(PartialEq, Debug, Default, Clone, Serialize, Deserialize, Jsonizable)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct ServoGroup {
     /// Le vecteur de servos
     pub servos: [Servo; 9],
+    /// Masque des emplacements de `servos` qui contiennent une mise à jour (bit `i` pour
+    /// `servos[i]`). S'il est nul, la trame décrit le groupe complet.
+    #[serde(default)]
+    pub mask: u16,
+    /// Les commandes sont chargées dans les servos sans être exécutées, jusqu'au prochain
+    /// déclenchement. Les doigts d'une pince démarrent ainsi en même temps au lieu de l'un
+    /// après l'autre.
+    #[serde(default)]
+    pub prepare: bool,
+    /// Démarre simultanément toutes les commandes préparées, y compris celles de cette trame
+    #[serde(default)]
+    pub trigger: bool,
+    /// Numéro de la commande, voir [`CommandSeq`](crate::transmission::seq::CommandSeq)
+    #[serde(default)]
+    pub counter: u16,
+    /// Le bus de la carte auquel sont branchés les servos de la trame, voir [`ServoBuses`].
+    /// Deux servos sur des bus différents peuvent avoir le même identifiant. L'encodage binaire
+    /// ne transporte pas ce champ : une trame binaire concerne toujours le bus 0.
+    #[serde(default)]
+    pub bus: u8,
 }
 
 /// Relation d'équivalence partielle pour le module `Servo2019`, utile pour le débug.
@@ -70,12 +141,21 @@ impl PartialEq for Servo {
                     && self.control == other.control
                     && self.blocked == other.blocked
                     && self.mode == other.mode
-                    && self.color == other.color))
+                    && self.color == other.color
+                    && self.max_speed == other.max_speed
+                    && self.max_acceleration == other.max_acceleration
+                    && self.torque_limit == other.torque_limit
+                    && self.compliance_margin == other.compliance_margin
+                    && self.load == other.load
+                    && self.temperature == other.temperature
+                    && self.voltage == other.voltage
+                    && self.error == other.error))
     }
 }
 
 /// Comportement du servo-moteur lorsqu'il est bloqué.
 #[derive(Debug, PartialEq, Copy, Clone, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum BlockingMode {
     /// Le servo relâche la pression lorsqu'il est bloqué.
     Unblocking = 0,
@@ -91,6 +171,7 @@ impl Default for BlockingMode {
 
 /// Représente le sens de rotation du servo moteur lorsqu'il est contrôle en vitesse
 #[derive(Debug, PartialEq, Copy, Clone, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum Rotation {
     /// Rotation trigonométrique, qui est le sens par défaut
     CounterClockwise,
@@ -106,6 +187,7 @@ impl Default for Rotation {
 
 /// Commande du servo-moteur.
 #[derive(Debug, PartialEq, Copy, Clone, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum Control {
     /// Commande en vitesse.
     Speed,
@@ -121,6 +203,7 @@ impl Default for Control {
 
 /// Couleur émise par le servo-moteur.
 #[derive(Debug, PartialEq, Copy, Clone, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum Color {
     /// Couleur noire
     Black = 0x00,
@@ -147,22 +230,232 @@ impl Default for Color {
 }
 
 impl ServoGroup {
-    /// Désérialisation d'un JSON en `ServoGroup`
-    pub fn from_json_slice(slice: &[u8]) -> Result<Self, ()> {
-        let result = from_slice(slice);
-        match result {
-            Ok(t) => t,
-            Err(_) => Err(()),
+    /// Crée une mise à jour partielle ne contenant que `servos`, ou `None` s'il y en a trop ou
+    /// s'il n'y en a aucun : un masque nul décrirait le groupe complet.
+    pub fn partial(servos: &[Servo]) -> Option<Self> {
+        let mut group = ServoGroup::default();
+        if servos.is_empty() || servos.len() > group.servos.len() {
+            return None;
+        }
+        for (index, servo) in servos.iter().enumerate() {
+            group.servos[index] = *servo;
+            group.mask |= 1 << index;
+        }
+        Some(group)
+    }
+
+    /// Crée une trame qui démarre les commandes préparées sans rien changer d'autre
+    pub fn trigger() -> Self {
+        ServoGroup {
+            trigger: true,
+            ..Default::default()
+        }
+    }
+
+    /// Renvoie vrai si la trame ne fait que démarrer les commandes préparées
+    pub fn is_trigger_only(&self) -> bool {
+        self.trigger && self.servos.iter().all(|servo| servo.id == 0)
+    }
+
+    /// Renvoie vrai si la trame ne contient qu'une partie du groupe
+    pub fn is_partial(&self) -> bool {
+        self.mask != 0
+    }
+
+    /// Renvoie les servos contenus dans la trame
+    pub fn updated(&self) -> impl Iterator<Item = &Servo> {
+        let mask = self.mask;
+        self.servos
+            .iter()
+            .enumerate()
+            .filter(move |&(index, servo)| servo.id != 0 && (mask == 0 || mask & (1 << index) != 0))
+            .map(|(_, servo)| servo)
+    }
+
+    /// Applique `update` sur ce groupe : les servos déjà présents sont remplacés, les autres sont
+    /// ajoutés dans un emplacement libre. Une mise à jour complète remplace tout le groupe.
+    ///
+    /// Renvoie `Err` avec le servo qui n'a pas pu être ajouté si le groupe est plein. Une trame
+    /// de déclenchement seul (voir [`ServoGroup::trigger`]) ne change pas le groupe.
+    pub fn merge(&mut self, update: &ServoGroup) -> Result<(), Servo> {
+        if update.is_trigger_only() {
+            return Ok(());
+        }
+        if !update.is_partial() {
+            self.servos = update.servos;
+            return Ok(());
+        }
+        for servo in update.updated() {
+            let slot = match self.servos.iter().position(|s| s.id == servo.id) {
+                Some(index) => index,
+                None => match self.servos.iter().position(|s| s.id == 0) {
+                    Some(index) => index,
+                    None => return Err(*servo),
+                },
+            };
+            self.servos[slot] = *servo;
+        }
+        Ok(())
+    }
+
+    /// Construit la mise à jour partielle contenant les servos qui ont changé depuis `previous`,
+    /// ou `None` si aucun servo n'a changé
+    pub fn changes_since(&self, previous: &ServoGroup) -> Option<ServoGroup> {
+        let mut update = ServoGroup {
+            bus: self.bus,
+            ..Default::default()
+        };
+        let changed = self
+            .servos
+            .iter()
+            .filter(|servo| servo.id != 0)
+            .filter(|servo| !previous.servos.iter().any(|old| old == *servo));
+        for (index, servo) in changed.enumerate() {
+            update.servos[index] = *servo;
+            update.mask |= 1 << index;
+        }
+        if update.is_partial() {
+            Some(update)
+        } else {
+            None
+        }
+    }
+}
+
+/// Calibration d'un servo-moteur : décalage du zéro, butées logicielles et sens de rotation.
+///
+/// Elle est stockée par la carte (voir [`crate::persistence`]) et appliquée entre la commande
+/// reçue de l'informatique et le servo : l'informatique manipule des positions logiques, qui ne
+/// changent pas quand on remplace un servo. Une trame [`ServoCalibration`] change la calibration
+/// du servo `id` (voir [`ServoCalibrations::set`]).
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Deserialize, Serialize, Jsonizable)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ServoCalibration {
+    /// Identifiant du servo-moteur (0 : emplacement libre)
+    pub id: u8,
+    /// Décalage ajouté à toutes les positions commandées, en unités de position
+    pub offset: i16,
+    /// Position minimale autorisée
+    pub min_position: u16,
+    /// Position maximale autorisée (0 : pas de limite)
+    pub max_position: u16,
+    /// Le servo est monté à l'envers : les positions sont symétrisées par rapport au milieu des
+    /// butées et le sens de rotation est inversé
+    #[serde(default)]
+    pub inverted: bool,
+}
+
+impl ServoCalibration {
+    fn max(&self) -> u16 {
+        if self.max_position == 0 {
+            u16::max_value()
+        } else {
+            self.max_position
+        }
+    }
+
+    fn mirror(&self, position: i32) -> i32 {
+        if self.inverted {
+            i32::from(self.min_position) + i32::from(self.max()) - position
+        } else {
+            position
+        }
+    }
+
+    /// Convertit une position commandée en position à envoyer au servo
+    pub fn apply(&self, position: u16) -> u16 {
+        let position = self.mirror(i32::from(position)) + i32::from(self.offset);
+        let position = position.max(0).min(i32::from(u16::max_value())) as u16;
+        position.max(self.min_position).min(self.max())
+    }
+
+    /// Convertit une position lue sur le servo en position logique, inverse de
+    /// [`ServoCalibration::apply`] entre les butées
+    pub fn unapply(&self, position: u16) -> u16 {
+        let position = self.mirror(i32::from(position) - i32::from(self.offset));
+        position.max(0).min(i32::from(u16::max_value())) as u16
+    }
+
+    /// Convertit un sens de rotation commandé en sens de rotation du servo
+    pub fn rotation(&self, rotation: Rotation) -> Rotation {
+        match (self.inverted, rotation) {
+            (false, rotation) => rotation,
+            (true, Rotation::CounterClockwise) => Rotation::Clockwise,
+            (true, Rotation::Clockwise) => Rotation::CounterClockwise,
+        }
+    }
+
+    /// Renvoie la commande à envoyer au servo pour exécuter la commande `servo`
+    pub fn command(&self, servo: &Servo) -> Servo {
+        let mut command = *servo;
+        match servo.control {
+            Control::Position => command.data = self.apply(servo.data),
+            Control::Speed => command.rotation = self.rotation(servo.rotation),
+        }
+        command
+    }
+}
+
+/// Les calibrations des servos d'un [`ServoGroup`]
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ServoCalibrations {
+    /// Les calibrations, une par emplacement
+    pub servos: [ServoCalibration; 9],
+}
+
+impl ServoCalibrations {
+    /// Renvoie la calibration du servo `id`, s'il y en a une
+    pub fn get(&self, id: u8) -> Option<&ServoCalibration> {
+        self.servos
+            .iter()
+            .find(|calibration| id != 0 && calibration.id == id)
+    }
+
+    /// Remplace la calibration du servo `calibration.id`, ou l'ajoute dans un emplacement libre.
+    ///
+    /// Renvoie `Err` avec la calibration si son identifiant est nul ou s'il n'y a plus de place.
+    pub fn set(&mut self, calibration: ServoCalibration) -> Result<(), ServoCalibration> {
+        if calibration.id == 0 {
+            return Err(calibration);
+        }
+        let slot = match self.servos.iter().position(|c| c.id == calibration.id) {
+            Some(index) => index,
+            None => match self.servos.iter().position(|c| c.id == 0) {
+                Some(index) => index,
+                None => return Err(calibration),
+            },
+        };
+        self.servos[slot] = calibration;
+        Ok(())
+    }
+
+    /// Renvoie la commande à envoyer au servo pour exécuter `servo`, inchangée s'il n'a pas de
+    /// calibration
+    pub fn command(&self, servo: &Servo) -> Servo {
+        self.get(servo.id)
+            .map_or(*servo, |calibration| calibration.command(servo))
+    }
+
+    /// Convertit la position lue sur le servo `servo` en position logique
+    pub fn feedback(&self, servo: &mut Servo) {
+        if let Some(calibration) = self.get(servo.id) {
+            servo.known_position = calibration.unapply(servo.known_position);
         }
     }
 }
 
 #[cfg(test)]
 mod test {
-    use super::{BlockingMode, Color, Control, Rotation, Servo};
+    use super::{
+        error, BlockingMode, Color, Control, Rotation, Servo, ServoCalibration, ServoCalibrations,
+        ServoGroup,
+    };
     use crate::transmission::Jsonizable;
-    use heapless::consts::U256;
+    use crate::units::{Celsius, MilliVolt};
+    use heapless::consts::{U256, U4096};
     use heapless::String;
+    use std::vec::Vec;
     type N = U256;
 
     #[test]
@@ -176,6 +469,14 @@ mod test {
             blocked: false,
             mode: BlockingMode::HoldOnBlock,
             color: Color::Blue,
+            max_speed: 0,
+            max_acceleration: 0,
+            torque_limit: 0,
+            compliance_margin: 0,
+            load: 0,
+            temperature: Celsius(0),
+            voltage: MilliVolt(0),
+            error: 0,
         };
         let strd: String<N> = servo.to_string().unwrap();
         let _data =
@@ -209,4 +510,157 @@ mod test {
                 \"known_position\":511,\"mode\":\"Unblocking\",\"rotation\":\"CounterClockwise\"}";
         let servo2 = Servo::from_json_slice(data.as_bytes()).unwrap();
     }
+
+    #[test]
+    fn ser_deser_servo_torque() {
+        let servo = Servo {
+            id: 7,
+            known_position: 300,
+            data: 310,
+            torque_limit: 250,
+            compliance_margin: 8,
+            ..Default::default()
+        };
+        let strd: String<N> = servo.to_string().unwrap();
+        let servo2 = Servo::from_json_slice(strd.as_bytes()).unwrap();
+        assert_eq!(servo, servo2);
+        assert_eq!(servo2.torque_limit, 250);
+        assert_eq!(servo2.compliance_margin, 8);
+        assert_ne!(
+            servo,
+            Servo {
+                torque_limit: 1000,
+                ..servo
+            }
+        );
+    }
+
+    #[test]
+    fn servo_feedback() {
+        let data = "{\"blocked\":false,\"color\":\"Green\",\"control\":\"Position\",\"data\":511,\"id\":4,\
+                \"known_position\":500,\"mode\":\"Unblocking\",\"rotation\":\"CounterClockwise\",\
+                \"load\":-320,\"temperature\":71,\"voltage\":11800,\"error\":36}";
+        let servo = Servo::from_json_slice(data.as_bytes()).unwrap();
+        assert_eq!(servo.load, -320);
+        assert_eq!(servo.voltage, MilliVolt(11800));
+        assert!(servo.has_error(error::OVERLOAD));
+        assert!(!servo.has_error(error::ENCODER));
+        assert!(servo.is_overheating(Celsius(80)));
+        assert!(!Servo { error: 0, ..servo }.is_overheating(Celsius(80)));
+        assert!(Servo { error: 0, ..servo }.is_overheating(Celsius(70)));
+    }
+
+    #[test]
+    fn partial_group_update() {
+        let servo = |id, position| Servo {
+            id,
+            known_position: position,
+            data: position,
+            ..Default::default()
+        };
+        let mut group = ServoGroup::default();
+        group.servos[0] = servo(1, 100);
+        group.servos[1] = servo(2, 200);
+        let previous = group.clone();
+
+        let update = ServoGroup::partial(&[servo(2, 250), servo(5, 500)]).unwrap();
+        assert!(update.is_partial());
+        let strd: String<U4096> = update.to_string().unwrap();
+        let update = ServoGroup::from_json_slice(strd.as_bytes()).unwrap();
+        assert_eq!(update.updated().count(), 2);
+
+        group.merge(&update).unwrap();
+        assert_eq!(group.servos[0], servo(1, 100));
+        assert_eq!(group.servos[1], servo(2, 250));
+        assert_eq!(group.servos[2], servo(5, 500));
+
+        let changes = group.changes_since(&previous).unwrap();
+        assert_eq!(changes.mask, 0b11);
+        let ids: Vec<u8> = changes.updated().map(|servo| servo.id).collect();
+        assert_eq!(ids, vec![2, 5]);
+
+        let before = group.clone();
+        group.merge(&ServoGroup::trigger()).unwrap();
+        assert_eq!(group, before);
+
+        let full = ServoGroup::default();
+        assert!(!full.is_partial());
+        group.merge(&full).unwrap();
+        assert_eq!(group.updated().count(), 0);
+        assert!(ServoGroup::partial(&[servo(1, 0); 10]).is_none());
+        assert!(ServoGroup::partial(&[]).is_none());
+
+        // Sans changement il n'y a pas de mise à jour, qui serait prise pour le groupe complet
+        let before = group.clone();
+        assert!(group.changes_since(&before).is_none());
+    }
+
+    #[test]
+    fn servo_calibration() {
+        let calibration = ServoCalibration {
+            id: 3,
+            offset: -20,
+            min_position: 100,
+            max_position: 900,
+            inverted: false,
+        };
+        assert_eq!(calibration.apply(500), 480);
+        assert_eq!(calibration.apply(10), 100);
+        assert_eq!(calibration.apply(1000), 900);
+        assert_eq!(ServoCalibration::default().apply(1000), 1000);
+
+        let mut calibrations = ServoCalibrations::default();
+        calibrations.servos[2] = calibration;
+        assert_eq!(calibrations.get(3), Some(&calibration));
+        assert_eq!(calibrations.get(0), None);
+    }
+
+    #[test]
+    fn servo_calibration_inverted() {
+        let data = "{\"id\":3,\"offset\":10,\"min_position\":0,\"max_position\":1000,\
+                    \"inverted\":true}";
+        let calibration = ServoCalibration::from_json_slice(data.as_bytes()).unwrap();
+        assert_eq!(calibration.apply(100), 910);
+        assert_eq!(calibration.unapply(910), 100);
+        assert_eq!(calibration.apply(0), 1000);
+        assert_eq!(
+            calibration.rotation(Rotation::Clockwise),
+            Rotation::CounterClockwise
+        );
+
+        let mut calibrations = ServoCalibrations::default();
+        calibrations.set(calibration).unwrap();
+        calibrations
+            .set(ServoCalibration {
+                offset: -10,
+                ..calibration
+            })
+            .unwrap();
+        assert_eq!(calibrations.servos[1].id, 0);
+        assert!(calibrations.set(ServoCalibration::default()).is_err());
+
+        let servo = Servo {
+            id: 3,
+            data: 100,
+            ..Default::default()
+        };
+        let command = calibrations.command(&servo);
+        assert_eq!(command.data, 890);
+        let mut feedback = Servo {
+            known_position: 890,
+            ..command
+        };
+        calibrations.feedback(&mut feedback);
+        assert_eq!(feedback.known_position, 100);
+
+        let speed = Servo {
+            control: Control::Speed,
+            ..servo
+        };
+        assert_eq!(calibrations.command(&speed).rotation, Rotation::Clockwise);
+        assert_eq!(
+            calibrations.command(&Servo { id: 4, ..speed }),
+            Servo { id: 4, ..speed }
+        );
+    }
 }
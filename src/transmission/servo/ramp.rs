@@ -0,0 +1,247 @@
+//! Génération de rampes de position pour les servo-moteurs.
+//!
+//! Envoyer directement la position commandée à un servo le fait bouger à pleine vitesse, ce qui
+//! abîme les mécanismes fragiles (les doigts de la pince par exemple). Le [`ServoScheduler`]
+//! génère à chaque pas de temps une consigne intermédiaire qui respecte la vitesse et
+//! l'accélération maximales demandées dans la trame.
+
+use super::{Control, Servo, ServoGroup};
+
+#[allow(unused_imports)]
+use libm::F32Ext;
+
+/// Nombre de servos suivis par le `ServoScheduler`
+const MAX_RAMPS: usize = 9;
+
+#[derive(Debug, Default, Copy, Clone)]
+struct Ramp {
+    id: u8,
+    position: f32,
+    velocity: f32,
+    target: u16,
+    max_speed: f32,
+    max_acceleration: f32,
+    /// Commande préparée : cible, vitesse et accélération maximales
+    pending: Option<(u16, f32, f32)>,
+}
+
+impl Ramp {
+    fn new(servo: &Servo) -> Self {
+        Ramp {
+            id: servo.id,
+            position: f32::from(servo.known_position),
+            velocity: 0.0,
+            target: servo.known_position,
+            max_speed: 0.0,
+            max_acceleration: 0.0,
+            pending: None,
+        }
+    }
+
+    fn start(&mut self, target: u16, max_speed: f32, max_acceleration: f32) {
+        self.target = target;
+        self.max_speed = max_speed;
+        self.max_acceleration = max_acceleration;
+        self.pending = None;
+    }
+
+    fn step(&mut self, dt: f32) -> u16 {
+        let distance = f32::from(self.target) - self.position;
+        if self.max_speed <= 0.0 {
+            self.position = f32::from(self.target);
+            self.velocity = 0.0;
+            return self.target;
+        }
+        // Vitesse maximale permettant encore de s'arrêter sur la cible
+        let stopping_speed = if self.max_acceleration > 0.0 {
+            (2.0 * self.max_acceleration * distance.abs()).sqrt()
+        } else {
+            self.max_speed
+        };
+        let wanted = self.max_speed.min(stopping_speed) * distance.signum();
+        self.velocity = if self.max_acceleration > 0.0 {
+            let max_delta = self.max_acceleration * dt;
+            self.velocity + (wanted - self.velocity).max(-max_delta).min(max_delta)
+        } else {
+            wanted
+        };
+        let step = self.velocity * dt;
+        if step.abs() >= distance.abs() || distance == 0.0 {
+            self.position = f32::from(self.target);
+            self.velocity = 0.0;
+        } else {
+            self.position += step;
+        }
+        self.position.round() as u16
+    }
+}
+
+/// Calcule les consignes intermédiaires des servos commandés en position.
+#[derive(Debug, Default)]
+pub struct ServoScheduler {
+    ramps: [Ramp; MAX_RAMPS],
+}
+
+impl ServoScheduler {
+    /// Crée un planificateur sans servo
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Prend en compte les nouvelles commandes de `group`. Un servo vu pour la première fois
+    /// part de sa position connue. Les commandes d'un groupe à préparer n'ont d'effet qu'au
+    /// prochain déclenchement, où toutes les rampes préparées partent au même pas de temps.
+    pub fn update(&mut self, group: &ServoGroup) {
+        for servo in group.updated() {
+            if servo.control != Control::Position {
+                continue;
+            }
+            let ramp = match self.ramps.iter().position(|ramp| ramp.id == servo.id) {
+                Some(index) => &mut self.ramps[index],
+                None => match self.ramps.iter_mut().find(|ramp| ramp.id == 0) {
+                    Some(free) => {
+                        *free = Ramp::new(servo);
+                        free
+                    }
+                    None => continue,
+                },
+            };
+            let command = (
+                servo.data,
+                f32::from(servo.max_speed),
+                f32::from(servo.max_acceleration),
+            );
+            if group.prepare {
+                ramp.pending = Some(command);
+            } else {
+                ramp.start(command.0, command.1, command.2);
+            }
+        }
+        if group.trigger {
+            for ramp in self.ramps.iter_mut() {
+                if let Some((target, max_speed, max_acceleration)) = ramp.pending {
+                    ramp.start(target, max_speed, max_acceleration);
+                }
+            }
+        }
+    }
+
+    /// Oublie le servo `id`, qui repartira de sa position connue à la prochaine commande
+    pub fn forget(&mut self, id: u8) {
+        if let Some(ramp) = self.ramps.iter_mut().find(|ramp| ramp.id == id) {
+            *ramp = Ramp::default();
+        }
+    }
+
+    /// Avance de `dt` millisecondes et appelle `apply` avec l'identifiant et la consigne de
+    /// position de chaque servo suivi.
+    pub fn tick<F: FnMut(u8, u16)>(&mut self, dt: u32, mut apply: F) {
+        let dt = dt as f32 / 1000.0;
+        for ramp in self.ramps.iter_mut().filter(|ramp| ramp.id != 0) {
+            let setpoint = ramp.step(dt);
+            apply(ramp.id, setpoint);
+        }
+    }
+
+    /// Renvoie `true` si le servo `id` a atteint sa position commandée
+    pub fn is_done(&self, id: u8) -> bool {
+        self.ramps
+            .iter()
+            .find(|ramp| ramp.id == id)
+            .map_or(true, |ramp| {
+                ramp.position == f32::from(ramp.target) && ramp.velocity == 0.0
+            })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::ServoScheduler;
+    use crate::transmission::servo::{Control, Servo, ServoGroup};
+    use std::vec::Vec;
+
+    fn group(servo: Servo) -> ServoGroup {
+        ServoGroup::partial(&[servo]).unwrap()
+    }
+
+    fn run(scheduler: &mut ServoScheduler, ticks: usize) -> Vec<u16> {
+        let mut setpoints = Vec::new();
+        for _ in 0..ticks {
+            scheduler.tick(100, |_, setpoint| setpoints.push(setpoint));
+        }
+        setpoints
+    }
+
+    #[test]
+    fn ramp_speed_limited() {
+        let mut scheduler = ServoScheduler::new();
+        scheduler.update(&group(Servo {
+            id: 3,
+            known_position: 100,
+            control: Control::Position,
+            data: 350,
+            max_speed: 1000,
+            ..Default::default()
+        }));
+        assert_eq!(run(&mut scheduler, 4), vec![200, 300, 350, 350]);
+        assert!(scheduler.is_done(3));
+    }
+
+    #[test]
+    fn ramp_acceleration_limited() {
+        let mut scheduler = ServoScheduler::new();
+        scheduler.update(&group(Servo {
+            id: 3,
+            known_position: 0,
+            control: Control::Position,
+            data: 100,
+            max_speed: 1000,
+            max_acceleration: 1000,
+            ..Default::default()
+        }));
+        let setpoints = run(&mut scheduler, 20);
+        assert_eq!(&setpoints[..3], &[10, 30, 60]);
+        assert!(setpoints.windows(2).all(|w| w[0] <= w[1]));
+        assert_eq!(*setpoints.last().unwrap(), 100);
+        assert!(scheduler.is_done(3));
+    }
+
+    #[test]
+    fn no_limit_goes_straight_to_target() {
+        let mut scheduler = ServoScheduler::new();
+        scheduler.update(&group(Servo {
+            id: 1,
+            known_position: 20,
+            control: Control::Position,
+            data: 500,
+            ..Default::default()
+        }));
+        assert_eq!(run(&mut scheduler, 1), vec![500]);
+        scheduler.forget(1);
+        assert!(run(&mut scheduler, 1).is_empty());
+    }
+
+    #[test]
+    fn prepared_ramps_start_together() {
+        let finger = |id, data| Servo {
+            id,
+            known_position: 100,
+            control: Control::Position,
+            data,
+            max_speed: 1000,
+            ..Default::default()
+        };
+        let mut scheduler = ServoScheduler::new();
+        let mut left = group(finger(1, 300));
+        left.prepare = true;
+        scheduler.update(&left);
+        assert_eq!(run(&mut scheduler, 1), vec![100]);
+
+        let mut right = group(finger(2, 300));
+        right.prepare = true;
+        scheduler.update(&right);
+        scheduler.update(&ServoGroup::trigger());
+        assert_eq!(run(&mut scheduler, 2), vec![200, 200, 300, 300]);
+        assert!(scheduler.is_done(1) && scheduler.is_done(2));
+    }
+}
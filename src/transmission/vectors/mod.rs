@@ -0,0 +1,248 @@
+//! Génération des vecteurs de test de référence.
+//!
+//! L'informatique (C++) doit lire et écrire exactement les mêmes octets que la librairie. Ce module
+//! produit, pour chaque type de trame, l'encodage canonique de l'exemple utilisé par
+//! [`crate::transmission::selftest`] : le JSON de chaque trame et, pour les trames qui en ont
+//! un, l'encodage binaire. Quelques variantes couvrent en plus les encodages optionnels : mise à
+//! jour partielle et déclenchement des servos, centrale inertielle sans magnétomètre. Les fichiers
+//! écrits par [`write_test_vectors`] servent de données de conformité côté informatique.
+//!
+//! Ce module n'est disponible qu'avec la feature `std` :
+//!
+//! ```ignore
+//! librobot::transmission::vectors::write_test_vectors(Path::new("fixtures"))?;
+//! ```
+
+use crate::transmission::imu::ImuFrame;
+use crate::transmission::selftest::{
+    imu_sample, motors_sample, servo_sample, visit_samples, SampleVisitor,
+};
+use crate::transmission::servo::binary::ServoFrameError;
+use crate::transmission::{Error, Jsonizable, MessageKind, ServoGroup};
+use heapless::consts::U4096;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::string::String;
+use std::vec::Vec;
+
+/// L'encodage canonique d'une trame
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TestVector {
+    /// Le type de la trame
+    pub kind: MessageKind,
+    /// Le nom du fichier du vecteur, par exemple `servo.json` ou `servo.bin`
+    pub name: String,
+    /// Les octets de la trame
+    pub bytes: Vec<u8>,
+}
+
+/// Renvoie le nom de `kind` en minuscules, les mots séparés par des `_`
+fn file_stem(kind: MessageKind) -> String {
+    let debug = format!("{:?}", kind);
+    let mut stem = String::new();
+    let mut previous_lowercase = false;
+    for c in debug.chars() {
+        if c.is_ascii_uppercase() && previous_lowercase {
+            stem.push('_');
+        }
+        previous_lowercase = c.is_ascii_lowercase();
+        stem.push(c.to_ascii_lowercase());
+    }
+    stem
+}
+
+#[derive(Default)]
+struct JsonVectors {
+    vectors: Vec<TestVector>,
+    /// La première erreur de sérialisation, le visiteur ne pouvant pas la renvoyer
+    error: Option<Error>,
+}
+
+impl JsonVectors {
+    /// Ajoute le JSON de `sample` sous le nom `<kind>_<variant>.json`
+    fn push_variant<T: Jsonizable>(
+        &mut self,
+        kind: MessageKind,
+        variant: &str,
+        sample: &T,
+    ) -> Result<(), Error> {
+        let name = format!("{}_{}.json", file_stem(kind), variant);
+        self.push(kind, name, sample)
+    }
+
+    fn push<T: Jsonizable>(
+        &mut self,
+        kind: MessageKind,
+        name: String,
+        sample: &T,
+    ) -> Result<(), Error> {
+        let json: heapless::String<U4096> = sample.to_string()?;
+        self.vectors.push(TestVector {
+            kind,
+            name,
+            bytes: json.as_bytes().to_vec(),
+        });
+        Ok(())
+    }
+}
+
+impl SampleVisitor for JsonVectors {
+    fn visit<T: Jsonizable + PartialEq>(&mut self, kind: MessageKind, sample: &T) {
+        if let Err(e) = self.push(kind, format!("{}.json", file_stem(kind)), sample) {
+            self.error.get_or_insert(e);
+        }
+    }
+}
+
+/// Renvoie les vecteurs de test de tous les types de trame, ou l'erreur d'encodage d'un des
+/// exemples
+pub fn test_vectors() -> Result<Vec<TestVector>, Error> {
+    let mut json = JsonVectors::default();
+    visit_samples(&mut json);
+    if let Some(e) = json.error.take() {
+        return Err(e);
+    }
+    // `partial` ne refuse qu'une liste de servos vide ou trop longue
+    let partial = ServoGroup::partial(&servo_sample().servos[1..2])
+        .ok_or(Error::ServoFrame(ServoFrameError::Empty))?;
+    let partial = ServoGroup {
+        prepare: true,
+        counter: 13,
+        ..partial
+    };
+    json.push_variant(MessageKind::Servo, "partial", &partial)?;
+    json.push_variant(MessageKind::Servo, "trigger", &ServoGroup::trigger())?;
+    let imu = ImuFrame {
+        mag: None,
+        ..imu_sample()
+    };
+    json.push_variant(MessageKind::Imu, "no_mag", &imu)?;
+    let mut vectors = json.vectors;
+
+    let servos = servo_sample().into_bytes()?;
+    vectors.push(TestVector {
+        kind: MessageKind::Servo,
+        name: format!("{}.bin", file_stem(MessageKind::Servo)),
+        bytes: servos.to_vec(),
+    });
+    let motors = motors_sample().into_bytes()?;
+    vectors.push(TestVector {
+        kind: MessageKind::Motors,
+        name: format!("{}.bin", file_stem(MessageKind::Motors)),
+        bytes: motors.to_vec(),
+    });
+    Ok(vectors)
+}
+
+/// Écrit chaque vecteur de test dans un fichier du dossier `dir`, qui est créé si besoin
+pub fn write_test_vectors(dir: &Path) -> io::Result<()> {
+    let vectors = test_vectors().map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+    fs::create_dir_all(dir)?;
+    for vector in vectors {
+        fs::write(dir.join(&vector.name), &vector.bytes)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{file_stem, test_vectors, TestVector};
+    use crate::transmission::imu::ImuFrame;
+    use crate::transmission::motor::MotorsGroup;
+    use crate::transmission::selftest::{
+        motors_sample, servo_sample, visit_samples, SampleVisitor,
+    };
+    use crate::transmission::{Jsonizable, Message, MessageKind, ServoGroup};
+    use std::vec::Vec;
+
+    fn find<'a>(vectors: &'a [TestVector], name: &str) -> &'a [u8] {
+        &vectors
+            .iter()
+            .find(|v| v.name == name)
+            .unwrap_or_else(|| panic!("missing {}", name))
+            .bytes
+    }
+
+    fn message(bytes: &[u8]) -> Message {
+        bytes.iter().cloned().collect()
+    }
+
+    #[test]
+    fn vectors_cover_every_kind() {
+        let vectors = test_vectors().unwrap();
+        for value in 0..=255u8 {
+            if let Ok(kind) = MessageKind::from_u8(value) {
+                let name = format!("{}.json", file_stem(kind));
+                assert!(
+                    vectors.iter().any(|v| v.kind == kind && v.name == name),
+                    "missing {}",
+                    name
+                );
+            }
+        }
+        assert_eq!(file_stem(MessageKind::IO), "io");
+        assert_eq!(
+            file_stem(MessageKind::NavigationParameters),
+            "navigation_parameters"
+        );
+    }
+
+    struct Decode(Vec<TestVector>);
+
+    impl SampleVisitor for Decode {
+        fn visit<T: Jsonizable + PartialEq>(&mut self, kind: MessageKind, sample: &T) {
+            let name = format!("{}.json", file_stem(kind));
+            let decoded = T::from_json_slice(find(&self.0, &name));
+            assert!(decoded.ok().as_ref() == Some(sample), "{} differs", name);
+        }
+    }
+
+    #[test]
+    fn vectors_are_decodable() {
+        visit_samples(&mut Decode(test_vectors().unwrap()));
+    }
+
+    #[test]
+    fn binary_vectors_round_trip() {
+        let vectors = test_vectors().unwrap();
+        let bytes = find(&vectors, "servo.bin");
+        assert_eq!(bytes[0], 2);
+        let servo = ServoGroup::new(message(bytes)).unwrap();
+        let sample = servo_sample();
+        for (decoded, expected) in servo.servos.iter().zip(sample.servos.iter()) {
+            assert_eq!(decoded.id, expected.id);
+            assert_eq!(decoded.known_position, expected.known_position);
+            assert_eq!(decoded.data, expected.data);
+            assert_eq!(decoded.control, expected.control);
+            assert_eq!(decoded.blocked, expected.blocked);
+            assert_eq!(decoded.mode, expected.mode);
+            assert_eq!(decoded.color, expected.color);
+            assert_eq!(decoded.torque_limit, expected.torque_limit);
+            assert_eq!(decoded.compliance_margin, expected.compliance_margin);
+        }
+        assert_eq!(servo.into_bytes().unwrap().as_slice(), bytes);
+
+        let bytes = find(&vectors, "motors.bin");
+        assert_eq!(&bytes[..3], &[2, 1, 1]);
+        assert_eq!(MotorsGroup::new(message(bytes)).unwrap(), motors_sample());
+    }
+
+    #[test]
+    fn optional_encodings() {
+        let vectors = test_vectors().unwrap();
+        let partial = ServoGroup::from_json_slice(find(&vectors, "servo_partial.json")).unwrap();
+        assert!(partial.is_partial());
+        assert!(partial.prepare);
+        assert_eq!(partial.mask, 0b1);
+        assert_eq!(partial.servos[0], servo_sample().servos[1]);
+
+        let trigger = ServoGroup::from_json_slice(find(&vectors, "servo_trigger.json")).unwrap();
+        assert!(trigger.is_trigger_only());
+
+        let imu = ImuFrame::from_json_slice(find(&vectors, "imu_no_mag.json")).unwrap();
+        assert_eq!(imu.mag, None);
+        let imu = ImuFrame::from_json_slice(find(&vectors, "imu.json")).unwrap();
+        assert!(imu.mag.is_some());
+    }
+}
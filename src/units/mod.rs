@@ -1,10 +1,20 @@
-//! Ce module contiens du code permettant de gérer les unités de longeurs
+//! Ce module contiens du code permettant de gérer les unités de longeurs et d'angles, ainsi que
+//! les grandeurs électriques, les grandeurs des PWM et les nombres à virgule fixe échangés avec
+//! l'informatique
+//!
+//! Les unités sont (dé)sérialisées comme leur valeur entière, ce qui permet de les utiliser
+//! directement comme champs des trames.
 
+use core::f32::consts::PI;
 use core::fmt::{Display, Formatter, Result};
-use core::ops::{Add, Div, Mul, Sub};
+use core::ops::{Add, Div, Mul, Neg, Sub};
+
+#[allow(unused_imports)]
+use libm::F32Ext;
 
 /// Une longueur exprimée en millimètre
-#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct MilliMeter(pub i64);
 
 impl MilliMeter {
@@ -22,6 +32,41 @@ impl MilliMeter {
     pub fn as_millimeters(self) -> i64 {
         self.0
     }
+
+    /// Addition qui renvoie `None` en cas de dépassement
+    pub fn checked_add(self, rhs: MilliMeter) -> Option<MilliMeter> {
+        self.0.checked_add(rhs.0).map(MilliMeter)
+    }
+
+    /// Soustraction qui renvoie `None` en cas de dépassement
+    pub fn checked_sub(self, rhs: MilliMeter) -> Option<MilliMeter> {
+        self.0.checked_sub(rhs.0).map(MilliMeter)
+    }
+
+    /// Multiplication par un scalaire qui renvoie `None` en cas de dépassement
+    pub fn checked_mul(self, rhs: i64) -> Option<MilliMeter> {
+        self.0.checked_mul(rhs).map(MilliMeter)
+    }
+
+    /// Division par un scalaire qui renvoie `None` si `rhs` vaut 0 ou en cas de dépassement
+    pub fn checked_div(self, rhs: i64) -> Option<MilliMeter> {
+        self.0.checked_div(rhs).map(MilliMeter)
+    }
+
+    /// Addition bornée aux valeurs extrêmes
+    pub fn saturating_add(self, rhs: MilliMeter) -> MilliMeter {
+        MilliMeter(self.0.saturating_add(rhs.0))
+    }
+
+    /// Soustraction bornée aux valeurs extrêmes
+    pub fn saturating_sub(self, rhs: MilliMeter) -> MilliMeter {
+        MilliMeter(self.0.saturating_sub(rhs.0))
+    }
+
+    /// Multiplication par un scalaire bornée aux valeurs extrêmes
+    pub fn saturating_mul(self, rhs: i64) -> MilliMeter {
+        MilliMeter(self.0.saturating_mul(rhs))
+    }
 }
 
 impl Display for MilliMeter {
@@ -44,6 +89,8 @@ impl Div for MilliMeter {
     }
 }
 
+/// Le produit de deux longueurs n'est pas une longueur : cette implémentation n'est gardée que pour
+/// ne pas casser le code existant, il faut lui préférer `MilliMeter * i64`.
 impl Mul for MilliMeter {
     type Output = MilliMeter;
     fn mul(self, rhs: MilliMeter) -> Self::Output {
@@ -51,6 +98,34 @@ impl Mul for MilliMeter {
     }
 }
 
+impl Mul<i64> for MilliMeter {
+    type Output = MilliMeter;
+    fn mul(self, rhs: i64) -> Self::Output {
+        MilliMeter(self.as_millimeters() * rhs)
+    }
+}
+
+impl Mul<f32> for MilliMeter {
+    type Output = MilliMeter;
+    fn mul(self, rhs: f32) -> Self::Output {
+        MilliMeter((self.as_millimeters() as f32 * rhs).round() as i64)
+    }
+}
+
+impl Div<i64> for MilliMeter {
+    type Output = MilliMeter;
+    fn div(self, rhs: i64) -> Self::Output {
+        MilliMeter(self.as_millimeters() / rhs)
+    }
+}
+
+impl Neg for MilliMeter {
+    type Output = MilliMeter;
+    fn neg(self) -> Self::Output {
+        MilliMeter(-self.as_millimeters())
+    }
+}
+
 impl Sub for MilliMeter {
     type Output = MilliMeter;
     fn sub(self, rhs: MilliMeter) -> Self::Output {
@@ -58,10 +133,362 @@ impl Sub for MilliMeter {
     }
 }
 
+/// Une longueur exprimée en micromètre, pour les calculs qui ont besoin d'une précision
+/// inférieure au millimètre (odométrie, positions en dixièmes de millimètre des trames)
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct MicroMeter(pub i64);
+
+impl MicroMeter {
+    /// Crée une longueur à partir d'une valeur en dixièmes de millimètre
+    pub fn from_tenths_of_millimeter(tenths: i64) -> Self {
+        MicroMeter(tenths * 100)
+    }
+
+    /// Récupère la valeur en micromètre
+    pub fn as_micrometers(self) -> i64 {
+        self.0
+    }
+
+    /// Récupère la valeur en dixièmes de millimètre, arrondie au plus proche
+    pub fn as_tenths_of_millimeter(self) -> i64 {
+        round_div(self.0, 100)
+    }
+
+    /// Récupère la valeur en millimètre, arrondie au plus proche
+    pub fn as_millimeters(self) -> MilliMeter {
+        MilliMeter(round_div(self.0, 1000))
+    }
+}
+
+/// Division entière arrondie au plus proche, les demis étant arrondis en s'éloignant de zéro
+fn round_div(value: i64, divisor: i64) -> i64 {
+    if value >= 0 {
+        (value + divisor / 2) / divisor
+    } else {
+        (value - divisor / 2) / divisor
+    }
+}
+
+impl From<MilliMeter> for MicroMeter {
+    fn from(value: MilliMeter) -> Self {
+        MicroMeter(value.as_millimeters() * 1000)
+    }
+}
+
+impl Display for MicroMeter {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        write!(f, "{} µm", self.as_micrometers())
+    }
+}
+
+impl Add for MicroMeter {
+    type Output = MicroMeter;
+    fn add(self, rhs: MicroMeter) -> Self::Output {
+        MicroMeter(self.as_micrometers() + rhs.as_micrometers())
+    }
+}
+
+impl Sub for MicroMeter {
+    type Output = MicroMeter;
+    fn sub(self, rhs: MicroMeter) -> Self::Output {
+        MicroMeter(self.as_micrometers() - rhs.as_micrometers())
+    }
+}
+
+impl Neg for MicroMeter {
+    type Output = MicroMeter;
+    fn neg(self) -> Self::Output {
+        MicroMeter(-self.as_micrometers())
+    }
+}
+
+/// Un angle exprimé en milliradians
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Angle(pub i64);
+
+impl Angle {
+    /// Crée un angle à partir d'une valeur en radians
+    pub fn from_radians(radians: f32) -> Self {
+        Angle((radians * 1000.0).round() as i64)
+    }
+
+    /// Crée un angle à partir d'une valeur en degrés
+    pub fn from_degrees(degrees: f32) -> Self {
+        Angle::from_radians(degrees * PI / 180.0)
+    }
+
+    /// Récupère la valeur en milliradians
+    pub fn as_milliradians(self) -> i64 {
+        self.0
+    }
+
+    /// Récupère la valeur en radians
+    pub fn as_radians(self) -> f32 {
+        self.0 as f32 / 1000.0
+    }
+
+    /// Récupère la valeur en degrés
+    pub fn as_degrees(self) -> f32 {
+        self.as_radians() * 180.0 / PI
+    }
+
+    /// Renvoie l'angle équivalent compris dans `[-π, π[`
+    pub fn normalize(self) -> Angle {
+        let radians = self.as_radians();
+        let turns = ((radians + PI) / (2.0 * PI)).floor();
+        let normalized = Angle::from_radians(radians - turns * 2.0 * PI);
+        // L'arrondi au milliradian peut donner tout juste π
+        if normalized.as_radians() >= PI {
+            normalized - Angle::from_radians(2.0 * PI)
+        } else {
+            normalized
+        }
+    }
+}
+
+impl Display for Angle {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        write!(f, "{} mrad", self.as_milliradians())
+    }
+}
+
+impl Add for Angle {
+    type Output = Angle;
+    fn add(self, rhs: Angle) -> Self::Output {
+        Angle(self.as_milliradians() + rhs.as_milliradians())
+    }
+}
+
+impl Sub for Angle {
+    type Output = Angle;
+    fn sub(self, rhs: Angle) -> Self::Output {
+        Angle(self.as_milliradians() - rhs.as_milliradians())
+    }
+}
+
+impl Neg for Angle {
+    type Output = Angle;
+    fn neg(self) -> Self::Output {
+        Angle(-self.as_milliradians())
+    }
+}
+
+impl Mul<i64> for Angle {
+    type Output = Angle;
+    fn mul(self, rhs: i64) -> Self::Output {
+        Angle(self.as_milliradians() * rhs)
+    }
+}
+
+impl Div<i64> for Angle {
+    type Output = Angle;
+    fn div(self, rhs: i64) -> Self::Output {
+        Angle(self.as_milliradians() / rhs)
+    }
+}
+
+/// Implémente l'affichage et les opérations arithmétiques d'une unité `$unit` qui contient un
+/// entier `$inner`, affichée avec le suffixe `$suffix`
+macro_rules! scalar_unit {
+    ($unit:ident, $inner:ty, $suffix:expr) => {
+        impl Display for $unit {
+            fn fmt(&self, f: &mut Formatter) -> Result {
+                write!(f, "{} {}", self.0, $suffix)
+            }
+        }
+
+        impl Add for $unit {
+            type Output = $unit;
+            fn add(self, rhs: $unit) -> Self::Output {
+                $unit(self.0 + rhs.0)
+            }
+        }
+
+        impl Sub for $unit {
+            type Output = $unit;
+            fn sub(self, rhs: $unit) -> Self::Output {
+                $unit(self.0 - rhs.0)
+            }
+        }
+
+        impl Neg for $unit {
+            type Output = $unit;
+            fn neg(self) -> Self::Output {
+                $unit(-self.0)
+            }
+        }
+
+        impl Mul<$inner> for $unit {
+            type Output = $unit;
+            fn mul(self, rhs: $inner) -> Self::Output {
+                $unit(self.0 * rhs)
+            }
+        }
+
+        impl Div<$inner> for $unit {
+            type Output = $unit;
+            fn div(self, rhs: $inner) -> Self::Output {
+                $unit(self.0 / rhs)
+            }
+        }
+    };
+}
+
+/// Une tension exprimée en millivolts
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct MilliVolt(pub i32);
+
+scalar_unit!(MilliVolt, i32, "mV");
+
+/// Un courant exprimé en milliampères
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct MilliAmpere(pub i32);
+
+scalar_unit!(MilliAmpere, i32, "mA");
+
+/// Une température exprimée en degrés Celsius
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Celsius(pub i16);
+
+scalar_unit!(Celsius, i16, "°C");
+
+/// Une fréquence exprimée en hertz
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Hertz(pub u32);
+
+impl Hertz {
+    /// Crée une fréquence à partir d'une valeur en kilohertz
+    pub fn from_kilohertz(khz: u32) -> Self {
+        Hertz(khz * 1000)
+    }
+
+    /// Renvoie la période correspondante en microsecondes, ou `None` pour une fréquence nulle
+    pub fn period_us(self) -> Option<u32> {
+        1_000_000u32.checked_div(self.0)
+    }
+}
+
+impl Display for Hertz {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        write!(f, "{} Hz", self.0)
+    }
+}
+
+/// Un rapport cyclique, en dix-millièmes (`DutyCycle::FULL` vaut 100%). Une valeur désérialisée
+/// est bornée à 100% comme avec [`DutyCycle::from_ten_thousandths`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[serde(from = "u16")]
+pub struct DutyCycle(u16);
+
+impl From<u16> for DutyCycle {
+    fn from(value: u16) -> Self {
+        DutyCycle::from_ten_thousandths(value)
+    }
+}
+
+impl DutyCycle {
+    /// Rapport cyclique de 100%
+    pub const FULL: DutyCycle = DutyCycle(10_000);
+
+    /// Crée un rapport cyclique à partir d'une valeur en dix-millièmes, bornée à 100%
+    pub fn from_ten_thousandths(value: u16) -> Self {
+        DutyCycle(value.min(DutyCycle::FULL.0))
+    }
+
+    /// Crée un rapport cyclique à partir d'un pourcentage, borné à 100%
+    pub fn from_percent(percent: u8) -> Self {
+        DutyCycle::from_ten_thousandths(u16::from(percent).saturating_mul(100))
+    }
+
+    /// Récupère la valeur en dix-millièmes
+    pub fn as_ten_thousandths(self) -> u16 {
+        self.0
+    }
+
+    /// Applique le rapport cyclique à `max_duty`, la valeur de la PWM correspondant à 100%
+    pub fn of(self, max_duty: u16) -> u16 {
+        (u32::from(max_duty) * u32::from(self.0) / u32::from(DutyCycle::FULL.0)) as u16
+    }
+}
+
+impl Display for DutyCycle {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        write!(f, "{}.{:02} %", self.0 / 100, self.0 % 100)
+    }
+}
+
+/// Un nombre signé à virgule fixe au format Q16.16 : les 16 bits de poids faible sont la partie
+/// fractionnaire. C'est le format des coefficients envoyés par l'informatique.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Fixed16(pub i32);
+
+impl Fixed16 {
+    /// Nombre de bits de la partie fractionnaire
+    pub const FRACTIONAL_BITS: u32 = 16;
+    /// La valeur 1
+    pub const ONE: Fixed16 = Fixed16(1 << Fixed16::FRACTIONAL_BITS);
+
+    /// Convertit un flottant, en saturant s'il est hors de la plage représentable
+    pub fn from_f32(value: f32) -> Self {
+        Fixed16((value * Fixed16::ONE.0 as f32).round() as i32)
+    }
+
+    /// Crée un nombre à partir de sa partie entière
+    pub fn from_integer(value: i16) -> Self {
+        Fixed16(i32::from(value) << Fixed16::FRACTIONAL_BITS)
+    }
+
+    /// Récupère la valeur sous forme de flottant
+    pub fn to_f32(self) -> f32 {
+        self.0 as f32 / Fixed16::ONE.0 as f32
+    }
+}
+
+impl Display for Fixed16 {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        write!(f, "{}", self.to_f32())
+    }
+}
+
+impl Add for Fixed16 {
+    type Output = Fixed16;
+    fn add(self, rhs: Fixed16) -> Self::Output {
+        Fixed16(self.0 + rhs.0)
+    }
+}
+
+impl Sub for Fixed16 {
+    type Output = Fixed16;
+    fn sub(self, rhs: Fixed16) -> Self::Output {
+        Fixed16(self.0 - rhs.0)
+    }
+}
+
+impl Neg for Fixed16 {
+    type Output = Fixed16;
+    fn neg(self) -> Self::Output {
+        Fixed16(-self.0)
+    }
+}
+
 #[cfg(test)]
 mod test {
 
-    use crate::units::MilliMeter;
+    use crate::units::{
+        Angle, Celsius, DutyCycle, Fixed16, Hertz, MicroMeter, MilliAmpere, MilliMeter, MilliVolt,
+    };
+    use heapless::consts::U64;
+    use heapless::String;
+    use serde_json_core::de::from_slice;
+    use serde_json_core::ser::to_string;
 
     #[test]
     fn mm_to_meter() {
@@ -85,4 +512,170 @@ mod test {
         assert_eq!(x * y, MilliMeter(215));
     }
 
+    #[test]
+    fn mm_scalar_operations() {
+        let x = MilliMeter(43);
+        assert_eq!(x * 3, MilliMeter(129));
+        assert_eq!(x / 2, MilliMeter(21));
+        assert_eq!(x * 0.5, MilliMeter(22));
+        assert_eq!(x * -1.5, MilliMeter(-65));
+        assert_eq!(-x, MilliMeter(-43));
+        assert!(MilliMeter(-5) < x);
+        assert_eq!(x.max(MilliMeter(50)), MilliMeter(50));
+    }
+
+    #[test]
+    fn mm_checked_and_saturating_operations() {
+        let max = MilliMeter(i64::max_value());
+        let x = MilliMeter(43);
+        assert_eq!(x.checked_add(MilliMeter(5)), Some(MilliMeter(48)));
+        assert_eq!(max.checked_add(x), None);
+        assert_eq!(MilliMeter(i64::min_value()).checked_sub(x), None);
+        assert_eq!(max.checked_mul(2), None);
+        assert_eq!(x.checked_div(0), None);
+        assert_eq!(x.checked_div(2), Some(MilliMeter(21)));
+        assert_eq!(max.saturating_add(x), max);
+        assert_eq!(
+            MilliMeter(i64::min_value()).saturating_sub(x),
+            MilliMeter(i64::min_value())
+        );
+        assert_eq!(max.saturating_mul(-2), MilliMeter(i64::min_value()));
+    }
+
+    #[test]
+    fn micrometer_conversions() {
+        assert_eq!(MicroMeter::from(MilliMeter(12)), MicroMeter(12_000));
+        assert_eq!(
+            MicroMeter::from_tenths_of_millimeter(-35),
+            MicroMeter(-3500)
+        );
+        assert_eq!(MicroMeter(1499).as_millimeters(), MilliMeter(1));
+        assert_eq!(MicroMeter(1500).as_millimeters(), MilliMeter(2));
+        assert_eq!(MicroMeter(-1500).as_millimeters(), MilliMeter(-2));
+        assert_eq!(MicroMeter(-1449).as_tenths_of_millimeter(), -14);
+        assert_eq!(
+            MicroMeter::from_tenths_of_millimeter(1234).as_tenths_of_millimeter(),
+            1234
+        );
+        assert_eq!(MicroMeter(10) - MicroMeter(25), -MicroMeter(15));
+        assert_eq!(MicroMeter(10) + MicroMeter(25), MicroMeter(35));
+    }
+
+    #[test]
+    fn angle_conversions() {
+        assert_eq!(Angle::from_radians(1.5), Angle(1500));
+        assert_eq!(Angle::from_degrees(90.0), Angle(1571));
+        assert_eq!(Angle::from_degrees(-180.0), Angle(-3142));
+        assert!((Angle(3142).as_degrees() - 180.0).abs() < 0.1);
+        assert_eq!(Angle(1500).as_radians(), 1.5);
+    }
+
+    #[test]
+    fn angle_arithmetic_operations() {
+        let x = Angle(1000);
+        let y = Angle(300);
+        assert_eq!(x + y, Angle(1300));
+        assert_eq!(x - y, Angle(700));
+        assert_eq!(-x, Angle(-1000));
+        assert_eq!(x * 3, Angle(3000));
+        assert_eq!(x / 4, Angle(250));
+        assert!(y < x);
+    }
+
+    #[test]
+    fn angle_normalize() {
+        assert_eq!(Angle(785).normalize(), Angle(785));
+        assert_eq!(Angle(-785).normalize(), Angle(-785));
+        assert_eq!(Angle(5890).normalize(), Angle(-393));
+        assert_eq!(Angle(-5498).normalize(), Angle(785));
+        assert_eq!(Angle(7068).normalize(), Angle(785));
+        assert_eq!(Angle(3142).normalize(), Angle(-3141));
+        assert_eq!(Angle(-3142).normalize(), Angle(3141));
+        assert_eq!(Angle(100_000).normalize(), Angle(-531));
+    }
+
+    #[test]
+    fn electrical_units() {
+        let cell = MilliVolt(3700);
+        assert_eq!(cell * 3, MilliVolt(11100));
+        assert_eq!(cell / 2, MilliVolt(1850));
+        assert_eq!(cell - MilliVolt(200), MilliVolt(3500));
+        assert!(MilliAmpere(-200) < MilliAmpere(0));
+        assert_eq!(-MilliAmpere(200) + MilliAmpere(500), MilliAmpere(300));
+        assert_eq!(Celsius(52).max(Celsius(-5)), Celsius(52));
+        assert_eq!(format!("{}", cell), "3700 mV");
+        assert_eq!(format!("{}", Celsius(-5)), "-5 °C");
+    }
+
+    #[test]
+    fn pwm_units() {
+        assert_eq!(Hertz::from_kilohertz(20), Hertz(20_000));
+        assert_eq!(Hertz(20_000).period_us(), Some(50));
+        assert_eq!(Hertz(0).period_us(), None);
+        assert_eq!(DutyCycle::from_percent(150), DutyCycle::FULL);
+        assert_eq!(DutyCycle::from_ten_thousandths(12_000), DutyCycle::FULL);
+        let duty = DutyCycle::from_percent(80);
+        assert_eq!(duty.as_ten_thousandths(), 8000);
+        assert_eq!(duty.of(1000), 800);
+        assert_eq!(DutyCycle::FULL.of(u16::max_value()), u16::max_value());
+        assert_eq!(
+            format!("{}", DutyCycle::from_ten_thousandths(1234)),
+            "12.34 %"
+        );
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct PwmFrame {
+        duty: DutyCycle,
+    }
+
+    #[test]
+    fn duty_cycle_deserialization() {
+        let frame = from_slice::<PwmFrame>(b"{\"duty\":8000}").unwrap();
+        assert_eq!(frame.duty.as_ten_thousandths(), 8000);
+        // Une valeur au-delà de 100% reçue de l'informatique est bornée
+        let frame = from_slice::<PwmFrame>(b"{\"duty\":12000}").unwrap();
+        assert_eq!(frame.duty, DutyCycle::FULL);
+        let json: String<U64> = to_string(&frame).unwrap();
+        assert_eq!(json, "{\"duty\":10000}");
+    }
+
+    #[test]
+    fn fixed16_conversions() {
+        assert_eq!(Fixed16::from_f32(1.0), Fixed16::ONE);
+        assert_eq!(Fixed16::from_f32(-0.5), Fixed16(-32768));
+        assert_eq!(Fixed16::from_integer(-3), Fixed16(-3 * 65536));
+        assert_eq!(Fixed16(98304).to_f32(), 1.5);
+        assert_eq!(Fixed16::from_f32(1e9), Fixed16(i32::max_value()));
+        assert_eq!(
+            Fixed16::ONE + Fixed16::ONE - Fixed16::from_f32(0.25),
+            Fixed16::from_f32(1.75)
+        );
+        assert_eq!(-Fixed16::ONE, Fixed16::from_integer(-1));
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct TypedFrame {
+        distance: MilliMeter,
+        precise: MicroMeter,
+        heading: Angle,
+        gain: Fixed16,
+    }
+
+    #[test]
+    fn units_ser_deser() {
+        let frame = TypedFrame {
+            distance: MilliMeter(-120),
+            precise: MicroMeter(1500),
+            heading: Angle(785),
+            gain: Fixed16::ONE,
+        };
+        let json: String<U64> = to_string(&frame).unwrap();
+        assert_eq!(
+            json,
+            "{\"distance\":-120,\"precise\":1500,\"heading\":785,\"gain\":65536}"
+        );
+        assert_eq!(from_slice::<TypedFrame>(json.as_bytes()).unwrap(), frame);
+    }
+
 }